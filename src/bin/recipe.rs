@@ -2,13 +2,29 @@
 //!
 //! Usage:
 //!   recipe install <path>           Install a recipe
+//!   recipe plan <path>              Show what install would do
+//!   recipe update [path...]         Check for newer upstream versions
+//!   recipe upgrade [path...]        Upgrade to newer upstream versions
 //!   recipe remove <path>            Remove an installed package
 //!   recipe cleanup <path>           Clean up build artifacts
 //!   recipe isinstalled <path>       Execute is_installed(ctx)
 //!   recipe isbuilt <path>           Execute is_built(ctx)
 //!   recipe isacquired <path>        Execute is_acquired(ctx)
 //!   recipe list                     List recipes in directory
+//!   recipe search <query>           Search names, descriptions and files
 //!   recipe info <path>              Show recipe info
+//!   recipe deps [path]              Show what a recipe pulls in
+//!   recipe rdeps <path>             Show what depends on a recipe
+//!   recipe tree <path>              Show a recipe's dependency tree
+//!   recipe orphans                  List unneeded dependency installs
+//!   recipe autoremove               Remove unneeded dependency installs
+//!   recipe owns <file>              Show which recipe installed a file
+//!   recipe history <path>           Show a recipe's ctx history
+//!   recipe rollback-ctx <path>      Restore ctx from a history entry
+//!   recipe lint [path...]           Check recipes without running them
+//!   recipe fmt [path...]            Rewrite recipes in the canonical layout
+//!   recipe new <name>               Create a recipe from a template
+//!   recipe cache ls|gc|verify       Manage the shared download cache
 
 use anyhow::Result;
 use clap::{CommandFactory, FromArgMatches};
@@ -19,10 +35,12 @@ mod cli;
 mod commands;
 #[path = "recipe/metadata.rs"]
 mod metadata;
+#[path = "recipe/scaffold.rs"]
+mod scaffold;
 
 fn default_help_footer() -> String {
     format!(
-        "Defaults:\n  Recipes directory: {}\n  Build directory: temporary directory if --build-dir is not set\n  LLM profiles: $XDG_CONFIG_HOME/recipe/llm.toml (or ~/.config/recipe/llm.toml)\n  JSON output: stdout by default; recipe logs and helper output stay on stderr\n\nExamples:\n  recipe install kitty\n  recipe install ./custom/foo.rhai --define VERSION=1.2.3\n  recipe isbuilt kitty --no-persist-ctx\n  recipe list\n\nManual pages:\n  man recipe\n  man 5 recipe-recipe\n  man 7 recipe-helpers\n\nRun 'recipe <command> --help' for command-specific options.",
+        "Defaults:\n  Recipes directory: {}\n  Build directory: temporary directory if --build-dir is not set\n  LLM profiles: $XDG_CONFIG_HOME/recipe/llm.toml (or ~/.config/recipe/llm.toml)\n  ctx state store (--ctx-store state): $XDG_STATE_HOME/recipe (or ~/.local/state/recipe)\n  JSON output: stdout by default; recipe logs and helper output stay on stderr\n\nExamples:\n  recipe install kitty\n  recipe install ./custom/foo.rhai --define VERSION=1.2.3\n  recipe install kitty --sysroot /mnt/slot-b --prefix /usr\n  recipe isbuilt kitty --no-persist-ctx\n  recipe list\n\nManual pages:\n  man recipe\n  man 5 recipe-recipe\n  man 7 recipe-helpers\n\nRun 'recipe <command> --help' for command-specific options.",
         metadata::default_recipes_path().display()
     )
 }
@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -41,18 +41,46 @@ pub(crate) struct Cli {
     /// Do not persist updated ctx back into recipe source files; useful for inspection and dry debugging.
     #[arg(long, global = true, default_value_t = false)]
     pub(crate) no_persist_ctx: bool,
+
+    /// Install into this root instead of `/`; install helpers re-root absolute paths under it and refuse writes outside it.
+    #[arg(long, global = true)]
+    pub(crate) sysroot: Option<PathBuf>,
+
+    /// Install prefix exposed to recipes as `PREFIX`.
+    #[arg(long, global = true, default_value = levitate_recipe::DEFAULT_PREFIX)]
+    pub(crate) prefix: String,
+
+    /// Where persisted ctx is kept: the recipe source (default) or `$XDG_STATE_HOME/recipe`, which leaves recipe files untouched.
+    #[arg(long, global = true, value_enum, default_value_t = CtxStoreKind::Source)]
+    pub(crate) ctx_store: CtxStoreKind,
+
+    /// Always download from the network instead of reusing `$XDG_CACHE_HOME/recipe/sources`.
+    #[arg(long, global = true)]
+    pub(crate) no_cache: bool,
+
+    /// Forbid network access: network helpers only serve the download cache and otherwise fail at once.
+    #[arg(long, global = true)]
+    pub(crate) offline: bool,
 }
 
 #[derive(Subcommand)]
 pub(crate) enum Commands {
     /// Install a package from a recipe file
     #[command(
-        after_help = "Examples:\n  recipe install kitty\n  recipe install kitty.rhai\n  recipe install ./recipes/kitty.rhai\n  recipe install kitty --autofix --autofix-attempts 3\n\n<RECIPE> may be:\n  - an absolute path\n  - a relative path\n  - a recipe name resolved under --recipes-path\n  - the same name with .rhai appended under --recipes-path"
+        after_help = "Examples:\n  recipe install kitty\n  recipe install kitty.rhai\n  recipe install ./recipes/kitty.rhai\n  recipe install kitty --dry-run\n  recipe install kitty --allow-conflicts\n  recipe install kitty --autofix --autofix-attempts 3\n\n<RECIPE> may be:\n  - an absolute path\n  - a relative path\n  - a recipe name resolved under --recipes-path\n  - the same name with .rhai appended under --recipes-path"
     )]
     Install {
         /// Recipe path or recipe name. If the file is not found directly, recipe will also look in --recipes-path and try appending `.rhai`.
         recipe: PathBuf,
 
+        /// Evaluate checks and dependencies only and print the install plan as JSON (same as `recipe plan`).
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+
+        /// Let install overwrite files listed in another recipe's installed-file manifest.
+        #[arg(long)]
+        allow_conflicts: bool,
+
         /// Automatically attempt to patch build/install failures using the configured LLM provider.
         #[arg(long)]
         autofix: bool,
@@ -74,6 +102,15 @@ pub(crate) enum Commands {
         autofix_allow_path: Vec<PathBuf>,
     },
 
+    /// Show what `install` would do without running any phase
+    #[command(
+        after_help = "Examples:\n  recipe plan kitty\n  recipe plan ./recipes/kitty.rhai --json-output plan.json\n\nRuns the recipe's is_installed/is_built/is_acquired checks, locates deps and build_deps, and prints the plan as JSON. Nothing is installed and ctx is not persisted."
+    )]
+    Plan {
+        /// Recipe path or recipe name. Name lookup follows the same rules as `recipe install`.
+        recipe: PathBuf,
+    },
+
     /// Remove an installed package
     #[command(
         after_help = "Examples:\n  recipe remove kitty\n  recipe remove ./recipes/kitty.rhai"
@@ -83,6 +120,28 @@ pub(crate) enum Commands {
         recipe: PathBuf,
     },
 
+    /// Check recipes for newer upstream versions
+    #[command(
+        after_help = "Examples:\n  recipe update\n  recipe update kitty openssl\n  recipe update --json-output updates.json\n\nRuns each recipe's check_update hook (every recipe under --recipes-path when none are given) and lists the recipes whose upstream version is newer than ctx.version. Nothing is installed and recipes are not modified."
+    )]
+    Update {
+        /// Recipe paths or names. Defaults to every recipe under --recipes-path.
+        recipes: Vec<PathBuf>,
+    },
+
+    /// Upgrade recipes to the newer version reported by check_update
+    #[command(
+        after_help = "Examples:\n  recipe upgrade\n  recipe upgrade kitty\n\nWith no recipes, upgrades every installed recipe under --recipes-path. The new version is written into ctx and acquire, build and install rerun in a fresh build directory; the old installation is kept until the new one succeeds. Recipes that are up to date are left alone."
+    )]
+    Upgrade {
+        /// Recipe paths or names. Defaults to every installed recipe under --recipes-path.
+        recipes: Vec<PathBuf>,
+
+        /// Let install overwrite files listed in another recipe's installed-file manifest.
+        #[arg(long)]
+        allow_conflicts: bool,
+    },
+
     /// Clean up build artifacts
     #[command(
         after_help = "Examples:\n  recipe cleanup kitty\n  recipe cleanup kitty --reason manual\n  recipe cleanup ./recipes/kitty.rhai --build-dir /tmp/recipe-build"
@@ -130,6 +189,19 @@ pub(crate) enum Commands {
     #[command(after_help = "Examples:\n  recipe list\n  recipe list --recipes-path ./recipes")]
     List,
 
+    /// Search recipes by name, description, homepage and installed files
+    #[command(
+        after_help = "Examples:\n  recipe search ripgrep\n  recipe search '^lib(ssl|crypto)'\n  recipe search 'bin/rg$'\n  recipe search compress --format json\n\n<QUERY> is a case-insensitive regular expression, matched against each recipe's name, description and homepage, and, for installed recipes, the paths in its installed-file manifest."
+    )]
+    Search {
+        /// Case-insensitive regular expression.
+        query: String,
+
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = SearchFormat::Text)]
+        format: SearchFormat,
+    },
+
     /// Show recipe information
     #[command(after_help = "Examples:\n  recipe info kitty\n  recipe info ./recipes/kitty.rhai")]
     Info {
@@ -137,6 +209,139 @@ pub(crate) enum Commands {
         recipe: PathBuf,
     },
 
+    /// Show what a recipe pulls in, or export the whole dependency graph
+    #[command(
+        after_help = "Examples:\n  recipe deps kitty\n  recipe deps kitty --format dot | dot -Tpng -o kitty.png\n  recipe deps --format json\n\nWith <RECIPE>, prints its transitive deps and build_deps in install order (text), or the reachable part of the graph (dot/json). Without <RECIPE>, exports the graph of every recipe under --recipes-path."
+    )]
+    Deps {
+        /// Recipe path or recipe name. Name lookup follows the same rules as `recipe install`.
+        recipe: Option<PathBuf>,
+
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = GraphFormat::Text)]
+        format: GraphFormat,
+    },
+
+    /// Show every recipe that depends on a recipe
+    #[command(
+        after_help = "Examples:\n  recipe rdeps openssl\n  recipe rdeps openssl --format dot\n\nFollows deps and build_deps of every recipe under --recipes-path, transitively."
+    )]
+    Rdeps {
+        /// Recipe path or recipe name. Name lookup follows the same rules as `recipe install`.
+        recipe: PathBuf,
+
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = GraphFormat::Text)]
+        format: GraphFormat,
+    },
+
+    /// Print the dependency tree of a recipe
+    #[command(
+        after_help = "Examples:\n  recipe tree kitty\n\nbuild_deps are marked [build]; recipes already shown are marked (shared) and not expanded again."
+    )]
+    Tree {
+        /// Recipe path or recipe name. Name lookup follows the same rules as `recipe install`.
+        recipe: PathBuf,
+    },
+
+    /// List recipes installed only as dependencies that nothing still needs
+    #[command(
+        after_help = "Examples:\n  recipe orphans\n  recipe orphans --json-output orphans.json\n\nA recipe is an orphan when its ctx has install_reason: \"dependency\" and no explicitly installed recipe reaches it through deps."
+    )]
+    Orphans,
+
+    /// Remove orphaned dependency recipes
+    #[command(
+        after_help = "Examples:\n  recipe autoremove\n\nRemoves every recipe listed by `recipe orphans`, dependents before their dependencies."
+    )]
+    Autoremove,
+
+    /// Show which recipe installed a file
+    #[command(
+        after_help = "Examples:\n  recipe owns /usr/bin/kitty\n  recipe owns ./usr/lib/libfoo.so --json-output owners.json\n\nLooks the path up in the installed-file manifests (installed_files in ctx) of every recipe under --recipes-path."
+    )]
+    Owns {
+        /// File path to look up. Relative paths are resolved against the current directory.
+        path: PathBuf,
+    },
+
+    /// Show the ctx history journal of a recipe
+    #[command(
+        after_help = "Examples:\n  recipe history kitty\n  recipe history kitty --json-output history.json\n\nEvery ctx persist appends an entry (phase, outcome, changed keys, full snapshot). Restore one with `recipe rollback-ctx`."
+    )]
+    History {
+        /// Recipe path or recipe name. Name lookup follows the same rules as `recipe install`.
+        recipe: PathBuf,
+    },
+
+    /// Restore a recipe's ctx to an earlier history entry
+    #[command(
+        after_help = "Examples:\n  recipe rollback-ctx kitty --to 3\n\nOnly ctx changes: no hook runs and no files are installed or removed. The restore is recorded as a new history entry."
+    )]
+    RollbackCtx {
+        /// Recipe path or recipe name. Name lookup follows the same rules as `recipe install`.
+        recipe: PathBuf,
+
+        /// History entry number (SEQ in `recipe history`) to restore.
+        #[arg(long, value_name = "N")]
+        to: u64,
+    },
+
+    /// Check recipes for common mistakes without running them
+    #[command(
+        after_help = "Examples:\n  recipe lint kitty\n  recipe lint\n  recipe lint --format json > lint.json\n\nCompiles each recipe (with its //! extends: bases) and reports a missing cleanup(ctx, reason), hooks with the wrong arity, check/phase functions that do not return ctx, unknown functions, a ctx without name/version, and hardcoded absolute paths. Exits non-zero when any error is found; warnings do not fail."
+    )]
+    Lint {
+        /// Recipe paths or names. Defaults to every recipe under --recipes-path.
+        recipes: Vec<PathBuf>,
+
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = LintFormat::Text)]
+        format: LintFormat,
+    },
+
+    /// Rewrite recipes in the canonical layout
+    #[command(
+        after_help = "Examples:\n  recipe fmt\n  recipe fmt kitty\n  recipe fmt --check\n\nNormalizes the //! header (extends order is kept), rewrites literal ctx blocks with sorted keys, and orders functions: helpers, is_installed, is_built, is_acquired, acquire, build, install, remove, check_update, cleanup. Comments move with the item below them; function bodies are not reformatted. --check writes nothing and exits non-zero when any recipe would change."
+    )]
+    Fmt {
+        /// Recipe paths or names. Defaults to every recipe under --recipes-path.
+        recipes: Vec<PathBuf>,
+
+        /// Report recipes that are not formatted instead of rewriting them.
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Create a recipe from a template
+    #[command(
+        after_help = "Examples:\n  recipe new foo\n  recipe new jq --template github-release --url https://github.com/jqlang/jq/releases/download/jq-1.7.1/jq-1.7.1.tar.gz\n  recipe new foo --template git --url https://github.com/example/foo.git --version 2.0.0\n  recipe new ripgrep --template rpm-shim -o ./ripgrep.rhai\n\nWrites <NAME>.rhai under --recipes-path unless -o is given. With --url, the tarball and github-release templates download the file once to fill in its sha256 and take the version from the URL; the URL is written with ${ctx.version} so `recipe upgrade` can follow new versions. Generated recipes pass `recipe lint`."
+    )]
+    New {
+        /// Recipe name; also the ctx name and the installed binary name.
+        name: String,
+
+        /// Recipe template.
+        #[arg(long, value_enum, default_value_t = RecipeTemplate::Tarball)]
+        template: RecipeTemplate,
+
+        /// Download URL (tarball, github-release) or repository URL (git).
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Package version; defaults to the version found in --url.
+        #[arg(long)]
+        version: Option<String>,
+
+        /// Output file instead of <NAME>.rhai under --recipes-path.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Overwrite an existing file.
+        #[arg(long)]
+        force: bool,
+    },
+
     /// Compute hashes for a file
     #[command(
         after_help = "Examples:\n  recipe hash ./downloads/foo.tar.xz\n\nPrints sha256, sha512, and blake3 values for use in recipe acquire() steps."
@@ -145,4 +350,84 @@ pub(crate) enum Commands {
         /// File path to hash.
         file: PathBuf,
     },
+
+    /// Inspect and prune the shared download cache
+    #[command(
+        after_help = "Examples:\n  recipe cache ls\n  recipe cache gc --older-than 7\n  recipe cache gc --all\n  recipe cache verify\n\nDownloads are cached under $XDG_CACHE_HOME/recipe/sources (default ~/.cache/recipe/sources), stored by SHA-256 and indexed by URL. Use --no-cache to bypass it for one run."
+    )]
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub(crate) enum CacheAction {
+    /// List cached URLs with their hash, size and whether a recipe verified them
+    Ls,
+
+    /// Remove cached downloads not used recently
+    Gc {
+        /// Remove entries not used for this many days.
+        #[arg(long, value_name = "DAYS", default_value_t = 30)]
+        older_than: u64,
+
+        /// Remove every cached download.
+        #[arg(long, conflicts_with = "older_than")]
+        all: bool,
+    },
+
+    /// Rehash every cached file and remove the ones that no longer match
+    Verify,
+}
+
+/// Backend for persisted ctx.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum CtxStoreKind {
+    /// Rewrite the `let ctx = #{...}` block in the recipe file.
+    Source,
+    /// Per-recipe JSON overlays under `$XDG_STATE_HOME/recipe/ctx/`.
+    State,
+}
+
+/// Output format for `recipe lint`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum LintFormat {
+    /// One `file:line: severity[code]: message` line per issue.
+    Text,
+    /// JSON array with one report (recipe and issues) per recipe.
+    Json,
+}
+
+/// Template for `recipe new`.
+/// Output format for `recipe search`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SearchFormat {
+    /// One `name version` line per recipe, with its description and matching files.
+    Text,
+    /// JSON array of recipe metadata with `installed`, `matched` and `files`.
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum RecipeTemplate {
+    /// Source tarball built with configure/make.
+    Tarball,
+    /// Git checkout of a tag, built with make.
+    Git,
+    /// Prebuilt binary from a GitHub release archive, with check_update.
+    GithubRelease,
+    /// Thin wrapper around a distribution RPM installed with dnf.
+    RpmShim,
+}
+
+/// Output format for dependency graph commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum GraphFormat {
+    /// One recipe name per line.
+    Text,
+    /// Graphviz DOT; build_deps edges are dashed.
+    Dot,
+    /// JSON object mapping recipe name to its path, deps, and build_deps.
+    Json,
 }
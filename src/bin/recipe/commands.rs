@@ -1,12 +1,20 @@
 use anyhow::{Context, Result};
-use levitate_recipe::{AutoFixConfig, RecipeEngine, helpers, output};
+use levitate_recipe::{
+    AutoFixConfig, CacheEntry, CtxStore, DepGraph, HistoryEntry, LintReport, MirrorTable,
+    RecipeEngine, RecipeInfo, RepoSet, SearchHit, Severity, SourceCache, UpdateInfo, catalog,
+    helpers, history, output,
+};
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use super::{
-    cli::{Cli, Commands},
-    metadata::{RecipeMetadata, enumerate_recipes},
+    cli::{
+        CacheAction, Cli, Commands, CtxStoreKind, GraphFormat, LintFormat, RecipeTemplate,
+        SearchFormat,
+    },
+    scaffold::{Scaffold, prefill_sha256},
 };
 
 pub(crate) fn execute(cli: Cli) -> Result<()> {
@@ -23,17 +31,26 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
     }
 
     let json_output = cli.json_output;
+    let engine_options = EngineOptions::new(
+        cli.sysroot,
+        cli.prefix,
+        cli.ctx_store,
+        cli.no_cache,
+        cli.offline,
+    )?;
 
     match cli.command {
         Commands::Install {
             recipe,
+            dry_run,
+            allow_conflicts,
             autofix,
             autofix_attempts,
             autofix_cwd,
             autofix_prompt_file,
             autofix_allow_path,
         } => {
-            let recipe_path = resolve_recipe_path(&recipe, &recipes_path)?;
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
             let autofix_cfg = if autofix {
                 Some(AutoFixConfig {
                     attempts: autofix_attempts.max(1),
@@ -50,34 +67,146 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
                 &cli.defines,
                 cli.no_persist_ctx,
                 cli.llm_profile.clone(),
+                &engine_options,
                 autofix_cfg,
+            )?
+            .with_allow_conflicts(allow_conflicts);
+            if dry_run {
+                let plan = engine.plan(&recipe_path)?;
+                emit_serialized(&plan, json_output.as_deref())?;
+            } else {
+                let ctx = engine.execute(&recipe_path)?;
+                emit_json(&ctx, json_output.as_deref())?;
+            }
+        }
+
+        Commands::Plan { recipe } => {
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
             )?;
-            let ctx = engine.execute(&recipe_path)?;
-            emit_json(&ctx, json_output.as_deref())?;
+            let plan = engine.plan(&recipe_path)?;
+            emit_serialized(&plan, json_output.as_deref())?;
         }
 
         Commands::Remove { recipe } => {
-            let recipe_path = resolve_recipe_path(&recipe, &recipes_path)?;
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
             let engine = create_engine(
                 cli.build_dir.as_deref(),
                 Some(&recipes_path),
                 &cli.defines,
                 cli.no_persist_ctx,
                 cli.llm_profile.clone(),
+                &engine_options,
                 None,
             )?;
             let ctx = engine.remove(&recipe_path)?;
             emit_json(&ctx, json_output.as_deref())?;
         }
 
+        Commands::Update { recipes } => {
+            let explicit = !recipes.is_empty();
+            let targets = if explicit {
+                recipes
+                    .iter()
+                    .map(|r| resolve_recipe_path(r, &recipes_path, &engine_options.repos))
+                    .collect::<Result<Vec<_>>>()?
+            } else {
+                engine_options.repos.recipe_files(Some(&recipes_path))
+            };
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+
+            let mut updates = Vec::new();
+            let mut failed = 0;
+            for path in &targets {
+                match engine.check_update(path) {
+                    Ok(Some(info)) if info.is_newer() => updates.push(info),
+                    Ok(Some(info)) if explicit => {
+                        output::info(&format!("{} is up to date", info.name))
+                    }
+                    Ok(None) if explicit => {
+                        output::warning(&format!("{} defines no check_update hook", path.display()))
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        output::warning(&format!("{e:#}"));
+                        failed += 1;
+                    }
+                }
+            }
+
+            match json_output.as_deref() {
+                Some(p) => emit_serialized(&updates, Some(p))?,
+                None => print_updates(&updates),
+            }
+            if failed > 0 {
+                anyhow::bail!("check_update failed for {failed} recipe(s)");
+            }
+        }
+
+        Commands::Upgrade {
+            recipes,
+            allow_conflicts,
+        } => {
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?
+            .with_allow_conflicts(allow_conflicts);
+            let explicit = !recipes.is_empty();
+            let targets = if explicit {
+                recipes
+                    .iter()
+                    .map(|r| resolve_recipe_path(r, &recipes_path, &engine_options.repos))
+                    .collect::<Result<Vec<_>>>()?
+            } else {
+                engine.installed_recipes()
+            };
+
+            let mut upgraded = Vec::new();
+            for path in &targets {
+                match engine.upgrade(path)? {
+                    Some(info) if info.is_newer() => upgraded.push(info),
+                    None if explicit => {
+                        output::warning(&format!("{} defines no check_update hook", path.display()))
+                    }
+                    _ => {}
+                }
+            }
+            if upgraded.is_empty() {
+                output::info("Nothing to upgrade");
+            }
+            emit_serialized(&upgraded, json_output.as_deref())?;
+        }
+
         Commands::Cleanup { recipe, reason } => {
-            let recipe_path = resolve_recipe_path(&recipe, &recipes_path)?;
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
             let engine = create_engine(
                 cli.build_dir.as_deref(),
                 Some(&recipes_path),
                 &cli.defines,
                 cli.no_persist_ctx,
                 cli.llm_profile.clone(),
+                &engine_options,
                 None,
             )?;
             let cleanup_reason = reason
@@ -90,13 +219,14 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
         }
 
         Commands::IsInstalled { recipe } => {
-            let recipe_path = resolve_recipe_path(&recipe, &recipes_path)?;
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
             let engine = create_engine(
                 cli.build_dir.as_deref(),
                 Some(&recipes_path),
                 &cli.defines,
                 cli.no_persist_ctx,
                 cli.llm_profile.clone(),
+                &engine_options,
                 None,
             )?;
             let ctx = engine.is_installed(&recipe_path)?;
@@ -104,13 +234,14 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
         }
 
         Commands::IsBuilt { recipe } => {
-            let recipe_path = resolve_recipe_path(&recipe, &recipes_path)?;
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
             let engine = create_engine(
                 cli.build_dir.as_deref(),
                 Some(&recipes_path),
                 &cli.defines,
                 cli.no_persist_ctx,
                 cli.llm_profile.clone(),
+                &engine_options,
                 None,
             )?;
             let ctx = engine.is_built(&recipe_path)?;
@@ -118,24 +249,377 @@ pub(crate) fn execute(cli: Cli) -> Result<()> {
         }
 
         Commands::IsAcquired { recipe } => {
-            let recipe_path = resolve_recipe_path(&recipe, &recipes_path)?;
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
             let engine = create_engine(
                 cli.build_dir.as_deref(),
                 Some(&recipes_path),
                 &cli.defines,
                 cli.no_persist_ctx,
                 cli.llm_profile.clone(),
+                &engine_options,
                 None,
             )?;
             let ctx = engine.is_acquired(&recipe_path)?;
             emit_json(&ctx, json_output.as_deref())?;
         }
 
-        Commands::List => list_recipes(&recipes_path)?,
+        Commands::List => {
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            list_recipes(&engine, &recipes_path, json_output.as_deref())?;
+        }
+
+        Commands::Search { query, format } => {
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let hits = engine.search(&query)?;
+            match format {
+                SearchFormat::Text => print_search(&hits),
+                SearchFormat::Json => emit_serialized(&hits, json_output.as_deref())?,
+            }
+        }
 
         Commands::Info { recipe } => {
-            let recipe_path = resolve_recipe_path(&recipe, &recipes_path)?;
-            show_info(&recipe_path)?;
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let info = engine.info(&recipe_path)?;
+            match json_output.as_deref() {
+                Some(p) => emit_serialized(&info, Some(p))?,
+                None => show_info(&info),
+            }
+        }
+
+        Commands::Deps { recipe, format } => {
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let recipe_path = recipe
+                .map(|r| resolve_recipe_path(&r, &recipes_path, &engine_options.repos))
+                .transpose()?;
+            let (graph, root) = engine.dep_graph(recipe_path.as_deref())?;
+            match root {
+                Some(root) => {
+                    let direct: Vec<String> = graph
+                        .get(&root)
+                        .map(|node| node.all_deps().cloned().collect())
+                        .unwrap_or_default();
+                    let order = graph.install_order(&root, &direct)?;
+                    let members: Vec<String> = std::iter::once(root).chain(order.clone()).collect();
+                    emit_graph(
+                        &graph.subgraph(&members),
+                        &order,
+                        format,
+                        json_output.as_deref(),
+                    )?;
+                }
+                None => {
+                    let names: Vec<String> = graph.nodes().keys().cloned().collect();
+                    emit_graph(&graph, &names, format, json_output.as_deref())?;
+                }
+            }
+        }
+
+        Commands::Rdeps { recipe, format } => {
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let (graph, root) = engine.dep_graph(Some(&recipe_path))?;
+            let root = root.expect("recipe path was given");
+            let dependents = graph.dependents(&root);
+            let members: Vec<String> = std::iter::once(root).chain(dependents.clone()).collect();
+            emit_graph(
+                &graph.subgraph(&members),
+                &dependents,
+                format,
+                json_output.as_deref(),
+            )?;
+        }
+
+        Commands::Tree { recipe } => {
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let (graph, root) = engine.dep_graph(Some(&recipe_path))?;
+            let root = root.expect("recipe path was given");
+            print!("{}", graph.render_tree(&root));
+        }
+
+        Commands::Orphans => {
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let orphans = engine.orphans()?;
+            match json_output.as_deref() {
+                Some(p) => emit_serialized(&orphans, Some(p))?,
+                None => {
+                    for orphan in &orphans {
+                        println!("{}", orphan.name);
+                    }
+                }
+            }
+        }
+
+        Commands::Autoremove => {
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let removed = engine.autoremove()?;
+            if removed.is_empty() {
+                output::info("No orphaned recipes to remove");
+            }
+            emit_serialized(&removed, json_output.as_deref())?;
+        }
+
+        Commands::Owns { path } => {
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let owners = engine.owners_of(&path);
+            if owners.is_empty() {
+                anyhow::bail!("No recipe owns {}", path.display());
+            }
+            match json_output.as_deref() {
+                Some(p) => emit_serialized(&owners, Some(p))?,
+                None => {
+                    for owner in &owners {
+                        println!(
+                            "{} is owned by {} ({})",
+                            path.display(),
+                            owner.name,
+                            owner.recipe_path.display()
+                        );
+                    }
+                }
+            }
+        }
+
+        Commands::History { recipe } => {
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let entries = engine.history(&recipe_path)?;
+            match json_output.as_deref() {
+                Some(p) => emit_serialized(&entries, Some(p))?,
+                None => print_history(&entries),
+            }
+        }
+
+        Commands::RollbackCtx { recipe, to } => {
+            let recipe_path = resolve_recipe_path(&recipe, &recipes_path, &engine_options.repos)?;
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let ctx = engine.rollback_ctx(&recipe_path, to)?;
+            emit_json(&ctx, json_output.as_deref())?;
+        }
+
+        Commands::Lint { recipes, format } => {
+            let targets = if recipes.is_empty() {
+                catalog::recipe_files(&recipes_path)
+            } else {
+                recipes
+                    .iter()
+                    .map(|r| resolve_recipe_path(r, &recipes_path, &engine_options.repos))
+                    .collect::<Result<Vec<_>>>()?
+            };
+            let engine = create_engine(
+                cli.build_dir.as_deref(),
+                Some(&recipes_path),
+                &cli.defines,
+                cli.no_persist_ctx,
+                cli.llm_profile.clone(),
+                &engine_options,
+                None,
+            )?;
+            let reports: Vec<LintReport> = targets.iter().map(|path| engine.lint(path)).collect();
+            match format {
+                LintFormat::Text => print_lint(&reports),
+                LintFormat::Json => emit_serialized(&reports, json_output.as_deref())?,
+            }
+            let failed = reports.iter().filter(|r| r.errors() > 0).count();
+            if failed > 0 {
+                anyhow::bail!("{failed} recipe(s) failed lint");
+            }
+        }
+
+        Commands::Fmt { recipes, check } => {
+            let targets = if recipes.is_empty() {
+                catalog::recipe_files(&recipes_path)
+            } else {
+                recipes
+                    .iter()
+                    .map(|r| resolve_recipe_path(r, &recipes_path, &engine_options.repos))
+                    .collect::<Result<Vec<_>>>()?
+            };
+            let mut changed = 0;
+            for path in &targets {
+                let source = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                let formatted = levitate_recipe::format::format_source(&source)
+                    .with_context(|| format!("Refusing to format {}", path.display()))?;
+                if formatted == source {
+                    continue;
+                }
+                changed += 1;
+                if check {
+                    println!("{}", path.display());
+                } else {
+                    std::fs::write(path, formatted)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    output::success(&format!("Formatted {}", path.display()));
+                }
+            }
+            if check && changed > 0 {
+                anyhow::bail!("{changed} recipe(s) are not formatted (run `recipe fmt`)");
+            }
+            output::info(&format!(
+                "{} recipe(s) checked, {changed} {}",
+                targets.len(),
+                if check { "to format" } else { "formatted" }
+            ));
+        }
+        Commands::New {
+            name,
+            template,
+            url,
+            version,
+            output: path,
+            force,
+        } => {
+            let path = path.unwrap_or_else(|| recipes_path.join(format!("{name}.rhai")));
+            if path.exists() && !force {
+                anyhow::bail!(
+                    "{} already exists (use --force to overwrite)",
+                    path.display()
+                );
+            }
+            let mut scaffold = Scaffold::new(template, &name, version.as_deref(), url.as_deref())?;
+            if let Some(url) = url.as_deref().filter(|_| template != RecipeTemplate::Git) {
+                if engine_options.offline {
+                    anyhow::bail!(
+                        "offline: computing the sha256 for --url needs {url}, but network access is disabled"
+                    );
+                }
+                scaffold.sha256 = prefill_sha256(url)?;
+            }
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            std::fs::write(&path, scaffold.render(template))
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            output::success(&format!("Created {}", path.display()));
+            output::detail(&format!("Check it with: recipe lint {}", path.display()));
+        }
+        Commands::Cache { action } => {
+            let cache = SourceCache::xdg();
+            match action {
+                CacheAction::Ls => {
+                    let entries = cache.entries();
+                    match json_output.as_deref() {
+                        Some(p) => emit_serialized(&entries, Some(p))?,
+                        None => print_cache(&entries),
+                    }
+                }
+                CacheAction::Gc { older_than, all } => {
+                    let older_than = if all {
+                        Duration::ZERO
+                    } else {
+                        Duration::from_secs(older_than * 24 * 60 * 60)
+                    };
+                    let report = cache.gc(older_than)?;
+                    output::success(&format!(
+                        "Removed {} cached download(s) and {} file(s), {} bytes freed",
+                        report.entries, report.blobs, report.bytes
+                    ));
+                }
+                CacheAction::Verify => {
+                    let removed = cache.verify()?;
+                    for entry in &removed {
+                        output::warning(&format!("corrupt: {} ({})", entry.url, entry.sha256));
+                    }
+                    if !removed.is_empty() {
+                        anyhow::bail!(
+                            "{} cached download(s) were corrupt and have been removed",
+                            removed.len()
+                        );
+                    }
+                    output::success(&format!("{} cached download(s) OK", cache.entries().len()));
+                }
+            }
         }
 
         Commands::Hash { file } => {
@@ -173,7 +657,11 @@ fn emit_json(ctx: &rhai::Map, path: Option<&Path>) -> Result<()> {
     let dynamic = rhai::Dynamic::from(ctx.clone());
     let json: serde_json::Value = rhai::serde::from_dynamic(&dynamic)
         .map_err(|e| anyhow::anyhow!("Failed to serialize ctx: {}", e))?;
-    let json_str = serde_json::to_string(&json)?;
+    emit_serialized(&json, path)
+}
+
+fn emit_serialized<T: serde::Serialize>(value: &T, path: Option<&Path>) -> Result<()> {
+    let json_str = serde_json::to_string(value)?;
     match path {
         Some(p) => {
             std::fs::write(p, &json_str)
@@ -186,12 +674,33 @@ fn emit_json(ctx: &rhai::Map, path: Option<&Path>) -> Result<()> {
     Ok(())
 }
 
+/// Print a dependency listing: `names` one per line (text), or `graph` as DOT
+/// or JSON.
+fn emit_graph(
+    graph: &DepGraph,
+    names: &[String],
+    format: GraphFormat,
+    json_output: Option<&Path>,
+) -> Result<()> {
+    match format {
+        GraphFormat::Text => {
+            for name in names {
+                println!("{name}");
+            }
+        }
+        GraphFormat::Dot => print!("{}", graph.to_dot()),
+        GraphFormat::Json => emit_serialized(graph.nodes(), json_output)?,
+    }
+    Ok(())
+}
+
 fn create_engine(
     build_dir: Option<&Path>,
     recipes_path: Option<&Path>,
     defines: &[String],
     no_persist_ctx: bool,
     llm_profile: Option<String>,
+    options: &EngineOptions,
     autofix: Option<AutoFixConfig>,
 ) -> Result<RecipeEngine> {
     let build_dir = match build_dir {
@@ -209,6 +718,14 @@ fn create_engine(
     let mut engine = RecipeEngine::new(build_dir)
         .with_ctx_persistence(!no_persist_ctx)
         .with_llm_profile(llm_profile)
+        .with_sysroot(options.sysroot.clone())
+        .with_prefix(options.prefix.clone())
+        .with_ctx_store(options.ctx_store.clone())
+        .with_history_dir(Some(history::xdg_dir()))
+        .with_repos(options.repos.clone())
+        .with_source_cache(options.source_cache.clone())
+        .with_mirrors(options.mirrors.clone())
+        .with_offline(options.offline)
         .with_autofix(autofix);
 
     if let Some(rp) = recipes_path {
@@ -226,7 +743,55 @@ fn create_engine(
     Ok(engine)
 }
 
-fn resolve_recipe_path(recipe: &Path, recipes_path: &Path) -> Result<PathBuf> {
+/// Install target, ctx store, repositories, download cache, mirrors and
+/// offline mode shared by every engine a command creates.
+struct EngineOptions {
+    sysroot: Option<PathBuf>,
+    prefix: String,
+    ctx_store: CtxStore,
+    repos: RepoSet,
+    source_cache: Option<SourceCache>,
+    mirrors: MirrorTable,
+    offline: bool,
+}
+
+impl EngineOptions {
+    fn new(
+        sysroot: Option<PathBuf>,
+        prefix: String,
+        ctx_store: CtxStoreKind,
+        no_cache: bool,
+        offline: bool,
+    ) -> Result<Self> {
+        let sysroot = match sysroot {
+            Some(dir) => {
+                if !dir.is_dir() {
+                    anyhow::bail!("Sysroot is not a directory: {}", dir.display());
+                }
+                Some(
+                    dir.canonicalize()
+                        .with_context(|| format!("Failed to resolve sysroot: {}", dir.display()))?,
+                )
+            }
+            None => None,
+        };
+        let ctx_store = match ctx_store {
+            CtxStoreKind::Source => CtxStore::Source,
+            CtxStoreKind::State => CtxStore::xdg(),
+        };
+        Ok(Self {
+            sysroot,
+            prefix,
+            ctx_store,
+            repos: RepoSet::xdg()?,
+            source_cache: (!no_cache).then(SourceCache::xdg),
+            mirrors: MirrorTable::xdg()?,
+            offline,
+        })
+    }
+}
+
+fn resolve_recipe_path(recipe: &Path, recipes_path: &Path, repos: &RepoSet) -> Result<PathBuf> {
     if recipe.is_absolute() {
         if recipe.exists() {
             return Ok(recipe.to_path_buf());
@@ -248,85 +813,258 @@ fn resolve_recipe_path(recipe: &Path, recipes_path: &Path) -> Result<PathBuf> {
         return Ok(with_ext);
     }
 
+    if let Some(found) = repos.find(Some(recipes_path), &recipe.to_string_lossy()) {
+        return Ok(found);
+    }
+
+    let searched: Vec<String> = repos
+        .search_order(Some(recipes_path))
+        .iter()
+        .map(|repo| repo.path.display().to_string())
+        .collect();
     anyhow::bail!(
         "Recipe not found: {}\nSearched in: {}",
         recipe.display(),
-        recipes_path.display()
+        searched.join(", ")
     )
 }
 
-fn list_recipes(recipes_path: &Path) -> Result<()> {
+fn list_recipes(
+    engine: &RecipeEngine,
+    recipes_path: &Path,
+    json_output: Option<&Path>,
+) -> Result<()> {
     use owo_colors::OwoColorize;
 
-    if !recipes_path.exists() {
-        output::info(&format!("No recipes found in {}", recipes_path.display()));
-        return Ok(());
+    let mut infos = Vec::new();
+    for path in engine.recipe_files() {
+        match engine.info(&path) {
+            Ok(info) => infos.push(info),
+            Err(e) => output::warning(&format!("{}: {e:#}", path.display())),
+        }
     }
 
-    let mut found = false;
-    for path in enumerate_recipes(recipes_path) {
-        let meta = RecipeMetadata::load(&path);
+    if let Some(p) = json_output {
+        return emit_serialized(&infos, Some(p));
+    }
+    if infos.is_empty() {
+        output::info(&format!("No recipes found in {}", recipes_path.display()));
+    }
+    for info in &infos {
         println!(
             "  {} {}",
-            meta.name.bold(),
-            meta.version.as_deref().unwrap_or("?").cyan()
+            info.name.bold(),
+            info.version.as_deref().unwrap_or("?").cyan()
         );
-        found = true;
     }
 
-    if !found {
-        output::info(&format!("No recipes found in {}", recipes_path.display()));
+    Ok(())
+}
+
+fn print_updates(updates: &[UpdateInfo]) {
+    use owo_colors::OwoColorize;
+
+    if updates.is_empty() {
+        output::info("All recipes are up to date");
+        return;
     }
 
-    Ok(())
+    let width = updates
+        .iter()
+        .map(|u| u.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("NAME".len());
+    println!(
+        "{:<width$}  {:<16} {}",
+        "NAME".bold(),
+        "CURRENT".bold(),
+        "LATEST".bold()
+    );
+    for update in updates {
+        println!(
+            "{:<width$}  {:<16} {}",
+            update.name,
+            update.current.as_deref().unwrap_or("?"),
+            update.latest.as_deref().unwrap_or("?").green()
+        );
+    }
 }
 
-fn show_info(recipe_path: &Path) -> Result<()> {
+fn print_cache(entries: &[CacheEntry]) {
     use owo_colors::OwoColorize;
 
-    let meta = RecipeMetadata::load(recipe_path);
+    if entries.is_empty() {
+        output::info("Download cache is empty");
+        return;
+    }
 
-    println!("{:<12} {}", "Name:".bold(), meta.name.bold().cyan());
+    println!(
+        "{:<12}  {:>12}  {:<8}  {}",
+        "SHA256".bold(),
+        "SIZE".bold(),
+        "VERIFIED".bold(),
+        "URL".bold()
+    );
+    for entry in entries {
+        let verified = if entry.verified { "yes" } else { "no" };
+        println!(
+            "{:<12}  {:>12}  {:<8}  {}",
+            &entry.sha256[..12.min(entry.sha256.len())],
+            entry.size,
+            verified,
+            entry.url
+        );
+    }
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+    output::info(&format!(
+        "{} cached download(s), {} bytes",
+        entries.len(),
+        total
+    ));
+}
+
+fn print_history(entries: &[HistoryEntry]) {
+    use owo_colors::OwoColorize;
+
+    if entries.is_empty() {
+        output::info("No ctx history recorded");
+        return;
+    }
+
+    println!(
+        "{:>4}  {:<19}  {:<10} {}",
+        "SEQ".bold(),
+        "TIME (UTC)".bold(),
+        "PHASE".bold(),
+        "OUTCOME".bold()
+    );
+    for entry in entries {
+        println!(
+            "{:>4}  {:<19}  {:<10} {}",
+            entry.seq,
+            history::format_timestamp(entry.timestamp),
+            entry.phase,
+            entry.outcome
+        );
+        for (key, change) in &entry.diff {
+            let show = |v: &Option<serde_json::Value>| {
+                v.as_ref()
+                    .map_or_else(|| "(unset)".to_string(), |v| v.to_string())
+            };
+            println!(
+                "        {key}: {} -> {}",
+                show(&change.before).dimmed(),
+                show(&change.after)
+            );
+        }
+    }
+}
+
+fn print_lint(reports: &[LintReport]) {
+    use owo_colors::OwoColorize;
+
+    for issue in reports.iter().flat_map(|r| &r.issues) {
+        let location = match issue.line {
+            Some(line) => format!("{}:{line}", issue.file.display()),
+            None => issue.file.display().to_string(),
+        };
+        let severity = match issue.severity {
+            Severity::Error => format!("error[{}]", issue.code).red().bold().to_string(),
+            Severity::Warning => format!("warning[{}]", issue.code).yellow().to_string(),
+        };
+        println!("{location}: {severity}: {}", issue.message);
+    }
+    let errors: usize = reports.iter().map(LintReport::errors).sum();
+    let warnings: usize = reports.iter().map(LintReport::warnings).sum();
+    let summary = format!(
+        "{} recipe(s) checked: {errors} error(s), {warnings} warning(s)",
+        reports.len()
+    );
+    if errors > 0 {
+        output::error(&summary);
+    } else {
+        output::success(&summary);
+    }
+}
+
+fn print_search(hits: &[SearchHit]) {
+    use owo_colors::OwoColorize;
+
+    if hits.is_empty() {
+        output::info("No recipes match");
+        return;
+    }
+    for hit in hits {
+        let installed = if hit.installed { " [installed]" } else { "" };
+        println!(
+            "  {} {}{}",
+            hit.info.name.bold(),
+            hit.info.version.as_deref().unwrap_or("?").cyan(),
+            installed.green()
+        );
+        if let Some(description) = &hit.info.description {
+            println!("      {description}");
+        }
+        for file in &hit.files {
+            println!("      {}", file.display().to_string().dimmed());
+        }
+    }
+}
+
+fn show_info(info: &RecipeInfo) {
+    use owo_colors::OwoColorize;
+
+    println!("{:<12} {}", "Name:".bold(), info.name.bold().cyan());
     println!(
         "{:<12} {}",
         "Version:".bold(),
-        meta.version.as_deref().unwrap_or("?").green()
+        info.version.as_deref().unwrap_or("?").green()
     );
-    if let Some(desc) = &meta.description {
-        println!("{:<12} {}", "Description:".bold(), desc);
+    for (label, value) in [
+        ("Description:", &info.description),
+        ("License:", &info.license),
+        ("Homepage:", &info.homepage),
+    ] {
+        if let Some(value) = value {
+            println!("{:<12} {}", label.bold(), value);
+        }
+    }
+    for (label, values) in [
+        ("Deps:", &info.deps),
+        ("Build deps:", &info.build_deps),
+        ("Hooks:", &info.hooks),
+    ] {
+        if !values.is_empty() {
+            println!("{:<12} {}", label.bold(), values.join(", "));
+        }
+    }
+    for base in &info.bases {
+        println!(
+            "{:<12} {}",
+            "Extends:".bold(),
+            base.display().to_string().dimmed()
+        );
     }
     println!(
         "{:<12} {}",
         "Recipe:".bold(),
-        recipe_path.display().to_string().dimmed()
+        info.recipe_path.display().to_string().dimmed()
     );
-
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::metadata::extract_string_value;
     use std::path::Path;
     use tempfile::TempDir;
 
-    #[test]
-    fn test_extract_string_value() {
-        assert_eq!(extract_string_value("name: \"test\","), "test");
-        assert_eq!(extract_string_value("version: \"1.0\","), "1.0");
-        assert_eq!(
-            extract_string_value("  description: \"A test package\","),
-            "A test package"
-        );
-    }
-
     #[test]
     fn test_resolve_recipe_path_absolute() {
         let (_dir, recipes_path) = create_test_recipes_dir();
         let recipe_path = write_recipe(&recipes_path, "test", "");
 
-        let result = resolve_recipe_path(&recipe_path, &recipes_path);
+        let result = resolve_recipe_path(&recipe_path, &recipes_path, &RepoSet::default());
         assert!(result.is_ok());
     }
 
@@ -335,7 +1073,7 @@ mod tests {
         let (_dir, recipes_path) = create_test_recipes_dir();
         write_recipe(&recipes_path, "test", "");
 
-        let result = resolve_recipe_path(Path::new("test"), &recipes_path);
+        let result = resolve_recipe_path(Path::new("test"), &recipes_path, &RepoSet::default());
         assert!(result.is_ok());
         assert!(result.unwrap().ends_with("test.rhai"));
     }
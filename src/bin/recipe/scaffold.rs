@@ -0,0 +1,501 @@
+//! `recipe new`: recipe skeletons from built-in templates
+//!
+//! Every template defines ctx (`name`, `version`), the check and phase
+//! functions, and `cleanup(ctx, reason)`, and passes `recipe lint` and
+//! `recipe fmt --check` as generated. Download URLs are written with `${ctx.version}` in place of the
+//! version so `recipe upgrade` can move them forward.
+
+use anyhow::{Context, Result, anyhow, bail};
+use levitate_recipe::{helpers, output};
+use std::path::Path;
+
+use super::cli::RecipeTemplate;
+
+/// Version used when none is given and none can be read from the URL.
+const PLACEHOLDER_VERSION: &str = "0.0.0";
+
+/// Archive suffixes stripped before looking for a version in a file name.
+const ARCHIVE_SUFFIXES: [&str; 10] = [
+    ".tar.gz", ".tar.xz", ".tar.bz2", ".tar.zst", ".tgz", ".txz", ".tbz2", ".tar", ".zip", ".rpm",
+];
+
+const TARBALL: &str = r#"// @NAME@: built from a source tarball.
+//
+// Generated by `recipe new --template tarball`. Adjust build() and install()
+// to the package's build system. `recipe hash <file>` prints the sha256.
+
+let ctx = #{
+    archive: "",
+    name: "@NAME@",
+    sha256: "@SHA256@",
+    src_dir: "",
+    version: "@VERSION@",
+};
+
+fn source_url(ctx) {
+    `@URL@`
+}
+
+fn is_installed(ctx) {
+    if !is_file(`${SYSROOT}${PREFIX}/bin/${ctx.name}`) { throw "not installed"; }
+    ctx
+}
+
+fn is_built(ctx) {
+    if ctx.src_dir == "" || !is_file(`${ctx.src_dir}/${ctx.name}`) { throw "not built"; }
+    ctx
+}
+
+fn is_acquired(ctx) {
+    if ctx.archive == "" || !is_file(ctx.archive) { throw "not acquired"; }
+    ctx
+}
+
+fn acquire(ctx) {
+    let url = source_url(ctx);
+    let archive = download(url, `${BUILD_DIR}/${basename(url)}`);
+    verify_sha256(archive, ctx.sha256);
+    ctx.archive = archive;
+    ctx
+}
+
+fn build(ctx) {
+    extract(ctx.archive, BUILD_DIR);
+    let src_dir = `${BUILD_DIR}/${ctx.name}-${ctx.version}`;
+    shell_in(src_dir, `./configure --prefix=${PREFIX}`);
+    shell_in(src_dir, `make -j${NPROC}`);
+    ctx.src_dir = src_dir;
+    ctx
+}
+
+fn install(ctx) {
+    shell_in(ctx.src_dir, `make install DESTDIR=${STAGE_DIR}`);
+    ctx
+}
+
+fn cleanup(ctx, reason) {
+    ctx
+}
+"#;
+
+const GIT: &str = r#"// @NAME@: built from a git checkout.
+//
+// Generated by `recipe new --template git`. Adjust git_ref() to upstream's
+// tag scheme and build()/install() to its build system.
+
+let ctx = #{
+    name: "@NAME@",
+    repo_url: "@URL@",
+    src_dir: "",
+    version: "@VERSION@",
+};
+
+fn git_ref(ctx) {
+    `v${ctx.version}`
+}
+
+fn is_installed(ctx) {
+    if !is_file(`${SYSROOT}${PREFIX}/bin/${ctx.name}`) { throw "not installed"; }
+    ctx
+}
+
+fn is_built(ctx) {
+    if ctx.src_dir == "" || !is_file(`${ctx.src_dir}/${ctx.name}`) { throw "not built"; }
+    ctx
+}
+
+fn is_acquired(ctx) {
+    if ctx.src_dir == "" || !is_dir(`${ctx.src_dir}/.git`) { throw "not acquired"; }
+    ctx
+}
+
+fn acquire(ctx) {
+    let src_dir = git_clone(ctx.repo_url, BUILD_DIR);
+    shell_in(src_dir, `git checkout --quiet ${git_ref(ctx)}`);
+    ctx.src_dir = src_dir;
+    ctx
+}
+
+fn build(ctx) {
+    shell_in(ctx.src_dir, `make -j${NPROC} PREFIX=${PREFIX}`);
+    ctx
+}
+
+fn install(ctx) {
+    shell_in(ctx.src_dir, `make install PREFIX=${PREFIX} DESTDIR=${STAGE_DIR}`);
+    ctx
+}
+
+fn cleanup(ctx, reason) {
+    ctx
+}
+"#;
+
+const GITHUB_RELEASE: &str = r#"// @NAME@: prebuilt binary from a GitHub release.
+//
+// Generated by `recipe new --template github-release`. A new release usually
+// ships a new checksum: return `#{ version: ..., sha256: ... }` from
+// check_update() or update ctx.sha256 before `recipe upgrade`.
+
+let ctx = #{
+    archive: "",
+    bin_path: "",
+    name: "@NAME@",
+    repo: "@REPO@",
+    sha256: "@SHA256@",
+    version: "@VERSION@",
+};
+
+fn release_url(ctx) {
+    `@URL@`
+}
+
+fn is_installed(ctx) {
+    if !is_file(`${SYSROOT}${PREFIX}/bin/${ctx.name}`) { throw "not installed"; }
+    ctx
+}
+
+fn is_built(ctx) {
+    if ctx.bin_path == "" || !is_file(ctx.bin_path) { throw "not built"; }
+    ctx
+}
+
+fn is_acquired(ctx) {
+    if ctx.archive == "" || !is_file(ctx.archive) { throw "not acquired"; }
+    ctx
+}
+
+fn acquire(ctx) {
+    let url = release_url(ctx);
+    let archive = download(url, `${BUILD_DIR}/${basename(url)}`);
+    verify_sha256(archive, ctx.sha256);
+    ctx.archive = archive;
+    ctx
+}
+
+fn build(ctx) {
+    let dir = `${BUILD_DIR}/release`;
+    mkdir(dir);
+    extract(ctx.archive, dir);
+    let found = glob_list(`${dir}/**/${ctx.name}`);
+    if found.len() == 0 { throw `no ${ctx.name} binary in ${ctx.archive}`; }
+    ctx.bin_path = found[0];
+    ctx
+}
+
+fn install(ctx) {
+    let bin_dir = `${STAGE_DIR}${PREFIX}/bin`;
+    mkdir(bin_dir);
+    copy_file(ctx.bin_path, `${bin_dir}/${ctx.name}`);
+    chmod(`${bin_dir}/${ctx.name}`, 0o755);
+    ctx
+}
+
+fn check_update(ctx) {
+    let tag = github_latest_release(ctx.repo);
+    if tag.starts_with("v") { tag.sub_string(1) } else { tag }
+}
+
+fn cleanup(ctx, reason) {
+    ctx
+}
+"#;
+
+const RPM_SHIM: &str = r#"// @NAME@: shim over the distribution's RPM package.
+//
+// Generated by `recipe new --template rpm-shim`. dnf owns the installed
+// files, so the install manifest stays empty and remove() goes through dnf.
+
+let ctx = #{
+    name: "@NAME@",
+    rpm: "@NAME@",
+    version: "@VERSION@",
+};
+
+fn is_installed(ctx) {
+    if !rpm_installed(ctx.rpm) { throw "not installed"; }
+    ctx.version = rpm_version(ctx.rpm);
+    ctx
+}
+
+fn is_built(ctx) {
+    ctx
+}
+
+fn is_acquired(ctx) {
+    if !dnf_package_available(ctx.rpm) { throw "not available"; }
+    ctx
+}
+
+fn acquire(ctx) {
+    if !dnf_package_available(ctx.rpm) {
+        throw `${ctx.rpm} is not available from the configured repositories`;
+    }
+    ctx
+}
+
+fn build(ctx) {
+    ctx
+}
+
+fn install(ctx) {
+    dnf_install([ctx.rpm]);
+    ctx.version = rpm_version(ctx.rpm);
+    ctx
+}
+
+fn remove(ctx) {
+    shell(`dnf remove -y ${ctx.rpm}`);
+    ctx
+}
+
+fn cleanup(ctx, reason) {
+    ctx
+}
+"#;
+
+/// Values substituted into a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Scaffold {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    /// Download URL (tarball, github-release) or repository URL (git), with
+    /// the version already replaced by `${ctx.version}`.
+    pub(crate) url: String,
+    /// `owner/repo` for the github-release template.
+    pub(crate) repo: String,
+    pub(crate) sha256: String,
+}
+
+impl Scaffold {
+    /// Scaffold values for `name`, taking the version from `version`, else from
+    /// `url`, else a placeholder. Without a URL the template's example URL is
+    /// used. Nothing is downloaded; see [`prefill_sha256`].
+    pub(crate) fn new(
+        template: RecipeTemplate,
+        name: &str,
+        version: Option<&str>,
+        url: Option<&str>,
+    ) -> Result<Self> {
+        check_literal("name", name)?;
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '+' | '-'))
+        {
+            bail!("Invalid recipe name `{name}`: use letters, digits, `.`, `_`, `+` and `-`");
+        }
+        if let Some(url) = url {
+            check_literal("URL", url)?;
+        }
+        if let Some(version) = version {
+            check_literal("version", version)?;
+        }
+        if url.is_some() && template == RecipeTemplate::RpmShim {
+            bail!("--url is not used by the rpm-shim template");
+        }
+
+        let version = version
+            .map(str::to_string)
+            .or_else(|| url.and_then(|u| guess_version(name, u)))
+            .unwrap_or_else(|| PLACEHOLDER_VERSION.to_string());
+        let repo = url
+            .and_then(github_repo)
+            .unwrap_or_else(|| format!("OWNER/{name}"));
+        let url = match (template, url) {
+            (RecipeTemplate::Git, Some(url)) => url.to_string(),
+            (RecipeTemplate::Git, None) => format!("https://github.com/OWNER/{name}.git"),
+            (RecipeTemplate::GithubRelease, Some(url)) => {
+                let url = url_template(url, &version);
+                match url.strip_prefix(&format!("https://github.com/{repo}/")) {
+                    Some(rest) => format!("https://github.com/${{ctx.repo}}/{rest}"),
+                    None => url,
+                }
+            }
+            (RecipeTemplate::GithubRelease, None) => {
+                "https://github.com/${ctx.repo}/releases/download/v${ctx.version}/${ctx.name}-${ctx.version}-x86_64-linux.tar.gz".to_string()
+            }
+            (_, Some(url)) => url_template(url, &version),
+            (_, None) => {
+                "https://example.com/${ctx.name}/${ctx.name}-${ctx.version}.tar.gz".to_string()
+            }
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            version,
+            url,
+            repo,
+            sha256: String::new(),
+        })
+    }
+
+    /// Recipe source for `template`.
+    pub(crate) fn render(&self, template: RecipeTemplate) -> String {
+        let source = match template {
+            RecipeTemplate::Tarball => TARBALL,
+            RecipeTemplate::Git => GIT,
+            RecipeTemplate::GithubRelease => GITHUB_RELEASE,
+            RecipeTemplate::RpmShim => RPM_SHIM,
+        };
+        source
+            .replace("@NAME@", &self.name)
+            .replace("@VERSION@", &self.version)
+            .replace("@URL@", &self.url)
+            .replace("@REPO@", &self.repo)
+            .replace("@SHA256@", &self.sha256)
+    }
+}
+
+/// Download `url` into a temporary directory and return its sha256, as
+/// `recipe hash` would print it.
+pub(crate) fn prefill_sha256(url: &str) -> Result<String> {
+    let dir = tempfile::tempdir().context("Failed to create a temporary directory")?;
+    let file_name = url
+        .rsplit('/')
+        .next()
+        .filter(|s| !s.is_empty())
+        .unwrap_or("download");
+    let dest = dir.path().join(file_name);
+    output::info(&format!("Downloading {url} to compute its sha256..."));
+    let path = helpers::acquire::download(url, &dest.to_string_lossy())
+        .map_err(|e| anyhow!("Failed to download {url}: {e}"))?;
+    let hashes = helpers::acquire::compute_hashes(Path::new(&path))
+        .with_context(|| format!("Failed to compute hashes for {path}"))?;
+    Ok(hashes.sha256)
+}
+
+/// Values end up inside Rhai string literals.
+fn check_literal(what: &str, value: &str) -> Result<()> {
+    if value.contains(['"', '`', '\\', '\n']) {
+        bail!("Invalid {what} `{value}`: quotes, backticks and backslashes are not allowed");
+    }
+    Ok(())
+}
+
+/// Version in the file name of `url` (`foo-1.2.3.tar.gz`,
+/// `foo-1.2.3-x86_64.rpm`), else in a GitHub release tag
+/// (`/releases/download/v1.2.3/`). A leading `v` is dropped.
+pub(crate) fn guess_version(name: &str, url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let stem = ARCHIVE_SUFFIXES
+        .iter()
+        .find_map(|suffix| file.strip_suffix(suffix))
+        .unwrap_or(file);
+    let stem = stem.strip_prefix(name).unwrap_or(stem);
+    find_version(stem).or_else(|| {
+        let tag = path
+            .split("/releases/download/")
+            .nth(1)?
+            .split('/')
+            .next()?;
+        find_version(tag)
+    })
+}
+
+/// First `N.N[.N...]` run that starts a `-`/`_`/`v`-separated token.
+fn find_version(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    (0..bytes.len()).find_map(|start| {
+        let at_boundary = start == 0 || matches!(bytes[start - 1], b'-' | b'_' | b'v' | b'V');
+        if !at_boundary || !bytes[start].is_ascii_digit() {
+            return None;
+        }
+        let len = text[start..]
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(text.len() - start);
+        let version = text[start..start + len].trim_end_matches('.');
+        version.contains('.').then(|| version.to_string())
+    })
+}
+
+/// `url` with every occurrence of `version` replaced by `${ctx.version}`.
+pub(crate) fn url_template(url: &str, version: &str) -> String {
+    if version.is_empty() || version == PLACEHOLDER_VERSION {
+        return url.to_string();
+    }
+    url.replace(version, "${ctx.version}")
+}
+
+/// `owner/repo` of a `https://github.com/owner/repo/...` URL.
+fn github_repo(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("https://github.com/")?;
+    let mut parts = rest.split('/');
+    let owner = parts.next().filter(|s| !s.is_empty())?;
+    let repo = parts.next().filter(|s| !s.is_empty())?;
+    Some(format!("{owner}/{}", repo.trim_end_matches(".git")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess_version_from_file_name_and_tag() {
+        assert_eq!(
+            guess_version("jq", "https://example.com/jq/jq-1.7.1.tar.gz").as_deref(),
+            Some("1.7.1")
+        );
+        assert_eq!(
+            guess_version(
+                "ripgrep",
+                "https://github.com/BurntSushi/ripgrep/releases/download/14.1.0/ripgrep-14.1.0-x86_64-unknown-linux-musl.tar.gz"
+            )
+            .as_deref(),
+            Some("14.1.0")
+        );
+        assert_eq!(
+            guess_version(
+                "fd",
+                "https://github.com/sharkdp/fd/releases/download/v10.2.0/fd-musl-amd64.tar.gz"
+            )
+            .as_deref(),
+            Some("10.2.0")
+        );
+        assert_eq!(
+            guess_version("p7zip", "https://example.com/p7zip-16.02-1.el9.x86_64.rpm").as_deref(),
+            Some("16.02")
+        );
+        assert_eq!(guess_version("foo", "https://example.com/foo.tar.gz"), None);
+    }
+
+    #[test]
+    fn test_github_release_url_uses_ctx_version_and_repo() {
+        let scaffold = Scaffold::new(
+            RecipeTemplate::GithubRelease,
+            "ripgrep",
+            None,
+            Some(
+                "https://github.com/BurntSushi/ripgrep/releases/download/14.1.0/ripgrep-14.1.0-x86_64-unknown-linux-musl.tar.gz",
+            ),
+        )
+        .unwrap();
+        assert_eq!(scaffold.version, "14.1.0");
+        assert_eq!(scaffold.repo, "BurntSushi/ripgrep");
+        assert_eq!(
+            scaffold.url,
+            "https://github.com/${ctx.repo}/releases/download/${ctx.version}/ripgrep-${ctx.version}-x86_64-unknown-linux-musl.tar.gz"
+        );
+
+        let source = scaffold.render(RecipeTemplate::GithubRelease);
+        assert!(source.contains("repo: \"BurntSushi/ripgrep\","));
+        assert!(source.contains("version: \"14.1.0\","));
+        assert!(!source.contains('@'));
+    }
+
+    #[test]
+    fn test_scaffold_rejects_unsafe_values() {
+        assert!(Scaffold::new(RecipeTemplate::Tarball, "a/b", None, None).is_err());
+        assert!(Scaffold::new(RecipeTemplate::Tarball, "foo", Some("1\"0"), None).is_err());
+        assert!(
+            Scaffold::new(
+                RecipeTemplate::RpmShim,
+                "foo",
+                None,
+                Some("https://example.com/foo.rpm")
+            )
+            .is_err()
+        );
+    }
+}
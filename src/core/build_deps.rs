@@ -1,14 +1,25 @@
 //! Build dependency resolution for recipes
 //!
 //! When a recipe declares `let build_deps = ["linux-deps"];`, the executor
-//! resolves each dep by finding `{name}.rhai` in the recipes search path,
-//! executing it to install tools into `BUILD_DIR/.tools/`, then prepending
-//! `.tools/` bin dirs to PATH before the build phase.
-
-use super::output;
-use super::runner;
+//! resolves the transitive dependency graph of those names (see
+//! [`super::deps::DepGraph`]), executes each `{name}.rhai` in install order to
+//! install tools into `BUILD_DIR/.tools/`, then prepends `.tools/` bin dirs to
+//! PATH before the build phase.
+
+use super::deps::DepGraph;
+use super::executor::{
+    CompiledRecipe, StagedInstall, compile_recipe, install_scope, other_recipe_claims, persist_ctx,
+    staged_install,
+};
+use super::lock::acquire_recipe_lock;
+use super::offline;
+use super::orphans::{self, InstallReason};
+use super::plan::PlannedDependency;
+use super::{ctx_store, manifest, output, runner, stage};
+use crate::helpers::internal::tracking::Claims;
 use anyhow::{Context, Result, anyhow};
 use rhai::{Engine, Scope};
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -28,8 +39,10 @@ pub struct BuildDepsResolver<'a> {
     build_dir: &'a Path,
     recipes_path: Option<&'a Path>,
     defines: &'a [(String, String)],
-    execution_stack: Vec<String>,
     autofix: Option<crate::AutoFixConfig>,
+    persist_ctx: bool,
+    sysroot: Option<&'a Path>,
+    allow_conflicts: bool,
 }
 
 impl<'a> BuildDepsResolver<'a> {
@@ -45,35 +58,76 @@ impl<'a> BuildDepsResolver<'a> {
             build_dir,
             recipes_path,
             defines,
-            execution_stack: Vec::new(),
             autofix: autofix.cloned(),
+            persist_ctx: true,
+            sysroot: None,
+            allow_conflicts: false,
         }
     }
 
-    /// Resolve and install all build deps, returning the `.tools/` prefix path.
-    pub fn resolve_and_install(&mut self, deps: &[String]) -> Result<PathBuf> {
+    /// Whether installed dependencies get their ctx (install reason and
+    /// installed-file manifest) persisted. Default is `true`.
+    pub fn with_persist_ctx(mut self, persist_ctx: bool) -> Self {
+        self.persist_ctx = persist_ctx;
+        self
+    }
+
+    /// Install dependencies into `sysroot` instead of `/`, confined the same
+    /// way as the main install.
+    pub fn with_sysroot(mut self, sysroot: Option<&'a Path>) -> Self {
+        self.sysroot = sysroot;
+        self
+    }
+
+    /// Let dependency installs overwrite files owned by other recipes. Default
+    /// is `false`, as for the main install.
+    pub fn with_allow_conflicts(mut self, allow: bool) -> Self {
+        self.allow_conflicts = allow;
+        self
+    }
+
+    /// Resolve `deps` of recipe `root` transitively and install them in
+    /// dependency order, returning the `.tools/` prefix path.
+    pub fn resolve_and_install(&self, root: &str, deps: &[String]) -> Result<PathBuf> {
         let tools_prefix = self.build_dir.join(".tools");
 
-        for dep in deps {
-            if self.execution_stack.contains(dep) {
-                anyhow::bail!(
-                    "Circular build dependency detected: {} -> {}",
-                    self.execution_stack.join(" -> "),
-                    dep
-                );
-            }
-            self.execution_stack.push(dep.clone());
-            self.install_dep(dep, &tools_prefix)?;
-            self.execution_stack.pop();
+        let order = self.install_order(root, deps)?;
+        if order.len() > deps.len() {
+            let names: Vec<&str> = order.iter().map(|(name, _)| name.as_str()).collect();
+            output::detail(&format!("Dependency install order: {}", names.join(" → ")));
+        }
+        for (dep, recipe_path) in &order {
+            self.install_dep(dep, &tools_prefix, recipe_path)?;
         }
 
         Ok(tools_prefix)
     }
 
-    /// Find and execute a single dep recipe.
-    fn install_dep(&self, name: &str, tools_prefix: &Path) -> Result<()> {
-        let recipe_path = self.find_recipe(name)?;
+    /// Transitive closure of `deps` in install order, with recipe paths.
+    fn install_order(&self, root: &str, deps: &[String]) -> Result<Vec<(String, PathBuf)>> {
+        let graph = DepGraph::reachable(
+            self.engine,
+            self.recipes_path,
+            self.build_dir,
+            self.defines,
+            deps,
+        );
+        let order = graph.install_order(root, deps)?;
+        Ok(order
+            .into_iter()
+            .map(|name| {
+                let recipe_path = graph
+                    .get(&name)
+                    .expect("ordered nodes exist")
+                    .recipe_path
+                    .clone();
+                (name, recipe_path)
+            })
+            .collect())
+    }
 
+    /// Execute a single dep recipe.
+    fn install_dep(&self, name: &str, tools_prefix: &Path, recipe_path: &Path) -> Result<()> {
         let max_attempts = self
             .autofix
             .as_ref()
@@ -81,7 +135,10 @@ impl<'a> BuildDepsResolver<'a> {
             .unwrap_or(0);
 
         for attempt in 0..=max_attempts {
-            match self.install_dep_once(name, tools_prefix, &recipe_path) {
+            let result = offline::running(recipe_path, || {
+                self.install_dep_once(name, tools_prefix, recipe_path)
+            });
+            match result {
                 Ok(()) => return Ok(()),
                 Err(DepAttemptError::Fatal(e)) => return Err(e),
                 Err(DepAttemptError::Phase {
@@ -105,7 +162,7 @@ impl<'a> BuildDepsResolver<'a> {
                     let failure = format!("{error:#}");
                     super::autofix::run_and_apply(
                         cfg,
-                        &recipe_path,
+                        recipe_path,
                         self.recipes_path,
                         self.defines,
                         reason,
@@ -127,45 +184,15 @@ impl<'a> BuildDepsResolver<'a> {
         tools_prefix: &Path,
         recipe_path: &Path,
     ) -> std::result::Result<(), DepAttemptError> {
-        let compiled = super::executor::compile_recipe(self.engine, recipe_path, self.recipes_path)
-            .map_err(DepAttemptError::Fatal)?;
-        let ast = compiled.ast;
-        let base_dir = compiled.base_dir;
-
-        let recipe_dir = recipe_path
-            .parent()
-            .map(|p| p.to_string_lossy().to_string())
-            .unwrap_or_else(|| ".".to_string());
-
         let dep_build_dir = self.build_dir.join(format!(".deps/{}", name));
         fs::create_dir_all(&dep_build_dir)
             .with_context(|| format!("Failed to create dep build dir for {}", name))
             .map_err(DepAttemptError::Fatal)?;
 
-        let mut scope = Scope::new();
-        scope.push_constant("RECIPE_DIR", recipe_dir);
-        if let Some(ref bd) = base_dir {
-            scope.push_constant("BASE_RECIPE_DIR", bd.to_string_lossy().to_string());
-        }
-        scope.push_constant("BUILD_DIR", dep_build_dir.to_string_lossy().to_string());
-        scope.push_constant("TOOLS_PREFIX", tools_prefix.to_string_lossy().to_string());
-        scope.push_constant("ARCH", std::env::consts::ARCH);
-        scope.push_constant("NPROC", num_cpus::get() as i64);
-
-        for (key, value) in self.defines {
-            scope.push_constant(key.as_str(), value.clone());
-        }
-
-        // Run top-level to populate ctx
-        self.engine
-            .run_ast_with_scope(&mut scope, &ast)
-            .map_err(|e| {
-                DepAttemptError::Fatal(anyhow!("Failed to run dependency recipe {}: {}", name, e))
-            })?;
-
-        let ctx_map: rhai::Map = scope.get_value("ctx").ok_or_else(|| {
-            DepAttemptError::Fatal(anyhow!("Dependency recipe {} missing ctx", name))
-        })?;
+        let (compiled, mut scope, ctx_map) = self
+            .load_dep(name, tools_prefix, recipe_path)
+            .map_err(DepAttemptError::Fatal)?;
+        let ast = compiled.ast.clone();
 
         // Check if already installed
         let needs_install =
@@ -263,12 +290,45 @@ impl<'a> BuildDepsResolver<'a> {
                 "executing dependency install hook",
             );
             let ctx_before = ctx.clone();
-            match self
-                .engine
-                .call_fn::<rhai::Map>(&mut scope, &ast, "install", (ctx,))
-            {
-                Ok(new_ctx) => {
+            // Same staged path as the main install flow: install() writes
+            // into STAGE_DIR, which is committed only if it succeeds.
+            let stage_dir = stage::stage_dir(&dep_build_dir);
+            stage::prepare(&stage_dir).map_err(DepAttemptError::Fatal)?;
+            // Tools installed into TOOLS_PREFIX live in the build dir and are
+            // not recorded as installed files.
+            let scratch = [dep_build_dir.clone(), tools_prefix.to_path_buf()];
+            let tools_before = tool_entries(tools_prefix);
+            let root = self.sysroot.unwrap_or(Path::new("/"));
+            // Like the main install, a dependency may not overwrite files
+            // owned by other recipes.
+            let claims = if self.allow_conflicts {
+                Claims::default()
+            } else {
+                other_recipe_claims(&compiled, self.recipes_path, &stage_dir, root)
+            };
+            let install = StagedInstall {
+                name,
+                stage_dir: &stage_dir,
+                root,
+                scratch: &scratch,
+                sysroot: self.sysroot,
+                claims,
+            };
+            match staged_install(self.engine, &ast, &mut scope, ctx, install, Ok) {
+                Ok((new_ctx, installed)) => {
                     ctx = new_ctx;
+                    // A dependency that only put tools into the build dir is
+                    // gone with it; it is not recorded as installed.
+                    let build_dir_only =
+                        installed.is_empty() && tool_entries(tools_prefix) != tools_before;
+                    if !build_dir_only {
+                        manifest::store(&mut ctx, &installed);
+                        orphans::mark(&mut ctx, InstallReason::Dependency);
+                        if self.persist_ctx {
+                            persist_dep_ctx(self.engine, self.recipes_path, recipe_path, &ctx)
+                                .map_err(DepAttemptError::Fatal)?;
+                        }
+                    }
                     output::hook_event(
                         name,
                         "dependency.install",
@@ -281,7 +341,7 @@ impl<'a> BuildDepsResolver<'a> {
                             self.engine,
                             &ast,
                             &mut scope,
-                            ctx,
+                            ctx.clone(),
                             "auto.install.success",
                         );
                     }
@@ -303,7 +363,7 @@ impl<'a> BuildDepsResolver<'a> {
                     return Err(DepAttemptError::Phase {
                         reason: "auto.install.failure",
                         phase: "install",
-                        error: anyhow!("tool dependency {} install failed: {}", name, e),
+                        error: anyhow!("tool dependency {} {:#}", name, e),
                     });
                 }
             }
@@ -319,23 +379,92 @@ impl<'a> BuildDepsResolver<'a> {
         Ok(())
     }
 
-    /// Find a recipe file by name in the search path.
-    fn find_recipe(&self, name: &str) -> Result<PathBuf> {
-        let filename = format!("{}.rhai", name);
+    /// Compile a dep recipe and run its top-level with the dependency scope.
+    fn load_dep(
+        &self,
+        name: &str,
+        tools_prefix: &Path,
+        recipe_path: &Path,
+    ) -> Result<(CompiledRecipe, Scope<'static>, rhai::Map)> {
+        let compiled = compile_recipe(self.engine, recipe_path, self.recipes_path)?;
 
-        if let Some(sp) = self.recipes_path {
-            let candidate = sp.join(&filename);
-            if candidate.exists() {
-                return Ok(candidate);
-            }
-        }
+        let dep_build_dir = self.build_dir.join(format!(".deps/{}", name));
+        let mut scope = install_scope(&compiled, &dep_build_dir, self.defines);
+        scope.push_constant("TOOLS_PREFIX", tools_prefix.to_string_lossy().to_string());
 
-        anyhow::bail!(
-            "Dependency recipe '{}' not found (search_path: {:?})",
-            filename,
-            self.recipes_path
-        )
+        // Run top-level to populate ctx
+        self.engine
+            .run_ast_with_scope(&mut scope, &compiled.ast)
+            .map_err(|e| anyhow!("Failed to run dependency recipe {}: {}", name, e))?;
+
+        let ctx_map: rhai::Map = scope
+            .get_value("ctx")
+            .ok_or_else(|| anyhow!("Dependency recipe {} missing ctx", name))?;
+
+        Ok((compiled, scope, ctx_map))
+    }
+
+    /// Resolve `deps` of recipe `root` transitively and evaluate each one's
+    /// `is_installed(ctx)` without running any phase. Results are in install
+    /// order.
+    pub fn plan(&self, root: &str, deps: &[String]) -> Result<Vec<PlannedDependency>> {
+        let tools_prefix = self.build_dir.join(".tools");
+
+        self.install_order(root, deps)?
+            .into_iter()
+            .map(|(name, recipe_path)| {
+                let (compiled, scope, ctx_map) =
+                    self.load_dep(&name, &tools_prefix, &recipe_path)?;
+                let needs_install = runner::check_throws(
+                    self.engine,
+                    &compiled.ast,
+                    &scope,
+                    "is_installed",
+                    &ctx_map,
+                );
+                Ok(PlannedDependency {
+                    name,
+                    recipe_path,
+                    needs_install,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Every path under the tools prefix, to tell whether an install added tools.
+fn tool_entries(tools_prefix: &Path) -> HashSet<PathBuf> {
+    walkdir::WalkDir::new(tools_prefix)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// Persist a dependency's ctx after a successful install.
+///
+/// Dependency recipes otherwise run without ctx persistence; this records the
+/// install reason and installed-file manifest so `recipe orphans` and
+/// `recipe remove` can find them later. The recipe is compiled again under
+/// its lock and written through the active ctx store, like the main install.
+fn persist_dep_ctx(
+    engine: &Engine,
+    recipes_path: Option<&Path>,
+    recipe_path: &Path,
+    ctx_map: &rhai::Map,
+) -> Result<()> {
+    let _lock = acquire_recipe_lock(recipe_path)?;
+    let mut compiled = compile_recipe(engine, recipe_path, recipes_path)?;
+    if ctx_store::state_file(recipe_path).is_none() && compiled.ctx_source().is_none() {
+        return Ok(());
     }
+    persist_ctx(
+        &mut compiled,
+        ctx_map,
+        "install",
+        "dependency",
+        "Failed to persist dependency ctx",
+    )
 }
 
 fn cleanup_hook(
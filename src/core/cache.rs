@@ -0,0 +1,535 @@
+//! Shared download cache (`recipe cache`)
+//!
+//! Files fetched by `download`, `download_with_resume`, single-file `torrent`
+//! and `github_download_release` are stored once by content, as
+//! `<root>/sha256/<hash>`, and indexed by URL in `<root>/urls/<hash of
+//! url>.json`. A later fetch of the same URL copies the cached file to its
+//! destination instead of downloading it again, so fresh build directories do
+//! not refetch sources.
+//!
+//! When a recipe verifies a file that came through the cache
+//! (`verify_sha256`, `verify_sha512`, `verify_blake3`), its entry is marked
+//! verified. A failed verification drops the URL entry, so the next run
+//! downloads the file again.
+//!
+//! The cache is selected per [`crate::RecipeEngine`] (off by default), which
+//! makes it active on the current thread for the duration of each operation.
+
+use super::output;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Content-addressed download cache rooted at a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceCache {
+    root: PathBuf,
+}
+
+/// One cached URL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    /// SHA-256 of the content, which is also its file name in the cache.
+    pub sha256: String,
+    pub size: u64,
+    /// File name the content was first downloaded as.
+    pub name: String,
+    /// Whether a recipe has verified the content after fetching it.
+    #[serde(default)]
+    pub verified: bool,
+    /// Unix time of the download.
+    pub fetched_at: u64,
+    /// Unix time of the last download or cache hit.
+    pub last_used: u64,
+}
+
+/// What [`SourceCache::gc`] removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GcReport {
+    pub entries: usize,
+    pub blobs: usize,
+    pub bytes: u64,
+}
+
+impl SourceCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Cache at `$XDG_CACHE_HOME/recipe/sources` (default
+    /// `~/.cache/recipe/sources`).
+    pub fn xdg() -> Self {
+        let cache_home = std::env::var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| {
+                dirs::home_dir()
+                    .unwrap_or_else(|| PathBuf::from("."))
+                    .join(".cache")
+            });
+        Self::new(cache_home.join("recipe/sources"))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Cached URLs, sorted by URL.
+    pub fn entries(&self) -> Vec<CacheEntry> {
+        let mut entries: Vec<CacheEntry> = fs::read_dir(self.root.join("urls"))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+            .filter_map(|text| serde_json::from_str(&text).ok())
+            .collect();
+        entries.sort_by(|a, b| a.url.cmp(&b.url));
+        entries
+    }
+
+    /// The cached entry for `url`, if its content is present.
+    pub fn lookup(&self, url: &str) -> Option<CacheEntry> {
+        let text = fs::read_to_string(self.entry_file(url)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&text).ok()?;
+        self.blob(&entry.sha256).is_file().then_some(entry)
+    }
+
+    /// Remove entries not used for `older_than` (all of them for
+    /// [`Duration::ZERO`]), then content no entry refers to.
+    pub fn gc(&self, older_than: Duration) -> Result<GcReport> {
+        let mut report = GcReport::default();
+        let cutoff = now().saturating_sub(older_than.as_secs());
+        let mut kept = HashSet::new();
+        for entry in self.entries() {
+            if older_than.is_zero() || entry.last_used < cutoff {
+                self.forget(&entry.url)?;
+                report.entries += 1;
+            } else {
+                kept.insert(entry.sha256);
+            }
+        }
+        for (sha256, blob) in self.blobs() {
+            if !kept.contains(&sha256) {
+                report.bytes += blob.metadata().map(|m| m.len()).unwrap_or(0);
+                fs::remove_file(&blob)
+                    .with_context(|| format!("Failed to remove {}", blob.display()))?;
+                report.blobs += 1;
+            }
+        }
+        let _ = fs::remove_dir_all(self.root.join("tmp"));
+        Ok(report)
+    }
+
+    /// Rehash every cached file. Content that no longer matches its hash is
+    /// removed together with the entries referring to it, which are
+    /// returned.
+    pub fn verify(&self) -> Result<Vec<CacheEntry>> {
+        let mut corrupt = HashSet::new();
+        for (sha256, blob) in self.blobs() {
+            if sha256_file(&blob).ok().as_deref() != Some(sha256.as_str()) {
+                fs::remove_file(&blob)
+                    .with_context(|| format!("Failed to remove {}", blob.display()))?;
+                corrupt.insert(sha256);
+            }
+        }
+        let mut removed = Vec::new();
+        for entry in self.entries() {
+            if corrupt.contains(&entry.sha256) || !self.blob(&entry.sha256).is_file() {
+                self.forget(&entry.url)?;
+                removed.push(entry);
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Copy the cached content of `url` to `dest`.
+    fn fetch(&self, url: &str, dest: &Path) -> Result<Option<CacheEntry>> {
+        let Some(mut entry) = self.lookup(url) else {
+            return Ok(None);
+        };
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(self.blob(&entry.sha256), dest)
+            .with_context(|| format!("Failed to copy cached {url} to {}", dest.display()))?;
+        entry.last_used = now();
+        self.write_entry(&entry)?;
+        Ok(Some(entry))
+    }
+
+    /// Copy cached content to `dest` through a temporary file beside it,
+    /// only if `check` accepts it. With `sha256`, content fetched from any
+    /// URL is used; otherwise the content cached for `url`. Accepted content
+    /// is recorded, verified, for `url`; a rejected URL entry is dropped.
+    fn fetch_checked(
+        &self,
+        url: &str,
+        sha256: Option<&str>,
+        dest: &Path,
+        check: impl FnOnce(&Path) -> bool,
+    ) -> Result<Option<CacheEntry>> {
+        let now = now();
+        let entry = match sha256.map(str::to_ascii_lowercase) {
+            Some(sha256) => {
+                let blob = self.blob(&sha256);
+                if !blob.is_file() {
+                    return Ok(None);
+                }
+                match self.lookup(url).filter(|entry| entry.sha256 == sha256) {
+                    Some(entry) => entry,
+                    None => CacheEntry {
+                        url: url.to_string(),
+                        size: fs::metadata(&blob)?.len(),
+                        name: dest
+                            .file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or_default(),
+                        sha256,
+                        verified: false,
+                        fetched_at: now,
+                        last_used: now,
+                    },
+                }
+            }
+            None => match self.lookup(url) {
+                Some(entry) => entry,
+                None => return Ok(None),
+            },
+        };
+        let parent = match dest.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(parent)?;
+        let tmp = tempfile::NamedTempFile::new_in(parent)?;
+        fs::copy(self.blob(&entry.sha256), tmp.path())
+            .with_context(|| format!("Failed to copy cached {url} to {}", dest.display()))?;
+        if !check(tmp.path()) {
+            if sha256.is_none() {
+                output::warning(&format!("dropping cached download of {url}"));
+                self.forget(url)?;
+            }
+            return Ok(None);
+        }
+        tmp.persist(dest)
+            .with_context(|| format!("Failed to write {}", dest.display()))?;
+        let entry = CacheEntry {
+            verified: true,
+            last_used: now,
+            ..entry
+        };
+        self.write_entry(&entry)?;
+        Ok(Some(entry))
+    }
+
+    /// Add `file`, just downloaded from `url`, to the cache.
+    fn store(&self, url: &str, file: &Path) -> Result<CacheEntry> {
+        let sha256 = sha256_file(file)?;
+        let blob = self.blob(&sha256);
+        if !blob.is_file() {
+            let tmp = self.tmp_file()?;
+            fs::copy(file, &tmp)
+                .with_context(|| format!("Failed to copy {} into the cache", file.display()))?;
+            fs::create_dir_all(self.root.join("sha256"))?;
+            fs::rename(&tmp, &blob)?;
+        }
+        let now = now();
+        let entry = CacheEntry {
+            url: url.to_string(),
+            sha256,
+            size: fs::metadata(&blob)?.len(),
+            name: file
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            verified: false,
+            fetched_at: now,
+            last_used: now,
+        };
+        self.write_entry(&entry)?;
+        Ok(entry)
+    }
+
+    fn forget(&self, url: &str) -> Result<()> {
+        let file = self.entry_file(url);
+        match fs::remove_file(&file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", file.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn write_entry(&self, entry: &CacheEntry) -> Result<()> {
+        let tmp = self.tmp_file()?;
+        fs::write(&tmp, serde_json::to_string_pretty(entry)?)?;
+        fs::create_dir_all(self.root.join("urls"))?;
+        fs::rename(&tmp, self.entry_file(&entry.url))?;
+        Ok(())
+    }
+
+    fn tmp_file(&self) -> Result<PathBuf> {
+        let dir = self.root.join("tmp");
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        let (_, path) = tempfile::NamedTempFile::new_in(&dir)?.keep()?;
+        Ok(path)
+    }
+
+    fn entry_file(&self, url: &str) -> PathBuf {
+        let key = hex::encode(Sha256::digest(url.as_bytes()));
+        self.root.join("urls").join(format!("{key}.json"))
+    }
+
+    fn blob(&self, sha256: &str) -> PathBuf {
+        self.root.join("sha256").join(sha256)
+    }
+
+    /// Every cached file with the hash it is stored under.
+    fn blobs(&self) -> Vec<(String, PathBuf)> {
+        fs::read_dir(self.root.join("sha256"))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                (
+                    entry.file_name().to_string_lossy().to_string(),
+                    entry.path(),
+                )
+            })
+            .collect()
+    }
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+struct Active {
+    cache: SourceCache,
+    /// Files fetched through the cache in this operation, by URL.
+    fetched: HashMap<PathBuf, String>,
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Active>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with `cache` as the active download cache on this thread.
+pub(crate) fn scoped<R>(cache: Option<&SourceCache>, f: impl FnOnce() -> R) -> R {
+    let active = cache.map(|cache| Active {
+        cache: cache.clone(),
+        fetched: HashMap::new(),
+    });
+    let previous = ACTIVE.with(|a| a.replace(active));
+    let result = f();
+    ACTIVE.with(|a| *a.borrow_mut() = previous);
+    result
+}
+
+fn key(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// File name `url` was cached under, when the active cache has it.
+pub(crate) fn cached_name(url: &str) -> Option<String> {
+    ACTIVE.with(|a| {
+        let active = a.borrow();
+        active.as_ref()?.cache.lookup(url).map(|entry| entry.name)
+    })
+}
+
+/// The most recently fetched entry of the active cache matching `filter`
+/// whose content is present.
+pub(crate) fn newest(filter: impl Fn(&CacheEntry) -> bool) -> Option<CacheEntry> {
+    ACTIVE.with(|a| {
+        let active = a.borrow();
+        let cache = &active.as_ref()?.cache;
+        cache
+            .entries()
+            .into_iter()
+            .filter(|entry| filter(entry) && cache.blob(&entry.sha256).is_file())
+            .max_by_key(|entry| entry.fetched_at)
+    })
+}
+
+/// Copy `url` from the active cache to `dest`. Returns `false` when there is
+/// no active cache or it does not have `url`.
+pub(crate) fn fetch(url: &str, dest: &Path) -> bool {
+    ACTIVE.with(|a| {
+        let mut active = a.borrow_mut();
+        let Some(active) = active.as_mut() else {
+            return false;
+        };
+        match active.cache.fetch(url, dest) {
+            Ok(Some(entry)) => {
+                output::detail(&format!(
+                    "using cached {} ({} bytes)",
+                    entry.name, entry.size
+                ));
+                active.fetched.insert(key(dest), url.to_string());
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                output::warning(&format!("download cache: {e:#}"));
+                false
+            }
+        }
+    })
+}
+
+/// Copy cached content for `url` to `dest` if `check` accepts it (see
+/// [`SourceCache::fetch_checked`]). Returns `false` when there is no active
+/// cache, it does not have the content, or the check fails.
+pub(crate) fn fetch_checked(
+    url: &str,
+    sha256: Option<&str>,
+    dest: &Path,
+    check: impl FnOnce(&Path) -> bool,
+) -> bool {
+    ACTIVE.with(|a| {
+        let mut active = a.borrow_mut();
+        let Some(active) = active.as_mut() else {
+            return false;
+        };
+        match active.cache.fetch_checked(url, sha256, dest, check) {
+            Ok(Some(entry)) => {
+                output::detail(&format!(
+                    "using cached {} ({} bytes)",
+                    entry.name, entry.size
+                ));
+                active.fetched.insert(key(dest), url.to_string());
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                output::warning(&format!("download cache: {e:#}"));
+                false
+            }
+        }
+    })
+}
+
+/// Add `file`, just downloaded from `url`, to the active cache.
+pub(crate) fn store(url: &str, file: &Path) {
+    ACTIVE.with(|a| {
+        let mut active = a.borrow_mut();
+        let Some(active) = active.as_mut() else {
+            return;
+        };
+        match active.cache.store(url, file) {
+            Ok(_) => {
+                active.fetched.insert(key(file), url.to_string());
+            }
+            Err(e) => output::warning(&format!("download cache: {e:#}")),
+        }
+    })
+}
+
+/// Record the outcome of a hash check of `file`. A file that came through
+/// the cache is marked verified, or its URL is dropped when the check failed.
+pub(crate) fn verified(file: &Path, ok: bool) {
+    ACTIVE.with(|a| {
+        let active = a.borrow();
+        let Some(active) = active.as_ref() else {
+            return;
+        };
+        let Some(url) = active.fetched.get(&key(file)) else {
+            return;
+        };
+        let result = if ok {
+            active
+                .cache
+                .lookup(url)
+                .map(|entry| {
+                    active.cache.write_entry(&CacheEntry {
+                        verified: true,
+                        ..entry
+                    })
+                })
+                .unwrap_or(Ok(()))
+        } else {
+            output::warning(&format!("dropping cached download of {url}"));
+            active.cache.forget(url)
+        };
+        if let Err(e) = result {
+            output::warning(&format!("download cache: {e:#}"));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cache_fetch_verify_and_gc() {
+        let dir = TempDir::new().unwrap();
+        let cache = SourceCache::new(dir.path().join("cache"));
+        let url = "https://example.com/foo-1.0.tar.gz";
+        let first = dir.path().join("a/foo-1.0.tar.gz");
+        let second = dir.path().join("b/foo-1.0.tar.gz");
+        fs::create_dir_all(first.parent().unwrap()).unwrap();
+        fs::write(&first, "hello world").unwrap();
+
+        scoped(Some(&cache), || {
+            assert!(!fetch(url, &second));
+            store(url, &first);
+            assert!(fetch(url, &second));
+            verified(&second, true);
+        });
+        assert_eq!(fs::read_to_string(&second).unwrap(), "hello world");
+        let entries = cache.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].sha256,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert!(entries[0].verified);
+
+        // No active cache: nothing is read or written.
+        assert!(!fetch(url, &dir.path().join("c")));
+
+        fs::write(cache.blob(&entries[0].sha256), "tampered").unwrap();
+        assert_eq!(cache.verify().unwrap(), entries);
+        assert!(cache.entries().is_empty());
+
+        scoped(Some(&cache), || store(url, &first));
+        let report = cache.gc(Duration::from_secs(3600)).unwrap();
+        assert_eq!(report, GcReport::default());
+        let report = cache.gc(Duration::ZERO).unwrap();
+        assert_eq!((report.entries, report.blobs, report.bytes), (1, 1, 11));
+    }
+
+    #[test]
+    fn test_cache_drops_url_when_verification_fails() {
+        let dir = TempDir::new().unwrap();
+        let cache = SourceCache::new(dir.path().join("cache"));
+        let file = dir.path().join("foo.tar.gz");
+        fs::write(&file, "stale").unwrap();
+
+        scoped(Some(&cache), || {
+            store("https://example.com/latest.tar.gz", &file);
+            verified(&file, false);
+        });
+        assert!(cache.entries().is_empty());
+    }
+}
@@ -0,0 +1,35 @@
+//! Recipe catalog: the `*.rhai` files under a recipes directory.
+
+use std::path::{Path, PathBuf};
+
+/// Recipe files directly under `recipes_path`, sorted by path.
+///
+/// A missing or unreadable directory yields an empty list.
+pub fn recipe_files(recipes_path: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(recipes_path)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == "rhai"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Recipe files in `recipes_path` and the configured repositories whose
+/// persisted ctx records an install reason, i.e. recipes that are currently
+/// installed.
+pub fn installed_recipes(recipes_path: &Path) -> Vec<PathBuf> {
+    super::repos::active()
+        .recipe_files(Some(recipes_path))
+        .into_iter()
+        .filter(|path| {
+            std::fs::read_to_string(path)
+                .ok()
+                .and_then(|source| super::ctx_store::persisted(path, &source))
+                .and_then(|ctx| super::orphans::InstallReason::from_ctx(&ctx))
+                .is_some()
+        })
+        .collect()
+}
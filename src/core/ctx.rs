@@ -2,6 +2,7 @@
 //!
 //! Recipes store state in a `ctx` map literal. This module provides:
 //! - `find_ctx_block`: locate ctx in source
+//! - `parse_literal`: read persisted ctx without running the recipe
 //! - `serialize`: convert Map to Rhai literal
 //! - `persist`: update ctx in source file
 
@@ -78,8 +79,26 @@ pub fn serialize(map: &rhai::Map) -> String {
     out
 }
 
+/// Rhai statements that merge `map` into an existing `ctx` and drop the
+/// `removed` keys (used for state store overlays).
+pub(crate) fn overlay_statement(map: &rhai::Map, removed: &[String]) -> String {
+    let mut out = format!(
+        "ctx.mixin({});",
+        format_value(&Dynamic::from_map(map.clone()))
+    );
+    for key in removed {
+        out.push_str(&format!("\nctx.remove(\"{}\");", escape_string(key)));
+    }
+    out
+}
+
 /// Format a Dynamic value as a Rhai literal
 fn format_value(v: &Dynamic) -> String {
+    format_value_at(v, 1)
+}
+
+/// Format a value nested `depth` levels deep (arrays and maps span lines).
+fn format_value_at(v: &Dynamic, depth: usize) -> String {
     if v.is_string() {
         // Escape the string properly
         let s = v.clone().into_string().unwrap();
@@ -90,12 +109,57 @@ fn format_value(v: &Dynamic) -> String {
         v.as_bool().unwrap().to_string()
     } else if v.is_unit() {
         "()".to_string()
+    } else if v.is_array() {
+        let arr = v.clone().into_array().unwrap();
+        if arr.is_empty() {
+            return "[]".to_string();
+        }
+        let items: Vec<String> = arr.iter().map(|i| format_value_at(i, depth + 1)).collect();
+        format_block("[", &items, "]", depth)
+    } else if v.is_map() {
+        let map = v.clone().cast::<rhai::Map>();
+        if map.is_empty() {
+            return "#{}".to_string();
+        }
+        let mut keys: Vec<_> = map.keys().collect();
+        keys.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        let items: Vec<String> = keys
+            .into_iter()
+            .map(|k| format!("{}: {}", format_key(k), format_value_at(&map[k], depth + 1)))
+            .collect();
+        format_block("#{", &items, "}", depth)
     } else {
         // Fallback for other types
         format!("\"{}\"", escape_string(&v.to_string()))
     }
 }
 
+/// One item per line, indented one level deeper than the enclosing key.
+fn format_block(open: &str, items: &[String], close: &str, depth: usize) -> String {
+    let indent = "    ".repeat(depth + 1);
+    let mut out = format!("{open}\n");
+    for item in items {
+        out.push_str(&format!("{indent}{item},\n"));
+    }
+    out.push_str(&"    ".repeat(depth));
+    out.push_str(close);
+    out
+}
+
+/// Map keys that are not plain identifiers must be quoted.
+fn format_key(key: &str) -> String {
+    let mut chars = key.chars();
+    let is_ident = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_ident {
+        key.to_string()
+    } else {
+        format!("\"{}\"", escape_string(key))
+    }
+}
+
 /// Escape special characters in strings
 fn escape_string(s: &str) -> String {
     let mut out = String::with_capacity(s.len());
@@ -112,6 +176,21 @@ fn escape_string(s: &str) -> String {
     out
 }
 
+/// Evaluate the ctx literal in source without running the rest of the recipe
+///
+/// Only works for literal values (which is what `persist` writes); returns
+/// `None` when the block is missing or uses expressions that need scope.
+pub fn parse_literal(source: &str) -> Option<rhai::Map> {
+    let (start, end) = find_ctx_block(source)?;
+    let literal = source[start..end]
+        .strip_prefix("let ctx =")?
+        .trim()
+        .trim_end_matches(';');
+    rhai::Engine::new_raw()
+        .eval_expression::<rhai::Map>(literal)
+        .ok()
+}
+
 /// Replace the ctx block in source with updated values
 pub fn persist(source: &str, map: &rhai::Map) -> Result<String> {
     let (start, end) = find_ctx_block(source).ok_or_else(|| anyhow!("ctx block not found"))?;
@@ -158,6 +237,25 @@ fn foo() {}"#;
         assert!(result.is_some());
     }
 
+    #[test]
+    fn test_parse_literal() {
+        let source = r#"let ctx = #{
+    name: "test",
+    installed_files: [
+        "/usr/bin/test",
+    ],
+};
+fn acquire(ctx) { ctx }"#;
+        let map = parse_literal(source).unwrap();
+        assert_eq!(map["name"].clone().into_string().unwrap(), "test");
+        assert_eq!(
+            map["installed_files"].clone().into_array().unwrap().len(),
+            1
+        );
+
+        assert!(parse_literal("let ctx = #{ dir: BUILD_DIR };").is_none());
+    }
+
     #[test]
     fn test_serialize_simple() {
         let mut map = rhai::Map::new();
@@ -189,6 +287,31 @@ fn acquire(ctx) { ctx }"#;
         assert!(result.contains("// Header"));
     }
 
+    #[test]
+    fn test_serialize_arrays_and_maps_roundtrip() {
+        let mut nested = rhai::Map::new();
+        nested.insert("a-b".into(), Dynamic::from(1_i64));
+        let mut map = rhai::Map::new();
+        map.insert(
+            "files".into(),
+            Dynamic::from(vec![Dynamic::from("/usr/bin/x"), Dynamic::from("/etc/x")]),
+        );
+        map.insert("empty".into(), Dynamic::from(rhai::Array::new()));
+        map.insert("meta".into(), Dynamic::from(nested));
+
+        let literal = serialize(&map);
+        assert!(literal.contains("    files: [\n        \"/usr/bin/x\",\n"));
+        assert!(literal.contains("empty: [],"));
+        assert!(literal.contains("\"a-b\": 1,"));
+
+        let engine = rhai::Engine::new();
+        let source = format!("{literal}\nctx");
+        let parsed: rhai::Map = engine.eval(&source).unwrap();
+        let files = parsed["files"].clone().into_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(find_ctx_block(&source).unwrap().1, literal.len());
+    }
+
     #[test]
     fn test_escape_string() {
         assert_eq!(escape_string("hello"), "hello");
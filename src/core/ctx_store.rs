@@ -0,0 +1,340 @@
+//! Where persisted ctx lives
+//!
+//! By default ctx is persisted by rewriting the `let ctx = #{...}` block in the
+//! recipe source ([`CtxStore::Source`]). That does not work for read-only
+//! recipe repos (e.g. `/etc/recipe/repos/...`) and dirties git-tracked ones, so
+//! an engine can instead keep ctx in a state directory
+//! ([`CtxStore::StateDir`], `$XDG_STATE_HOME/recipe` by default):
+//! - each recipe gets a JSON file at `<dir>/ctx/<absolute recipe path>.json`
+//! - the file holds only the keys whose values differ from the recipe's ctx
+//!   literal, so edits to the recipe still show through; literal keys that
+//!   ctx no longer has are listed under `"$removed"`
+//! - compiled recipes apply it as an overlay (`ctx.mixin(...)`, then
+//!   `ctx.remove(...)` for removed keys) after their top-level statements;
+//!   recipe sources are never written
+//! - recipe locks live under `<dir>/lock/`, so recipe directories may be
+//!   read-only
+//!
+//! The ctx history journal is never written into recipe directories: a state
+//! directory store keeps it under `<dir>/history/`, and the source store keeps
+//! none unless the engine is given a history directory (see
+//! [`crate::RecipeEngine::with_history_dir`]).
+//!
+//! The store is selected per [`crate::RecipeEngine`], which makes it active on
+//! the current thread for the duration of each operation.
+
+use super::ctx;
+use anyhow::{Context, Result, anyhow};
+use std::cell::RefCell;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+/// Backend for persisted ctx.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CtxStore {
+    /// Rewrite the ctx block in the recipe source.
+    #[default]
+    Source,
+    /// Keep per-recipe JSON overlays under this directory.
+    StateDir(PathBuf),
+}
+
+impl CtxStore {
+    /// State-directory store at `$XDG_STATE_HOME/recipe` (default
+    /// `~/.local/state/recipe`).
+    pub fn xdg() -> Self {
+        Self::StateDir(xdg_state_dir())
+    }
+
+    /// State file holding the ctx overlay for `recipe_path`, if this store
+    /// keeps one.
+    pub fn state_file(&self, recipe_path: &Path) -> Option<PathBuf> {
+        match self {
+            Self::Source => None,
+            Self::StateDir(dir) => Some(mirror(&dir.join("ctx"), recipe_path, ".json")),
+        }
+    }
+
+    /// Lock file guarding `recipe_path` against concurrent runs: next to the
+    /// recipe (`foo.rhai.lock`), or under `<dir>/lock/` for a state directory.
+    pub fn lock_file(&self, recipe_path: &Path) -> PathBuf {
+        match self {
+            Self::Source => recipe_path.with_extension("rhai.lock"),
+            Self::StateDir(dir) => mirror(&dir.join("lock"), recipe_path, ".lock"),
+        }
+    }
+
+    /// ctx history journal of `recipe_path` (see `recipe history`) under
+    /// `<dir>/history/`, if this store keeps one. The source store does not:
+    /// recipe repos never get a journal.
+    pub fn history_file(&self, recipe_path: &Path) -> Option<PathBuf> {
+        match self {
+            Self::Source => None,
+            Self::StateDir(dir) => Some(mirror(&dir.join("history"), recipe_path, ".jsonl")),
+        }
+    }
+}
+
+/// `$XDG_STATE_HOME/recipe`, default `~/.local/state/recipe`.
+#[cfg(not(test))]
+pub(crate) fn xdg_state_dir() -> PathBuf {
+    let state_home = std::env::var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            dirs::home_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join(".local/state")
+        });
+    state_home.join("recipe")
+}
+
+/// Unit tests never touch the real state directory.
+#[cfg(test)]
+pub(crate) fn xdg_state_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("recipe-test-state-{}", std::process::id()))
+}
+
+/// `<dir>/<absolute recipe path><suffix>`.
+pub(crate) fn mirror(dir: &Path, recipe_path: &Path, suffix: &str) -> PathBuf {
+    let recipe_path = recipe_path
+        .canonicalize()
+        .unwrap_or_else(|_| recipe_path.to_path_buf());
+    let mut file = dir.to_path_buf();
+    for component in recipe_path.components() {
+        if let Component::Normal(part) = component {
+            file.push(part);
+        }
+    }
+    let mut name = file.into_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+thread_local! {
+    static ACTIVE: RefCell<CtxStore> = const { RefCell::new(CtxStore::Source) };
+}
+
+/// Run `f` with `store` as the active ctx store on this thread.
+pub(crate) fn scoped<R>(store: &CtxStore, f: impl FnOnce() -> R) -> R {
+    let previous = ACTIVE.with(|a| a.replace(store.clone()));
+    let result = f();
+    ACTIVE.with(|a| *a.borrow_mut() = previous);
+    result
+}
+
+/// State file for `recipe_path` in the active store.
+pub(crate) fn state_file(recipe_path: &Path) -> Option<PathBuf> {
+    ACTIVE.with(|a| a.borrow().state_file(recipe_path))
+}
+
+/// Lock file for `recipe_path` in the active store.
+pub(crate) fn lock_file(recipe_path: &Path) -> PathBuf {
+    ACTIVE.with(|a| a.borrow().lock_file(recipe_path))
+}
+
+/// History journal for `recipe_path` in the active store, if it keeps one.
+pub(crate) fn history_file(recipe_path: &Path) -> Option<PathBuf> {
+    ACTIVE.with(|a| a.borrow().history_file(recipe_path))
+}
+
+/// State file key listing the literal keys removed from ctx.
+const REMOVED_KEY: &str = "$removed";
+
+/// Persisted changes to a recipe's ctx literal.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Overlay {
+    /// Keys added or changed, with their values.
+    pub set: rhai::Map,
+    /// Literal keys that ctx no longer has.
+    pub removed: Vec<String>,
+}
+
+impl Overlay {
+    /// Apply to `ctx_map` in place.
+    pub(crate) fn apply(&self, ctx_map: &mut rhai::Map) {
+        for key in &self.removed {
+            ctx_map.remove(key.as_str());
+        }
+        ctx_map.extend(self.set.clone());
+    }
+}
+
+/// The persisted overlay for `recipe_path`, if the active store has one.
+pub(crate) fn overlay(recipe_path: &Path) -> Result<Option<Overlay>> {
+    let Some(file) = state_file(recipe_path) else {
+        return Ok(None);
+    };
+    let text = match fs::read_to_string(&file) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", file.display())),
+    };
+    let json: serde_json::Value = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid ctx state file {}", file.display()))?;
+    let mut set = rhai::serde::to_dynamic(json)
+        .ok()
+        .and_then(|value| value.try_cast::<rhai::Map>())
+        .ok_or_else(|| anyhow!("ctx state file {} is not an object", file.display()))?;
+    let removed = set
+        .remove(REMOVED_KEY)
+        .and_then(|value| value.into_array().ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|key| key.into_string().ok())
+        .collect();
+    Ok(Some(Overlay { set, removed }))
+}
+
+/// Persisted ctx of a recipe without running it: the ctx literal in `source`
+/// with the active store's overlay applied.
+pub fn persisted(recipe_path: &Path, source: &str) -> Option<rhai::Map> {
+    let literal = ctx::parse_literal(source);
+    match overlay(recipe_path).ok().flatten() {
+        Some(overlay) => {
+            let mut merged = literal.unwrap_or_default();
+            overlay.apply(&mut merged);
+            Some(merged)
+        }
+        None => literal,
+    }
+}
+
+/// Write `ctx_map` to `state_file`, keeping only keys that
+/// differ from `literal` (the recipe's ctx literal) and recording literal keys
+/// that `ctx_map` lacks as removed.
+pub(crate) fn save(
+    state_file: &Path,
+    literal: Option<&rhai::Map>,
+    ctx_map: &rhai::Map,
+) -> Result<()> {
+    let same = |key: &str, value: &rhai::Dynamic| {
+        literal.and_then(|l| l.get(key)).is_some_and(|old| {
+            old.to_string() == value.to_string() && old.type_name() == value.type_name()
+        })
+    };
+    let mut overlay: rhai::Map = ctx_map
+        .iter()
+        .filter(|(key, value)| !same(key.as_str(), value))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let removed: rhai::Array = literal
+        .into_iter()
+        .flat_map(|l| l.keys())
+        .filter(|key| !ctx_map.contains_key(*key))
+        .map(|key| key.to_string().into())
+        .collect();
+    if !removed.is_empty() {
+        overlay.insert(REMOVED_KEY.into(), removed.into());
+    }
+    let json: serde_json::Value = rhai::serde::from_dynamic(&rhai::Dynamic::from(overlay))
+        .map_err(|e| anyhow!("ctx is not serializable: {e}"))?;
+    let mut text = serde_json::to_string_pretty(&json)?;
+    text.push('\n');
+
+    let parent = state_file
+        .parent()
+        .ok_or_else(|| anyhow!("State file has no parent: {}", state_file.display()))?;
+    fs::create_dir_all(parent)
+        .with_context(|| format!("Failed to create state directory {}", parent.display()))?;
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".recipe-ctx.")
+        .suffix(".tmp")
+        .tempfile_in(parent)?;
+    tmp.as_file_mut().write_all(text.as_bytes())?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(state_file)
+        .with_context(|| format!("Failed to write {}", state_file.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_state_file_mirrors_recipe_path() {
+        let store = CtxStore::StateDir(PathBuf::from("/var/state/recipe"));
+        assert_eq!(
+            store.state_file(Path::new("/etc/recipe/repos/rocky10/foo.rhai")),
+            Some(PathBuf::from(
+                "/var/state/recipe/ctx/etc/recipe/repos/rocky10/foo.rhai.json"
+            ))
+        );
+        assert_eq!(
+            store.lock_file(Path::new("/etc/recipe/repos/rocky10/foo.rhai")),
+            Path::new("/var/state/recipe/lock/etc/recipe/repos/rocky10/foo.rhai.lock")
+        );
+        assert_eq!(CtxStore::Source.state_file(Path::new("/x.rhai")), None);
+        assert_eq!(
+            CtxStore::Source.lock_file(Path::new("/x.rhai")),
+            Path::new("/x.rhai.lock")
+        );
+        // The journal never lands in recipe directories.
+        assert_eq!(CtxStore::Source.history_file(Path::new("/x.rhai")), None);
+    }
+
+    #[test]
+    fn test_save_keeps_only_changed_keys() {
+        let dir = TempDir::new().unwrap();
+        let recipe = dir.path().join("foo.rhai");
+        let source = "let ctx = #{ name: \"foo\", version: \"1.0\" };\n";
+        fs::write(&recipe, source).unwrap();
+        let store = CtxStore::StateDir(dir.path().join("state"));
+
+        let mut ctx_map = ctx::parse_literal(source).unwrap();
+        ctx_map.insert("version".into(), "2.0".into());
+        ctx_map.insert("jobs".into(), 4_i64.into());
+        let file = store.state_file(&recipe).unwrap();
+        save(&file, ctx::parse_literal(source).as_ref(), &ctx_map).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(json, serde_json::json!({ "version": "2.0", "jobs": 4 }));
+
+        scoped(&store, || {
+            let merged = persisted(&recipe, source).unwrap();
+            assert_eq!(merged["name"].clone().into_string().unwrap(), "foo");
+            assert_eq!(merged["version"].clone().into_string().unwrap(), "2.0");
+            assert_eq!(merged["jobs"].as_int().unwrap(), 4);
+        });
+        assert_eq!(
+            persisted(&recipe, source).unwrap()["version"]
+                .clone()
+                .into_string()
+                .unwrap(),
+            "1.0"
+        );
+    }
+
+    #[test]
+    fn test_save_round_trips_removed_keys() {
+        let dir = TempDir::new().unwrap();
+        let recipe = dir.path().join("foo.rhai");
+        let source = "let ctx = #{ name: \"foo\", installed_files: [\"/a\"] };\n";
+        fs::write(&recipe, source).unwrap();
+        let store = CtxStore::StateDir(dir.path().join("state"));
+
+        let mut ctx_map = ctx::parse_literal(source).unwrap();
+        ctx_map.remove("installed_files");
+        let file = store.state_file(&recipe).unwrap();
+        save(&file, ctx::parse_literal(source).as_ref(), &ctx_map).unwrap();
+
+        scoped(&store, || {
+            let merged = persisted(&recipe, source).unwrap();
+            assert!(!merged.contains_key("installed_files"));
+            assert_eq!(merged["name"].clone().into_string().unwrap(), "foo");
+
+            // Compiled recipes see the removal too.
+            let compiled =
+                crate::core::executor::compile_recipe(&rhai::Engine::new(), &recipe, None).unwrap();
+            let mut scope = rhai::Scope::new();
+            rhai::Engine::new()
+                .run_ast_with_scope(&mut scope, &compiled.ast)
+                .unwrap();
+            let ctx_map: rhai::Map = scope.get_value("ctx").unwrap();
+            assert!(!ctx_map.contains_key("installed_files"));
+        });
+    }
+}
@@ -0,0 +1,569 @@
+//! Dependency graph across all recipes in a recipes directory
+//!
+//! Every recipe may declare `let deps = [...]` (needed before any phase) and
+//! `let build_deps = [...]` (needed before build). A dependency name `foo`
+//! refers to the first `foo.rhai` in the recipes directory and the configured
+//! repositories (see [`super::repos`]), so graph nodes are keyed by recipe
+//! file stem. `repo/foo` names `foo.rhai` in one repository and is its own
+//! node.
+//!
+//! [`DepGraph::scan`] runs the top-level of every recipe to read both arrays;
+//! [`DepGraph::reachable`] reads only the recipes a set of names leads to, which
+//! is all an install or plan needs. [`DepGraph::install_order`] then walks the transitive closure of a set of
+//! dependencies and returns it in install order (dependencies first). Cycles
+//! are reported with the full path, e.g. `a -> b -> c -> a`.
+//!
+//! The same graph backs `recipe deps`, `recipe rdeps`, and `recipe tree`
+//! ([`DepGraph::dependents`], [`DepGraph::render_tree`], [`DepGraph::to_dot`]).
+
+use super::executor::{compile_recipe, install_scope, scope_string_array};
+use super::repos;
+use anyhow::{Result, anyhow};
+use rhai::Engine;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// One recipe and the dependencies it declares.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DepNode {
+    pub recipe_path: PathBuf,
+    /// Runtime dependencies (`deps`).
+    pub deps: Vec<String>,
+    /// Build-time dependencies (`build_deps`).
+    pub build_deps: Vec<String>,
+}
+
+impl DepNode {
+    /// `deps` followed by `build_deps`, without duplicates.
+    pub fn all_deps(&self) -> impl Iterator<Item = &String> {
+        let mut seen = HashSet::new();
+        self.deps
+            .iter()
+            .chain(self.build_deps.iter())
+            .filter(move |d| seen.insert(d.as_str()))
+    }
+}
+
+/// `deps`/`build_deps` graph of every recipe under a recipes directory.
+#[derive(Debug, Clone, Default)]
+pub struct DepGraph {
+    nodes: BTreeMap<String, DepNode>,
+    /// Recipes that failed to compile or run; only an error when reached.
+    broken: BTreeMap<String, String>,
+}
+
+impl DepGraph {
+    /// Read `deps` and `build_deps` from every recipe under `recipes_path`
+    /// and the active repositories, plus each `repo/name` dependency they
+    /// reach.
+    ///
+    /// Each recipe's top-level runs with the same constants the install flow
+    /// provides (`BUILD_DIR` points at the recipe's dependency build dir under
+    /// `build_dir`). Recipes that fail to load are remembered and reported only
+    /// if a resolution reaches them.
+    pub fn scan(
+        engine: &Engine,
+        recipes_path: &Path,
+        build_dir: &Path,
+        defines: &[(String, String)],
+    ) -> Self {
+        let mut graph = Self::default();
+        for recipe_path in repos::active().recipe_files(Some(recipes_path)) {
+            let name = recipe_name(&recipe_path);
+            graph.read(
+                engine,
+                name,
+                &recipe_path,
+                Some(recipes_path),
+                build_dir,
+                defines,
+            );
+        }
+        graph.add_qualified(engine, recipes_path, build_dir, defines);
+        graph
+    }
+
+    /// Read only the recipes reachable from `names` through `deps` and
+    /// `build_deps`, located like [`DepGraph::scan`] locates them. Without a
+    /// recipes path only the active repositories are searched.
+    ///
+    /// Names that match no recipe stay missing; recipes that fail to load are
+    /// remembered as in [`DepGraph::scan`].
+    pub fn reachable(
+        engine: &Engine,
+        recipes_path: Option<&Path>,
+        build_dir: &Path,
+        defines: &[(String, String)],
+        names: &[String],
+    ) -> Self {
+        let repos = repos::active();
+        let mut graph = Self::default();
+        let mut pending = names.to_vec();
+        let mut seen = HashSet::new();
+        while let Some(name) = pending.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            let Some(path) = repos.find(recipes_path, &name) else {
+                continue;
+            };
+            graph.read(
+                engine,
+                name.clone(),
+                &path,
+                recipes_path,
+                build_dir,
+                defines,
+            );
+            if let Some(node) = graph.nodes.get(&name) {
+                pending.extend(node.all_deps().cloned());
+            }
+        }
+        graph
+    }
+
+    fn read(
+        &mut self,
+        engine: &Engine,
+        name: String,
+        recipe_path: &Path,
+        recipes_path: Option<&Path>,
+        build_dir: &Path,
+        defines: &[(String, String)],
+    ) {
+        match read_node(engine, recipe_path, recipes_path, build_dir, defines) {
+            Ok(node) => {
+                self.nodes.insert(name, node);
+            }
+            Err(e) => {
+                self.broken.insert(name, format!("{e:#}"));
+            }
+        }
+    }
+
+    /// Add a node for every `repo/name` dependency not in the graph yet.
+    /// Names that match no recipe stay missing.
+    fn add_qualified(
+        &mut self,
+        engine: &Engine,
+        recipes_path: &Path,
+        build_dir: &Path,
+        defines: &[(String, String)],
+    ) {
+        let repos = repos::active();
+        let mut tried = HashSet::new();
+        loop {
+            let pending: Vec<String> = self
+                .nodes
+                .values()
+                .flat_map(DepNode::all_deps)
+                .filter(|dep| dep.contains('/') && !self.nodes.contains_key(*dep))
+                .filter(|dep| tried.insert((*dep).clone()))
+                .cloned()
+                .collect();
+            if pending.is_empty() {
+                return;
+            }
+            for dep in pending {
+                if let Some(path) = repos.find(Some(recipes_path), &dep) {
+                    self.read(engine, dep, &path, Some(recipes_path), build_dir, defines);
+                }
+            }
+        }
+    }
+
+    /// Read one recipe and add it to the graph, returning its node name.
+    ///
+    /// Used for recipes given by path that may live outside `recipes_path`.
+    /// Unlike [`DepGraph::scan`], a load failure is returned as an error.
+    pub fn add_recipe(
+        &mut self,
+        engine: &Engine,
+        recipe_path: &Path,
+        recipes_path: &Path,
+        build_dir: &Path,
+        defines: &[(String, String)],
+    ) -> Result<String> {
+        let name = recipe_name(recipe_path);
+        let node = read_node(engine, recipe_path, Some(recipes_path), build_dir, defines)?;
+        self.insert(name.clone(), node);
+        self.add_qualified(engine, recipes_path, build_dir, defines);
+        Ok(name)
+    }
+
+    /// Add or replace a node.
+    pub fn insert(&mut self, name: impl Into<String>, node: DepNode) {
+        let name = name.into();
+        self.broken.remove(&name);
+        self.nodes.insert(name, node);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DepNode> {
+        self.nodes.get(name)
+    }
+
+    /// All loadable recipes, by name.
+    pub fn nodes(&self) -> &BTreeMap<String, DepNode> {
+        &self.nodes
+    }
+
+    /// Recipes whose `deps` or `build_deps` name `name` directly, sorted.
+    pub fn reverse_deps(&self, name: &str) -> Vec<String> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.all_deps().any(|d| d == name))
+            .map(|(n, _)| n.clone())
+            .collect()
+    }
+
+    /// Every recipe that depends on `name`, directly or transitively, sorted.
+    pub fn dependents(&self, name: &str) -> Vec<String> {
+        let mut found = BTreeSet::new();
+        let mut queue = vec![name.to_string()];
+        while let Some(current) = queue.pop() {
+            for rdep in self.reverse_deps(&current) {
+                if rdep != name && found.insert(rdep.clone()) {
+                    queue.push(rdep);
+                }
+            }
+        }
+        found.into_iter().collect()
+    }
+
+    /// The part of the graph made of `names` and the edges between them.
+    ///
+    /// Edges to recipes outside `names` are kept on the nodes, so a subgraph
+    /// still shows every declared dependency of its members.
+    pub fn subgraph<'a>(&self, names: impl IntoIterator<Item = &'a String>) -> DepGraph {
+        let nodes = names
+            .into_iter()
+            .filter_map(|n| self.nodes.get(n).map(|node| (n.clone(), node.clone())))
+            .collect();
+        DepGraph {
+            nodes,
+            broken: BTreeMap::new(),
+        }
+    }
+
+    /// Graphviz DOT rendering. `deps` edges are solid, `build_deps` edges are
+    /// dashed.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph deps {\n  rankdir=TB;\n  node [shape=box];\n");
+        for name in self.nodes.keys() {
+            let _ = writeln!(out, "  \"{name}\";");
+        }
+        for (name, node) in &self.nodes {
+            for dep in &node.deps {
+                let _ = writeln!(out, "  \"{name}\" -> \"{dep}\";");
+            }
+            for dep in &node.build_deps {
+                let _ = writeln!(
+                    out,
+                    "  \"{name}\" -> \"{dep}\" [style=dashed, label=\"build\"];"
+                );
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Text tree of everything `root` pulls in.
+    ///
+    /// `build_deps` are marked `[build]`. A recipe already shown elsewhere in
+    /// the tree is marked `(shared)` and not expanded again; cycles and
+    /// missing recipes are marked instead of failing.
+    pub fn render_tree(&self, root: &str) -> String {
+        let mut out = format!("{root}\n");
+        let mut shown = HashSet::from([root.to_string()]);
+        let mut stack = vec![root.to_string()];
+        self.render_children(root, "", &mut shown, &mut stack, &mut out);
+        out
+    }
+
+    fn render_children(
+        &self,
+        name: &str,
+        prefix: &str,
+        shown: &mut HashSet<String>,
+        stack: &mut Vec<String>,
+        out: &mut String,
+    ) {
+        let Some(node) = self.nodes.get(name) else {
+            return;
+        };
+        let children: Vec<(&String, bool)> = node
+            .deps
+            .iter()
+            .map(|d| (d, false))
+            .chain(node.build_deps.iter().map(|d| (d, true)))
+            .collect();
+        for (i, (child, build)) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let branch = if last { "└── " } else { "├── " };
+            let mut label = child.to_string();
+            if *build {
+                label.push_str(" [build]");
+            }
+            let expand = if stack.contains(child) {
+                label.push_str(" (cycle)");
+                false
+            } else if !self.nodes.contains_key(*child) {
+                label.push_str(" (missing)");
+                false
+            } else if !shown.insert(child.to_string()) {
+                label.push_str(" (shared)");
+                false
+            } else {
+                true
+            };
+            let _ = writeln!(out, "{prefix}{branch}{label}");
+            if expand {
+                let child_prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+                stack.push(child.to_string());
+                self.render_children(child, &child_prefix, shown, stack, out);
+                stack.pop();
+            }
+        }
+    }
+
+    /// Transitive closure of `direct` in install order (dependencies first).
+    ///
+    /// `root` is the recipe that declared `direct`; it is never part of the
+    /// result, but a dependency that leads back to it is a cycle. Both `deps`
+    /// and `build_deps` edges are followed below the first level.
+    pub fn install_order(&self, root: &str, direct: &[String]) -> Result<Vec<String>> {
+        let mut order = Vec::new();
+        let mut done = HashSet::new();
+        let mut stack = vec![root.to_string()];
+        for dep in direct {
+            self.visit(dep, &mut stack, &mut done, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn visit(
+        &self,
+        name: &str,
+        stack: &mut Vec<String>,
+        done: &mut HashSet<String>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if let Some(pos) = stack.iter().position(|n| n == name) {
+            let mut cycle = stack[pos..].to_vec();
+            cycle.push(name.to_string());
+            anyhow::bail!("Dependency cycle detected: {}", cycle.join(" -> "));
+        }
+        if done.contains(name) {
+            return Ok(());
+        }
+        let node = self.node(name, stack.last().map(String::as_str))?;
+
+        stack.push(name.to_string());
+        for dep in node.all_deps() {
+            self.visit(dep, stack, done, order)?;
+        }
+        stack.pop();
+
+        done.insert(name.to_string());
+        order.push(name.to_string());
+        Ok(())
+    }
+
+    fn node(&self, name: &str, required_by: Option<&str>) -> Result<&DepNode> {
+        if let Some(node) = self.nodes.get(name) {
+            return Ok(node);
+        }
+        let required_by = required_by
+            .map(|r| format!(" (required by {r})"))
+            .unwrap_or_default();
+        match self.broken.get(name) {
+            Some(e) => Err(anyhow!(
+                "Dependency recipe '{name}'{required_by} failed to load: {e}"
+            )),
+            None => Err(anyhow!(
+                "Dependency recipe '{name}.rhai'{required_by} not found"
+            )),
+        }
+    }
+}
+
+/// Graph node name for a recipe file (its file stem).
+pub fn recipe_name(recipe_path: &Path) -> String {
+    recipe_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+fn read_node(
+    engine: &Engine,
+    recipe_path: &Path,
+    recipes_path: Option<&Path>,
+    build_dir: &Path,
+    defines: &[(String, String)],
+) -> Result<DepNode> {
+    let compiled = compile_recipe(engine, recipe_path, recipes_path)?;
+    let dep_build_dir = build_dir.join(format!(".deps/{}", recipe_name(recipe_path)));
+
+    // The same scope a dependency install runs with (see `build_deps`).
+    let mut scope = install_scope(&compiled, &dep_build_dir, defines);
+    scope.push_constant(
+        "TOOLS_PREFIX",
+        build_dir.join(".tools").to_string_lossy().to_string(),
+    );
+
+    engine
+        .run_ast_with_scope(&mut scope, &compiled.ast)
+        .map_err(|e| anyhow!("Failed to run recipe {}: {}", recipe_path.display(), e))?;
+
+    Ok(DepNode {
+        recipe_path: compiled.recipe_path,
+        deps: scope_string_array(&scope, "deps"),
+        build_deps: scope_string_array(&scope, "build_deps"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str], &[&str])]) -> DepGraph {
+        let mut graph = DepGraph::default();
+        for (name, deps, build_deps) in edges {
+            graph.insert(
+                *name,
+                DepNode {
+                    recipe_path: PathBuf::from(format!("{name}.rhai")),
+                    deps: deps.iter().map(|s| s.to_string()).collect(),
+                    build_deps: build_deps.iter().map(|s| s.to_string()).collect(),
+                },
+            );
+        }
+        graph
+    }
+
+    fn names(direct: &[&str]) -> Vec<String> {
+        direct.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_install_order_diamond() {
+        let g = graph(&[
+            ("app", &["web", "db"], &[]),
+            ("web", &["http"], &[]),
+            ("db", &["json"], &["cc"]),
+            ("http", &["core"], &[]),
+            ("json", &["core"], &[]),
+            ("core", &[], &[]),
+            ("cc", &[], &[]),
+        ]);
+        let order = g.install_order("app", &names(&["web", "db"])).unwrap();
+        assert_eq!(order, ["core", "http", "web", "json", "cc", "db"]);
+    }
+
+    #[test]
+    fn test_install_order_reports_cycle_path() {
+        let g = graph(&[
+            ("app", &["a"], &[]),
+            ("a", &["b"], &[]),
+            ("b", &[], &["c"]),
+            ("c", &["a"], &[]),
+        ]);
+        let err = g.install_order("app", &names(&["a"])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Dependency cycle detected: a -> b -> c -> a"
+        );
+
+        let g = graph(&[("app", &["a"], &[]), ("a", &["app"], &[])]);
+        let err = g.install_order("app", &names(&["a"])).unwrap_err();
+        assert!(err.to_string().contains("app -> a -> app"));
+    }
+
+    #[test]
+    fn test_dependents_and_tree() {
+        let g = graph(&[
+            ("app", &["web", "db"], &[]),
+            ("web", &["core"], &[]),
+            ("db", &["core"], &["cc"]),
+            ("core", &[], &[]),
+            ("cc", &[], &[]),
+        ]);
+        assert_eq!(g.reverse_deps("core"), ["db", "web"]);
+        assert_eq!(g.dependents("core"), ["app", "db", "web"]);
+        assert!(g.dependents("app").is_empty());
+
+        assert_eq!(
+            g.render_tree("app"),
+            "app\n├── web\n│   └── core\n└── db\n    ├── core (shared)\n    └── cc [build]\n"
+        );
+
+        let dot = g.subgraph(&["db".to_string()]).to_dot();
+        assert!(dot.contains("\"db\" -> \"core\";"));
+        assert!(dot.contains("\"db\" -> \"cc\" [style=dashed"));
+        assert!(!dot.contains("\"web\""));
+    }
+
+    #[test]
+    fn test_reachable_reads_only_needed_recipes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let write = |name: &str, body: &str| {
+            std::fs::write(dir.path().join(format!("{name}.rhai")), body).unwrap();
+        };
+        write("app", r#"let deps = ["lib"];"#);
+        write("lib", r#"let build_deps = ["cc"]; let stage = STAGE_DIR;"#);
+        write("cc", "");
+        write("other", r#"throw "must not run";"#);
+
+        let engine = Engine::new();
+        let build_dir = dir.path().join("build");
+        let g = DepGraph::reachable(&engine, Some(dir.path()), &build_dir, &[], &names(&["lib"]));
+        assert_eq!(
+            g.install_order("app", &names(&["lib"])).unwrap(),
+            ["cc", "lib"]
+        );
+        assert!(g.get("app").is_none());
+        assert!(g.get("other").is_none());
+        assert!(g.broken.is_empty());
+
+        let g = DepGraph::scan(&engine, dir.path(), &build_dir, &[]);
+        assert!(g.broken.contains_key("other"));
+    }
+
+    #[test]
+    fn test_reachable_searches_repos_without_recipes_path() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("lib.rhai"), r#"let deps = ["core"];"#).unwrap();
+        std::fs::write(dir.path().join("core.rhai"), "").unwrap();
+        let repos = repos::RepoSet::new(vec![repos::Repo {
+            name: "main".to_string(),
+            path: dir.path().to_path_buf(),
+            priority: 0,
+        }])
+        .unwrap();
+
+        let engine = Engine::new();
+        let build_dir = dir.path().join("build");
+        let g = repos::scoped(&repos, || {
+            DepGraph::reachable(&engine, None, &build_dir, &[], &names(&["lib"]))
+        });
+        assert_eq!(
+            g.install_order("app", &names(&["lib"])).unwrap(),
+            ["core", "lib"]
+        );
+        assert_eq!(
+            g.get("lib").unwrap().recipe_path,
+            dir.path().join("lib.rhai")
+        );
+    }
+
+    #[test]
+    fn test_install_order_missing_dep() {
+        let g = graph(&[("a", &["gone"], &[])]);
+        let err = g.install_order("app", &names(&["a"])).unwrap_err();
+        assert!(err.to_string().contains("'gone.rhai' (required by a)"));
+    }
+}
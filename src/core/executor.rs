@@ -3,38 +3,49 @@
 //! Executes recipes using the ctx pattern where:
 //! - `is_acquired(ctx)`, `is_built(ctx)`, `is_installed(ctx)` throw if phase needed
 //! - `acquire(ctx)`, `build(ctx)`, `install(ctx)` return updated ctx
-//! - `ctx` is persisted to the recipe file after each phase (unless disabled)
+//! - `ctx` is persisted after each phase (unless disabled), to the recipe file or
+//!   the engine's state store
 
+use crate::core::offline;
+use crate::core::orphans::InstallReason;
 use anyhow::{Context, Result, anyhow};
-use rhai::{AST, Engine};
+use rhai::{AST, Engine, Scope};
 use std::fs;
 use std::path::{Path, PathBuf};
 
 mod private;
 
-/// Parse `//! extends: <path>` from leading comments.
+pub(crate) use private::{
+    StagedInstall, install_scope, other_recipe_claims, persist_ctx, scope_string_array,
+    staged_install,
+};
+
+/// Parse the `//! extends: <path>` lines from leading comments, in order.
 ///
 /// Only looks at comment lines at the top of the file. Stops at the first
 /// non-comment, non-empty line.
-pub(crate) fn parse_extends(source: &str) -> Option<String> {
+pub(crate) fn parse_extends(source: &str) -> Vec<String> {
+    let mut bases = Vec::new();
     for line in source.lines() {
         let trimmed = line.trim();
         if trimmed.is_empty() {
             continue;
         }
         if let Some(rest) = trimmed.strip_prefix("//! extends:") {
-            return Some(rest.trim().to_string());
+            bases.push(rest.trim().to_string());
+            continue;
         }
         if !trimmed.starts_with("//") {
             break;
         }
     }
-    None
+    bases
 }
 
 /// Resolve a base recipe path.
 ///
-/// Tries relative to the child recipe's directory first, then the search path.
+/// Tries relative to the child recipe's directory first, then the search path,
+/// then each configured repository.
 fn resolve_base_path(
     base_rel: &str,
     child_path: &Path,
@@ -48,9 +59,9 @@ fn resolve_base_path(
         }
     }
 
-    // Try search path
-    if let Some(sp) = search_path {
-        let candidate = sp.join(base_rel);
+    // Try search path and repositories
+    for repo in super::repos::active().search_order(search_path) {
+        let candidate = repo.path.join(base_rel);
         if candidate.exists() {
             return Ok(candidate);
         }
@@ -64,23 +75,80 @@ fn resolve_base_path(
     )
 }
 
+/// A base recipe pulled in through `//! extends:`.
+#[derive(Debug)]
+pub(crate) struct BaseRecipe {
+    pub path: PathBuf,
+    pub source: String,
+}
+
 #[derive(Debug)]
 pub(crate) struct CompiledRecipe {
     pub ast: AST,
     /// The "main" recipe file (the one the user invoked).
     pub recipe_path: PathBuf,
     pub recipe_source: String,
-    /// Optional base recipe from `//! extends:`.
-    pub base_path: Option<PathBuf>,
-    pub base_source: Option<String>,
-    pub base_dir: Option<PathBuf>,
+    /// Base recipes from `//! extends:`, in merge order: every base comes
+    /// after its own bases, and the recipe itself comes last.
+    pub bases: Vec<BaseRecipe>,
+}
+
+impl CompiledRecipe {
+    /// Index of the base whose ctx literal the recipe uses when it declares
+    /// none itself: the last base in merge order that declares one.
+    pub fn ctx_base(&self) -> Option<usize> {
+        self.bases
+            .iter()
+            .rposition(|base| crate::core::ctx::find_ctx_block(&base.source).is_some())
+    }
+
+    /// Source holding the recipe's ctx literal: the recipe, or its
+    /// [`Self::ctx_base`].
+    pub fn ctx_source(&self) -> Option<&str> {
+        if crate::core::ctx::find_ctx_block(&self.recipe_source).is_some() {
+            return Some(&self.recipe_source);
+        }
+        self.ctx_base().map(|i| self.bases[i].source.as_str())
+    }
+
+    /// Push the base directory constants:
+    /// - `BASE_RECIPE_DIR`: directory of the last base in merge order (the
+    ///   direct base when the recipe extends a single one)
+    /// - `BASE_RECIPE_DIRS`: map of every base's file stem to its directory
+    pub fn push_base_constants(&self, scope: &mut Scope) {
+        let Some(last) = self.bases.last() else {
+            return;
+        };
+        let dir = |base: &BaseRecipe| {
+            base.path
+                .parent()
+                .unwrap_or(Path::new("."))
+                .to_string_lossy()
+                .to_string()
+        };
+        scope.push_constant("BASE_RECIPE_DIR", dir(last));
+        let dirs: rhai::Map = self
+            .bases
+            .iter()
+            .map(|base| {
+                (
+                    crate::core::deps::recipe_name(&base.path).into(),
+                    dir(base).into(),
+                )
+            })
+            .collect();
+        scope.push_constant("BASE_RECIPE_DIRS", dirs);
+    }
 }
 
 /// Compile a recipe with `//! extends:` resolution.
 ///
-/// If the child recipe declares `//! extends: <base>`, the base is compiled first
-/// and merged with the child AST. Child functions with the same name+arity replace
-/// base functions. Top-level statements run base-first, then child.
+/// A recipe may declare several `//! extends: <base>` lines, and bases may
+/// extend other bases. The layers are merged in order: each base's own bases
+/// first, then its `extends` lines top to bottom, then the recipe itself. A
+/// base reached twice is merged once, at its first position; a cycle, or two
+/// different bases with the same file stem, is an error. Later layers' functions with the same name+arity replace earlier
+/// ones, and top-level statements run in merge order.
 ///
 /// Returns the merged AST plus the source texts/paths needed for ctx persistence.
 pub(crate) fn compile_recipe(
@@ -95,61 +163,132 @@ pub(crate) fn compile_recipe(
     let source = fs::read_to_string(&recipe_path)
         .with_context(|| format!("Failed to read recipe: {}", recipe_path.display()))?;
 
-    let extends = parse_extends(&source);
-
-    if let Some(base_rel) = extends {
-        let base_path = resolve_base_path(&base_rel, &recipe_path, search_path)?;
-        let base_path = base_path
-            .canonicalize()
-            .unwrap_or_else(|_| base_path.to_path_buf());
-        let base_source = fs::read_to_string(&base_path)
-            .with_context(|| format!("Failed to read base recipe: {}", base_path.display()))?;
-
-        // Reject recursive extends
-        if parse_extends(&base_source).is_some() {
-            anyhow::bail!(
-                "Recursive extends not supported: {} extends {} which also extends",
-                recipe_path.display(),
-                base_path.display()
-            );
-        }
+    let mut bases = Vec::new();
+    collect_bases(
+        &recipe_path,
+        &source,
+        search_path,
+        &mut vec![recipe_path.clone()],
+        &mut bases,
+    )?;
 
-        let mut base_ast = engine.compile(&base_source).map_err(|e| {
+    let mut ast = AST::empty();
+    for base in &bases {
+        ast += engine.compile(&base.source).map_err(|e| {
             anyhow!(
                 "Failed to compile base recipe {}: {}",
-                base_path.display(),
+                base.path.display(),
                 e
             )
         })?;
+    }
+    ast += engine
+        .compile(&source)
+        .map_err(|e| anyhow!("Failed to compile recipe {}: {}", recipe_path.display(), e))?;
+    apply_overlay(engine, &mut ast, &recipe_path)?;
 
-        let child_ast = engine
-            .compile(&source)
-            .map_err(|e| anyhow!("Failed to compile recipe {}: {}", recipe_path.display(), e))?;
+    Ok(CompiledRecipe {
+        ast,
+        recipe_path,
+        recipe_source: source,
+        bases,
+    })
+}
 
-        // Merge: child overrides base functions, top-level runs base then child
-        base_ast += child_ast;
+/// Append the bases of `recipe_path` to `bases` in merge order. `stack` holds
+/// the chain of recipes being resolved, for cycle detection.
+fn collect_bases(
+    recipe_path: &Path,
+    source: &str,
+    search_path: Option<&Path>,
+    stack: &mut Vec<PathBuf>,
+    bases: &mut Vec<BaseRecipe>,
+) -> Result<()> {
+    for base_rel in parse_extends(source) {
+        let base_path = resolve_base_path(&base_rel, recipe_path, search_path)?;
+        let base_path = base_path.canonicalize().unwrap_or(base_path);
 
-        let base_dir = base_path.parent().map(|p| p.to_path_buf());
-        Ok(CompiledRecipe {
-            ast: base_ast,
-            recipe_path,
-            recipe_source: source,
-            base_path: Some(base_path),
-            base_source: Some(base_source),
-            base_dir,
-        })
-    } else {
-        let ast = engine
-            .compile(&source)
-            .map_err(|e| anyhow!("Failed to compile recipe: {}", e))?;
-        Ok(CompiledRecipe {
-            ast,
-            recipe_path,
-            recipe_source: source,
-            base_path: None,
-            base_source: None,
-            base_dir: None,
-        })
+        if let Some(start) = stack.iter().position(|p| *p == base_path) {
+            let cycle: Vec<String> = stack[start..]
+                .iter()
+                .chain(std::iter::once(&base_path))
+                .map(|p| p.display().to_string())
+                .collect();
+            anyhow::bail!("extends cycle: {}", cycle.join(" -> "));
+        }
+        if bases.iter().any(|base| base.path == base_path) {
+            continue;
+        }
+
+        let base_source = fs::read_to_string(&base_path)
+            .with_context(|| format!("Failed to read base recipe: {}", base_path.display()))?;
+        stack.push(base_path.clone());
+        collect_bases(&base_path, &base_source, search_path, stack, bases)?;
+        stack.pop();
+        // BASE_RECIPE_DIRS is keyed by file stem, so stems must be unique.
+        let stem = crate::core::deps::recipe_name(&base_path);
+        if let Some(other) = bases
+            .iter()
+            .find(|base| crate::core::deps::recipe_name(&base.path) == stem)
+        {
+            anyhow::bail!(
+                "base recipes {} and {} share the name '{}'; rename one",
+                other.path.display(),
+                base_path.display(),
+                stem
+            );
+        }
+        bases.push(BaseRecipe {
+            path: base_path,
+            source: base_source,
+        });
+    }
+    Ok(())
+}
+
+/// Append the active ctx store's overlay for `recipe_path`, so it applies after
+/// the recipe's own top-level statements.
+fn apply_overlay(engine: &Engine, ast: &mut AST, recipe_path: &Path) -> Result<()> {
+    if let Some(overlay) = crate::core::ctx_store::overlay(recipe_path)? {
+        *ast += engine
+            .compile(crate::core::ctx::overlay_statement(
+                &overlay.set,
+                &overlay.removed,
+            ))
+            .map_err(|e| anyhow!("Failed to compile ctx state overlay: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Options for an install run.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InstallOptions<'a> {
+    pub persist_ctx: bool,
+    pub autofix: Option<&'a crate::AutoFixConfig>,
+    /// Allow install to overwrite files owned by other recipes.
+    pub allow_conflicts: bool,
+    /// Run acquire, build and install regardless of the `is_*` checks.
+    pub force: bool,
+    /// Install reason recorded in ctx on success.
+    pub reason: InstallReason,
+    /// Target root for the install phase; `None` is `/`.
+    pub sysroot: Option<&'a Path>,
+    /// ctx to run the lifecycle with instead of the recipe's own, e.g. an
+    /// upgrade's new version when ctx is not persisted.
+    pub ctx: Option<&'a rhai::Map>,
+}
+
+impl Default for InstallOptions<'_> {
+    fn default() -> Self {
+        Self {
+            persist_ctx: true,
+            autofix: None,
+            allow_conflicts: false,
+            force: false,
+            reason: InstallReason::Explicit,
+            sysroot: None,
+            ctx: None,
+        }
     }
 }
 
@@ -163,46 +302,70 @@ pub(crate) fn compile_recipe(
 /// 5. Persist ctx after each step
 ///
 /// Returns the final ctx map containing all recipe state.
-pub fn install(
+pub(crate) fn install_with_options(
     engine: &Engine,
     build_dir: &Path,
     recipe_path: &Path,
     defines: &[(String, String)],
-    persist_ctx: bool,
     search_path: Option<&Path>,
+    options: &InstallOptions,
 ) -> Result<rhai::Map> {
-    private::install(
-        engine,
-        build_dir,
-        recipe_path,
-        defines,
-        persist_ctx,
-        search_path,
-    )
+    offline::running(recipe_path, || {
+        private::install_with_options(
+            engine,
+            build_dir,
+            recipe_path,
+            defines,
+            search_path,
+            options,
+        )
+    })
 }
 
-pub(crate) fn install_with_options(
+/// Upgrade a recipe to the version reported by `check_update`
+///
+/// Reruns acquire, build and install for the new version in a fresh build
+/// directory; the old installation is kept until the new one succeeds.
+/// Returns `None` when the recipe has no `check_update` hook.
+pub(crate) fn upgrade(
     engine: &Engine,
     build_dir: &Path,
     recipe_path: &Path,
     defines: &[(String, String)],
-    persist_ctx: bool,
     search_path: Option<&Path>,
-    autofix: Option<&crate::AutoFixConfig>,
-) -> Result<rhai::Map> {
-    private::install_with_options(
-        engine,
-        build_dir,
-        recipe_path,
-        defines,
-        persist_ctx,
-        search_path,
-        autofix,
-    )
+    options: &InstallOptions,
+) -> Result<Option<crate::core::update::UpdateInfo>> {
+    offline::running(recipe_path, || {
+        private::upgrade(
+            engine,
+            build_dir,
+            recipe_path,
+            defines,
+            search_path,
+            options,
+        )
+    })
+}
+
+/// Plan an install without running any phase
+///
+/// Evaluates the recipe's checks and locates its dependencies, returning what
+/// `install` would do. Nothing is installed and ctx is not persisted.
+pub fn plan(
+    engine: &Engine,
+    build_dir: &Path,
+    recipe_path: &Path,
+    defines: &[(String, String)],
+    search_path: Option<&Path>,
+) -> Result<crate::core::plan::InstallPlan> {
+    offline::running(recipe_path, || {
+        private::plan(engine, build_dir, recipe_path, defines, search_path)
+    })
 }
 
 /// Remove an installed package
 ///
+/// `remove(ctx)` runs confined to `sysroot`, like `install(ctx)`.
 /// Returns the final ctx map after removal.
 pub fn remove(
     engine: &Engine,
@@ -210,13 +373,25 @@ pub fn remove(
     search_path: Option<&Path>,
     defines: &[(String, String)],
     persist_ctx: bool,
+    sysroot: Option<&Path>,
 ) -> Result<rhai::Map> {
-    private::remove(engine, recipe_path, search_path, defines, persist_ctx)
+    offline::running(recipe_path, || {
+        private::remove(
+            engine,
+            recipe_path,
+            search_path,
+            defines,
+            persist_ctx,
+            sysroot,
+        )
+    })
 }
 
 /// Clean up build artifacts
 ///
-/// Returns the final ctx map after cleanup.
+/// `cleanup(ctx, reason)` runs confined to `sysroot`, with `build_dir` left
+/// writable. Returns the final ctx map after cleanup.
+#[allow(clippy::too_many_arguments)]
 pub fn cleanup(
     engine: &Engine,
     build_dir: &Path,
@@ -225,16 +400,49 @@ pub fn cleanup(
     defines: &[(String, String)],
     reason: &str,
     persist_ctx: bool,
+    sysroot: Option<&Path>,
 ) -> Result<rhai::Map> {
-    private::cleanup(
-        engine,
-        build_dir,
-        recipe_path,
-        search_path,
-        defines,
-        reason,
-        persist_ctx,
-    )
+    offline::running(recipe_path, || {
+        private::cleanup(
+            engine,
+            build_dir,
+            recipe_path,
+            search_path,
+            defines,
+            reason,
+            persist_ctx,
+            sysroot,
+        )
+    })
+}
+
+/// Restore the recipe's persisted ctx to history entry `seq`
+///
+/// Returns the restored ctx. No hook runs.
+pub fn rollback_ctx(
+    engine: &Engine,
+    recipe_path: &Path,
+    search_path: Option<&Path>,
+    seq: u64,
+) -> Result<rhai::Map> {
+    offline::running(recipe_path, || {
+        private::rollback_ctx(engine, recipe_path, search_path, seq)
+    })
+}
+
+/// Run the recipe's `check_update` hook.
+///
+/// Returns `None` when the recipe defines no hook. Nothing is persisted.
+pub fn check_update(
+    engine: &Engine,
+    build_dir: &Path,
+    recipe_path: &Path,
+    search_path: Option<&Path>,
+    defines: &[(String, String)],
+) -> Result<Option<crate::core::update::UpdateInfo>> {
+    offline::running(recipe_path, || {
+        private::check_update(engine, build_dir, recipe_path, search_path, defines)
+    })
 }
 
 /// Execute `is_installed(ctx)` manually.
@@ -247,7 +455,9 @@ pub fn is_installed(
     search_path: Option<&Path>,
     defines: &[(String, String)],
 ) -> Result<rhai::Map> {
-    private::is_installed(engine, build_dir, recipe_path, search_path, defines)
+    offline::running(recipe_path, || {
+        private::is_installed(engine, build_dir, recipe_path, search_path, defines)
+    })
 }
 
 /// Execute `is_built(ctx)` manually.
@@ -260,7 +470,9 @@ pub fn is_built(
     search_path: Option<&Path>,
     defines: &[(String, String)],
 ) -> Result<rhai::Map> {
-    private::is_built(engine, build_dir, recipe_path, search_path, defines)
+    offline::running(recipe_path, || {
+        private::is_built(engine, build_dir, recipe_path, search_path, defines)
+    })
 }
 
 /// Execute `is_acquired(ctx)` manually.
@@ -273,5 +485,7 @@ pub fn is_acquired(
     search_path: Option<&Path>,
     defines: &[(String, String)],
 ) -> Result<rhai::Map> {
-    private::is_acquired(engine, build_dir, recipe_path, search_path, defines)
+    offline::running(recipe_path, || {
+        private::is_acquired(engine, build_dir, recipe_path, search_path, defines)
+    })
 }
@@ -1,11 +1,11 @@
 use crate::core::lock::acquire_recipe_lock;
-use crate::core::{output, runner};
+use crate::core::{manifest, orphans, output, runner};
 use anyhow::{Result, anyhow};
 use rhai::{Engine, Scope};
 use std::path::Path;
 
 use super::reporting::{report_phase_failure, report_phase_success};
-use super::state::{maybe_cleanup, persist_ctx};
+use super::state::{confined, maybe_cleanup, persist_ctx};
 use crate::core::executor::compile_recipe;
 
 pub(crate) fn remove(
@@ -14,6 +14,7 @@ pub(crate) fn remove(
     search_path: Option<&Path>,
     defines: &[(String, String)],
     persist_ctx_enabled: bool,
+    sysroot: Option<&Path>,
 ) -> Result<rhai::Map> {
     let recipe_path = recipe_path
         .canonicalize()
@@ -32,10 +33,7 @@ pub(crate) fn remove(
 
     let mut scope = Scope::new();
     scope.push_constant("RECIPE_DIR", recipe_dir);
-    if let Some(ref bd) = compiled.base_dir {
-        let base_dir = bd.to_string_lossy().to_string();
-        scope.push_constant("BASE_RECIPE_DIR", base_dir);
-    }
+    compiled.push_base_constants(&mut scope);
     for (key, value) in defines {
         scope.push_constant(key.as_str(), value.clone());
     }
@@ -52,23 +50,82 @@ pub(crate) fn remove(
         .and_then(|v| v.clone().into_string().ok())
         .unwrap_or_else(|| "package".to_string());
 
-    if !runner::has_fn(&ast, "remove") {
+    let has_remove = runner::has_fn(&ast, "remove");
+    if !has_remove && manifest::from_ctx(&ctx_map).is_empty() {
         output::hook_event(&name, "remove", "missing", "required remove hook missing");
-        return Err(anyhow!("{} has no remove function", name));
+        return Err(anyhow!(
+            "{} has no remove function and no installed-file manifest",
+            name
+        ));
     }
 
     output::action(&format!("Removing {}", name));
-    output::sub_action("remove");
-    output::hook_event(&name, "remove", "running", "executing recipe hook");
 
-    ctx_map = runner::run_phase(engine, &ast, &mut scope, "remove", ctx_map).inspect_err(|e| {
-        report_phase_failure(&name, "remove", e);
-    })?;
-    report_phase_success(&name, "remove");
+    if has_remove {
+        output::sub_action("remove");
+        output::hook_event(&name, "remove", "running", "executing recipe hook");
+
+        // Like install(), remove() only reaches files inside the sysroot.
+        ctx_map = confined(sysroot, &[], || {
+            runner::run_phase(engine, &ast, &mut scope, "remove", ctx_map)
+        })
+        .inspect_err(|e| {
+            report_phase_failure(&name, "remove", e);
+        })?;
+        report_phase_success(&name, "remove");
+    }
+
+    // Without remove(), delete what the installed-file manifest lists. A custom
+    // remove() owns removal and may keep files on purpose.
+    let installed = manifest::from_ctx(&ctx_map);
+    if !has_remove && !installed.is_empty() {
+        output::sub_action("remove installed files");
+        output::hook_event(
+            &name,
+            "remove.files",
+            "running",
+            "deleting files from installed-file manifest",
+        );
+        let remaining = manifest::uninstall(&installed);
+        manifest::store(&mut ctx_map, &remaining);
+        if !remaining.files.is_empty() {
+            // Keep `installed` as-is and persist what is still on disk.
+            if persist_ctx_enabled {
+                persist_ctx(
+                    &mut compiled,
+                    &ctx_map,
+                    "remove",
+                    "partial",
+                    "Failed to persist ctx after partial remove",
+                )?;
+            }
+            let err = anyhow!(
+                "{} files could not be removed: {}",
+                remaining.files.len(),
+                remaining
+                    .files
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            report_phase_failure(&name, "remove.files", &err);
+            return Err(err);
+        }
+        report_phase_success(&name, "remove.files");
+        if ctx_map.contains_key("installed") {
+            ctx_map.insert("installed".into(), false.into());
+        }
+    }
+
+    ctx_map.remove(orphans::INSTALL_REASON);
+
     if persist_ctx_enabled {
         persist_ctx(
             &mut compiled,
             &ctx_map,
+            "remove",
+            "success",
             "Failed to persist ctx after remove",
         )?;
     }
@@ -80,6 +137,7 @@ pub(crate) fn remove(
 /// Clean up build artifacts
 ///
 /// Returns the final ctx map after cleanup.
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn cleanup(
     engine: &Engine,
     build_dir: &Path,
@@ -88,6 +146,7 @@ pub(crate) fn cleanup(
     defines: &[(String, String)],
     reason: &str,
     persist_ctx_enabled: bool,
+    sysroot: Option<&Path>,
 ) -> Result<rhai::Map> {
     let recipe_path = recipe_path
         .canonicalize()
@@ -106,10 +165,7 @@ pub(crate) fn cleanup(
 
     let mut scope = Scope::new();
     scope.push_constant("RECIPE_DIR", recipe_dir);
-    if let Some(ref bd) = compiled.base_dir {
-        let base_dir = bd.to_string_lossy().to_string();
-        scope.push_constant("BASE_RECIPE_DIR", base_dir);
-    }
+    compiled.push_base_constants(&mut scope);
     scope.push_constant("BUILD_DIR", build_dir.to_string_lossy().to_string());
     for (key, value) in defines {
         scope.push_constant(key.as_str(), value.clone());
@@ -136,10 +192,12 @@ pub(crate) fn cleanup(
     output::sub_action("cleanup");
     output::hook_event(&name, "cleanup", "running", "executing recipe hook");
 
-    ctx_map = maybe_cleanup(
-        engine, &ast, &mut scope, ctx_map, reason, /* best_effort */ false,
-        /* require_defined */ true,
-    )
+    ctx_map = confined(sysroot, &[build_dir.to_path_buf()], || {
+        maybe_cleanup(
+            engine, &ast, &mut scope, ctx_map, reason, /* best_effort */ false,
+            /* require_defined */ true,
+        )
+    })
     .inspect_err(|e| {
         report_phase_failure(&name, "cleanup", e);
     })?;
@@ -148,6 +206,8 @@ pub(crate) fn cleanup(
         persist_ctx(
             &mut compiled,
             &ctx_map,
+            "cleanup",
+            "success",
             "Failed to persist ctx after cleanup",
         )?;
     }
@@ -241,10 +301,7 @@ pub(crate) fn run_check(
 
     let mut scope = Scope::new();
     scope.push_constant("RECIPE_DIR", recipe_dir);
-    if let Some(ref bd) = compiled.base_dir {
-        let base_dir = bd.to_string_lossy().to_string();
-        scope.push_constant("BASE_RECIPE_DIR", base_dir);
-    }
+    compiled.push_base_constants(&mut scope);
     scope.push_constant("BUILD_DIR", build_dir.to_string_lossy().to_string());
     for (key, value) in defines {
         scope.push_constant(key.clone(), value.clone());
@@ -2,8 +2,10 @@ use anyhow::{Context, Result};
 use rhai::Engine;
 use std::path::Path;
 
+use crate::core::executor::InstallOptions;
 use crate::core::lock::acquire_recipe_lock;
 use crate::core::output;
+use crate::core::schema::SchemaViolation;
 
 use super::flow::install_once;
 
@@ -17,23 +19,19 @@ pub(crate) enum InstallAttemptError {
     },
 }
 
-pub(crate) fn install(
-    engine: &Engine,
-    build_dir: &Path,
-    recipe_path: &Path,
-    defines: &[(String, String)],
-    persist_ctx: bool,
-    search_path: Option<&Path>,
-) -> Result<rhai::Map> {
-    install_with_options(
-        engine,
-        build_dir,
-        recipe_path,
-        defines,
-        persist_ctx,
-        search_path,
-        None,
-    )
+impl InstallAttemptError {
+    /// Failure of `phase`. A ctx schema violation is fatal instead: it is a
+    /// contract error, not something autofix should patch.
+    pub(crate) fn phase(reason: &'static str, phase: &'static str, error: anyhow::Error) -> Self {
+        if error.is::<SchemaViolation>() {
+            return Self::Fatal(error);
+        }
+        Self::Phase {
+            reason,
+            phase,
+            error,
+        }
+    }
 }
 
 pub(crate) fn install_with_options(
@@ -41,10 +39,10 @@ pub(crate) fn install_with_options(
     build_dir: &Path,
     recipe_path: &Path,
     defines: &[(String, String)],
-    persist_ctx: bool,
     search_path: Option<&Path>,
-    autofix: Option<&crate::AutoFixConfig>,
+    options: &InstallOptions,
 ) -> Result<rhai::Map> {
+    let autofix = options.autofix;
     let recipe_path = recipe_path
         .canonicalize()
         .unwrap_or_else(|_| recipe_path.to_path_buf());
@@ -59,9 +57,8 @@ pub(crate) fn install_with_options(
             build_dir,
             &recipe_path,
             defines,
-            persist_ctx,
             search_path,
-            autofix,
+            options,
         ) {
             Ok(ctx) => return Ok(ctx),
             Err(InstallAttemptError::Fatal(e)) => {
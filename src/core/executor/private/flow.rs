@@ -1,14 +1,23 @@
-use crate::core::executor::compile_recipe;
-use crate::core::output;
+use crate::core::deps::recipe_name;
+use crate::core::executor::{InstallOptions, compile_recipe};
+use crate::core::orphans;
 use crate::core::runner;
+use crate::core::schema::CtxSchema;
+use crate::core::stage;
+use crate::core::{manifest, output};
+use crate::helpers::internal::tracking::Claims;
 use anyhow::anyhow;
-use rhai::{Engine, Scope};
+use rhai::Engine;
 use std::path::Path;
 
 use super::{
     attempt::InstallAttemptError,
-    reporting::{friendly_reason, report_check_result, report_phase_failure, report_phase_success},
-    state::{check_phase_with_reason, maybe_cleanup, persist_ctx, resolve_deps},
+    reporting::{friendly_reason, report_phase_failure, report_phase_success},
+    state::{
+        CheckChain, StagedInstall, check_phase_with_reason, install_scope, maybe_cleanup,
+        other_recipe_claims, persist_ctx, resolve_deps, run_check_chain, scope_string_array,
+        staged_install,
+    },
 };
 
 pub(crate) fn install_once(
@@ -16,36 +25,16 @@ pub(crate) fn install_once(
     build_dir: &Path,
     recipe_path: &Path,
     defines: &[(String, String)],
-    persist_ctx_enabled: bool,
     search_path: Option<&Path>,
-    autofix: Option<&crate::AutoFixConfig>,
+    options: &InstallOptions,
 ) -> std::result::Result<rhai::Map, InstallAttemptError> {
-    let autofix_enabled = autofix.is_some();
+    let persist_ctx_enabled = options.persist_ctx;
+    let autofix_enabled = options.autofix.is_some();
     let mut compiled =
         compile_recipe(engine, recipe_path, search_path).map_err(InstallAttemptError::Fatal)?;
     let ast = compiled.ast.clone();
 
-    // Derive RECIPE_DIR from the recipe file's parent directory
-    let recipe_dir = recipe_path
-        .parent()
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_else(|| ".".to_string());
-
-    // Set up scope with constants
-    let mut scope = Scope::new();
-    scope.push_constant("RECIPE_DIR", recipe_dir);
-    if let Some(ref bd) = compiled.base_dir {
-        scope.push_constant("BASE_RECIPE_DIR", bd.to_string_lossy().to_string());
-    }
-    scope.push_constant("BUILD_DIR", build_dir.to_string_lossy().to_string());
-    scope.push_constant("ARCH", std::env::consts::ARCH);
-    scope.push_constant("NPROC", num_cpus::get() as i64);
-    scope.push_constant("RPM_PATH", std::env::var("RPM_PATH").unwrap_or_default());
-
-    // Inject user-defined constants (from --define KEY=VALUE)
-    for (key, value) in defines {
-        scope.push_constant(key.as_str(), value.clone());
-    }
+    let mut scope = install_scope(&compiled, build_dir, defines);
 
     // Run script to populate scope (this sets up ctx)
     engine
@@ -56,6 +45,10 @@ pub(crate) fn install_once(
     let mut ctx_map: rhai::Map = scope.get_value("ctx").ok_or_else(|| {
         InstallAttemptError::Fatal(anyhow!("Recipe missing 'let ctx = #{{...}}'"))
     })?;
+    if let Some(ctx) = options.ctx {
+        ctx_map = ctx.clone();
+        scope.set_value("ctx", ctx_map.clone());
+    }
 
     // Get package name for logging
     let name = ctx_map
@@ -69,61 +62,55 @@ pub(crate) fn install_once(
                 .to_string()
         });
 
+    // Optional `ctx_schema`: checked now and after every phase.
+    let schema = CtxSchema::from_scope(&scope).map_err(|e| {
+        InstallAttemptError::Fatal(e.context(format!("{name}: invalid ctx_schema")))
+    })?;
+    let check_ctx = |phase: &str, ctx: rhai::Map| match &schema {
+        Some(schema) => schema.check(&name, phase, &ctx).map(|()| ctx),
+        None => Ok(ctx),
+    };
+    ctx_map = check_ctx("top-level script", ctx_map).map_err(InstallAttemptError::Fatal)?;
+
     output::action(&format!("Preparing recipe for {}", name));
     output::hook_event(&name, "prepare", "running", "starting recipe execution");
     output::detail(&format!("Recipe: {}", recipe_path.display()));
-    if let Some(base_path) = &compiled.base_path {
-        output::detail(&format!("Extends base recipe: {}", base_path.display()));
+    for base in &compiled.bases {
+        output::detail(&format!("Extends base recipe: {}", base.path.display()));
     }
 
-    // Check steps (reverse order) - throw means "needs this step".
-    //
-    // IMPORTANT: `is_*` checks may also *return an updated ctx* (e.g. setting
-    // `ctx.source_path` or `ctx.build_dir`). When the check passes, we must carry
-    // the returned ctx forward even if the phase is skipped.
-    let (needs_install, checked_ctx, needs_install_reason) =
-        check_phase_with_reason(engine, &ast, &scope, "is_installed", &ctx_map);
-    report_check_result(
-        &name,
-        "is_installed",
-        needs_install,
-        needs_install_reason.as_deref(),
+    let checks = if options.force {
+        CheckChain::forced(ctx_map)
+    } else {
+        run_check_chain(engine, &ast, &scope, &name, ctx_map)
+    };
+    let planned = checks.planned_phases(&ast);
+    let (needs_install, needs_build, needs_acquire) = (
+        checks.needs_install,
+        checks.needs_build,
+        checks.needs_acquire,
     );
-    ctx_map = checked_ctx;
+    ctx_map = checks.ctx;
 
-    let mut needs_build = false;
-    let mut needs_acquire = false;
     if needs_install {
-        let (nb, checked_ctx, build_reason) =
-            check_phase_with_reason(engine, &ast, &scope, "is_built", &ctx_map);
-        report_check_result(&name, "is_built", nb, build_reason.as_deref());
-        needs_build = nb;
-        ctx_map = checked_ctx;
-
-        if needs_build {
-            let (na, checked_ctx, acquire_reason) =
-                check_phase_with_reason(engine, &ast, &scope, "is_acquired", &ctx_map);
-            report_check_result(&name, "is_acquired", na, acquire_reason.as_deref());
-            needs_acquire = na;
-            ctx_map = checked_ctx;
-        }
-    }
-
-    if needs_install {
-        let mut planned = Vec::new();
-        if needs_acquire {
-            planned.push("acquire");
-        }
-        if needs_build && runner::has_fn(&ast, "build") {
-            planned.push("build");
-        }
-        planned.push("install");
         output::detail(&format!("Recipe flow: {}", planned.join(" → ")));
     }
 
     let cleanup_auto_supported = runner::has_fn_arity(&ast, "cleanup", 2);
 
     if !needs_install {
+        // Asking for an installed recipe by name makes it explicit, even if it
+        // was pulled in as a dependency.
+        if orphans::mark(&mut ctx_map, options.reason) && persist_ctx_enabled {
+            persist_ctx(
+                &mut compiled,
+                &ctx_map,
+                "mark",
+                "success",
+                "Failed to persist ctx after marking explicit",
+            )
+            .map_err(InstallAttemptError::Fatal)?;
+        }
         output::hook_event(&name, "install", "skipped", "already installed");
         output::detail("All checks passed; nothing to do.");
         output::skip(&format!("{} already installed, skipping", name));
@@ -156,23 +143,9 @@ pub(crate) fn install_once(
     // Resolve dependencies declared in scope.
     // - `deps`: resolved before all phases (tools needed for acquire/build/install)
     // - `build_deps`: resolved only before build phase (compile-time tools)
-    let deps: Vec<String> = scope
-        .get_value::<rhai::Array>("deps")
-        .map(|arr| {
-            arr.iter()
-                .filter_map(|v| v.clone().into_string().ok())
-                .collect()
-        })
-        .unwrap_or_default();
-
-    let build_deps: Vec<String> = scope
-        .get_value::<rhai::Array>("build_deps")
-        .map(|arr| {
-            arr.iter()
-                .filter_map(|v| v.clone().into_string().ok())
-                .collect()
-        })
-        .unwrap_or_default();
+    let deps = scope_string_array(&scope, "deps");
+    let build_deps = scope_string_array(&scope, "build_deps");
+    let dep_root = recipe_name(&compiled.recipe_path);
 
     if deps.is_empty() {
         output::detail("No runtime dependency recipes declared (`deps`).");
@@ -192,8 +165,16 @@ pub(crate) fn install_once(
     // Resolve `deps` immediately (needed for all phases)
     let _env_guard = if !deps.is_empty() {
         Some(
-            resolve_deps(engine, build_dir, search_path, defines, &deps, autofix)
-                .map_err(InstallAttemptError::Fatal)?,
+            resolve_deps(
+                engine,
+                build_dir,
+                search_path,
+                defines,
+                &dep_root,
+                &deps,
+                options,
+            )
+            .map_err(InstallAttemptError::Fatal)?,
         )
     } else {
         None
@@ -208,7 +189,9 @@ pub(crate) fn install_once(
         output::detail("Checking/refreshing source artifacts");
         output::hook_event(&name, "acquire", "running", "executing recipe hook");
         let ctx_before = ctx_map.clone();
-        match runner::run_phase(engine, &ast, &mut scope, "acquire", ctx_map) {
+        match runner::run_phase(engine, &ast, &mut scope, "acquire", ctx_map)
+            .and_then(|c| check_ctx("acquire", c))
+        {
             Ok(new_ctx) => {
                 ctx_map = new_ctx;
                 report_phase_success(&name, "acquire");
@@ -216,6 +199,8 @@ pub(crate) fn install_once(
                     persist_ctx(
                         &mut compiled,
                         &ctx_map,
+                        "acquire",
+                        "success",
                         "Failed to persist ctx after acquire",
                     )
                     .map_err(InstallAttemptError::Fatal)?;
@@ -237,6 +222,8 @@ pub(crate) fn install_once(
                         persist_ctx(
                             &mut compiled,
                             &ctx_map,
+                            "cleanup",
+                            "success",
                             "Failed to persist ctx after cleanup",
                         )
                         .map_err(InstallAttemptError::Fatal)?;
@@ -257,11 +244,11 @@ pub(crate) fn install_once(
                         /* require_defined */ false,
                     );
                 }
-                return Err(InstallAttemptError::Phase {
-                    reason: "auto.acquire.failure",
-                    phase: "acquire",
-                    error: e,
-                });
+                return Err(InstallAttemptError::phase(
+                    "auto.acquire.failure",
+                    "acquire",
+                    e,
+                ));
             }
         }
     }
@@ -269,8 +256,11 @@ pub(crate) fn install_once(
     if needs_build && runner::has_fn(&ast, "build") {
         // Re-check: does the recipe still need building after acquire ran?
         // (acquire may have updated ctx such that is_built now passes)
-        let (still_needs_build, checked_ctx, post_acquire_build_reason) =
-            check_phase_with_reason(engine, &ast, &scope, "is_built", &ctx_map);
+        let (still_needs_build, checked_ctx, post_acquire_build_reason) = if options.force {
+            (true, ctx_map.clone(), None)
+        } else {
+            check_phase_with_reason(engine, &ast, &scope, "is_built", &ctx_map)
+        };
         if let Some(reason) = post_acquire_build_reason {
             output::detail(&friendly_reason(
                 "is_built (post acquire)",
@@ -288,8 +278,9 @@ pub(crate) fn install_once(
                     build_dir,
                     search_path,
                     defines,
+                    &dep_root,
                     &build_deps,
-                    autofix,
+                    options,
                 )
                 .map_err(InstallAttemptError::Fatal)?,
             )
@@ -302,13 +293,21 @@ pub(crate) fn install_once(
             output::detail("Compiling or assembling build products");
             output::hook_event(&name, "build", "running", "executing recipe hook");
             let ctx_before = ctx_map.clone();
-            match runner::run_phase(engine, &ast, &mut scope, "build", ctx_map) {
+            match runner::run_phase(engine, &ast, &mut scope, "build", ctx_map)
+                .and_then(|c| check_ctx("build", c))
+            {
                 Ok(new_ctx) => {
                     ctx_map = new_ctx;
                     report_phase_success(&name, "build");
                     if persist_ctx_enabled {
-                        persist_ctx(&mut compiled, &ctx_map, "Failed to persist ctx after build")
-                            .map_err(InstallAttemptError::Fatal)?;
+                        persist_ctx(
+                            &mut compiled,
+                            &ctx_map,
+                            "build",
+                            "success",
+                            "Failed to persist ctx after build",
+                        )
+                        .map_err(InstallAttemptError::Fatal)?;
                     }
 
                     if cleanup_auto_supported {
@@ -326,6 +325,8 @@ pub(crate) fn install_once(
                             persist_ctx(
                                 &mut compiled,
                                 &ctx_map,
+                                "cleanup",
+                                "success",
                                 "Failed to persist ctx after cleanup",
                             )
                             .map_err(InstallAttemptError::Fatal)?;
@@ -345,11 +346,7 @@ pub(crate) fn install_once(
                             /* require_defined */ false,
                         );
                     }
-                    return Err(InstallAttemptError::Phase {
-                        reason: "auto.build.failure",
-                        phase: "build",
-                        error: e,
-                    });
+                    return Err(InstallAttemptError::phase("auto.build.failure", "build", e));
                 }
             }
         }
@@ -360,7 +357,36 @@ pub(crate) fn install_once(
         output::detail("Applying package files to destination");
         output::hook_event(&name, "install", "running", "executing recipe hook");
         let ctx_before = ctx_map.clone();
-        match runner::run_phase(engine, &ast, &mut scope, "install", ctx_map) {
+        let stage_dir = stage::stage_dir(build_dir);
+        let root = options.sysroot.unwrap_or(Path::new("/"));
+        stage::prepare(&stage_dir).map_err(InstallAttemptError::Fatal)?;
+        // Files owned by other recipes may not be overwritten: helpers refuse
+        // them while install() runs, and the staged tree is checked again
+        // before commit.
+        let claims = if options.allow_conflicts {
+            Claims::default()
+        } else {
+            other_recipe_claims(&compiled, search_path, &stage_dir, root)
+        };
+        // A violating install() must not reach the target root.
+        let scratch = [build_dir.to_path_buf()];
+        let install = StagedInstall {
+            name: &name,
+            stage_dir: &stage_dir,
+            root,
+            scratch: &scratch,
+            sysroot: options.sysroot,
+            claims,
+        };
+        let result = staged_install(engine, &ast, &mut scope, ctx_map, install, |new_ctx| {
+            check_ctx("install", new_ctx)
+        })
+        .map(|(mut new_ctx, installed)| {
+            manifest::store(&mut new_ctx, &installed);
+            orphans::mark(&mut new_ctx, options.reason);
+            new_ctx
+        });
+        match result {
             Ok(new_ctx) => {
                 ctx_map = new_ctx;
                 report_phase_success(&name, "install");
@@ -368,6 +394,8 @@ pub(crate) fn install_once(
                     persist_ctx(
                         &mut compiled,
                         &ctx_map,
+                        "install",
+                        "success",
                         "Failed to persist ctx after install",
                     )
                     .map_err(InstallAttemptError::Fatal)?;
@@ -388,6 +416,8 @@ pub(crate) fn install_once(
                         persist_ctx(
                             &mut compiled,
                             &ctx_map,
+                            "cleanup",
+                            "success",
                             "Failed to persist ctx after cleanup",
                         )
                         .map_err(InstallAttemptError::Fatal)?;
@@ -407,11 +437,11 @@ pub(crate) fn install_once(
                         /* require_defined */ false,
                     );
                 }
-                return Err(InstallAttemptError::Phase {
-                    reason: "auto.install.failure",
-                    phase: "install",
-                    error: e,
-                });
+                return Err(InstallAttemptError::phase(
+                    "auto.install.failure",
+                    "install",
+                    e,
+                ));
             }
         }
     }
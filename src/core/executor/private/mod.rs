@@ -1,11 +1,23 @@
 mod actions;
 mod attempt;
 mod flow;
+mod plan;
 mod reporting;
+mod rollback;
 mod state;
+mod update;
+mod upgrade;
 
 #[cfg(test)]
 mod tests;
 
 pub(crate) use actions::{cleanup, is_acquired, is_built, is_installed, remove};
-pub(crate) use attempt::{install, install_with_options};
+pub(crate) use attempt::install_with_options;
+pub(crate) use plan::plan;
+pub(crate) use rollback::rollback_ctx;
+pub(crate) use state::{
+    StagedInstall, install_scope, other_recipe_claims, persist_ctx, scope_string_array,
+    staged_install,
+};
+pub(crate) use update::check_update;
+pub(crate) use upgrade::upgrade;
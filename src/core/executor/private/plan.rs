@@ -0,0 +1,99 @@
+use crate::core::build_deps::BuildDepsResolver;
+use crate::core::deps::recipe_name;
+use crate::core::executor::compile_recipe;
+use crate::core::lock::acquire_recipe_lock;
+use crate::core::output;
+use crate::core::plan::InstallPlan;
+use anyhow::{Result, anyhow};
+use rhai::Engine;
+use std::path::Path;
+
+use super::state::{install_scope, run_check_chain, scope_string_array};
+
+/// Evaluate what `install` would do without running any phase.
+///
+/// Runs the recipe top-level and the `is_*` check chain exactly like the
+/// install flow, then locates and checks (but never installs) `deps` and,
+/// when a build is planned, `build_deps`. Nothing is persisted.
+pub(crate) fn plan(
+    engine: &Engine,
+    build_dir: &Path,
+    recipe_path: &Path,
+    defines: &[(String, String)],
+    search_path: Option<&Path>,
+) -> Result<InstallPlan> {
+    let recipe_path = recipe_path
+        .canonicalize()
+        .unwrap_or_else(|_| recipe_path.to_path_buf());
+
+    let _lock = acquire_recipe_lock(&recipe_path)?;
+
+    let compiled = compile_recipe(engine, &recipe_path, search_path)?;
+    let ast = compiled.ast.clone();
+    let mut scope = install_scope(&compiled, build_dir, defines);
+
+    engine
+        .run_ast_with_scope(&mut scope, &ast)
+        .map_err(|e| anyhow!("Failed to run recipe: {}", e))?;
+
+    let ctx_map: rhai::Map = scope
+        .get_value("ctx")
+        .ok_or_else(|| anyhow!("Recipe missing 'let ctx = #{{...}}'"))?;
+
+    let name = ctx_map
+        .get("name")
+        .and_then(|v| v.clone().into_string().ok())
+        .unwrap_or_else(|| {
+            recipe_path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        });
+
+    output::action(&format!("Planning install for {}", name));
+    output::hook_event(&name, "plan", "running", "evaluating checks only");
+    output::detail(&format!("Recipe: {}", recipe_path.display()));
+
+    let checks = run_check_chain(engine, &ast, &scope, &name, ctx_map);
+    let phases = checks.planned_phases(&ast);
+
+    let resolver = BuildDepsResolver::new(engine, build_dir, search_path, defines, None);
+    let root = recipe_name(&recipe_path);
+    let (deps, build_deps) = if checks.needs_install {
+        let deps = resolver.plan(&root, &scope_string_array(&scope, "deps"))?;
+        let build_deps = if phases.contains(&"build") {
+            resolver.plan(&root, &scope_string_array(&scope, "build_deps"))?
+        } else {
+            Vec::new()
+        };
+        (deps, build_deps)
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
+    if checks.needs_install {
+        output::detail(&format!("Recipe flow: {}", phases.join(" → ")));
+        for dep in deps.iter().chain(build_deps.iter()) {
+            let state = if dep.needs_install {
+                "would be installed"
+            } else {
+                "already installed"
+            };
+            output::detail(&format!("Dependency {}: {}", dep.name, state));
+        }
+    } else {
+        output::detail("All checks passed; nothing to do.");
+    }
+    output::hook_event(&name, "plan", "success", "plan complete");
+
+    Ok(InstallPlan {
+        name,
+        recipe_path,
+        needs_install: checks.needs_install,
+        checks: checks.checks,
+        phases: phases.into_iter().map(str::to_string).collect(),
+        deps,
+        build_deps,
+    })
+}
@@ -0,0 +1,42 @@
+use crate::core::executor::compile_recipe;
+use crate::core::history;
+use crate::core::lock::acquire_recipe_lock;
+use crate::core::output;
+use anyhow::Result;
+use rhai::Engine;
+use std::path::Path;
+
+use super::state::persist_ctx;
+
+/// Restore the ctx snapshot of history entry `seq`.
+///
+/// Only persisted ctx changes: no hook runs and nothing is installed or
+/// removed. The restore is itself recorded as a `rollback` history entry.
+pub(crate) fn rollback_ctx(
+    engine: &Engine,
+    recipe_path: &Path,
+    search_path: Option<&Path>,
+    seq: u64,
+) -> Result<rhai::Map> {
+    let recipe_path = recipe_path
+        .canonicalize()
+        .unwrap_or_else(|_| recipe_path.to_path_buf());
+
+    let _lock = acquire_recipe_lock(&recipe_path)?;
+
+    let entries = history::entries(&recipe_path)?;
+    let ctx_map = history::snapshot(&entries, seq)?;
+    let mut compiled = compile_recipe(engine, &recipe_path, search_path)?;
+    persist_ctx(
+        &mut compiled,
+        &ctx_map,
+        "rollback",
+        &format!("restored #{seq}"),
+        "Failed to persist rolled back ctx",
+    )?;
+    output::success(&format!(
+        "{}: ctx restored to history entry {seq}",
+        recipe_path.display()
+    ));
+    Ok(ctx_map)
+}
@@ -1,11 +1,147 @@
-use crate::core::executor::CompiledRecipe;
-use crate::core::{build_deps, ctx, output};
+use crate::core::executor::{CompiledRecipe, InstallOptions};
+use crate::core::ownership::OwnershipIndex;
+use crate::core::plan::PlannedCheck;
+use crate::core::{build_deps, ctx, ctx_store, history, output, runner, stage};
+use crate::helpers::internal::sysroot;
+use crate::helpers::internal::tracking::{self, Claims, Manifest};
 use anyhow::{Context, Result, anyhow};
 use rhai::{AST, Engine, Scope};
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
+use super::reporting::report_check_result;
+
+/// Build the scope used by the install flow: path constants, host facts, and
+/// user defines.
+///
+/// `STAGE_DIR` is only created (fresh) right before the install phase runs.
+pub(crate) fn install_scope(
+    compiled: &CompiledRecipe,
+    build_dir: &Path,
+    defines: &[(String, String)],
+) -> Scope<'static> {
+    // Derive RECIPE_DIR from the recipe file's parent directory
+    let recipe_dir = compiled
+        .recipe_path
+        .parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|| ".".to_string());
+
+    let mut scope = Scope::new();
+    scope.push_constant("RECIPE_DIR", recipe_dir);
+    compiled.push_base_constants(&mut scope);
+    scope.push_constant("BUILD_DIR", build_dir.to_string_lossy().to_string());
+    scope.push_constant(
+        "STAGE_DIR",
+        stage::stage_dir(build_dir).to_string_lossy().to_string(),
+    );
+    scope.push_constant("ARCH", std::env::consts::ARCH);
+    scope.push_constant("NPROC", num_cpus::get() as i64);
+    scope.push_constant("RPM_PATH", std::env::var("RPM_PATH").unwrap_or_default());
+
+    // Inject user-defined constants (from --define KEY=VALUE)
+    for (key, value) in defines {
+        scope.push_constant(key.as_str(), value.clone());
+    }
+    scope
+}
+
+/// Read a top-level string array (e.g. `deps`, `build_deps`) from scope.
+pub(crate) fn scope_string_array(scope: &Scope, key: &str) -> Vec<String> {
+    scope
+        .get_value::<rhai::Array>(key)
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.clone().into_string().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Outcome of the `is_installed` → `is_built` → `is_acquired` check chain.
+pub(crate) struct CheckChain {
+    pub needs_install: bool,
+    pub needs_build: bool,
+    pub needs_acquire: bool,
+    /// ctx as returned by the last passing check.
+    pub ctx: rhai::Map,
+    pub checks: Vec<PlannedCheck>,
+}
+
+impl CheckChain {
+    /// A chain that needs every phase, for forced reinstalls.
+    pub(crate) fn forced(ctx: rhai::Map) -> Self {
+        Self {
+            needs_install: true,
+            needs_build: true,
+            needs_acquire: true,
+            ctx,
+            checks: Vec::new(),
+        }
+    }
+
+    /// Phases the install flow would run for this outcome.
+    pub(crate) fn planned_phases(&self, ast: &AST) -> Vec<&'static str> {
+        let mut planned = Vec::new();
+        if !self.needs_install {
+            return planned;
+        }
+        if self.needs_acquire {
+            planned.push("acquire");
+        }
+        if self.needs_build && runner::has_fn(ast, "build") {
+            planned.push("build");
+        }
+        planned.push("install");
+        planned
+    }
+}
+
+/// Evaluate checks in reverse phase order - a throw means "needs this step".
+///
+/// `is_built` only runs when install is needed, and `is_acquired` only when
+/// build is needed.
+pub(crate) fn run_check_chain(
+    engine: &Engine,
+    ast: &AST,
+    scope: &Scope,
+    name: &str,
+    ctx: rhai::Map,
+) -> CheckChain {
+    // IMPORTANT: `is_*` checks may also *return an updated ctx* (e.g. setting
+    // `ctx.source_path` or `ctx.build_dir`). When the check passes, we must carry
+    // the returned ctx forward even if the phase is skipped.
+    let mut chain = CheckChain {
+        needs_install: false,
+        needs_build: false,
+        needs_acquire: false,
+        ctx,
+        checks: Vec::new(),
+    };
+
+    for check in ["is_installed", "is_built", "is_acquired"] {
+        let (needs, checked_ctx, reason) =
+            check_phase_with_reason(engine, ast, scope, check, &chain.ctx);
+        report_check_result(name, check, needs, reason.as_deref());
+        chain.ctx = checked_ctx;
+        chain.checks.push(PlannedCheck {
+            check: check.to_string(),
+            needs_work: needs,
+            reason,
+        });
+        match check {
+            "is_installed" => chain.needs_install = needs,
+            "is_built" => chain.needs_build = needs,
+            _ => chain.needs_acquire = needs,
+        }
+        if !needs {
+            break;
+        }
+    }
+    chain
+}
+
 /// Check whether a step is needed (true when the check throws).
 ///
 /// If the check passes, returns the ctx value that the check returned.
@@ -69,43 +205,83 @@ pub(crate) fn maybe_cleanup(
     }
 }
 
+/// Persist ctx through the active ctx store and record it in the recipe's
+/// history journal as `phase`/`outcome`.
 pub(crate) fn persist_ctx(
+    compiled: &mut CompiledRecipe,
+    ctx_map: &rhai::Map,
+    phase: &str,
+    outcome: &str,
+    err_ctx: &'static str,
+) -> Result<()> {
+    let previous = compiled
+        .ctx_source()
+        .and_then(|source| ctx_store::persisted(&compiled.recipe_path, source));
+
+    write_ctx(compiled, ctx_map, err_ctx)?;
+
+    if let Err(e) = history::record(
+        &compiled.recipe_path,
+        phase,
+        outcome,
+        previous.as_ref(),
+        ctx_map,
+    ) {
+        output::warning(&format!("failed to record ctx history: {e:#}"));
+    }
+    Ok(())
+}
+
+fn write_ctx(
     compiled: &mut CompiledRecipe,
     ctx_map: &rhai::Map,
     err_ctx: &'static str,
 ) -> Result<()> {
+    if let Some(state_file) = ctx_store::state_file(&compiled.recipe_path) {
+        let literal = compiled.ctx_source().and_then(ctx::parse_literal);
+        return ctx_store::save(&state_file, literal.as_ref(), ctx_map).with_context(|| err_ctx);
+    }
+
     // Prefer persisting to the main recipe. If it doesn't declare ctx (common when
-    // using `//! extends:` for shared logic), fall back to the base recipe.
+    // using `//! extends:` for shared logic), fall back to the base recipe whose
+    // ctx literal it inherits.
+    let ctx_base = compiled.ctx_base();
     let (path, source): (&Path, &mut String) =
         if ctx::find_ctx_block(&compiled.recipe_source).is_some() {
             (&compiled.recipe_path, &mut compiled.recipe_source)
-        } else if let (Some(base_path), Some(base_source)) =
-            (&compiled.base_path, &mut compiled.base_source)
-        {
-            if ctx::find_ctx_block(base_source).is_some() {
-                (base_path, base_source)
-            } else {
-                return Err(anyhow!(
-                    "ctx block not found in recipe {} or base {:?}",
-                    compiled.recipe_path.display(),
-                    base_path.display()
-                ))
-                .with_context(|| err_ctx);
-            }
-        } else {
+        } else if let Some(i) = ctx_base {
+            let base = &mut compiled.bases[i];
+            (&base.path, &mut base.source)
+        } else if compiled.bases.is_empty() {
             return Err(anyhow!(
                 "ctx block not found in recipe {} (no base recipe)",
                 compiled.recipe_path.display()
             ))
             .with_context(|| err_ctx);
+        } else {
+            let bases: Vec<String> = compiled
+                .bases
+                .iter()
+                .map(|base| base.path.display().to_string())
+                .collect();
+            return Err(anyhow!(
+                "ctx block not found in recipe {} or bases {:?}",
+                compiled.recipe_path.display(),
+                bases
+            ))
+            .with_context(|| err_ctx);
         };
 
     *source = ctx::persist(source, ctx_map).with_context(|| err_ctx)?;
+    write_atomic(path, source).with_context(|| err_ctx)
+}
 
+/// Replace `path` with `contents` atomically: write a sibling temp file with
+/// the existing permissions and rename it into place.
+pub(crate) fn write_atomic(path: &Path, contents: &str) -> Result<()> {
     let parent = path
         .parent()
-        .ok_or_else(|| anyhow!("Recipe path has no parent directory: {}", path.display()))
-        .with_context(|| err_ctx)?;
+        .ok_or_else(|| anyhow!("Path has no parent directory: {}", path.display()))?;
 
     // Preserve existing file permissions where possible.
     let existing_perms = fs::metadata(path).map(|m| m.permissions()).ok();
@@ -114,54 +290,61 @@ pub(crate) fn persist_ctx(
     let mut tmp = tempfile::Builder::new()
         .prefix(".recipe-ctx.")
         .suffix(".tmp")
-        .tempfile_in(parent)
-        .with_context(|| err_ctx)?;
+        .tempfile_in(parent)?;
 
-    tmp.as_file_mut()
-        .write_all(source.as_bytes())
-        .with_context(|| err_ctx)?;
-    tmp.as_file().sync_all().with_context(|| err_ctx)?;
+    tmp.as_file_mut().write_all(contents.as_bytes())?;
+    tmp.as_file().sync_all()?;
 
     if let Some(perms) = existing_perms {
         // Best-effort; failure should still abort to avoid surprising permission changes.
-        fs::set_permissions(tmp.path(), perms).with_context(|| err_ctx)?;
+        fs::set_permissions(tmp.path(), perms)?;
     }
 
     // Keep temp file on drop so we can rename it into place. If the rename fails,
     // explicitly remove it to avoid leaving junk behind.
-    let (_f, tmp_path) = tmp.keep().with_context(|| err_ctx)?;
+    let (_f, tmp_path) = tmp.keep()?;
     drop(_f);
 
     if let Err(e) = fs::rename(&tmp_path, path) {
         let _ = fs::remove_file(&tmp_path);
-        return Err(e).with_context(|| err_ctx);
+        return Err(e).with_context(|| format!("Failed to write {}", path.display()));
     }
 
     // Ensure the directory entry update is durable on Unix.
     #[cfg(unix)]
     {
         use std::fs::File;
-        File::open(parent)
-            .and_then(|d| d.sync_all())
-            .with_context(|| err_ctx)?;
+        File::open(parent).and_then(|d| d.sync_all())?;
     }
     Ok(())
 }
 
 /// Resolve dependency recipes, install tools, and set up PATH + env vars.
 /// Returns an RAII guard that restores the environment on drop.
+///
+/// `root` is the recipe declaring `dep_names` (its file stem); the whole
+/// dependency graph below it is installed in topological order.
 pub(crate) fn resolve_deps(
     engine: &Engine,
     build_dir: &Path,
     search_path: Option<&Path>,
     defines: &[(String, String)],
+    root: &str,
     dep_names: &[String],
-    autofix: Option<&crate::AutoFixConfig>,
+    options: &InstallOptions,
 ) -> Result<EnvRestoreGuard> {
     let original_path = std::env::var("PATH").unwrap_or_default();
-    let mut resolver =
-        build_deps::BuildDepsResolver::new(engine, build_dir, search_path, defines, autofix);
-    let tools_prefix = resolver.resolve_and_install(dep_names)?;
+    let resolver = build_deps::BuildDepsResolver::new(
+        engine,
+        build_dir,
+        search_path,
+        defines,
+        options.autofix,
+    )
+    .with_persist_ctx(options.persist_ctx)
+    .with_sysroot(options.sysroot)
+    .with_allow_conflicts(options.allow_conflicts);
+    let tools_prefix = resolver.resolve_and_install(root, dep_names)?;
 
     // Safety: we're single-threaded during recipe execution
     unsafe {
@@ -230,3 +413,129 @@ impl Drop for EnvRestoreGuard {
         }
     }
 }
+
+/// Paths owned by other recipes under `search_path`, for the install-time
+/// conflict check.
+///
+/// The recipe being installed and its base recipe are left out so a reinstall
+/// never conflicts with its own files.
+pub(crate) fn other_recipe_claims(
+    compiled: &CompiledRecipe,
+    search_path: Option<&Path>,
+    stage_dir: &Path,
+    root: &Path,
+) -> Claims {
+    let Some(search_path) = search_path else {
+        return Claims::default();
+    };
+    let mut exclude = vec![compiled.recipe_path.clone()];
+    exclude.extend(compiled.bases.iter().map(|base| base.path.clone()));
+    let index = OwnershipIndex::build_excluding(search_path, &exclude);
+    Claims {
+        owners: index.claims(),
+        stage: Some((stage_dir.to_path_buf(), root.to_path_buf())),
+    }
+}
+
+/// Where and how [`staged_install`] runs `install(ctx)`.
+pub(crate) struct StagedInstall<'a> {
+    /// Recipe name, for hook events.
+    pub name: &'a str,
+    /// Prepared (fresh) stage directory, `STAGE_DIR`.
+    pub stage_dir: &'a Path,
+    /// Target root the stage is committed into.
+    pub root: &'a Path,
+    /// Scratch space: writes below these paths are neither recorded nor
+    /// re-rooted under the sysroot.
+    pub scratch: &'a [PathBuf],
+    pub sysroot: Option<&'a Path>,
+    pub claims: Claims,
+}
+
+/// Run the `install` phase into the stage and commit it into the target root.
+///
+/// Helpers record what install() writes outside the scratch paths, refuse
+/// paths in `claims`, and with a sysroot re-root absolute destinations under
+/// it. `check` validates the returned ctx before anything is committed. The
+/// stage is discarded on failure, so nothing reaches the target root unless
+/// install() succeeds and the staged tree commits cleanly.
+pub(crate) fn staged_install(
+    engine: &Engine,
+    ast: &AST,
+    scope: &mut Scope,
+    ctx: rhai::Map,
+    install: StagedInstall,
+    check: impl FnOnce(rhai::Map) -> Result<rhai::Map>,
+) -> Result<(rhai::Map, Manifest)> {
+    let (result, mut installed) =
+        tracking::record_guarded(install.scratch, install.claims.clone(), || {
+            confined(install.sysroot, install.scratch, || {
+                runner::run_phase(engine, ast, scope, "install", ctx)
+            })
+        });
+    let new_ctx = result.and_then(check).inspect_err(|_| {
+        let _ = stage::discard(install.stage_dir);
+    })?;
+    installed.merge(commit_stage(
+        install.name,
+        install.stage_dir,
+        install.root,
+        &install.claims,
+    )?);
+    Ok((new_ctx, installed))
+}
+
+/// Run `f` confined to `sysroot` (see [`sysroot::confine`]), or as is without
+/// one.
+pub(crate) fn confined<R>(sysroot: Option<&Path>, scratch: &[PathBuf], f: impl FnOnce() -> R) -> R {
+    match sysroot {
+        Some(sysroot) => sysroot::confine(sysroot, scratch, f),
+        None => f(),
+    }
+}
+
+/// Commit the stage tree into the target root and remove the stage.
+///
+/// Staged files that would land on a path in `claims` (owned by another
+/// recipe) abort the commit before anything is moved. The stage is discarded
+/// whether or not the commit succeeds; a failed commit has already been
+/// rolled back by [`stage::commit`].
+pub(crate) fn commit_stage(
+    name: &str,
+    stage_dir: &Path,
+    root: &Path,
+    claims: &Claims,
+) -> Result<Manifest> {
+    let result = stage::conflicts(stage_dir, root, &claims.owners).and_then(|conflicts| {
+        if conflicts.is_empty() {
+            return stage::commit(stage_dir, root);
+        }
+        for (path, owner) in &conflicts {
+            output::error(&tracking::conflict_message(path, owner));
+        }
+        output::hook_event(
+            name,
+            "commit",
+            "conflict",
+            "staged files owned by other recipes",
+        );
+        Err(anyhow!(
+            "{} staged file(s) owned by other recipes (first: {})",
+            conflicts.len(),
+            conflicts[0].0.display()
+        ))
+    });
+    if let Err(e) = stage::discard(stage_dir) {
+        output::warning(&format!("{e:#}"));
+    }
+    let committed = result.map_err(|e| anyhow!("commit failed: {e:#}"))?;
+    if !committed.is_empty() {
+        output::detail(&format!(
+            "Committed {} staged path(s) into {}",
+            committed.files.len(),
+            root.display()
+        ));
+        output::hook_event(name, "commit", "success", "staged files committed");
+    }
+    Ok(committed)
+}
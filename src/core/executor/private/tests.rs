@@ -1,11 +1,35 @@
 mod private_tests {
-    use crate::core::executor::{compile_recipe, install, parse_extends};
+    use crate::core::executor::{
+        InstallOptions, compile_recipe, install_with_options, parse_extends, plan,
+    };
     use crate::core::runner;
     use crate::helpers;
     use rhai::Engine;
     use std::fs;
+    use std::path::Path;
     use tempfile::TempDir;
 
+    fn install(
+        engine: &Engine,
+        build_dir: &Path,
+        recipe_path: &Path,
+        defines: &[(String, String)],
+        persist_ctx: bool,
+        search_path: Option<&Path>,
+    ) -> anyhow::Result<rhai::Map> {
+        install_with_options(
+            engine,
+            build_dir,
+            recipe_path,
+            defines,
+            search_path,
+            &InstallOptions {
+                persist_ctx,
+                ..Default::default()
+            },
+        )
+    }
+
     fn create_engine() -> Engine {
         let mut engine = Engine::new();
         helpers::register_all(&mut engine);
@@ -213,6 +237,89 @@ fn cleanup(ctx, reason) { ctx }
         assert!(result.is_ok(), "Failed: {:?}", result);
     }
 
+    #[test]
+    fn test_plan_skips_build_deps_when_already_built() {
+        let dir = TempDir::new().unwrap();
+        let build_dir = dir.path().join("build");
+        fs::create_dir_all(&build_dir).unwrap();
+
+        let recipe_path = dir.path().join("test.rhai");
+        fs::write(
+            &recipe_path,
+            r#"
+let build_deps = ["missing-compiler"];
+let ctx = #{ name: "test", built: true };
+
+fn is_installed(ctx) { throw "not installed"; }
+fn is_built(ctx) {
+    if !ctx.built { throw "not built"; }
+    ctx
+}
+fn build(ctx) { ctx }
+fn install(ctx) { throw "should not run"; }
+fn cleanup(ctx, reason) { ctx }
+"#,
+        )
+        .unwrap();
+
+        let engine = create_engine();
+        let result = plan(&engine, &build_dir, &recipe_path, &[], None).unwrap();
+        assert!(result.needs_install);
+        assert_eq!(result.phases, vec!["install"]);
+        assert_eq!(result.checks.len(), 2);
+        assert!(result.build_deps.is_empty());
+
+        // Once a build is needed, an unresolvable build dep fails the plan.
+        fs::write(
+            &recipe_path,
+            fs::read_to_string(&recipe_path)
+                .unwrap()
+                .replace("built: true", "built: false"),
+        )
+        .unwrap();
+        let err = plan(&engine, &build_dir, &recipe_path, &[], None).unwrap_err();
+        assert!(err.to_string().contains("missing-compiler"), "{err}");
+    }
+
+    #[test]
+    fn test_install_commits_stage_only_on_success() {
+        let dir = TempDir::new().unwrap();
+        let build_dir = dir.path().join("build");
+        fs::create_dir_all(&build_dir).unwrap();
+        let target = dir.path().join("root/opt/pkg");
+        let defines = vec![("TARGET".to_string(), target.display().to_string())];
+
+        let recipe_path = dir.path().join("staged.rhai");
+        let recipe = r#"
+let ctx = #{ name: "staged", fail: false };
+
+fn is_installed(ctx) { throw "not installed"; }
+fn is_built(ctx) { ctx }
+fn install(ctx) {
+    mkdir(`${STAGE_DIR}${TARGET}`);
+    write_file(`${STAGE_DIR}${TARGET}/file.txt`, "staged");
+    if ctx.fail { throw "late failure"; }
+    ctx
+}
+fn cleanup(ctx, reason) { ctx }
+"#;
+        fs::write(&recipe_path, recipe.replace("fail: false", "fail: true")).unwrap();
+
+        let engine = create_engine();
+        let err = install(&engine, &build_dir, &recipe_path, &defines, false, None).unwrap_err();
+        assert!(err.to_string().contains("late failure"), "{err}");
+        assert!(!target.exists(), "failed install must not touch the root");
+        assert!(!build_dir.join(".stage").exists());
+
+        fs::write(&recipe_path, recipe).unwrap();
+        install(&engine, &build_dir, &recipe_path, &defines, false, None).unwrap();
+        assert_eq!(
+            fs::read_to_string(target.join("file.txt")).unwrap(),
+            "staged"
+        );
+        assert!(!build_dir.join(".stage").exists());
+    }
+
     #[test]
     fn test_has_fn() {
         let engine = Engine::new();
@@ -226,23 +333,28 @@ fn cleanup(ctx, reason) { ctx }
     fn test_parse_extends() {
         assert_eq!(
             parse_extends("//! extends: base.rhai\nlet ctx = #{};"),
-            Some("base.rhai".to_string())
+            vec!["base.rhai".to_string()]
         );
         assert_eq!(
             parse_extends("//! extends:  linux-base.rhai \nlet ctx = #{};"),
-            Some("linux-base.rhai".to_string())
+            vec!["linux-base.rhai".to_string()]
         );
         assert_eq!(
             parse_extends("// comment\n//! extends: base.rhai\nlet ctx = #{};"),
-            Some("base.rhai".to_string())
+            vec!["base.rhai".to_string()]
         );
-        assert_eq!(parse_extends("let ctx = #{};"), None);
+        assert!(parse_extends("let ctx = #{};").is_empty());
         assert_eq!(
             parse_extends("\n\n//! extends: base.rhai"),
-            Some("base.rhai".to_string())
+            vec!["base.rhai".to_string()]
         );
         // Non-comment line before extends stops parsing
-        assert_eq!(parse_extends("let x = 1;\n//! extends: base.rhai"), None);
+        assert!(parse_extends("let x = 1;\n//! extends: base.rhai").is_empty());
+        // Several extends lines, in order
+        assert_eq!(
+            parse_extends("//! extends: a.rhai\n// note\n//! extends: b.rhai\nlet ctx = #{};"),
+            vec!["a.rhai".to_string(), "b.rhai".to_string()]
+        );
     }
 
     #[test]
@@ -323,7 +435,132 @@ fn cleanup(ctx, reason) { ctx }
     }
 
     #[test]
-    fn test_extends_recursive_rejected() {
+    fn test_extends_chains_and_multiple_bases_merge_in_order() {
+        let dir = TempDir::new().unwrap();
+        let layers = dir.path().join("layers");
+        fs::create_dir_all(&layers).unwrap();
+        let trace =
+            |name: &str| format!("fn {name}() {{ \"{name}\" }}\nfn who() {{ \"{name}\" }}\n");
+
+        fs::write(
+            layers.join("generic.rhai"),
+            format!("let order = [\"generic\"];\n{}", trace("generic")),
+        )
+        .unwrap();
+        fs::write(
+            layers.join("gnu.rhai"),
+            format!(
+                "//! extends: generic.rhai\norder.push(\"gnu\");\n{}",
+                trace("gnu")
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("mixin.rhai"),
+            format!(
+                "//! extends: layers/generic.rhai\norder.push(\"mixin\");\n{}",
+                trace("mixin")
+            ),
+        )
+        .unwrap();
+        let child = dir.path().join("child.rhai");
+        fs::write(
+            &child,
+            "//! extends: layers/gnu.rhai\n//! extends: mixin.rhai\norder.push(\"child\");\nlet ctx = #{};",
+        )
+        .unwrap();
+
+        let engine = create_engine();
+        let compiled = compile_recipe(&engine, &child, None).unwrap();
+        let names: Vec<String> = compiled
+            .bases
+            .iter()
+            .map(|base| crate::core::deps::recipe_name(&base.path))
+            .collect();
+        // The shared base is merged once, before everything that extends it.
+        assert_eq!(names, ["generic", "gnu", "mixin"]);
+
+        let mut scope = rhai::Scope::new();
+        compiled.push_base_constants(&mut scope);
+        engine
+            .run_ast_with_scope(&mut scope, &compiled.ast)
+            .unwrap();
+        let order: Vec<String> = scope
+            .get_value::<rhai::Array>("order")
+            .unwrap()
+            .into_iter()
+            .map(|v| v.into_string().unwrap())
+            .collect();
+        assert_eq!(order, ["generic", "gnu", "mixin", "child"]);
+
+        // Later layers override functions; every layer's own functions remain.
+        let who: String = engine
+            .call_fn(&mut scope, &compiled.ast, "who", ())
+            .unwrap();
+        assert_eq!(who, "mixin");
+        let gnu: String = engine
+            .call_fn(&mut scope, &compiled.ast, "gnu", ())
+            .unwrap();
+        assert_eq!(gnu, "gnu");
+
+        let dirs = scope.get_value::<rhai::Map>("BASE_RECIPE_DIRS").unwrap();
+        let layers = layers.canonicalize().unwrap();
+        assert_eq!(
+            dirs["gnu"].clone().into_string().unwrap(),
+            layers.to_string_lossy()
+        );
+        assert_eq!(
+            scope.get_value::<String>("BASE_RECIPE_DIR").unwrap(),
+            dir.path().canonicalize().unwrap().to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn test_extends_cycle_rejected() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("a.rhai"), "//! extends: b.rhai\n").unwrap();
+        fs::write(dir.path().join("b.rhai"), "//! extends: a.rhai\n").unwrap();
+        let child = dir.path().join("child.rhai");
+        fs::write(&child, "//! extends: a.rhai\nlet ctx = #{};").unwrap();
+
+        let engine = create_engine();
+        let err = compile_recipe(&engine, &child, None)
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("extends cycle: "), "{err}");
+        assert!(
+            err.contains("a.rhai -> ") && err.ends_with("a.rhai"),
+            "{err}"
+        );
+
+        let self_ref = dir.path().join("self.rhai");
+        fs::write(&self_ref, "//! extends: self.rhai\nlet ctx = #{};").unwrap();
+        assert!(compile_recipe(&engine, &self_ref, None).is_err());
+    }
+
+    #[test]
+    fn test_extends_bases_with_same_stem_rejected() {
+        let dir = TempDir::new().unwrap();
+        for sub in ["a", "b"] {
+            fs::create_dir_all(dir.path().join(sub)).unwrap();
+            fs::write(dir.path().join(sub).join("base.rhai"), "").unwrap();
+        }
+        let child = dir.path().join("child.rhai");
+        fs::write(
+            &child,
+            "//! extends: a/base.rhai\n//! extends: b/base.rhai\nlet ctx = #{};",
+        )
+        .unwrap();
+
+        let engine = create_engine();
+        let err = compile_recipe(&engine, &child, None)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("share the name 'base'"), "{err}");
+    }
+
+    #[test]
+    fn test_extends_missing_nested_base_rejected() {
         let dir = TempDir::new().unwrap();
 
         let grandparent = dir.path().join("grandparent.rhai");
@@ -0,0 +1,75 @@
+use crate::core::executor::compile_recipe;
+use crate::core::update::{self, CHECK_UPDATE, UpdateInfo};
+use crate::core::{output, runner};
+use anyhow::{Result, anyhow};
+use rhai::Engine;
+use std::path::Path;
+
+use super::state::install_scope;
+
+/// Run `check_update(ctx)` (or `check_update()`) for a recipe.
+///
+/// Returns `None` when the recipe defines no update hook. Nothing is
+/// persisted: update discovery never touches the recipe or the target root.
+pub(crate) fn check_update(
+    engine: &Engine,
+    build_dir: &Path,
+    recipe_path: &Path,
+    search_path: Option<&Path>,
+    defines: &[(String, String)],
+) -> Result<Option<UpdateInfo>> {
+    let recipe_path = recipe_path
+        .canonicalize()
+        .unwrap_or_else(|_| recipe_path.to_path_buf());
+
+    let compiled = compile_recipe(engine, &recipe_path, search_path)?;
+    let ast = compiled.ast.clone();
+    let takes_ctx = runner::has_fn_arity(&ast, CHECK_UPDATE, 1);
+    if !takes_ctx && !runner::has_fn_arity(&ast, CHECK_UPDATE, 0) {
+        return Ok(None);
+    }
+
+    let mut scope = install_scope(&compiled, build_dir, defines);
+    engine.run_ast_with_scope(&mut scope, &ast)?;
+
+    let ctx_map: rhai::Map = scope
+        .get_value("ctx")
+        .ok_or_else(|| anyhow!("Recipe missing ctx"))?;
+    let name = ctx_map
+        .get("name")
+        .and_then(|v| v.clone().into_string().ok())
+        .unwrap_or_else(|| crate::core::deps::recipe_name(&recipe_path));
+    let current = ctx_map
+        .get("version")
+        .and_then(|v| v.clone().into_string().ok());
+
+    let hook = format!("update.{CHECK_UPDATE}");
+    output::hook_event(&name, &hook, "running", "checking upstream version");
+    let result = if takes_ctx {
+        engine.call_fn::<rhai::Dynamic>(&mut scope, &ast, CHECK_UPDATE, (ctx_map,))
+    } else {
+        engine.call_fn::<rhai::Dynamic>(&mut scope, &ast, CHECK_UPDATE, ())
+    };
+    let value = result.map_err(|e| {
+        output::hook_event(&name, &hook, "failed", &format!("{e}"));
+        anyhow!("{name}: {CHECK_UPDATE} failed: {e}")
+    })?;
+    let (latest, metadata) = update::interpret(value).map_err(|e| {
+        output::hook_event(&name, &hook, "failed", &format!("{e}"));
+        anyhow!("{name}: {e}")
+    })?;
+
+    let info = UpdateInfo {
+        name,
+        recipe_path,
+        current,
+        latest,
+        metadata,
+    };
+    let msg = match (&info.latest, info.is_newer()) {
+        (Some(latest), true) => format!("update available: {latest}"),
+        _ => "up to date".to_string(),
+    };
+    output::hook_event(&info.name, &hook, "success", &msg);
+    Ok(Some(info))
+}
@@ -0,0 +1,229 @@
+use crate::core::executor::{InstallOptions, compile_recipe};
+use crate::core::lock::acquire_recipe_lock;
+use crate::core::orphans::InstallReason;
+use crate::core::update::UpdateInfo;
+use crate::core::{ctx_store, manifest, output};
+use crate::helpers::internal::tracking::Manifest;
+use anyhow::{Context, Result, anyhow};
+use rhai::Engine;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::attempt::InstallAttemptError;
+use super::flow::install_once;
+use super::state::{install_scope, persist_ctx, write_atomic};
+use super::update::check_update;
+
+/// Upgrade a recipe to the version reported by its `check_update` hook.
+///
+/// The new version (and any ctx key the hook returned next to it) is written
+/// into the recipe's ctx, then acquire → build → install run unconditionally
+/// in a fresh build directory. The old installation stays in place until the
+/// new install commits; files only the old version installed are removed
+/// afterwards. On failure the recipe source is restored. Without ctx
+/// persistence the new ctx is only passed to the install; nothing is written.
+///
+/// Returns `None` when the recipe has no `check_update` hook. The upgrade was
+/// applied when the returned info [`UpdateInfo::is_newer`].
+pub(crate) fn upgrade(
+    engine: &Engine,
+    build_dir: &Path,
+    recipe_path: &Path,
+    defines: &[(String, String)],
+    search_path: Option<&Path>,
+    options: &InstallOptions,
+) -> Result<Option<UpdateInfo>> {
+    let recipe_path = recipe_path
+        .canonicalize()
+        .unwrap_or_else(|_| recipe_path.to_path_buf());
+
+    let _lock = acquire_recipe_lock(&recipe_path)?;
+
+    let Some(info) = check_update(engine, build_dir, &recipe_path, search_path, defines)? else {
+        return Ok(None);
+    };
+    let name = info.name.clone();
+    let Some(latest) = info.latest.clone().filter(|_| info.is_newer()) else {
+        output::hook_event(&name, "upgrade", "skipped", "up to date");
+        output::skip(&format!("{name} is up to date"));
+        return Ok(Some(info));
+    };
+    let current = info.current.as_deref().unwrap_or("?");
+
+    output::action(&format!("Upgrading {name} {current} → {latest}"));
+    output::hook_event(
+        &name,
+        "upgrade",
+        "running",
+        &format!("{current} -> {latest}"),
+    );
+
+    let mut compiled = compile_recipe(engine, &recipe_path, search_path)?;
+    let originals = options.persist_ctx.then(|| Sources::capture(&compiled));
+    let mut scope = install_scope(&compiled, build_dir, defines);
+    engine
+        .run_ast_with_scope(&mut scope, &compiled.ast.clone())
+        .map_err(|e| anyhow!("Failed to run recipe: {}", e))?;
+    let old_ctx: rhai::Map = scope
+        .get_value("ctx")
+        .ok_or_else(|| anyhow!("Recipe missing 'let ctx = #{{...}}'"))?;
+    let reason = InstallReason::from_ctx(&old_ctx).unwrap_or(InstallReason::Explicit);
+
+    // Point ctx at the new version before the lifecycle reruns. Metadata keys
+    // only replace fields the recipe already has (e.g. `url`, `sha256`).
+    let mut new_ctx = old_ctx.clone();
+    new_ctx.insert("version".into(), latest.clone().into());
+    for (key, value) in &info.metadata {
+        if new_ctx.contains_key(key.as_str()) {
+            let value = rhai::serde::to_dynamic(value)
+                .map_err(|e| anyhow!("Invalid check_update metadata `{key}`: {e}"))?;
+            new_ctx.insert(key.as_str().into(), value);
+        }
+    }
+    // Without persistence the new ctx is handed to the install directly and
+    // nothing is written.
+    if options.persist_ctx {
+        persist_ctx(
+            &mut compiled,
+            &new_ctx,
+            "upgrade",
+            "started",
+            "Failed to persist upgraded version",
+        )?;
+    }
+
+    let fresh_dir = build_dir.join(format!(
+        "upgrade-{}-{}",
+        crate::core::deps::recipe_name(&recipe_path),
+        sanitize(&latest)
+    ));
+    let result = fs::remove_dir_all(&fresh_dir)
+        .or_else(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(e),
+        })
+        .and_then(|()| fs::create_dir_all(&fresh_dir))
+        .with_context(|| format!("Failed to create build dir {}", fresh_dir.display()))
+        .and_then(|()| {
+            output::detail(&format!("Fresh build dir: {}", fresh_dir.display()));
+            let upgrade_options = InstallOptions {
+                force: true,
+                reason,
+                ctx: (!options.persist_ctx).then_some(&new_ctx),
+                ..*options
+            };
+            install_once(
+                engine,
+                &fresh_dir,
+                &recipe_path,
+                defines,
+                search_path,
+                &upgrade_options,
+            )
+            .map_err(|e| match e {
+                InstallAttemptError::Fatal(error) | InstallAttemptError::Phase { error, .. } => {
+                    error
+                }
+            })
+        });
+
+    let installed_ctx = match result {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            if let Err(restore_err) = originals.as_ref().map_or(Ok(()), Sources::restore) {
+                output::warning(&format!("failed to restore recipe source: {restore_err:#}"));
+            }
+            output::hook_event(&name, "upgrade", "failed", &format!("{e}"));
+            output::error(&format!(
+                "{name}: upgrade to {latest} failed; keeping {current}"
+            ));
+            return Err(e.context(format!("Upgrade of {name} to {latest} failed")));
+        }
+    };
+
+    // Files the old version installed that the new one no longer ships.
+    let old = manifest::from_ctx(&old_ctx);
+    let new = manifest::from_ctx(&installed_ctx);
+    let stale = Manifest {
+        files: old
+            .files
+            .into_iter()
+            .filter(|f| !new.files.contains(f))
+            .collect(),
+        dirs: old
+            .dirs
+            .into_iter()
+            .filter(|d| !new.dirs.contains(d))
+            .collect(),
+    };
+    if !stale.is_empty() {
+        output::detail(&format!(
+            "Removing {} file(s) left over from {current}",
+            stale.files.len()
+        ));
+        for file in manifest::uninstall(&stale).files {
+            output::warning(&format!("could not remove stale {}", file.display()));
+        }
+    }
+
+    output::success(&format!("{name} upgraded to {latest}"));
+    output::hook_event(
+        &name,
+        "upgrade",
+        "success",
+        &format!("{current} -> {latest}"),
+    );
+    Ok(Some(info))
+}
+
+/// Where the recipe's ctx is persisted, as it was before the upgrade: the
+/// recipe (and base recipes) sources, or the ctx state file when the engine
+/// uses a state store. `None` marks a state file that did not exist yet.
+struct Sources(Vec<(PathBuf, Option<String>)>);
+
+impl Sources {
+    fn capture(compiled: &crate::core::executor::CompiledRecipe) -> Self {
+        if let Some(state_file) = ctx_store::state_file(&compiled.recipe_path) {
+            let state = fs::read_to_string(&state_file).ok();
+            return Self(vec![(state_file, state)]);
+        }
+        let recipe = (
+            compiled.recipe_path.clone(),
+            Some(compiled.recipe_source.clone()),
+        );
+        let bases = compiled
+            .bases
+            .iter()
+            .map(|base| (base.path.clone(), Some(base.source.clone())));
+        Self(std::iter::once(recipe).chain(bases).collect())
+    }
+
+    /// Write every source back; each file is replaced atomically.
+    fn restore(&self) -> Result<()> {
+        for (path, source) in &self.0 {
+            match source {
+                Some(source) => write_atomic(path, source),
+                None => fs::remove_file(path).or_else(|e| match e.kind() {
+                    std::io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(e.into()),
+                }),
+            }
+            .with_context(|| format!("Failed to restore {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Keep a version string safe for use in a directory name.
+fn sanitize(version: &str) -> String {
+    version
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,542 @@
+//! Canonical recipe layout (`recipe fmt`)
+//!
+//! [`format_source`] rewrites a recipe into one layout:
+//! - the header first: every leading comment and blank line, as
+//!   [`super::executor::parse_extends`] reads them, with `//! key: value`
+//!   spacing normalized (`extends` order is kept, it is the merge order)
+//! - top-level statements next, in their original order; a literal ctx block
+//!   is rewritten by [`ctx::serialize`], so keys are sorted
+//! - functions last: helpers in their original order, then the checks
+//!   (`is_installed`, `is_built`, `is_acquired`), the phases (`acquire`,
+//!   `build`, `install`), `remove`, `check_update` and `cleanup`
+//!
+//! Comments move with the item they precede, runs of blank lines collapse to
+//! one, and function bodies are left as written. Functions are hoisted in
+//! Rhai, so reordering them does not change behavior. A ctx block holding
+//! comments or values that do not round-trip (e.g. expressions) is kept as
+//! is, and a source whose top level cannot be split into items is only
+//! header-normalized. A result that would change the `extends` bases or no
+//! longer compile is refused.
+
+use super::ctx;
+use super::executor::parse_extends;
+use anyhow::{Result, bail};
+
+/// Hooks in canonical order; other functions come before them.
+pub(crate) const HOOK_ORDER: [&str; 9] = [
+    "is_installed",
+    "is_built",
+    "is_acquired",
+    "acquire",
+    "build",
+    "install",
+    "remove",
+    "check_update",
+    "cleanup",
+];
+
+/// Statements that may end with a `}` instead of `;`.
+const BLOCK_KEYWORDS: [&str; 6] = ["if", "for", "while", "loop", "do", "try"];
+
+/// Canonical form of a recipe source. Formatting is idempotent.
+///
+/// Fails, rather than return a source that means something else, when the
+/// result has different `//! extends:` bases or does not compile.
+pub fn format_source(source: &str) -> Result<String> {
+    let output = layout(source);
+    if parse_extends(&output) != parse_extends(source) {
+        bail!("formatting would change the recipe's `//! extends:` bases");
+    }
+    if let Err(e) = rhai::Engine::new_raw().compile(&output) {
+        bail!("formatted recipe does not compile: {e}");
+    }
+    Ok(output)
+}
+
+fn layout(source: &str) -> String {
+    let (header, body) = split_header(source);
+    let mut out = String::new();
+    if !header.is_empty() {
+        out.push_str(&header.join("\n"));
+        out.push('\n');
+    }
+
+    let Some((items, trailing)) = split_items(body) else {
+        if !out.is_empty() && !body.trim().is_empty() {
+            out.push('\n');
+        }
+        out.push_str(body.trim_start_matches('\n'));
+        return out;
+    };
+
+    let (mut functions, statements): (Vec<Item>, Vec<Item>) =
+        items.into_iter().partition(|item| item.function.is_some());
+    functions.sort_by_key(|item| {
+        item.function
+            .as_deref()
+            .and_then(|name| HOOK_ORDER.iter().position(|hook| *hook == name))
+            .map_or(0, |i| i + 1)
+    });
+
+    for (i, item) in statements.iter().enumerate() {
+        // Consecutive statements stay together unless a blank line separated them.
+        if !out.is_empty() && (i == 0 || item.blank_before) {
+            out.push('\n');
+        }
+        out.push_str(&item.render());
+        out.push('\n');
+    }
+    for item in &functions {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&item.render());
+        out.push('\n');
+    }
+    let trailing = normalize_trivia(trailing);
+    if !trailing.is_empty() {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&trailing);
+        out.push('\n');
+    }
+    out
+}
+
+/// One top-level statement or function with the comments before it.
+#[derive(Debug)]
+struct Item {
+    trivia: String,
+    text: String,
+    /// Function name; `None` for statements.
+    function: Option<String>,
+    blank_before: bool,
+    /// A blank line separates the comments from the item.
+    detached: bool,
+}
+
+impl Item {
+    fn render(&self) -> String {
+        let text = if self.text.starts_with("let ctx = #{") {
+            canonical_ctx(&self.text).unwrap_or_else(|| self.text.clone())
+        } else {
+            self.text.clone()
+        };
+        match (self.trivia.is_empty(), self.detached) {
+            (true, _) => text,
+            (false, false) => format!("{}\n{text}", self.trivia),
+            (false, true) => format!("{}\n\n{text}", self.trivia),
+        }
+    }
+}
+
+/// Leading comment lines (`//!` lines normalized, runs of blank lines
+/// collapsed) and the rest of the source.
+fn split_header(source: &str) -> (Vec<String>, &str) {
+    let mut header: Vec<String> = Vec::new();
+    let mut rest = source;
+    while !rest.is_empty() {
+        let (line, next) = rest.split_once('\n').unwrap_or((rest, ""));
+        let trimmed = line.trim();
+        if let Some(doc) = trimmed.strip_prefix("//!") {
+            header.push(normalize_header_line(doc));
+        } else if trimmed.starts_with("//") {
+            header.push(trimmed.to_string());
+        } else if !trimmed.is_empty() {
+            break;
+        } else if header.last().is_some_and(|last| !last.is_empty()) {
+            header.push(String::new());
+        }
+        rest = next;
+    }
+    while header.last().is_some_and(|last| last.is_empty()) {
+        header.pop();
+    }
+    (header, rest)
+}
+
+/// `//! key: value` with single spaces; other doc lines keep their text.
+fn normalize_header_line(doc: &str) -> String {
+    let text = doc.trim_end();
+    if let Some((key, value)) = text.trim_start().split_once(':') {
+        let is_key = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if is_key {
+            return format!("//! {key}: {}", value.trim());
+        }
+    }
+    if text.trim().is_empty() {
+        "//!".to_string()
+    } else if text.starts_with(' ') {
+        format!("//!{text}")
+    } else {
+        format!("//! {text}")
+    }
+}
+
+/// The ctx block re-serialized, when it is a plain literal without comments
+/// whose values survive the round trip.
+fn canonical_ctx(text: &str) -> Option<String> {
+    if has_comment(text) {
+        return None;
+    }
+    let map = ctx::parse_literal(text)?;
+    let serialized = ctx::serialize(&map);
+    let reparsed = ctx::parse_literal(&serialized)?;
+    let same = format!("{:?}", rhai::Dynamic::from_map(map))
+        == format!("{:?}", rhai::Dynamic::from_map(reparsed));
+    same.then_some(serialized)
+}
+
+/// Comment lines without trailing whitespace or surrounding and repeated
+/// blank lines.
+fn normalize_trivia(trivia: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in trivia.lines().map(str::trim_end) {
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+    lines
+        .iter()
+        .map(|line| line.trim_start())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Split the top level into items and the trivia after the last one. `None`
+/// when an item does not end (unbalanced brackets, unterminated strings).
+fn split_items(body: &str) -> Option<(Vec<Item>, &str)> {
+    let bytes = body.as_bytes();
+    let mut items = Vec::new();
+    let mut pos = 0;
+    loop {
+        let start = skip_trivia(bytes, pos)?;
+        if start >= bytes.len() {
+            return Some((items, &body[pos..]));
+        }
+        let function = function_name(&body[start..]);
+        let end = if function.is_some() {
+            function_end(bytes, start)?
+        } else {
+            statement_end(body, start)?
+        };
+        let end = same_line_comment_end(bytes, end);
+        let trivia = &body[pos..start];
+        let gap = &trivia[trivia.trim_end().len()..];
+        items.push(Item {
+            trivia: normalize_trivia(trivia),
+            text: body[start..end].to_string(),
+            function,
+            blank_before: trivia.lines().skip(1).any(|line| line.trim().is_empty()),
+            detached: gap.matches('\n').count() > 1,
+        });
+        pos = end;
+    }
+}
+
+/// Name of the function an item defines (`fn name(` or `private fn name(`).
+fn function_name(text: &str) -> Option<String> {
+    let text = text.strip_prefix("private").map_or(text, str::trim_start);
+    let rest = text.strip_prefix("fn")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let name: String = rest
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Index after the closing `}` of the function starting at `start`.
+fn function_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start;
+    while i < bytes.len() {
+        let next = skip_literal(bytes, i)?;
+        if next != i {
+            i = next;
+            continue;
+        }
+        if bytes[i] == b'{' {
+            return block_end(bytes, i + 1);
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Index after the `;` (or final `}` of a block statement) ending the
+/// statement at `start`.
+fn statement_end(body: &str, start: usize) -> Option<usize> {
+    let bytes = body.as_bytes();
+    let word: String = body[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+    let is_block = BLOCK_KEYWORDS.contains(&word.as_str());
+    let mut depth = 0usize;
+    let mut i = start;
+    while i < bytes.len() {
+        let next = skip_literal(bytes, i)?;
+        if next != i {
+            i = next;
+            continue;
+        }
+        match bytes[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 && bytes[i] == b'}' && is_block {
+                    let after = skip_trivia(bytes, i + 1)?;
+                    let rest = &body[after..];
+                    let continues = rest.starts_with(';')
+                        || ["else", "catch", "while"]
+                            .iter()
+                            .any(|kw| rest.starts_with(kw));
+                    if !continues {
+                        return Some(i + 1);
+                    }
+                }
+            }
+            b';' if depth == 0 => return Some(i + 1),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Index after the `}` closing a block whose `{` precedes `i`.
+fn block_end(bytes: &[u8], mut i: usize) -> Option<usize> {
+    let mut depth = 1usize;
+    while i < bytes.len() {
+        let next = skip_literal(bytes, i)?;
+        if next != i {
+            i = next;
+            continue;
+        }
+        match bytes[i] {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Skip a string, character literal or comment starting at `i`; returns `i`
+/// when there is none and `None` when it is unterminated.
+fn skip_literal(bytes: &[u8], i: usize) -> Option<usize> {
+    match (bytes[i], bytes.get(i + 1)) {
+        (b'/', Some(b'/')) => Some(
+            bytes[i..]
+                .iter()
+                .position(|b| *b == b'\n')
+                .map_or(bytes.len(), |n| i + n),
+        ),
+        (b'/', Some(b'*')) => {
+            // Block comments nest in Rhai.
+            let mut depth = 0usize;
+            let mut j = i;
+            while j + 1 < bytes.len() {
+                match (bytes[j], bytes[j + 1]) {
+                    (b'/', b'*') => {
+                        depth += 1;
+                        j += 2;
+                    }
+                    (b'*', b'/') => {
+                        depth -= 1;
+                        j += 2;
+                        if depth == 0 {
+                            return Some(j);
+                        }
+                    }
+                    _ => j += 1,
+                }
+            }
+            None
+        }
+        (quote @ (b'"' | b'\''), _) => {
+            let mut j = i + 1;
+            while j < bytes.len() {
+                match bytes[j] {
+                    b'\\' => j += 2,
+                    b if b == quote => return Some(j + 1),
+                    _ => j += 1,
+                }
+            }
+            None
+        }
+        (b'`', _) => {
+            let mut j = i + 1;
+            while j < bytes.len() {
+                match (bytes[j], bytes.get(j + 1)) {
+                    (b'`', _) => return Some(j + 1),
+                    (b'$', Some(b'{')) => j = block_end(bytes, j + 2)?,
+                    _ => j += 1,
+                }
+            }
+            None
+        }
+        _ => Some(i),
+    }
+}
+
+/// First code byte at or after `i` (past whitespace and comments).
+fn skip_trivia(bytes: &[u8], mut i: usize) -> Option<usize> {
+    while i < bytes.len() {
+        if bytes[i].is_ascii_whitespace() {
+            i += 1;
+        } else if bytes[i] == b'/' && matches!(bytes.get(i + 1), Some(b'/' | b'*')) {
+            i = skip_literal(bytes, i)?;
+        } else {
+            break;
+        }
+    }
+    Some(i)
+}
+
+/// Extend an item to a `// comment` on the same line after it.
+fn same_line_comment_end(bytes: &[u8], end: usize) -> usize {
+    let mut i = end;
+    while i < bytes.len() && matches!(bytes[i], b' ' | b'\t') {
+        i += 1;
+    }
+    if bytes.get(i) == Some(&b'/') && bytes.get(i + 1) == Some(&b'/') {
+        return skip_literal(bytes, i).unwrap_or(end);
+    }
+    end
+}
+
+/// Whether `text` contains a comment outside strings.
+fn has_comment(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'/' && matches!(bytes.get(i + 1), Some(b'/' | b'*')) {
+            return true;
+        }
+        match skip_literal(bytes, i) {
+            Some(next) if next != i => i = next,
+            Some(_) => i += 1,
+            None => return false,
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_orders_header_statements_and_hooks() {
+        let source = r#"//!description:   Foo tool
+//! extends: mixins/static.rhai
+// The package.
+let ctx = #{ version: "1.0", name: "foo", url: "https://example.com/foo" };
+let deps = ["bar"];   // runtime deps
+
+
+fn cleanup(ctx, reason) { ctx }
+
+// Installs the binary.
+fn install(ctx) {
+    let s = `${ctx.name}; }`;
+    ctx
+}
+fn helper(x) { x }
+fn is_installed(ctx) { throw "no"; }
+fn acquire(ctx) { ctx }
+"#;
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            r#"//! description: Foo tool
+//! extends: mixins/static.rhai
+// The package.
+
+let ctx = #{
+    name: "foo",
+    url: "https://example.com/foo",
+    version: "1.0",
+};
+let deps = ["bar"];   // runtime deps
+
+fn helper(x) { x }
+
+fn is_installed(ctx) { throw "no"; }
+
+fn acquire(ctx) { ctx }
+
+// Installs the binary.
+fn install(ctx) {
+    let s = `${ctx.name}; }`;
+    ctx
+}
+
+fn cleanup(ctx, reason) { ctx }
+"#
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_keeps_ctx_it_cannot_round_trip() {
+        let commented = "let ctx = #{\n    name: \"foo\", // the name\n};\n";
+        assert_eq!(format_source(commented).unwrap(), commented);
+
+        let computed = "let ctx = #{ name: \"foo\", dir: BUILD_DIR + \"/x\" };\n";
+        assert_eq!(format_source(computed).unwrap(), computed);
+
+        let float = "let ctx = #{ name: \"foo\", ratio: 1.5 };\n";
+        assert_eq!(format_source(float).unwrap(), float);
+    }
+
+    #[test]
+    fn test_format_block_statements_and_unbalanced_sources() {
+        let source = "let x = 1;\nif x > 0 {\n    print(\"a\");\n} else {\n    print(\"b\");\n}\nfn install(ctx) { ctx }\nconst Y = 2;\n";
+        assert_eq!(
+            format_source(source).unwrap(),
+            "let x = 1;\nif x > 0 {\n    print(\"a\");\n} else {\n    print(\"b\");\n}\nconst Y = 2;\n\nfn install(ctx) { ctx }\n"
+        );
+
+        let broken = "//!  name: a\nfn install(ctx) { ctx\n";
+        assert_eq!(layout(broken), "//! name: a\n\nfn install(ctx) { ctx\n");
+        let err = format_source(broken).unwrap_err().to_string();
+        assert!(err.contains("does not compile"), "{err}");
+    }
+
+    #[test]
+    fn test_format_keeps_extends_below_plain_comments() {
+        let source = "// Child recipe\n//! extends: base.rhai\n\nfn install(ctx) { ctx }\n\nfn helper() { 1 }\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "// Child recipe\n//! extends: base.rhai\n\nfn helper() { 1 }\n\nfn install(ctx) { ctx }\n"
+        );
+        assert_eq!(parse_extends(&formatted), ["base.rhai"]);
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+
+        // Spacing that `parse_extends` does not accept is not "fixed" into a base.
+        let err = format_source("//!extends: base.rhai\nfn install(ctx) { ctx }\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("extends"), "{err}");
+    }
+}
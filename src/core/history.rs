@@ -0,0 +1,322 @@
+//! ctx history journal
+//!
+//! Every ctx persist also appends one JSON line to the recipe's journal: under
+//! the engine's history directory when one is set
+//! ([`crate::RecipeEngine::with_history_dir`]; the CLI uses [`xdg_dir`]), else
+//! under a state directory store (see [`ctx_store::CtxStore::history_file`]).
+//! With neither, nothing is journaled. An entry records:
+//! - `seq`: 1-based position in the journal
+//! - `timestamp`: seconds since the Unix epoch
+//! - `phase` and `outcome`: what persisted ctx (e.g. `install`/`success`)
+//! - `diff`: the keys that changed, with their values before and after
+//! - `ctx`: the full ctx snapshot that was persisted
+//!
+//! Only the last [`HISTORY_LIMIT`] entries are kept; older ones are dropped
+//! when a new entry is recorded, and `seq` keeps counting. `recipe rollback-ctx`
+//! restores an older snapshot by persisting it again, which adds a `rollback`
+//! entry.
+
+use super::ctx_store;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Entries kept in a journal.
+pub const HISTORY_LIMIT: usize = 100;
+
+/// One persisted ctx snapshot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub phase: String,
+    pub outcome: String,
+    pub diff: BTreeMap<String, CtxChange>,
+    pub ctx: serde_json::Map<String, Value>,
+}
+
+/// Value of one ctx key before and after a persist. `None` means the key was
+/// absent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CtxChange {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+thread_local! {
+    static DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// `$XDG_STATE_HOME/recipe/history` (default `~/.local/state/recipe/history`).
+pub fn xdg_dir() -> PathBuf {
+    ctx_store::xdg_state_dir().join("history")
+}
+
+/// Run `f` with journals kept under `dir` on this thread; `None` leaves them
+/// to the active ctx store.
+pub(crate) fn scoped<R>(dir: Option<&Path>, f: impl FnOnce() -> R) -> R {
+    let previous = DIR.with(|d| d.replace(dir.map(Path::to_path_buf)));
+    let result = f();
+    DIR.with(|d| *d.borrow_mut() = previous);
+    result
+}
+
+/// Journal of `recipe_path`, if anything keeps one.
+fn journal(recipe_path: &Path) -> Option<PathBuf> {
+    DIR.with(|d| {
+        d.borrow()
+            .as_deref()
+            .map(|dir| ctx_store::mirror(dir, recipe_path, ".jsonl"))
+    })
+    .or_else(|| ctx_store::history_file(recipe_path))
+}
+
+/// Append a snapshot of `ctx_map` to the journal of `recipe_path`, if it has
+/// one.
+///
+/// `previous` is the ctx that was persisted before; the diff is taken against
+/// it.
+pub(crate) fn record(
+    recipe_path: &Path,
+    phase: &str,
+    outcome: &str,
+    previous: Option<&rhai::Map>,
+    ctx_map: &rhai::Map,
+) -> Result<()> {
+    let Some(journal) = journal(recipe_path) else {
+        return Ok(());
+    };
+    let before = previous.map(to_json).transpose()?.unwrap_or_default();
+    let after = to_json(ctx_map)?;
+    let mut entries = read(&journal)?;
+    let entry = HistoryEntry {
+        seq: entries.last().map_or(1, |e| e.seq + 1),
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        phase: phase.to_string(),
+        outcome: outcome.to_string(),
+        diff: diff(&before, &after),
+        ctx: after,
+    };
+
+    let parent = journal
+        .parent()
+        .ok_or_else(|| anyhow!("Journal has no parent: {}", journal.display()))?;
+    fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    if entries.len() < HISTORY_LIMIT {
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        return OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal)
+            .and_then(|mut f| f.write_all(line.as_bytes()))
+            .with_context(|| format!("Failed to append to {}", journal.display()));
+    }
+
+    // Full: rewrite the journal with the newest entries.
+    entries.drain(..=entries.len() - HISTORY_LIMIT);
+    entries.push(entry);
+    let mut text = String::new();
+    for entry in &entries {
+        text.push_str(&serde_json::to_string(entry)?);
+        text.push('\n');
+    }
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".recipe-history.")
+        .suffix(".tmp")
+        .tempfile_in(parent)?;
+    tmp.as_file_mut().write_all(text.as_bytes())?;
+    tmp.persist(&journal)
+        .with_context(|| format!("Failed to write {}", journal.display()))?;
+    Ok(())
+}
+
+/// Journal entries of `recipe_path`, oldest first. Without a journal there are
+/// none.
+pub fn entries(recipe_path: &Path) -> Result<Vec<HistoryEntry>> {
+    journal(recipe_path).map_or_else(|| Ok(Vec::new()), |journal| read(&journal))
+}
+
+/// Entries of a journal file. A missing journal is empty.
+fn read(journal: &Path) -> Result<Vec<HistoryEntry>> {
+    let text = match fs::read_to_string(journal) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", journal.display())),
+    };
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}:{}: invalid history entry", journal.display(), i + 1))
+        })
+        .collect()
+}
+
+/// The ctx snapshot of entry `seq`, as a Rhai map.
+pub(crate) fn snapshot(entries: &[HistoryEntry], seq: u64) -> Result<rhai::Map> {
+    let entry = entries
+        .iter()
+        .find(|e| e.seq == seq)
+        .ok_or_else(|| anyhow!("no history entry {seq} (journal has {})", entries.len()))?;
+    rhai::serde::to_dynamic(&entry.ctx)
+        .ok()
+        .and_then(|value| value.try_cast::<rhai::Map>())
+        .ok_or_else(|| anyhow!("history entry {seq} is not a ctx map"))
+}
+
+fn to_json(map: &rhai::Map) -> Result<serde_json::Map<String, Value>> {
+    match rhai::serde::from_dynamic(&rhai::Dynamic::from(map.clone())) {
+        Ok(Value::Object(obj)) => Ok(obj),
+        Ok(_) => Ok(serde_json::Map::new()),
+        Err(e) => Err(anyhow!("ctx is not serializable: {e}")),
+    }
+}
+
+fn diff(
+    before: &serde_json::Map<String, Value>,
+    after: &serde_json::Map<String, Value>,
+) -> BTreeMap<String, CtxChange> {
+    before
+        .keys()
+        .chain(after.keys())
+        .filter(|key| before.get(*key) != after.get(*key))
+        .map(|key| {
+            (
+                key.clone(),
+                CtxChange {
+                    before: before.get(key).cloned(),
+                    after: after.get(key).cloned(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Format a Unix timestamp as `YYYY-MM-DD HH:MM:SS` (UTC).
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Civil-from-days (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ctx_store::CtxStore;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_record_appends_diffs_and_snapshots() {
+        let dir = TempDir::new().unwrap();
+        let recipe = dir.path().join("foo.rhai");
+        let store = CtxStore::StateDir(dir.path().join("state"));
+
+        let mut v1 = rhai::Map::new();
+        v1.insert("version".into(), "1.0".into());
+        let mut v2 = v1.clone();
+        v2.insert("version".into(), "2.0".into());
+        v2.insert("installed".into(), true.into());
+
+        ctx_store::scoped(&store, || {
+            record(&recipe, "acquire", "success", None, &v1).unwrap();
+            record(&recipe, "install", "success", Some(&v1), &v2).unwrap();
+
+            let entries = entries(&recipe).unwrap();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[1].seq, 2);
+            assert_eq!(entries[1].phase, "install");
+            assert_eq!(
+                entries[1].diff["version"],
+                CtxChange {
+                    before: Some("1.0".into()),
+                    after: Some("2.0".into()),
+                }
+            );
+            assert_eq!(entries[1].diff["installed"].before, None);
+            assert!(!entries[1].diff.contains_key("name"));
+
+            let restored = snapshot(&entries, 1).unwrap();
+            assert_eq!(restored["version"].clone().into_string().unwrap(), "1.0");
+            assert!(snapshot(&entries, 3).is_err());
+        });
+        assert!(store.history_file(&recipe).unwrap().exists());
+    }
+
+    #[test]
+    fn test_history_dir_overrides_store() {
+        let dir = TempDir::new().unwrap();
+        let recipe = dir.path().join("foo.rhai");
+        let history = dir.path().join("history");
+        let ctx_map = rhai::Map::new();
+
+        // The source store keeps no journal of its own.
+        record(&recipe, "install", "success", None, &ctx_map).unwrap();
+        assert!(entries(&recipe).unwrap().is_empty());
+
+        scoped(Some(&history), || {
+            record(&recipe, "install", "success", None, &ctx_map).unwrap();
+            assert_eq!(entries(&recipe).unwrap().len(), 1);
+        });
+        assert!(ctx_store::mirror(&history, &recipe, ".jsonl").exists());
+    }
+
+    #[test]
+    fn test_record_keeps_last_entries() {
+        let dir = TempDir::new().unwrap();
+        let recipe = dir.path().join("foo.rhai");
+        let store = CtxStore::StateDir(dir.path().join("state"));
+
+        ctx_store::scoped(&store, || {
+            for run in 0..HISTORY_LIMIT as i64 + 5 {
+                let mut ctx_map = rhai::Map::new();
+                ctx_map.insert("run".into(), run.into());
+                record(&recipe, "install", "success", None, &ctx_map).unwrap();
+            }
+            let entries = entries(&recipe).unwrap();
+            assert_eq!(entries.len(), HISTORY_LIMIT);
+            assert_eq!(entries[0].seq, 6);
+            assert_eq!(entries.last().unwrap().seq, HISTORY_LIMIT as u64 + 5);
+            assert!(snapshot(&entries, 1).is_err());
+        });
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(68_169_600), "1972-02-29 00:00:00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_timestamp(978_264_000), "2000-12-31 12:00:00");
+        assert_eq!(format_timestamp(1_709_251_199), "2024-02-29 23:59:59");
+        assert_eq!(format_timestamp(1_709_251_200), "2024-03-01 00:00:00");
+        // 2100 is not a leap year.
+        assert_eq!(format_timestamp(4_107_542_399), "2100-02-28 23:59:59");
+        assert_eq!(format_timestamp(4_107_542_400), "2100-03-01 00:00:00");
+    }
+}
@@ -0,0 +1,202 @@
+//! Recipe metadata without running the recipe (`recipe info`, `recipe list`)
+//!
+//! The recipe is compiled with its `//! extends:` bases and the top-level
+//! `let ctx`, `let deps` and `let build_deps` declarations are read from the
+//! merged AST. Only literal values are evaluated, so no helper runs; a
+//! declaration or ctx field built at runtime reads as missing. The last
+//! declaration wins, as it does when the statements run, and the active ctx
+//! store's overlay applies on top of ctx.
+
+use super::ctx_store;
+use super::deps::recipe_name;
+use super::executor::compile_recipe;
+use super::format::HOOK_ORDER;
+use anyhow::Result;
+use rhai::{Dynamic, Engine, Expr, Stmt};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Static description of a recipe.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RecipeInfo {
+    /// `ctx.name`, or the recipe's file stem when ctx has none.
+    pub name: String,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    pub homepage: Option<String>,
+    pub deps: Vec<String>,
+    pub build_deps: Vec<String>,
+    /// Lifecycle hooks defined by the recipe or its bases, in execution order.
+    pub hooks: Vec<String>,
+    pub recipe_path: PathBuf,
+    /// Base recipes from `//! extends:`, in merge order.
+    pub bases: Vec<PathBuf>,
+}
+
+pub(crate) fn load(
+    engine: &Engine,
+    recipe_path: &Path,
+    search_path: Option<&Path>,
+) -> Result<RecipeInfo> {
+    let compiled = compile_recipe(engine, recipe_path, search_path)?;
+    let declaration = |name: &str| {
+        compiled
+            .ast
+            .statements()
+            .iter()
+            .rev()
+            .find_map(|stmt| match stmt {
+                Stmt::Var(var, ..) if var.0.name == name => Some(&var.1),
+                _ => None,
+            })
+    };
+    let literal = |name: &str| declaration(name).and_then(Expr::get_literal_value);
+
+    // A ctx map with a computed field is not a literal as a whole; its
+    // entries are then read on their own so the literal ones are kept.
+    let mut ctx = match declaration("ctx") {
+        Some(Expr::Map(map, ..)) => map
+            .0
+            .iter()
+            .filter_map(|(key, value)| Some((key.name.as_str().into(), value.get_literal_value()?)))
+            .collect(),
+        Some(expr) => expr
+            .get_literal_value()
+            .and_then(|value| value.try_cast::<rhai::Map>())
+            .unwrap_or_default(),
+        None => rhai::Map::new(),
+    };
+    if let Some(overlay) = ctx_store::overlay(&compiled.recipe_path)? {
+        overlay.apply(&mut ctx);
+    }
+    let field = |key: &str| {
+        ctx.get(key)
+            .and_then(|value| value.clone().into_string().ok())
+    };
+    let strings = |value: Option<Dynamic>| -> Vec<String> {
+        value
+            .and_then(|value| value.into_array().ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|item| item.into_string().ok())
+            .collect()
+    };
+
+    let defined: Vec<_> = compiled.ast.iter_functions().map(|f| f.name).collect();
+    Ok(RecipeInfo {
+        name: field("name").unwrap_or_else(|| recipe_name(&compiled.recipe_path)),
+        version: field("version"),
+        description: field("description"),
+        license: field("license"),
+        homepage: field("homepage"),
+        deps: strings(literal("deps")),
+        build_deps: strings(literal("build_deps")),
+        hooks: HOOK_ORDER
+            .iter()
+            .filter(|hook| defined.contains(hook))
+            .map(|hook| hook.to_string())
+            .collect(),
+        bases: compiled
+            .bases
+            .iter()
+            .map(|base| base.path.clone())
+            .collect(),
+        recipe_path: compiled.recipe_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn load_info(dir: &TempDir, file: &str) -> RecipeInfo {
+        load(&Engine::new(), &dir.path().join(file), Some(dir.path())).unwrap()
+    }
+
+    #[test]
+    fn test_info_reads_literals_without_running() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("base.rhai"),
+            r#"let deps = ["zlib"];
+fn acquire(ctx) { ctx }
+fn cleanup(ctx, reason) { ctx }
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("pkg.rhai"),
+            r#"//! extends: base.rhai
+let ctx = #{
+    name: "mypackage",
+    version: "2.0",
+    description: "A test package",
+    license: "MIT",
+    homepage: "https://example.com",
+    notes: `line one
+line two`,
+    meta: #{ name: "nested", version: "9" },
+};
+let build_deps = ["cmake", "ninja"];
+throw "top-level code must not run";
+fn install(ctx) { ctx }
+fn is_installed(ctx) { ctx }
+"#,
+        )
+        .unwrap();
+
+        let info = load_info(&dir, "pkg.rhai");
+        assert_eq!(info.name, "mypackage");
+        assert_eq!(info.version.as_deref(), Some("2.0"));
+        assert_eq!(info.description.as_deref(), Some("A test package"));
+        assert_eq!(info.license.as_deref(), Some("MIT"));
+        assert_eq!(info.homepage.as_deref(), Some("https://example.com"));
+        assert_eq!(info.deps, ["zlib"]);
+        assert_eq!(info.build_deps, ["cmake", "ninja"]);
+        assert_eq!(
+            info.hooks,
+            ["is_installed", "acquire", "install", "cleanup"]
+        );
+        assert_eq!(info.bases.len(), 1);
+    }
+
+    #[test]
+    fn test_info_falls_back_to_file_stem() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("test.rhai"), "let ctx = #{};").unwrap();
+        let info = load_info(&dir, "test.rhai");
+        assert_eq!(info.name, "test");
+        assert_eq!(info.version, None);
+
+        fs::write(
+            dir.path().join("computed.rhai"),
+            "let ctx = #{ name: \"x\" + RECIPE_DIR, version: \"3\" };",
+        )
+        .unwrap();
+        let info = load_info(&dir, "computed.rhai");
+        assert_eq!(info.name, "computed");
+        assert_eq!(info.version.as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn test_info_keeps_literal_fields_next_to_computed_ones() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("mixed.rhai"),
+            r#"let ctx = #{
+    name: "mixed",
+    version: "1.2",
+    url: RECIPE_DIR + "/mixed-1.2.tar.gz",
+    homepage: "https://example.com",
+};"#,
+        )
+        .unwrap();
+        let info = load_info(&dir, "mixed.rhai");
+        assert_eq!(info.name, "mixed");
+        assert_eq!(info.version.as_deref(), Some("1.2"));
+        assert_eq!(info.homepage.as_deref(), Some("https://example.com"));
+    }
+}
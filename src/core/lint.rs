@@ -0,0 +1,473 @@
+//! Static recipe checks (`recipe lint`)
+//!
+//! A recipe is compiled with [`compile_recipe`] (so `//! extends:` layers are
+//! resolved) and inspected without running anything:
+//! - `missing-cleanup`: no `cleanup(ctx, reason)` hook
+//! - `hook-arity`: a lifecycle hook defined with the wrong number of parameters
+//! - `phase-return`: a check or phase function that cannot return ctx (empty
+//!   body, or ending in a `let`, an assignment, a loop or a constant)
+//! - `unknown-function`: a call to a function that is neither a registered
+//!   helper, a Rhai built-in, nor defined in the recipe or its bases
+//! - `missing-ctx`: no `let ctx = #{...}`
+//! - `ctx-key`: the ctx literal has no `name` or `version`
+//! - `hardcoded-path`: an absolute path literal; recipes should build paths
+//!   from `BUILD_DIR`, `STAGE_DIR`, `PREFIX` and `SYSROOT`
+//!
+//! The first five are errors, the last two warnings.
+
+use super::executor::compile_recipe;
+use rhai::{AST, ASTNode, Engine, Expr, Stmt};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Hooks the executor calls, with the arities it accepts.
+const HOOKS: [(&str, &[usize]); 9] = [
+    ("is_installed", &[1]),
+    ("is_built", &[1]),
+    ("is_acquired", &[1]),
+    ("acquire", &[1]),
+    ("build", &[1]),
+    ("install", &[1]),
+    ("remove", &[1]),
+    ("cleanup", &[2]),
+    ("check_update", &[0, 1]),
+];
+
+/// Hooks that must return the (updated) ctx map.
+const CTX_HOOKS: [&str; 8] = [
+    "is_installed",
+    "is_built",
+    "is_acquired",
+    "acquire",
+    "build",
+    "install",
+    "remove",
+    "cleanup",
+];
+
+/// Functions the Rhai language itself provides.
+const KEYWORD_FNS: [&str; 9] = [
+    "print",
+    "debug",
+    "type_of",
+    "eval",
+    "Fn",
+    "call",
+    "curry",
+    "is_def_fn",
+    "is_def_var",
+];
+
+/// Absolute path prefixes that are fine to hardcode.
+const ALLOWED_PATHS: [&str; 3] = ["/dev/", "/proc/", "/sys/"];
+
+/// How serious a [`LintIssue`] is. Errors fail `recipe lint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One problem found in a recipe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintIssue {
+    pub severity: Severity,
+    /// Stable check name, e.g. `missing-cleanup`.
+    pub code: &'static str,
+    pub message: String,
+    /// File the problem is in: the recipe or one of its bases.
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+/// Result of linting one recipe.
+#[derive(Debug, Clone, Serialize)]
+pub struct LintReport {
+    pub recipe: PathBuf,
+    pub issues: Vec<LintIssue>,
+}
+
+impl LintReport {
+    pub fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == severity)
+            .count()
+    }
+}
+
+/// Lint a recipe without executing it.
+///
+/// A recipe that does not compile yields a single `compile` error.
+pub fn lint(engine: &Engine, recipe_path: &Path, search_path: Option<&Path>) -> LintReport {
+    let recipe = recipe_path
+        .canonicalize()
+        .unwrap_or_else(|_| recipe_path.to_path_buf());
+    let mut issues = Vec::new();
+    match compile_recipe(engine, &recipe, search_path) {
+        Ok(compiled) => {
+            // Layers in merge order; each is compiled alone so findings point
+            // at the file that contains them.
+            let layers: Vec<(PathBuf, AST)> = compiled
+                .bases
+                .iter()
+                .map(|base| (base.path.as_path(), base.source.as_str()))
+                .chain(std::iter::once((
+                    compiled.recipe_path.as_path(),
+                    compiled.recipe_source.as_str(),
+                )))
+                .filter_map(|(path, source)| {
+                    Some((path.to_path_buf(), engine.compile(source).ok()?))
+                })
+                .collect();
+            Linter {
+                known: known_functions(engine, &compiled.ast),
+                issues: &mut issues,
+            }
+            .run(&compiled.recipe_path, &compiled.ast, &layers);
+        }
+        Err(e) => issues.push(LintIssue {
+            severity: Severity::Error,
+            code: "compile",
+            message: format!("{e:#}"),
+            file: recipe.clone(),
+            line: None,
+        }),
+    }
+    issues.sort_by(|a, b| (&a.file, a.line, a.severity).cmp(&(&b.file, b.line, b.severity)));
+    LintReport { recipe, issues }
+}
+
+struct Linter<'a> {
+    known: HashSet<String>,
+    issues: &'a mut Vec<LintIssue>,
+}
+
+impl Linter<'_> {
+    fn run(&mut self, recipe: &Path, merged: &AST, layers: &[(PathBuf, AST)]) {
+        self.check_hooks(recipe, merged, layers);
+        self.check_ctx(recipe, layers);
+        for (file, ast) in layers {
+            self.check_nodes(file, ast);
+        }
+        self.check_returns(layers);
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        code: &'static str,
+        file: &Path,
+        line: Option<usize>,
+        message: String,
+    ) {
+        self.issues.push(LintIssue {
+            severity,
+            code,
+            message,
+            file: file.to_path_buf(),
+            line,
+        });
+    }
+
+    fn check_hooks(&mut self, recipe: &Path, merged: &AST, layers: &[(PathBuf, AST)]) {
+        for (name, arities) in HOOKS {
+            let expected = arities
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<_>>()
+                .join(" or ");
+            for (file, ast) in layers {
+                for f in ast.iter_fn_def().filter(|f| f.name == name) {
+                    if !arities.contains(&f.params.len()) {
+                        self.push(
+                            Severity::Error,
+                            "hook-arity",
+                            file,
+                            f.body.position().line(),
+                            format!(
+                                "`{name}` takes {} parameter(s); the executor calls it with {expected}",
+                                f.params.len()
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+        if !merged
+            .iter_functions()
+            .any(|f| f.name == "cleanup" && f.params.len() == 2)
+        {
+            self.push(
+                Severity::Error,
+                "missing-cleanup",
+                recipe,
+                None,
+                "missing required `fn cleanup(ctx, reason)`".to_string(),
+            );
+        }
+    }
+
+    /// The ctx literal in effect is the last top-level `let ctx` in merge
+    /// order.
+    fn check_ctx(&mut self, recipe: &Path, layers: &[(PathBuf, AST)]) {
+        let declaration = layers.iter().rev().find_map(|(file, ast)| {
+            ast.statements().iter().rev().find_map(|stmt| match stmt {
+                Stmt::Var(var, ..) if var.0.name == "ctx" => Some((file, &var.1, stmt.position())),
+                _ => None,
+            })
+        });
+        let Some((file, value, pos)) = declaration else {
+            self.push(
+                Severity::Error,
+                "missing-ctx",
+                recipe,
+                None,
+                "missing `let ctx = #{...}`".to_string(),
+            );
+            return;
+        };
+        let keys: BTreeSet<String> = match value {
+            Expr::Map(map, _) => map.0.iter().map(|(k, _)| k.name.to_string()).collect(),
+            Expr::DynamicConstant(value, _) => match value.read_lock::<rhai::Map>() {
+                Some(map) => map.keys().map(|k| k.to_string()).collect(),
+                None => return,
+            },
+            // Built at runtime; nothing to check statically.
+            _ => return,
+        };
+        for key in ["name", "version"] {
+            if !keys.contains(key) {
+                self.push(
+                    Severity::Warning,
+                    "ctx-key",
+                    file,
+                    pos.line(),
+                    format!("ctx has no `{key}`"),
+                );
+            }
+        }
+    }
+
+    /// Unknown function calls and hardcoded paths.
+    fn check_nodes(&mut self, file: &Path, ast: &AST) {
+        let mut found = Vec::new();
+        ast.walk(&mut |path: &[ASTNode]| {
+            let Some(node) = path.last() else {
+                return true;
+            };
+            let line = node.position().line();
+            match node {
+                ASTNode::Expr(Expr::FnCall(call, _)) | ASTNode::Stmt(Stmt::FnCall(call, _))
+                    if call.op_token.is_none()
+                        && call.namespace.is_empty()
+                        && !self.known.contains(call.name.as_str()) =>
+                {
+                    found.push((
+                        Severity::Error,
+                        "unknown-function",
+                        line,
+                        format!(
+                            "unknown function `{}`: not a helper and not defined in the recipe",
+                            call.name
+                        ),
+                    ));
+                }
+                ASTNode::Expr(Expr::StringConstant(s, _)) if is_suspicious_path(s, path) => {
+                    found.push((
+                        Severity::Warning,
+                        "hardcoded-path",
+                        line,
+                        format!(
+                            "hardcoded path \"{s}\"; build paths from BUILD_DIR, STAGE_DIR or PREFIX"
+                        ),
+                    ));
+                }
+                _ => {}
+            }
+            true
+        });
+        for (severity, code, line, message) in found {
+            self.push(severity, code, file, line, message);
+        }
+    }
+
+    /// Check that ctx hooks end in a value. Only the definition that wins the
+    /// merge (the last layer defining it) is checked.
+    fn check_returns(&mut self, layers: &[(PathBuf, AST)]) {
+        let mut seen = HashSet::new();
+        for (file, ast) in layers.iter().rev() {
+            for f in ast.iter_fn_def() {
+                if !CTX_HOOKS.contains(&f.name.as_str())
+                    || !seen.insert((f.name.to_string(), f.params.len()))
+                {
+                    continue;
+                }
+                if let Some(line) = no_value(f.body.statements(), f.body.position().line()) {
+                    self.push(
+                        Severity::Error,
+                        "phase-return",
+                        file,
+                        line,
+                        format!("`{}` does not return ctx; end it with `ctx`", f.name),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Where a block fails to produce a value, if it definitely does.
+///
+/// Calls, variables and other expressions may evaluate to ctx, so only blocks
+/// that are empty or end in a statement with no value (or a non-map constant)
+/// are reported.
+fn no_value(block: &[Stmt], block_line: Option<usize>) -> Option<Option<usize>> {
+    let Some(last) = block.last() else {
+        return Some(block_line);
+    };
+    let line = last.position().line();
+    match last {
+        // `return x` and `throw` both leave the function.
+        Stmt::Return(..) => None,
+        Stmt::If(flow, _) => no_value(flow.body.statements(), line)
+            .or_else(|| no_value(flow.branch.statements(), line)),
+        Stmt::TryCatch(flow, _) => no_value(flow.body.statements(), line)
+            .or_else(|| no_value(flow.branch.statements(), line)),
+        Stmt::Block(block) => no_value(block.statements(), line),
+        Stmt::Expr(expr) => match expr.as_ref() {
+            Expr::Stmt(block) => no_value(block.statements(), line),
+            Expr::Unit(_)
+            | Expr::BoolConstant(..)
+            | Expr::IntegerConstant(..)
+            | Expr::FloatConstant(..)
+            | Expr::CharConstant(..)
+            | Expr::StringConstant(..) => Some(line),
+            _ => None,
+        },
+        Stmt::Noop(_)
+        | Stmt::Var(..)
+        | Stmt::Assignment(..)
+        | Stmt::While(..)
+        | Stmt::Do(..)
+        | Stmt::For(..)
+        | Stmt::Import(..)
+        | Stmt::Export(..)
+        | Stmt::Share(..) => Some(line),
+        _ => None,
+    }
+}
+
+/// An absolute path literal that is not a suffix appended to a variable
+/// (`PREFIX + "/bin"`) or part of an interpolated string.
+fn is_suspicious_path(s: &str, path: &[ASTNode]) -> bool {
+    let looks_absolute = s.len() > 1
+        && s.starts_with('/')
+        && s[1..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '.' || c == '_')
+        && !s.contains(char::is_whitespace);
+    if !looks_absolute || ALLOWED_PATHS.iter().any(|p| s.starts_with(p)) {
+        return false;
+    }
+    let Some(ASTNode::Expr(parent)) = path.len().checked_sub(2).map(|i| &path[i]) else {
+        return true;
+    };
+    match parent {
+        Expr::FnCall(call, _) if call.op_token.is_some() => {
+            // Only the left-most operand starts a path.
+            call.args
+                .first()
+                .is_some_and(|first| matches!(first, Expr::StringConstant(first, _) if first == s))
+        }
+        Expr::InterpolatedString(..) => false,
+        _ => true,
+    }
+}
+
+/// Names a recipe may call: registered helpers and Rhai's packages, keyword
+/// functions, and the functions of the merged recipe.
+fn known_functions(engine: &Engine, merged: &AST) -> HashSet<String> {
+    engine
+        .gen_fn_signatures(true)
+        .iter()
+        .filter_map(|sig| sig.split('(').next())
+        .map(|name| name.trim_start_matches("fn ").to_string())
+        .chain(KEYWORD_FNS.iter().map(|name| name.to_string()))
+        .chain(merged.iter_functions().map(|f| f.name.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn lint_source(source: &str) -> Vec<(&'static str, Option<usize>)> {
+        let dir = TempDir::new().unwrap();
+        let recipe = dir.path().join("foo.rhai");
+        fs::write(&recipe, source).unwrap();
+        let mut engine = Engine::new();
+        crate::helpers::register_all(&mut engine);
+        lint(&engine, &recipe, None)
+            .issues
+            .into_iter()
+            .map(|i| (i.code, i.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_recipe_has_no_issues() {
+        let issues = lint_source(
+            r#"let ctx = #{ name: "foo", version: "1.0" };
+fn is_installed(ctx) { if !is_file(PREFIX + "/bin/foo") { throw "not installed"; } ctx }
+fn acquire(ctx) { ctx.src = BUILD_DIR + "/src"; mkdir(`${ctx.src}/x`); ctx }
+fn install(ctx) {
+    if ctx.version == "" { return ctx; }
+    ctx
+}
+fn cleanup(ctx, reason) { ctx }
+fn check_update() { github_latest_release("example/foo") }
+"#,
+        );
+        assert_eq!(issues, []);
+    }
+
+    #[test]
+    fn test_reports_each_check() {
+        let issues = lint_source(
+            r#"let ctx = #{ name: "foo" };
+fn is_built(ctx, extra) { ctx }
+fn acquire(ctx) { ctx.done = true; }
+fn build(ctx) { no_such_helper(ctx); ctx }
+fn install(ctx) { write_file("/usr/bin/foo", "x"); ctx }
+"#,
+        );
+        assert_eq!(
+            issues,
+            [
+                ("missing-cleanup", None),
+                ("ctx-key", Some(1)),
+                ("hook-arity", Some(2)),
+                ("phase-return", Some(3)),
+                ("unknown-function", Some(4)),
+                ("hardcoded-path", Some(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_error_is_reported() {
+        assert_eq!(lint_source("let ctx = #{"), [("compile", None)]);
+    }
+}
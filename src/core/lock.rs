@@ -11,7 +11,11 @@ use std::path::Path;
 /// Acquire an exclusive lock on a recipe file to prevent concurrent execution.
 /// Returns a guard that releases the lock when dropped.
 pub fn acquire_recipe_lock(recipe_path: &Path) -> Result<RecipeLock> {
-    let lock_path = recipe_path.with_extension("rhai.lock");
+    let lock_path = super::ctx_store::lock_file(recipe_path);
+    if let Some(parent) = lock_path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create lock directory: {}", parent.display()))?;
+    }
 
     // IMPORTANT:
     // - Do not delete the lock file on contention. Another process may legitimately hold the lock.
@@ -0,0 +1,122 @@
+//! Installed-file manifest stored in recipe ctx
+//!
+//! After a successful install the executor stores what the install phase
+//! created under two ctx keys:
+//! - `installed_files`: files and symlinks, as absolute paths
+//! - `installed_dirs`: directories that did not exist before the install
+//!
+//! `recipe remove` uses the manifest to uninstall recipes that do not define
+//! `remove(ctx)`, and to sweep up whatever a custom `remove(ctx)` left behind.
+
+use super::output;
+use crate::helpers::internal::tracking::Manifest;
+use rhai::Dynamic;
+use std::path::PathBuf;
+
+/// ctx key holding installed files and symlinks.
+pub const INSTALLED_FILES: &str = "installed_files";
+/// ctx key holding directories created by the install.
+pub const INSTALLED_DIRS: &str = "installed_dirs";
+
+/// Store a manifest in ctx, replacing any previous one.
+pub(crate) fn store(ctx: &mut rhai::Map, manifest: &Manifest) {
+    ctx.insert(INSTALLED_FILES.into(), to_array(&manifest.files));
+    ctx.insert(INSTALLED_DIRS.into(), to_array(&manifest.dirs));
+}
+
+/// Read the manifest from ctx. Missing keys read as empty.
+pub(crate) fn from_ctx(ctx: &rhai::Map) -> Manifest {
+    Manifest {
+        files: from_array(ctx.get(INSTALLED_FILES)),
+        dirs: from_array(ctx.get(INSTALLED_DIRS)),
+    }
+}
+
+/// Delete everything in the manifest: files first, then empty directories
+/// deepest first.
+///
+/// Returns the files that could not be deleted. Directories that still hold
+/// other content are left in place and dropped from the manifest.
+pub(crate) fn uninstall(manifest: &Manifest) -> Manifest {
+    let mut remaining = Manifest::default();
+
+    for file in &manifest.files {
+        match std::fs::remove_file(file) {
+            Ok(()) => output::detail(&format!("rm {}", file.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                output::warning(&format!("failed to remove {}: {e}", file.display()));
+                remaining.files.push(file.clone());
+            }
+        }
+    }
+
+    let mut dirs = manifest.dirs.clone();
+    dirs.sort_by_key(|d| std::cmp::Reverse(d.components().count()));
+    for dir in dirs {
+        match std::fs::remove_dir(&dir) {
+            Ok(()) => output::detail(&format!("rmdir {}", dir.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(_) => output::detail(&format!("keeping non-empty {}", dir.display())),
+        }
+    }
+
+    remaining
+}
+
+fn to_array(paths: &[PathBuf]) -> Dynamic {
+    paths
+        .iter()
+        .map(|p| Dynamic::from(p.to_string_lossy().to_string()))
+        .collect::<rhai::Array>()
+        .into()
+}
+
+fn from_array(value: Option<&Dynamic>) -> Vec<PathBuf> {
+    value
+        .and_then(|v| v.clone().try_cast::<rhai::Array>())
+        .map(|arr| {
+            arr.into_iter()
+                .filter_map(|v| v.into_string().ok())
+                .map(PathBuf::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_ctx_roundtrip() {
+        let manifest = Manifest {
+            files: vec![PathBuf::from("/usr/bin/foo")],
+            dirs: vec![PathBuf::from("/usr/share/foo")],
+        };
+        let mut ctx = rhai::Map::new();
+        store(&mut ctx, &manifest);
+        assert_eq!(from_ctx(&ctx), manifest);
+        assert!(from_ctx(&rhai::Map::new()).is_empty());
+    }
+
+    #[test]
+    fn test_uninstall_keeps_shared_dirs() {
+        let dir = TempDir::new().unwrap();
+        let own = dir.path().join("share/pkg");
+        let shared = dir.path().join("share");
+        std::fs::create_dir_all(&own).unwrap();
+        std::fs::write(own.join("data"), "x").unwrap();
+        std::fs::write(shared.join("other"), "y").unwrap();
+
+        let remaining = uninstall(&Manifest {
+            files: vec![own.join("data")],
+            dirs: vec![shared.clone(), own.clone()],
+        });
+
+        assert!(remaining.is_empty());
+        assert!(!own.exists());
+        assert!(shared.join("other").exists());
+    }
+}
@@ -0,0 +1,180 @@
+//! Download mirrors (`recipe/mirrors.toml`)
+//!
+//! A mirror table rewrites download URLs by prefix:
+//!
+//! ```toml
+//! [mirrors]
+//! "https://ftp.gnu.org/gnu/" = ["https://mirror.example.org/gnu/"]
+//! ```
+//!
+//! The system files (`$XDG_CONFIG_DIRS/recipe/mirrors.toml`) are read first
+//! and the user file (`$XDG_CONFIG_HOME/recipe/mirrors.toml`) last; a later
+//! entry replaces an earlier one for the same prefix.
+//!
+//! `download` tries the mirrors of the longest matching prefix in order and
+//! falls back to the original URL last.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// URL prefix → mirror prefixes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MirrorTable {
+    rules: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MirrorsToml {
+    #[serde(default)]
+    mirrors: BTreeMap<String, Mirrors>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Mirrors {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl MirrorTable {
+    pub fn new(rules: BTreeMap<String, Vec<String>>) -> Self {
+        Self { rules }
+    }
+
+    /// Read `recipe/mirrors.toml` from the XDG config dirs.
+    ///
+    /// Missing files are skipped, so no configuration yields an empty table.
+    pub fn xdg() -> Result<Self> {
+        let mut files: Vec<PathBuf> = std::env::var("XDG_CONFIG_DIRS")
+            .unwrap_or_else(|_| "/etc/xdg".to_owned())
+            .split(':')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|dir| Path::new(dir).join("recipe/mirrors.toml"))
+            .collect();
+        let config_home = std::env::var("XDG_CONFIG_HOME")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(PathBuf::from)
+            .or_else(dirs::config_dir);
+        if let Some(home) = config_home {
+            files.push(home.join("recipe/mirrors.toml"));
+        }
+
+        let mut rules = BTreeMap::new();
+        for file in files.iter().filter(|f| f.is_file()) {
+            rules.extend(read_file(file)?);
+        }
+        Ok(Self::new(rules))
+    }
+
+    /// Read a single `mirrors.toml`.
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::new(read_file(path)?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// URLs to try for `url`: its mirrors in order, then `url` itself.
+    pub fn candidates(&self, url: &str) -> Vec<String> {
+        let rule = self
+            .rules
+            .iter()
+            .filter(|(prefix, _)| url.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len());
+        let mut candidates: Vec<String> = match rule {
+            Some((prefix, mirrors)) => mirrors
+                .iter()
+                .map(|mirror| format!("{mirror}{}", &url[prefix.len()..]))
+                .collect(),
+            None => Vec::new(),
+        };
+        if !candidates.iter().any(|c| c == url) {
+            candidates.push(url.to_string());
+        }
+        candidates
+    }
+}
+
+fn read_file(path: &Path) -> Result<BTreeMap<String, Vec<String>>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let parsed: MirrorsToml =
+        toml::from_str(&text).with_context(|| format!("Invalid TOML in {}", path.display()))?;
+    Ok(parsed
+        .mirrors
+        .into_iter()
+        .map(|(prefix, mirrors)| match mirrors {
+            Mirrors::One(mirror) => (prefix, vec![mirror]),
+            Mirrors::Many(mirrors) => (prefix, mirrors),
+        })
+        .collect())
+}
+
+thread_local! {
+    static ACTIVE: RefCell<MirrorTable> = RefCell::new(MirrorTable::default());
+}
+
+/// Run `f` with `mirrors` as the mirror table on this thread.
+pub(crate) fn scoped<R>(mirrors: &MirrorTable, f: impl FnOnce() -> R) -> R {
+    let previous = ACTIVE.with(|a| a.replace(mirrors.clone()));
+    let result = f();
+    ACTIVE.with(|a| *a.borrow_mut() = previous);
+    result
+}
+
+/// URLs to try for `url` under the active mirror table.
+pub(crate) fn candidates(url: &str) -> Vec<String> {
+    ACTIVE.with(|a| a.borrow().candidates(url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_mirrors_rewrite_longest_prefix_then_original() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("mirrors.toml");
+        fs::write(
+            &file,
+            r#"[mirrors]
+"https://ftp.gnu.org/" = "https://gnu.local/"
+"https://ftp.gnu.org/gnu/bash/" = ["https://bash.local/", "https://ftpmirror.gnu.org/bash/"]
+"#,
+        )
+        .unwrap();
+        let table = MirrorTable::load(&file).unwrap();
+
+        assert_eq!(
+            table.candidates("https://ftp.gnu.org/gnu/bash/bash-5.2.tar.gz"),
+            [
+                "https://bash.local/bash-5.2.tar.gz",
+                "https://ftpmirror.gnu.org/bash/bash-5.2.tar.gz",
+                "https://ftp.gnu.org/gnu/bash/bash-5.2.tar.gz",
+            ]
+        );
+        assert_eq!(
+            table.candidates("https://ftp.gnu.org/gnu/make/make-4.4.tar.gz"),
+            [
+                "https://gnu.local/gnu/make/make-4.4.tar.gz",
+                "https://ftp.gnu.org/gnu/make/make-4.4.tar.gz",
+            ]
+        );
+        assert_eq!(
+            table.candidates("https://example.com/x.tar.gz"),
+            ["https://example.com/x.tar.gz"]
+        );
+
+        fs::write(&file, "[mirrors]\n\"https://a/\" = 1\n").unwrap();
+        assert!(MirrorTable::load(&file).is_err());
+    }
+}
@@ -4,9 +4,28 @@
 
 mod autofix;
 pub mod build_deps;
+pub mod cache;
+pub mod catalog;
 pub mod ctx;
+pub mod ctx_store;
+pub mod deps;
 pub mod events;
 pub mod executor;
+pub mod format;
+pub mod history;
+pub mod info;
+pub mod lint;
 pub mod lock;
+pub mod manifest;
+pub mod mirrors;
+pub(crate) mod offline;
+pub mod orphans;
 pub mod output;
+pub mod ownership;
+pub mod plan;
+pub mod repos;
 pub mod runner;
+pub mod schema;
+pub mod search;
+pub(crate) mod stage;
+pub mod update;
@@ -0,0 +1,105 @@
+//! Offline mode (`--offline`)
+//!
+//! With offline mode active on the current thread, network helpers
+//! (`download`, `download_with_resume`, `torrent`, `http_get`,
+//! `fetch_sha256`, `git_clone`, the `github_*` helpers and the `llm_*`
+//! helpers) never open a connection. Helpers backed by the download cache
+//! (see [`super::cache`]) still serve URLs it has; everything else fails
+//! immediately with an `offline:` error naming the recipe and the URL, instead
+//! of waiting for a timeout.
+//!
+//! The executor records which recipe is running, so the error (and helper
+//! hook events) can name it and helpers can resolve paths against its
+//! directory.
+
+use super::deps::recipe_name;
+use rhai::EvalAltResult;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+
+thread_local! {
+    static OFFLINE: Cell<bool> = const { Cell::new(false) };
+    static RECIPE: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with offline mode on or off on this thread.
+pub(crate) fn scoped<R>(offline: bool, f: impl FnOnce() -> R) -> R {
+    let previous = OFFLINE.with(|o| o.replace(offline));
+    let result = f();
+    OFFLINE.with(|o| o.set(previous));
+    result
+}
+
+/// Run `f` as recipe `recipe_path`, so offline errors name it.
+pub(crate) fn running<R>(recipe_path: &Path, f: impl FnOnce() -> R) -> R {
+    let previous = RECIPE.with(|r| r.replace(Some(recipe_path.to_path_buf())));
+    let result = f();
+    RECIPE.with(|r| *r.borrow_mut() = previous);
+    result
+}
+
+/// Name of the recipe running on this thread, or `recipe` outside one.
+pub(crate) fn current_recipe() -> String {
+    RECIPE
+        .with(|r| r.borrow().as_deref().map(recipe_name))
+        .unwrap_or_else(|| "recipe".to_string())
+}
+
+/// Directory of the recipe running on this thread (its `RECIPE_DIR`).
+pub(crate) fn current_recipe_dir() -> Option<PathBuf> {
+    RECIPE.with(|r| {
+        r.borrow()
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+    })
+}
+
+pub(crate) fn is_offline() -> bool {
+    OFFLINE.with(Cell::get)
+}
+
+/// Fail if offline mode is on; `target` is the URL (or service) about to be
+/// contacted.
+pub(crate) fn check(target: &str) -> Result<(), Box<EvalAltResult>> {
+    refuse(target, "")
+}
+
+/// [`check`] for a helper that already looked in the download cache.
+pub(crate) fn check_uncached(url: &str) -> Result<(), Box<EvalAltResult>> {
+    refuse(url, " (not in the download cache)")
+}
+
+fn refuse(target: &str, note: &str) -> Result<(), Box<EvalAltResult>> {
+    if !is_offline() {
+        return Ok(());
+    }
+    let recipe = current_recipe();
+    Err(format!("offline: {recipe} needs {target}{note}, but network access is disabled").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_errors_name_recipe_and_url() {
+        let url = "https://example.com/foo.tar.gz";
+        assert!(check(url).is_ok());
+
+        let err = scoped(true, || {
+            running(Path::new("/recipes/foo.rhai"), || check_uncached(url))
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.contains(
+                "offline: foo needs https://example.com/foo.tar.gz (not in the download cache)"
+            ),
+            "{err}"
+        );
+
+        assert!(!is_offline());
+        assert!(check(url).is_ok());
+    }
+}
@@ -0,0 +1,184 @@
+//! Install reasons and orphan detection
+//!
+//! Every successful install records why the recipe is installed in its ctx
+//! (`install_reason`):
+//! - `"explicit"`: installed by `recipe install` (or [`crate::RecipeEngine::execute`])
+//! - `"dependency"`: installed only because another recipe listed it in `deps`
+//!   or `build_deps`
+//!
+//! `recipe remove` clears the key, so a recipe counts as installed while it has
+//! one. An orphan is a dependency-only recipe that no explicitly installed
+//! recipe still needs through a chain of `deps`. `build_deps` do not keep a
+//! recipe alive: they are only needed while building.
+
+use super::ctx_store;
+use super::deps::DepGraph;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+/// ctx key holding the install reason.
+pub const INSTALL_REASON: &str = "install_reason";
+
+/// Why a recipe is installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallReason {
+    Explicit,
+    Dependency,
+}
+
+impl InstallReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Explicit => "explicit",
+            Self::Dependency => "dependency",
+        }
+    }
+
+    /// Read the install reason from ctx. Missing or unknown values read as `None`.
+    pub fn from_ctx(ctx: &rhai::Map) -> Option<Self> {
+        match ctx
+            .get(INSTALL_REASON)?
+            .clone()
+            .into_string()
+            .ok()?
+            .as_str()
+        {
+            "explicit" => Some(Self::Explicit),
+            "dependency" => Some(Self::Dependency),
+            _ => None,
+        }
+    }
+}
+
+/// Record `reason` in ctx. An explicit install is never downgraded to a
+/// dependency one. Returns `true` when ctx changed.
+pub(crate) fn mark(ctx: &mut rhai::Map, reason: InstallReason) -> bool {
+    let current = InstallReason::from_ctx(ctx);
+    if current == Some(reason) || current == Some(InstallReason::Explicit) {
+        return false;
+    }
+    ctx.insert(INSTALL_REASON.into(), reason.as_str().into());
+    true
+}
+
+/// A dependency-only recipe nothing explicitly installed still needs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Orphan {
+    pub name: String,
+    pub recipe_path: PathBuf,
+}
+
+/// Install reason of every installed recipe in `graph`, read from persisted ctx.
+pub fn install_reasons(graph: &DepGraph) -> BTreeMap<String, InstallReason> {
+    graph
+        .nodes()
+        .iter()
+        .filter_map(|(name, node)| {
+            let source = std::fs::read_to_string(&node.recipe_path).ok()?;
+            let reason =
+                InstallReason::from_ctx(&ctx_store::persisted(&node.recipe_path, &source)?)?;
+            Some((name.clone(), reason))
+        })
+        .collect()
+}
+
+/// Orphaned recipes in removal order (dependents before their dependencies).
+pub fn find_orphans(
+    graph: &DepGraph,
+    reasons: &BTreeMap<String, InstallReason>,
+) -> Result<Vec<Orphan>> {
+    // Everything reachable from an explicit install through `deps` is needed.
+    let mut needed = HashSet::new();
+    let mut stack: Vec<&String> = reasons
+        .iter()
+        .filter(|(_, r)| **r == InstallReason::Explicit)
+        .map(|(name, _)| name)
+        .collect();
+    while let Some(name) = stack.pop() {
+        let Some(node) = graph.get(name) else {
+            continue;
+        };
+        for dep in &node.deps {
+            if reasons.contains_key(dep) && needed.insert(dep.clone()) {
+                stack.push(dep);
+            }
+        }
+    }
+
+    let orphans: Vec<String> = reasons
+        .iter()
+        .filter(|(name, r)| **r == InstallReason::Dependency && !needed.contains(*name))
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    // Install order puts dependencies first; removal needs the reverse.
+    let mut order = graph.install_order("", &orphans)?;
+    order.retain(|name| orphans.contains(name));
+    order.reverse();
+    Ok(order
+        .into_iter()
+        .map(|name| Orphan {
+            recipe_path: graph
+                .get(&name)
+                .expect("ordered nodes exist")
+                .recipe_path
+                .clone(),
+            name,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::deps::DepNode;
+
+    #[test]
+    fn test_mark_never_downgrades_explicit() {
+        let mut ctx = rhai::Map::new();
+        assert!(mark(&mut ctx, InstallReason::Dependency));
+        assert!(mark(&mut ctx, InstallReason::Explicit));
+        assert!(!mark(&mut ctx, InstallReason::Dependency));
+        assert_eq!(InstallReason::from_ctx(&ctx), Some(InstallReason::Explicit));
+    }
+
+    #[test]
+    fn test_find_orphans_in_removal_order() {
+        let mut graph = DepGraph::default();
+        for (name, deps, build_deps) in [
+            ("app", vec!["lib"], vec!["cc"]),
+            ("lib", vec!["core"], vec![]),
+            ("core", vec![], vec![]),
+            ("cc", vec![], vec![]),
+            ("old", vec!["oldlib"], vec![]),
+            ("oldlib", vec!["core"], vec![]),
+        ] {
+            graph.insert(
+                name,
+                DepNode {
+                    recipe_path: PathBuf::from(format!("{name}.rhai")),
+                    deps: deps.into_iter().map(String::from).collect(),
+                    build_deps: build_deps.into_iter().map(String::from).collect(),
+                },
+            );
+        }
+        let reasons = BTreeMap::from([
+            ("app".to_string(), InstallReason::Explicit),
+            ("lib".to_string(), InstallReason::Dependency),
+            ("core".to_string(), InstallReason::Dependency),
+            ("cc".to_string(), InstallReason::Dependency),
+            ("oldlib".to_string(), InstallReason::Dependency),
+        ]);
+
+        let names: Vec<String> = find_orphans(&graph, &reasons)
+            .unwrap()
+            .into_iter()
+            .map(|o| o.name)
+            .collect();
+        // `old` is not installed, so `oldlib` is orphaned; `cc` was only a build dep.
+        assert_eq!(names, ["oldlib", "cc"]);
+    }
+}
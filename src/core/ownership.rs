@@ -0,0 +1,136 @@
+//! File ownership across installed recipes
+//!
+//! Every installed recipe records its files in ctx (`installed_files`, see
+//! [`super::manifest`]). The ownership index reads the persisted ctx of every
+//! recipe in the recipes directory and the configured repositories and maps
+//! each installed path back to the recipe(s) that own it. It backs
+//! `recipe owns` and the conflict check that runs before the install phase.
+
+use super::{ctx_store, manifest, repos};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A recipe that lists a path in its installed-file manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileOwner {
+    /// Recipe name (`ctx.name`, falling back to the file stem).
+    pub name: String,
+    pub recipe_path: PathBuf,
+}
+
+/// Installed path -> owning recipes, built from persisted ctx.
+#[derive(Debug, Clone, Default)]
+pub struct OwnershipIndex {
+    owners: HashMap<PathBuf, Vec<FileOwner>>,
+}
+
+impl OwnershipIndex {
+    /// Index every recipe under `recipes_path` and the active repositories.
+    pub fn build(recipes_path: &Path) -> Self {
+        Self::build_excluding(recipes_path, &[])
+    }
+
+    /// Index every recipe under `recipes_path` except `exclude`.
+    ///
+    /// Used by install to leave out the recipe being installed (and its base
+    /// recipe), so reinstalls never conflict with themselves.
+    pub fn build_excluding(recipes_path: &Path, exclude: &[PathBuf]) -> Self {
+        let exclude: Vec<PathBuf> = exclude.iter().map(|p| canonical(p)).collect();
+        let mut index = Self::default();
+        for recipe_path in repos::active().recipe_files(Some(recipes_path)) {
+            if exclude.contains(&canonical(&recipe_path)) {
+                continue;
+            }
+            let Some(ctx_map) = std::fs::read_to_string(&recipe_path)
+                .ok()
+                .and_then(|source| ctx_store::persisted(&recipe_path, &source))
+            else {
+                continue;
+            };
+            let name = ctx_map
+                .get("name")
+                .and_then(|v| v.clone().into_string().ok())
+                .unwrap_or_else(|| {
+                    recipe_path
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                });
+            for file in manifest::from_ctx(&ctx_map).files {
+                index.owners.entry(file).or_default().push(FileOwner {
+                    name: name.clone(),
+                    recipe_path: recipe_path.clone(),
+                });
+            }
+        }
+        index
+    }
+
+    /// Recipes owning `path` (exact match on the recorded absolute path).
+    pub fn owners(&self, path: &Path) -> &[FileOwner] {
+        self.owners.get(path).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+
+    /// Each owned path with a short description of its owners, for the
+    /// install-time conflict check.
+    pub(crate) fn claims(&self) -> HashMap<PathBuf, String> {
+        self.owners
+            .iter()
+            .map(|(path, owners)| {
+                let desc = owners
+                    .iter()
+                    .map(|o| format!("{} ({})", o.name, o.recipe_path.display()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                (path.clone(), desc)
+            })
+            .collect()
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_index_maps_files_to_recipes() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("a.rhai"),
+            "let ctx = #{ name: \"alpha\", installed_files: [\"/usr/bin/a\", \"/usr/bin/shared\"] };",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("b.rhai"),
+            "let ctx = #{ installed_files: [\"/usr/bin/shared\"] };",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("c.rhai"), "let ctx = #{ name: \"c\" };").unwrap();
+
+        let index = OwnershipIndex::build(dir.path());
+        let names = |p: &str| -> Vec<String> {
+            index
+                .owners(Path::new(p))
+                .iter()
+                .map(|o| o.name.clone())
+                .collect()
+        };
+        assert_eq!(names("/usr/bin/a"), ["alpha"]);
+        assert_eq!(names("/usr/bin/shared"), ["alpha", "b"]);
+        assert!(names("/usr/bin/c").is_empty());
+
+        let without_a = OwnershipIndex::build_excluding(dir.path(), &[dir.path().join("a.rhai")]);
+        assert!(without_a.owners(Path::new("/usr/bin/a")).is_empty());
+    }
+}
@@ -0,0 +1,45 @@
+//! Install plans: what `recipe install` would do, without doing it.
+//!
+//! A plan is produced by running the recipe top-level and its `is_*` checks,
+//! then stopping before any phase executes. Dependencies are located and
+//! checked, never installed.
+
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Result of evaluating a recipe's checks without running any phase.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InstallPlan {
+    /// Recipe name (`ctx.name`, falling back to the file stem).
+    pub name: String,
+    pub recipe_path: PathBuf,
+    /// `false` when `is_installed(ctx)` passes and install would be a no-op.
+    pub needs_install: bool,
+    /// Checks in the order the install flow evaluates them.
+    pub checks: Vec<PlannedCheck>,
+    /// Phases install would run, in order (`acquire`, `build`, `install`).
+    pub phases: Vec<String>,
+    /// Runtime dependency recipes (`deps`), resolved before any phase.
+    pub deps: Vec<PlannedDependency>,
+    /// Build-time dependency recipes (`build_deps`), only listed when `build` is planned.
+    pub build_deps: Vec<PlannedDependency>,
+}
+
+/// Outcome of a single `is_*` check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedCheck {
+    pub check: String,
+    /// `true` when the check threw (the matching phase is needed).
+    pub needs_work: bool,
+    /// Thrown message, or a note when the check function is missing.
+    pub reason: Option<String>,
+}
+
+/// A dependency recipe that was located and checked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedDependency {
+    pub name: String,
+    pub recipe_path: PathBuf,
+    /// `true` when the dependency's `is_installed(ctx)` throws.
+    pub needs_install: bool,
+}
@@ -0,0 +1,348 @@
+//! Recipe repositories (`recipe/repos.toml`)
+//!
+//! Besides `--recipes-path`, recipes can come from named repositories listed
+//! in `recipe/repos.toml` under the XDG config dirs:
+//!
+//! ```toml
+//! [repos.rocky10]
+//! path = "/etc/recipe/repos/rocky10"
+//! priority = 10
+//! ```
+//!
+//! The system files (`$XDG_CONFIG_DIRS/recipe/repos.toml`) are read first and
+//! the user file (`$XDG_CONFIG_HOME/recipe/repos.toml`) last; a later entry
+//! replaces an earlier one of the same name. Relative paths are resolved
+//! against the file's directory.
+//!
+//! The recipes path is always searched first, as the repository `local`;
+//! configured repositories follow by descending priority, then by name. A
+//! recipe name resolves to the first repository that has `<name>.rhai`, and
+//! `<repo>/<name>` picks the recipe from one repository. Recipe lookup, the
+//! dependency graph, `//! extends:` bases and `import` all use this order.
+
+use anyhow::{Context, Result, bail};
+use rhai::module_resolvers::FileModuleResolver;
+use rhai::{Engine, EvalAltResult, Module, ModuleResolver, Position, Shared};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Name of the repository formed by the recipes path.
+pub const LOCAL_REPO: &str = "local";
+
+/// A named recipe directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Repo {
+    pub name: String,
+    pub path: PathBuf,
+    /// Higher priorities are searched first.
+    pub priority: i64,
+}
+
+/// Configured repositories in search order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepoSet {
+    repos: Vec<Repo>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReposToml {
+    #[serde(default)]
+    repos: BTreeMap<String, RepoToml>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RepoToml {
+    path: PathBuf,
+    #[serde(default)]
+    priority: i64,
+}
+
+impl RepoSet {
+    /// Repositories sorted into search order.
+    ///
+    /// Fails on an empty name, a name containing `/`, a name used twice, or
+    /// the reserved name `local`.
+    pub fn new(mut repos: Vec<Repo>) -> Result<Self> {
+        let mut seen = HashSet::new();
+        for repo in &repos {
+            if repo.name.is_empty() || repo.name.contains('/') {
+                bail!("Invalid repository name '{}'", repo.name);
+            }
+            if repo.name == LOCAL_REPO {
+                bail!("Repository name '{LOCAL_REPO}' is reserved for --recipes-path");
+            }
+            if !seen.insert(repo.name.as_str()) {
+                bail!("Repository '{}' is configured twice", repo.name);
+            }
+        }
+        repos.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.name.cmp(&b.name)));
+        Ok(Self { repos })
+    }
+
+    /// Read `recipe/repos.toml` from the XDG config dirs.
+    ///
+    /// Missing files are skipped, so no configuration yields an empty set.
+    pub fn xdg() -> Result<Self> {
+        let mut files: Vec<PathBuf> = std::env::var("XDG_CONFIG_DIRS")
+            .unwrap_or_else(|_| "/etc/xdg".to_owned())
+            .split(':')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|dir| Path::new(dir).join("recipe/repos.toml"))
+            .collect();
+        let config_home = std::env::var("XDG_CONFIG_HOME")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .map(PathBuf::from)
+            .or_else(dirs::config_dir);
+        if let Some(home) = config_home {
+            files.push(home.join("recipe/repos.toml"));
+        }
+
+        let mut repos = BTreeMap::new();
+        for file in files.iter().filter(|f| f.is_file()) {
+            for repo in read_file(file)? {
+                repos.insert(repo.name.clone(), repo);
+            }
+        }
+        Self::new(repos.into_values().collect())
+    }
+
+    /// Read a single `repos.toml`.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::new(read_file(path)?)
+    }
+
+    /// Configured repositories, in search order.
+    pub fn repos(&self) -> &[Repo] {
+        &self.repos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.repos.is_empty()
+    }
+
+    /// Every repository to search: `recipes_path` first (as `local`), then
+    /// the configured ones. A configured repository pointing at the recipes
+    /// path is left out.
+    pub fn search_order(&self, recipes_path: Option<&Path>) -> Vec<Repo> {
+        let mut order: Vec<Repo> = recipes_path
+            .map(|path| Repo {
+                name: LOCAL_REPO.to_string(),
+                path: path.to_path_buf(),
+                priority: i64::MAX,
+            })
+            .into_iter()
+            .collect();
+        for repo in &self.repos {
+            if !order.iter().any(|r| same_dir(&r.path, &repo.path)) {
+                order.push(repo.clone());
+            }
+        }
+        order
+    }
+
+    /// Locate recipe `name` (`<name>` or `<repo>/<name>`, with or without
+    /// `.rhai`).
+    pub fn find(&self, recipes_path: Option<&Path>, name: &str) -> Option<PathBuf> {
+        let order = self.search_order(recipes_path);
+        let file_in = |repo: &Repo, name: &str| {
+            let file = repo.path.join(name);
+            let file = if file.extension().is_some_and(|e| e == "rhai") {
+                file
+            } else {
+                repo.path.join(format!("{name}.rhai"))
+            };
+            file.is_file().then_some(file)
+        };
+        if let Some((repo, rest)) = name.split_once('/')
+            && let Some(repo) = order.iter().find(|r| r.name == repo)
+        {
+            return file_in(repo, rest);
+        }
+        order.iter().find_map(|repo| file_in(repo, name))
+    }
+
+    /// Recipe files of every repository in search order. A recipe shadowed by
+    /// one with the same file name in an earlier repository is left out.
+    pub fn recipe_files(&self, recipes_path: Option<&Path>) -> Vec<PathBuf> {
+        let mut seen = HashSet::new();
+        let mut files = Vec::new();
+        for repo in self.search_order(recipes_path) {
+            for file in super::catalog::recipe_files(&repo.path) {
+                if seen.insert(super::deps::recipe_name(&file)) {
+                    files.push(file);
+                }
+            }
+        }
+        files
+    }
+
+    /// Rhai module resolver for `import`, following the search order.
+    pub(crate) fn module_resolver(&self, recipes_path: Option<&Path>) -> RepoModuleResolver {
+        RepoModuleResolver {
+            repos: self
+                .search_order(recipes_path)
+                .into_iter()
+                .map(|repo| {
+                    let resolver = FileModuleResolver::new_with_path(&repo.path);
+                    (repo.name, resolver)
+                })
+                .collect(),
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<Repo>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let parsed: ReposToml =
+        toml::from_str(&text).with_context(|| format!("Invalid TOML in {}", path.display()))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    Ok(parsed
+        .repos
+        .into_iter()
+        .map(|(name, repo)| Repo {
+            name,
+            path: base.join(repo.path),
+            priority: repo.priority,
+        })
+        .collect())
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Resolves `import "name"` in the first repository that has `name.rhai`,
+/// and `import "<repo>/name"` in that repository only.
+pub(crate) struct RepoModuleResolver {
+    repos: Vec<(String, FileModuleResolver)>,
+}
+
+impl ModuleResolver for RepoModuleResolver {
+    fn resolve(
+        &self,
+        engine: &Engine,
+        source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Result<Shared<Module>, Box<EvalAltResult>> {
+        if let Some((repo, rest)) = path.split_once('/')
+            && let Some((_, resolver)) = self.repos.iter().find(|(name, _)| name == repo)
+        {
+            return resolver.resolve(engine, source, rest, pos);
+        }
+        for (_, resolver) in &self.repos {
+            match resolver.resolve(engine, source, path, pos) {
+                Err(e) if matches!(*e, EvalAltResult::ErrorModuleNotFound(..)) => continue,
+                result => return result,
+            }
+        }
+        Err(EvalAltResult::ErrorModuleNotFound(path.to_string(), pos).into())
+    }
+}
+
+thread_local! {
+    static ACTIVE: RefCell<RepoSet> = RefCell::new(RepoSet::default());
+}
+
+/// Run `f` with `repos` as the configured repositories on this thread.
+pub(crate) fn scoped<R>(repos: &RepoSet, f: impl FnOnce() -> R) -> R {
+    let previous = ACTIVE.with(|a| a.replace(repos.clone()));
+    let result = f();
+    ACTIVE.with(|a| *a.borrow_mut() = previous);
+    result
+}
+
+/// The repositories active on this thread.
+pub(crate) fn active() -> RepoSet {
+    ACTIVE.with(|a| a.borrow().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn repo(name: &str, path: &Path, priority: i64) -> Repo {
+        Repo {
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            priority,
+        }
+    }
+
+    #[test]
+    fn test_repos_search_order_and_qualified_names() {
+        let dir = TempDir::new().unwrap();
+        let (local, low, high) = (
+            dir.path().join("local"),
+            dir.path().join("low"),
+            dir.path().join("high"),
+        );
+        for (path, files) in [
+            (&local, &["ripgrep"][..]),
+            (&low, &["less", "ripgrep"]),
+            (&high, &["less"]),
+        ] {
+            fs::create_dir_all(path).unwrap();
+            for file in files {
+                fs::write(path.join(format!("{file}.rhai")), "").unwrap();
+            }
+        }
+
+        let set = RepoSet::new(vec![repo("low", &low, 1), repo("high", &high, 5)]).unwrap();
+        let names: Vec<_> = set
+            .search_order(Some(&local))
+            .into_iter()
+            .map(|r| r.name)
+            .collect();
+        assert_eq!(names, ["local", "high", "low"]);
+
+        assert_eq!(
+            set.find(Some(&local), "ripgrep"),
+            Some(local.join("ripgrep.rhai"))
+        );
+        assert_eq!(set.find(Some(&local), "less"), Some(high.join("less.rhai")));
+        assert_eq!(
+            set.find(Some(&local), "low/less"),
+            Some(low.join("less.rhai"))
+        );
+        assert_eq!(set.find(Some(&local), "high/ripgrep"), None);
+        assert_eq!(
+            set.recipe_files(Some(&local)),
+            [local.join("ripgrep.rhai"), high.join("less.rhai")]
+        );
+
+        assert!(RepoSet::new(vec![repo("local", &low, 0)]).is_err());
+        assert!(RepoSet::new(vec![repo("a/b", &low, 0)]).is_err());
+    }
+
+    #[test]
+    fn test_repos_toml_resolves_relative_paths() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("repos.toml");
+        fs::write(
+            &file,
+            "[repos.rocky10]\npath = \"rocky10\"\npriority = 10\n\n[repos.extra]\npath = \"/srv/extra\"\n",
+        )
+        .unwrap();
+        let set = RepoSet::load(&file).unwrap();
+        assert_eq!(
+            set.repos(),
+            [
+                repo("rocky10", &dir.path().join("rocky10"), 10),
+                repo("extra", Path::new("/srv/extra"), 0),
+            ]
+        );
+
+        fs::write(&file, "[repos.bad]\npriority = 1\n").unwrap();
+        assert!(RepoSet::load(&file).is_err());
+    }
+}
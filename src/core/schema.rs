@@ -0,0 +1,293 @@
+//! Optional ctx schema (`ctx_schema`)
+//!
+//! A recipe may declare which ctx keys it uses next to its ctx:
+//!
+//! ```rhai
+//! let ctx_schema = #{
+//!     name: "string",
+//!     version: "string",
+//!     jobs: #{ type: "int", required: false },
+//!     channel: #{ type: "string", values: ["stable", "beta"] },
+//! };
+//! ```
+//!
+//! A string declares a required key of that type. A map may set `type`
+//! (default `"any"`), `required` (default `true`) and `values`, the allowed
+//! values. Types are `string`, `int`, `float`, `bool`, `array`, `map`, `unit`
+//! and `any`.
+//!
+//! The executor validates ctx after the top-level script runs and after every
+//! phase. Undeclared keys are errors, so a typo cannot silently create a new
+//! field; keys the executor itself writes (`installed_files`, `installed_dirs`,
+//! `install_reason`) are always allowed.
+
+use super::{manifest, orphans};
+use anyhow::{Result, anyhow, bail};
+use rhai::Dynamic;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Name of the top-level schema variable.
+pub const CTX_SCHEMA: &str = "ctx_schema";
+
+/// Keys managed by the executor, valid in every ctx.
+const EXECUTOR_KEYS: [&str; 3] = [
+    manifest::INSTALLED_FILES,
+    manifest::INSTALLED_DIRS,
+    orphans::INSTALL_REASON,
+];
+
+const TYPES: [&str; 8] = [
+    "string", "int", "float", "bool", "array", "map", "unit", "any",
+];
+
+/// ctx that does not match the recipe's `ctx_schema`.
+///
+/// A contract error between the recipe and its caller: the executor reports it
+/// as fatal, so it never starts the autofix loop.
+#[derive(Debug)]
+pub(crate) struct SchemaViolation(String);
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SchemaViolation {}
+
+/// Parsed `ctx_schema`.
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CtxSchema {
+    fields: BTreeMap<String, Field>,
+}
+
+#[derive(Debug, Clone)]
+struct Field {
+    ty: String,
+    required: bool,
+    values: Option<rhai::Array>,
+}
+
+impl CtxSchema {
+    /// Read `ctx_schema` from the recipe scope. `None` when the recipe
+    /// declares none.
+    pub(crate) fn from_scope(scope: &rhai::Scope) -> Result<Option<Self>> {
+        let Some(value) = scope.get_value::<Dynamic>(CTX_SCHEMA) else {
+            return Ok(None);
+        };
+        let map = value
+            .try_cast::<rhai::Map>()
+            .ok_or_else(|| anyhow!("{CTX_SCHEMA} must be a map of key declarations"))?;
+        let mut fields = BTreeMap::new();
+        for (key, decl) in map {
+            fields.insert(key.to_string(), Field::parse(&key, decl)?);
+        }
+        Ok(Some(Self { fields }))
+    }
+
+    /// Check `ctx` against the schema after `phase`. The error, a
+    /// [`SchemaViolation`], names the recipe, the phase and the offending key.
+    pub(crate) fn check(&self, recipe: &str, phase: &str, ctx: &rhai::Map) -> Result<()> {
+        self.violation(ctx).map_or(Ok(()), |(key, problem)| {
+            Err(SchemaViolation(format!(
+                "{recipe}: ctx schema violation after {phase}: `{key}` {problem}"
+            ))
+            .into())
+        })
+    }
+
+    /// First violation as `(key, problem)`, in key order.
+    fn violation(&self, ctx: &rhai::Map) -> Option<(String, String)> {
+        for (key, field) in &self.fields {
+            match ctx.get(key.as_str()) {
+                None if field.required => return Some((key.clone(), "is required".into())),
+                None => {}
+                Some(value) => {
+                    if let Some(problem) = field.problem(value) {
+                        return Some((key.clone(), problem));
+                    }
+                }
+            }
+        }
+        ctx.keys()
+            .map(|key| key.as_str())
+            .filter(|key| !self.fields.contains_key(*key) && !EXECUTOR_KEYS.contains(key))
+            .min()
+            .map(|key| (key.to_string(), "is not declared in ctx_schema".into()))
+    }
+}
+
+impl Field {
+    fn parse(key: &str, decl: Dynamic) -> Result<Self> {
+        let field = if decl.is_string() {
+            Self {
+                ty: decl.into_string().unwrap_or_default(),
+                required: true,
+                values: None,
+            }
+        } else if let Some(map) = decl.clone().try_cast::<rhai::Map>() {
+            let ty = match map.get("type") {
+                Some(ty) => ty
+                    .clone()
+                    .into_string()
+                    .map_err(|_| anyhow!("{CTX_SCHEMA}.{key}.type must be a string"))?,
+                None => "any".to_string(),
+            };
+            let required = match map.get("required") {
+                Some(required) => required
+                    .as_bool()
+                    .map_err(|_| anyhow!("{CTX_SCHEMA}.{key}.required must be a bool"))?,
+                None => true,
+            };
+            let values = match map.get("values") {
+                Some(values) => Some(
+                    values
+                        .clone()
+                        .into_array()
+                        .map_err(|_| anyhow!("{CTX_SCHEMA}.{key}.values must be an array"))?,
+                ),
+                None => None,
+            };
+            if let Some(unknown) = map
+                .keys()
+                .find(|k| !["type", "required", "values"].contains(&k.as_str()))
+            {
+                bail!(
+                    "{CTX_SCHEMA}.{key}: unknown option `{unknown}` (expected type, required, values)"
+                );
+            }
+            Self {
+                ty,
+                required,
+                values,
+            }
+        } else {
+            bail!(
+                "{CTX_SCHEMA}.{key} must be a type name or a map, got {}",
+                decl.type_name()
+            );
+        };
+        if !TYPES.contains(&field.ty.as_str()) {
+            bail!(
+                "{CTX_SCHEMA}.{key}: unknown type `{}` (expected one of {})",
+                field.ty,
+                TYPES.join(", ")
+            );
+        }
+        Ok(field)
+    }
+
+    fn problem(&self, value: &Dynamic) -> Option<String> {
+        let actual = type_of(value);
+        if self.ty != "any" && self.ty != actual {
+            return Some(format!("must be {}, got {actual}", self.ty));
+        }
+        let values = self.values.as_ref()?;
+        let matches = |allowed: &Dynamic| {
+            type_of(allowed) == actual && allowed.to_string() == value.to_string()
+        };
+        if values.iter().any(matches) {
+            return None;
+        }
+        let allowed: Vec<String> = values.iter().map(literal).collect();
+        Some(format!(
+            "must be one of [{}], got {}",
+            allowed.join(", "),
+            literal(value)
+        ))
+    }
+}
+
+/// Schema type name of a value.
+fn type_of(value: &Dynamic) -> &'static str {
+    if value.is_string() {
+        "string"
+    } else if value.is_int() {
+        "int"
+    } else if value.is_float() {
+        "float"
+    } else if value.is_bool() {
+        "bool"
+    } else if value.is_array() {
+        "array"
+    } else if value.is_map() {
+        "map"
+    } else if value.is_unit() {
+        "unit"
+    } else {
+        "any"
+    }
+}
+
+fn literal(value: &Dynamic) -> String {
+    if value.is_string() {
+        format!("{:?}", value.to_string())
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(source: &str) -> Result<Option<CtxSchema>> {
+        let engine = rhai::Engine::new();
+        let mut scope = rhai::Scope::new();
+        engine.run_with_scope(&mut scope, source).unwrap();
+        CtxSchema::from_scope(&scope)
+    }
+
+    fn ctx(source: &str) -> rhai::Map {
+        rhai::Engine::new().eval(source).unwrap()
+    }
+
+    #[test]
+    fn test_schema_reports_recipe_phase_and_key() {
+        let schema = schema(
+            r#"let ctx_schema = #{
+                name: "string",
+                jobs: #{ type: "int", required: false },
+                channel: #{ type: "string", values: ["stable", "beta"] },
+            };"#,
+        )
+        .unwrap()
+        .unwrap();
+
+        let ok = ctx(r#"#{ name: "foo", channel: "beta", installed_files: [] }"#);
+        assert!(schema.check("foo", "install", &ok).is_ok());
+
+        let check = |source: &str| {
+            schema
+                .check("foo", "build", &ctx(source))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            check(r#"#{ channel: "beta" }"#),
+            "foo: ctx schema violation after build: `name` is required"
+        );
+        assert_eq!(
+            check(r#"#{ name: "foo", channel: "beta", jobs: "4" }"#),
+            "foo: ctx schema violation after build: `jobs` must be int, got string"
+        );
+        assert_eq!(
+            check(r#"#{ name: "foo", channel: "nightly" }"#),
+            "foo: ctx schema violation after build: `channel` must be one of [\"stable\", \"beta\"], got \"nightly\""
+        );
+        assert_eq!(
+            check(r#"#{ name: "foo", channel: "beta", verison: "1.0" }"#),
+            "foo: ctx schema violation after build: `verison` is not declared in ctx_schema"
+        );
+    }
+
+    #[test]
+    fn test_schema_declaration_errors() {
+        assert!(schema("let ctx = #{};").unwrap().is_none());
+        assert!(schema(r#"let ctx_schema = #{ a: "text" };"#).is_err());
+        assert!(schema(r#"let ctx_schema = #{ a: #{ type: "int", optional: true } };"#).is_err());
+        assert!(schema("let ctx_schema = [1];").is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Recipe search (`recipe search`)
+//!
+//! A case-insensitive regular expression is matched against each recipe's
+//! name (ctx name and file stem), description and homepage, read as in
+//! [`RecipeInfo`], and, for installed recipes, against the paths in the
+//! installed-file manifest. Every repository is searched (see
+//! [`super::repos`]); recipes that fail to compile are skipped.
+
+use super::info::{self, RecipeInfo};
+use super::orphans::InstallReason;
+use super::{ctx_store, deps, manifest, repos};
+use anyhow::{Result, anyhow};
+use regex::{Regex, RegexBuilder};
+use rhai::Engine;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// A recipe matching a search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub info: RecipeInfo,
+    pub installed: bool,
+    /// What matched: `name`, `description`, `homepage` and/or `files`.
+    pub matched: Vec<&'static str>,
+    /// Installed files whose path matched.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<PathBuf>,
+}
+
+/// Compile a search pattern (case-insensitive).
+pub(crate) fn pattern(query: &str) -> Result<Regex> {
+    RegexBuilder::new(query)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| anyhow!("Invalid search pattern `{query}`: {e}"))
+}
+
+/// Recipes in `recipes_path` and the active repositories matching
+/// `pattern`, in search order.
+pub(crate) fn search(
+    engine: &Engine,
+    recipes_path: Option<&Path>,
+    pattern: &Regex,
+) -> Vec<SearchHit> {
+    repos::active()
+        .recipe_files(recipes_path)
+        .into_iter()
+        .filter_map(|path| {
+            let info = info::load(engine, &path, recipes_path).ok()?;
+            hit(info, &path, pattern)
+        })
+        .collect()
+}
+
+fn hit(info: RecipeInfo, path: &Path, pattern: &Regex) -> Option<SearchHit> {
+    let persisted = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|source| ctx_store::persisted(path, &source));
+    let installed = persisted
+        .as_ref()
+        .and_then(InstallReason::from_ctx)
+        .is_some();
+    let files: Vec<PathBuf> = match (&persisted, installed) {
+        (Some(ctx), true) => manifest::from_ctx(ctx)
+            .files
+            .into_iter()
+            .filter(|file| pattern.is_match(&file.to_string_lossy()))
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let mut matched = Vec::new();
+    if pattern.is_match(&info.name) || pattern.is_match(&deps::recipe_name(path)) {
+        matched.push("name");
+    }
+    for (field, value) in [
+        ("description", &info.description),
+        ("homepage", &info.homepage),
+    ] {
+        if value.as_deref().is_some_and(|v| pattern.is_match(v)) {
+            matched.push(field);
+        }
+    }
+    if !files.is_empty() {
+        matched.push("files");
+    }
+
+    (!matched.is_empty()).then_some(SearchHit {
+        info,
+        installed,
+        matched,
+        files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_search_matches_fields_and_installed_files() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("ripgrep.rhai"),
+            r#"let ctx = #{
+    name: "ripgrep",
+    description: "Recursive line-oriented search",
+    homepage: "https://github.com/BurntSushi/ripgrep",
+    install_reason: "explicit",
+    installed_files: ["/usr/bin/rg", "/usr/share/man/man1/rg.1"],
+};"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("fd.rhai"),
+            r#"let ctx = #{ name: "fd", description: "Find files", installed_files: ["/usr/bin/rg"] };"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("broken.rhai"), "let ctx = #{").unwrap();
+
+        let engine = Engine::new();
+        let find = |query: &str| search(&engine, Some(dir.path()), &pattern(query).unwrap());
+
+        let hits = find("SEARCH");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].info.name, "ripgrep");
+        assert_eq!(hits[0].matched, ["description"]);
+
+        // fd is not installed, so its manifest is not searched.
+        let hits = find(r"bin/rg$");
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].installed);
+        assert_eq!(hits[0].matched, ["files"]);
+        assert_eq!(hits[0].files, [PathBuf::from("/usr/bin/rg")]);
+
+        let names: Vec<_> = find("^(fd|ripgrep)$")
+            .into_iter()
+            .map(|hit| hit.info.name)
+            .collect();
+        assert_eq!(names, ["fd", "ripgrep"]);
+
+        assert!(pattern("(").is_err());
+    }
+}
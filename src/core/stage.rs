@@ -0,0 +1,442 @@
+//! Staged installs and atomic commit.
+//!
+//! During the install phase recipes write into `STAGE_DIR`, a tree that mirrors
+//! the target root (`$STAGE_DIR/usr/bin/foo` lands at `/usr/bin/foo`). Once
+//! `install(ctx)` succeeds the engine commits the staged tree in two passes:
+//!
+//! 1. Every staged file or symlink is moved next to its destination under a
+//!    temporary name (rename, or reflink/copy when the stage lives on another
+//!    filesystem). Missing directories are created along the way.
+//! 2. Each temporary entry is renamed over its destination. An existing
+//!    destination is hard-linked to a backup first, so the swap itself is a
+//!    single atomic rename.
+//!
+//! Any error rolls back: backups are restored, new files and directories are
+//! removed, and the target root is left as it was.
+//!
+//! Before anything is moved, each destination's parent is resolved through
+//! symlinks; a commit whose destinations would land outside the target root
+//! (`$SYSROOT/lib -> /usr/lib`) fails without touching anything.
+
+use super::output;
+use crate::helpers::internal::sysroot;
+use crate::helpers::internal::tracking::Manifest;
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Stage directory for a build dir (`BUILD_DIR/.stage`).
+pub(crate) fn stage_dir(build_dir: &Path) -> PathBuf {
+    build_dir.join(".stage")
+}
+
+/// Create a fresh, empty stage directory, discarding any leftovers.
+pub(crate) fn prepare(stage_dir: &Path) -> Result<()> {
+    discard(stage_dir)?;
+    fs::create_dir_all(stage_dir)
+        .with_context(|| format!("Failed to create stage dir {}", stage_dir.display()))
+}
+
+/// Remove the stage directory and anything left in it.
+pub(crate) fn discard(stage_dir: &Path) -> Result<()> {
+    match fs::remove_dir_all(stage_dir) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => {
+            Err(e).with_context(|| format!("Failed to remove stage dir {}", stage_dir.display()))
+        }
+    }
+}
+
+/// Commit everything under `stage_dir` into `root`.
+///
+/// Returns the committed files and symlinks plus the directories the commit
+/// created under `root`. On error nothing under `root` is changed.
+pub(crate) fn commit(stage_dir: &Path, root: &Path) -> Result<Manifest> {
+    check_contained(stage_dir, root)?;
+    let mut tx = Transaction::default();
+    match tx.run(stage_dir, root) {
+        Ok(files) => Ok(Manifest {
+            files,
+            dirs: tx.finish(),
+        }),
+        Err(e) => {
+            tx.rollback();
+            Err(e)
+        }
+    }
+}
+
+/// Staged entries whose destination under `root` is claimed in `claims`.
+///
+/// Returns `(destination, owner)` pairs; run before [`commit`] so a conflict
+/// leaves `root` untouched.
+pub(crate) fn conflicts(
+    stage_dir: &Path,
+    root: &Path,
+    claims: &HashMap<PathBuf, String>,
+) -> Result<Vec<(PathBuf, String)>> {
+    let mut found = Vec::new();
+    if claims.is_empty() {
+        return Ok(found);
+    }
+    for entry in WalkDir::new(stage_dir)
+        .min_depth(1)
+        .follow_links(false)
+        .sort_by_file_name()
+    {
+        let entry =
+            entry.with_context(|| format!("Failed to walk stage dir {}", stage_dir.display()))?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let rel = entry
+            .path()
+            .strip_prefix(stage_dir)
+            .expect("walkdir yields paths under its root");
+        let dest = root.join(rel);
+        if let Some(owner) = claims.get(&dest) {
+            found.push((dest, owner.clone()));
+        }
+    }
+    Ok(found)
+}
+
+/// Fail when a staged entry would be committed outside `root` because a
+/// directory on its way there is a symlink leading elsewhere.
+fn check_contained(stage_dir: &Path, root: &Path) -> Result<()> {
+    let real_root = sysroot::real_path(root);
+    for entry in WalkDir::new(stage_dir).min_depth(1).follow_links(false) {
+        let entry =
+            entry.with_context(|| format!("Failed to walk stage dir {}", stage_dir.display()))?;
+        let rel = entry
+            .path()
+            .strip_prefix(stage_dir)
+            .expect("walkdir yields paths under its root");
+        let dest = root.join(rel);
+        let parent = dest.parent().unwrap_or(root);
+        if !sysroot::real_path(parent).starts_with(&real_root) {
+            return Err(anyhow!(sysroot::escape_message(&dest, root)));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Transaction {
+    /// Directories created under root, in creation order.
+    created_dirs: Vec<PathBuf>,
+    /// Staged entries moved next to their destination: (temp path, destination).
+    pending: Vec<(PathBuf, PathBuf)>,
+    /// Destinations already swapped in, with the backup of what they replaced.
+    swapped: Vec<(PathBuf, Option<PathBuf>)>,
+}
+
+impl Transaction {
+    fn run(&mut self, stage_dir: &Path, root: &Path) -> Result<Vec<PathBuf>> {
+        for entry in WalkDir::new(stage_dir)
+            .min_depth(1)
+            .follow_links(false)
+            .sort_by_file_name()
+        {
+            let entry = entry
+                .with_context(|| format!("Failed to walk stage dir {}", stage_dir.display()))?;
+            let rel = entry
+                .path()
+                .strip_prefix(stage_dir)
+                .expect("walkdir yields paths under its root");
+            let dest = root.join(rel);
+
+            if entry.file_type().is_dir() {
+                self.ensure_dir(entry.path(), &dest)?;
+            } else {
+                let tmp = self.move_beside(entry.path(), &dest)?;
+                self.pending.push((tmp, dest));
+            }
+        }
+
+        let pending = std::mem::take(&mut self.pending);
+        let mut committed = Vec::with_capacity(pending.len());
+        let mut pending = pending.into_iter();
+        while let Some((tmp, dest)) = pending.next() {
+            match swap_into_place(&tmp, &dest) {
+                Ok(backup) => {
+                    self.swapped.push((dest.clone(), backup));
+                    committed.push(dest);
+                }
+                Err(e) => {
+                    self.pending.push((tmp, dest));
+                    self.pending.extend(pending);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(committed)
+    }
+
+    fn ensure_dir(&mut self, staged: &Path, dest: &Path) -> Result<()> {
+        match fs::metadata(dest) {
+            Ok(meta) if meta.is_dir() => Ok(()),
+            Ok(_) => Err(anyhow!(
+                "Cannot commit directory over existing file: {}",
+                dest.display()
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                fs::create_dir(dest)
+                    .with_context(|| format!("Failed to create directory {}", dest.display()))?;
+                self.created_dirs.push(dest.to_path_buf());
+                let perms = fs::metadata(staged)?.permissions();
+                fs::set_permissions(dest, perms)
+                    .with_context(|| format!("Failed to set permissions on {}", dest.display()))
+            }
+            Err(e) => Err(e).with_context(|| format!("Failed to inspect {}", dest.display())),
+        }
+    }
+
+    /// Move a staged file or symlink next to `dest` under a temporary name.
+    fn move_beside(&mut self, staged: &Path, dest: &Path) -> Result<PathBuf> {
+        if let Ok(meta) = fs::symlink_metadata(dest)
+            && meta.is_dir()
+        {
+            return Err(anyhow!(
+                "Cannot commit file over existing directory: {}",
+                dest.display()
+            ));
+        }
+
+        let tmp = sibling(dest, "new");
+        remove_if_exists(&tmp)?;
+
+        // Same filesystem: a plain rename. Otherwise fall back to reflink/copy.
+        if fs::rename(staged, &tmp).is_ok() {
+            return Ok(tmp);
+        }
+
+        let meta = fs::symlink_metadata(staged)
+            .with_context(|| format!("Failed to inspect staged {}", staged.display()))?;
+        if meta.file_type().is_symlink() {
+            copy_symlink(staged, &tmp)?;
+        } else {
+            crate::helpers::install::copy_file_reflink(
+                &staged.to_string_lossy(),
+                &tmp.to_string_lossy(),
+            )
+            .map_err(|e| anyhow!("{e}"))
+            .with_context(|| format!("Failed to copy staged {}", staged.display()))?;
+            fs::set_permissions(&tmp, meta.permissions())
+                .with_context(|| format!("Failed to set permissions on {}", tmp.display()))?;
+        }
+        Ok(tmp)
+    }
+
+    fn rollback(self) {
+        for (dest, backup) in self.swapped.into_iter().rev() {
+            let restored = match &backup {
+                Some(backup) => fs::rename(backup, &dest),
+                None => fs::remove_file(&dest),
+            };
+            if let Err(e) = restored {
+                output::warning(&format!("rollback failed for {}: {e}", dest.display()));
+            }
+        }
+        for (tmp, _) in self.pending {
+            let _ = fs::remove_file(tmp);
+        }
+        for dir in self.created_dirs.into_iter().rev() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+
+    /// Drop backups and return the directories created by the commit.
+    fn finish(self) -> Vec<PathBuf> {
+        for (_, backup) in self.swapped {
+            if let Some(backup) = backup {
+                let _ = fs::remove_file(backup);
+            }
+        }
+        self.created_dirs
+    }
+}
+
+/// Hidden sibling of `dest` used during commit (`.<name>.recipe-<tag>`).
+fn sibling(dest: &Path, tag: &str) -> PathBuf {
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    dest.with_file_name(format!(".{name}.recipe-{tag}"))
+}
+
+/// Rename `tmp` over `dest`, returning the backup of what it replaced.
+fn swap_into_place(tmp: &Path, dest: &Path) -> Result<Option<PathBuf>> {
+    let backup = backup_existing(dest)?;
+    if let Err(e) = fs::rename(tmp, dest) {
+        if let Some(backup) = &backup {
+            // Undo a rename fallback in `backup_existing`; harmless after a hard link.
+            let _ = fs::rename(backup, dest);
+        }
+        return Err(e).with_context(|| format!("Failed to commit {}", dest.display()));
+    }
+    Ok(backup)
+}
+
+/// Keep the current destination reachable under a backup name.
+fn backup_existing(dest: &Path) -> Result<Option<PathBuf>> {
+    if fs::symlink_metadata(dest).is_err() {
+        return Ok(None);
+    }
+    let backup = sibling(dest, "old");
+    remove_if_exists(&backup)?;
+    // Hard link keeps `dest` in place until the swap rename; fall back to a
+    // rename on filesystems without hard links.
+    fs::hard_link(dest, &backup)
+        .or_else(|_| fs::rename(dest, &backup))
+        .with_context(|| format!("Failed to back up {}", dest.display()))?;
+    Ok(Some(backup))
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {}", path.display())),
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(link: &Path, dest: &Path) -> Result<()> {
+    let target =
+        fs::read_link(link).with_context(|| format!("Failed to read link {}", link.display()))?;
+    std::os::unix::fs::symlink(&target, dest)
+        .with_context(|| format!("Failed to create symlink {}", dest.display()))
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(link: &Path, _dest: &Path) -> Result<()> {
+    Err(anyhow!("Staged symlinks require unix: {}", link.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, PathBuf, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let stage = dir.path().join("stage");
+        let root = dir.path().join("root");
+        fs::create_dir_all(&stage).unwrap();
+        fs::create_dir_all(&root).unwrap();
+        (dir, stage, root)
+    }
+
+    #[test]
+    fn test_conflicts_maps_stage_to_root() {
+        let dir = TempDir::new().unwrap();
+        let stage = dir.path().join("stage");
+        let root = dir.path().join("root");
+        fs::create_dir_all(stage.join("usr/bin")).unwrap();
+        fs::write(stage.join("usr/bin/foo"), "new").unwrap();
+        fs::write(stage.join("usr/bin/bar"), "new").unwrap();
+
+        let claims = HashMap::from([(root.join("usr/bin/foo"), "foo".to_string())]);
+        let found = conflicts(&stage, &root, &claims).unwrap();
+        assert_eq!(found, vec![(root.join("usr/bin/foo"), "foo".to_string())]);
+    }
+
+    #[test]
+    fn test_commit_moves_tree_into_root() {
+        let (_dir, stage, root) = setup();
+        fs::create_dir_all(stage.join("usr/bin")).unwrap();
+        fs::write(stage.join("usr/bin/tool"), "new").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("tool", stage.join("usr/bin/alias")).unwrap();
+
+        let committed = commit(&stage, &root).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(root.join("usr/bin/tool")).unwrap(),
+            "new"
+        );
+        assert!(committed.files.contains(&root.join("usr/bin/tool")));
+        assert_eq!(committed.dirs, vec![root.join("usr"), root.join("usr/bin")]);
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(root.join("usr/bin/alias")).unwrap(),
+            Path::new("tool")
+        );
+        assert!(!stage.join("usr/bin/tool").exists());
+        assert!(!root.join("usr/bin/.tool.recipe-new").exists());
+    }
+
+    #[test]
+    fn test_commit_replaces_existing_file_without_leftovers() {
+        let (_dir, stage, root) = setup();
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::write(root.join("etc/conf"), "old").unwrap();
+        fs::create_dir_all(stage.join("etc")).unwrap();
+        fs::write(stage.join("etc/conf"), "new").unwrap();
+
+        commit(&stage, &root).unwrap();
+
+        assert_eq!(fs::read_to_string(root.join("etc/conf")).unwrap(), "new");
+        let names: Vec<_> = fs::read_dir(root.join("etc"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 1, "unexpected leftovers: {names:?}");
+    }
+
+    #[test]
+    fn test_commit_failure_rolls_back() {
+        let (_dir, stage, root) = setup();
+        fs::write(root.join("a"), "old-a").unwrap();
+        fs::create_dir_all(root.join("z")).unwrap();
+
+        fs::write(stage.join("a"), "new-a").unwrap();
+        fs::create_dir_all(stage.join("new/dir")).unwrap();
+        fs::write(stage.join("new/dir/file"), "x").unwrap();
+        // A file staged over an existing directory cannot be committed.
+        fs::write(stage.join("z"), "conflict").unwrap();
+
+        let err = commit(&stage, &root).unwrap_err();
+        assert!(err.to_string().contains("existing directory"), "{err}");
+
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "old-a");
+        assert!(root.join("z").is_dir());
+        assert!(!root.join("new").exists());
+        assert!(!root.join(".a.recipe-new").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_commit_rejects_symlinked_dir_leaving_root() {
+        let (dir, stage, root) = setup();
+        let outside = dir.path().join("host-lib");
+        fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("lib")).unwrap();
+        fs::write(stage.join("keep"), "x").unwrap();
+        fs::create_dir_all(stage.join("lib")).unwrap();
+        fs::write(stage.join("lib/libfoo.so"), "x").unwrap();
+
+        let err = commit(&stage, &root).unwrap_err();
+        assert!(err.to_string().contains("escapes sysroot"), "{err}");
+
+        assert!(!outside.join("libfoo.so").exists());
+        assert!(!root.join("keep").exists());
+        assert!(stage.join("lib/libfoo.so").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_commit_follows_symlinked_dir_inside_root() {
+        let (_dir, stage, root) = setup();
+        fs::create_dir_all(root.join("usr/lib")).unwrap();
+        std::os::unix::fs::symlink("usr/lib", root.join("lib")).unwrap();
+        fs::create_dir_all(stage.join("lib")).unwrap();
+        fs::write(stage.join("lib/libfoo.so"), "x").unwrap();
+
+        commit(&stage, &root).unwrap();
+
+        assert!(root.join("usr/lib/libfoo.so").exists());
+    }
+}
@@ -0,0 +1,144 @@
+//! Upstream version discovery (`check_update`)
+//!
+//! Recipes may define `check_update(ctx)` (or `check_update()`) to report the
+//! latest upstream version. The hook returns:
+//! - a string: the latest version
+//! - a map with a `version` key: the latest version plus extra metadata
+//!   (for example `url` or `sha256`), kept alongside the version
+//! - unit: no update available
+//!
+//! `recipe update` only reports; it never installs anything or edits recipes.
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+/// Name of the update hook.
+pub const CHECK_UPDATE: &str = "check_update";
+
+/// Result of running a recipe's `check_update` hook.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UpdateInfo {
+    pub name: String,
+    pub recipe_path: PathBuf,
+    /// `ctx.version` at the time of the check.
+    pub current: Option<String>,
+    /// Latest upstream version; `None` when the hook returned unit.
+    pub latest: Option<String>,
+    /// Extra keys returned next to `version` in a map result.
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+impl UpdateInfo {
+    /// Whether `latest` is newer than `current`. A recipe without a current
+    /// version is always considered outdated when the hook reports one.
+    pub fn is_newer(&self) -> bool {
+        match (&self.current, &self.latest) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(current), Some(latest)) => compare_versions(latest, current) == Ordering::Greater,
+        }
+    }
+}
+
+/// Split a `check_update` return value into the latest version and metadata.
+pub(crate) fn interpret(
+    value: rhai::Dynamic,
+) -> Result<(Option<String>, serde_json::Map<String, serde_json::Value>)> {
+    if value.is_unit() {
+        return Ok((None, serde_json::Map::new()));
+    }
+    if value.is_string() {
+        let version = value.into_string().unwrap_or_default();
+        let version = version.trim();
+        return Ok((
+            (!version.is_empty()).then(|| version.to_string()),
+            serde_json::Map::new(),
+        ));
+    }
+    if let Some(mut map) = value.clone().try_cast::<rhai::Map>() {
+        let version = map
+            .remove("version")
+            .ok_or_else(|| anyhow!("check_update returned a map without `version`"))?
+            .into_string()
+            .map_err(|t| anyhow!("check_update `version` must be a string, got {t}"))?;
+        let json: serde_json::Value = rhai::serde::from_dynamic(&rhai::Dynamic::from(map))
+            .map_err(|e| anyhow!("check_update metadata is not serializable: {e}"))?;
+        let metadata = match json {
+            serde_json::Value::Object(obj) => obj,
+            _ => serde_json::Map::new(),
+        };
+        return Ok((Some(version), metadata));
+    }
+    Err(anyhow!(
+        "check_update must return a version string, a map with `version`, or nothing; got {}",
+        value.type_name()
+    ))
+}
+
+/// Compare two version strings.
+///
+/// Semantic versions (with an optional leading `v`) compare per semver.
+/// Anything else is split on `.`, `-`, `_` and `+`; numeric segments compare
+/// numerically, others lexically, and a longer version wins a tie.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let strip = |s: &str| s.trim().trim_start_matches('v').to_string();
+    let (a, b) = (strip(a), strip(b));
+    if let (Ok(va), Ok(vb)) = (semver::Version::parse(&a), semver::Version::parse(&b)) {
+        return va.cmp(&vb);
+    }
+
+    let split = |s: &str| -> Vec<String> {
+        s.split(['.', '-', '_', '+'])
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect()
+    };
+    let (pa, pb) = (split(&a), split(&b));
+    for (x, y) in pa.iter().zip(&pb) {
+        let ord = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(nx), Ok(ny)) => nx.cmp(&ny),
+            _ => x.cmp(y),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    pa.len().cmp(&pb.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("1.10.0", "1.9.3"), Ordering::Greater);
+        assert_eq!(compare_versions("v2.0.0", "2.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("2.0.0", "2.0.0-rc1"), Ordering::Greater);
+        assert_eq!(compare_versions("6.12", "6.9.4"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2", "1.2.1"), Ordering::Less);
+        assert_eq!(compare_versions("2024.10", "2024.9"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_interpret_return_values() {
+        assert_eq!(interpret(rhai::Dynamic::UNIT).unwrap().0, None);
+        assert_eq!(
+            interpret("1.2.3".into()).unwrap().0.as_deref(),
+            Some("1.2.3")
+        );
+
+        let mut map = rhai::Map::new();
+        map.insert("version".into(), "2.0".into());
+        map.insert("url".into(), "https://example.com/x-2.0.tar.gz".into());
+        let (version, metadata) = interpret(rhai::Dynamic::from(map)).unwrap();
+        assert_eq!(version.as_deref(), Some("2.0"));
+        assert_eq!(metadata["url"], "https://example.com/x-2.0.tar.gz");
+
+        assert!(interpret(rhai::Dynamic::from(rhai::Map::new())).is_err());
+        assert!(interpret(42_i64.into()).is_err());
+    }
+}
@@ -14,17 +14,22 @@
 //! }
 //! ```
 
-use crate::core::output;
+use crate::core::{cache, mirrors, offline, output};
 use rhai::EvalAltResult;
 use std::io::{Read, Write};
 use std::path::Path;
 
 use super::super::internal::fs_utils;
+use super::super::internal::hash::{self, HashAlgorithm, StreamHasher};
 use super::super::internal::progress::{self, upgrade_to_bytes};
+use super::verify;
 
 /// Download a file from a URL to a specific destination.
 ///
-/// Returns the path to the downloaded file on success.
+/// When the download cache is enabled, a URL fetched before is copied from
+/// the cache instead. Mirrors configured for the URL (see
+/// [`crate::MirrorTable`]) are tried before it. Returns the path to the
+/// downloaded file on success.
 ///
 /// Throws on failure.
 ///
@@ -34,29 +39,208 @@ use super::super::internal::progress::{self, upgrade_to_bytes};
 /// verify_sha256(path, "abc123...");
 /// ```
 pub fn download(url: &str, dest: &str) -> Result<String, Box<EvalAltResult>> {
+    download_from(&[url.to_string()], dest, None)
+}
+
+/// Download a file and check it against `expected` in the same pass.
+///
+/// The digest (`sha256:`, `sha512:` or `blake3:` followed by the hex digest;
+/// a bare digest is SHA-256) is computed while the file streams into a
+/// temporary file beside `dest`, which is renamed to `dest` only if it
+/// matches. On a mismatch `dest` is left as it was. With a SHA-256 digest,
+/// the download cache can serve the content whatever URL it came from.
+///
+/// # Example
+/// ```rhai
+/// let path = download_verified(ctx.url, BUILD_DIR + "/foo.tar.gz", "sha256:abc123...");
+/// ```
+pub fn download_verified(
+    url: &str,
+    dest: &str,
+    expected: &str,
+) -> Result<String, Box<EvalAltResult>> {
+    verify::parse_hash(expected)?;
+    download_from(&[url.to_string()], dest, Some(expected))
+}
+
+/// Download a file from the first of several mirror URLs that works.
+///
+/// Each URL (and its configured mirrors) is tried in order; a failed mirror
+/// is reported as a `download` hook event and the next one is tried.
+///
+/// # Example
+/// ```rhai
+/// let path = download([
+///     "https://ftp.gnu.org/gnu/bash/bash-5.2.tar.gz",
+///     "https://ftpmirror.gnu.org/bash/bash-5.2.tar.gz",
+/// ], BUILD_DIR + "/bash-5.2.tar.gz");
+/// ```
+pub fn download_mirrors(urls: rhai::Array, dest: &str) -> Result<String, Box<EvalAltResult>> {
+    download_from(&mirror_urls(urls)?, dest, None)
+}
+
+/// [`download_mirrors`], checking each download against `expected` like
+/// [`download_verified`]. A mirror serving different content is skipped like
+/// a failed one.
+///
+/// # Example
+/// ```rhai
+/// let path = download(ctx.urls, BUILD_DIR + "/src.tar.gz", "sha256:abc123...");
+/// ```
+pub fn download_mirrors_verified(
+    urls: rhai::Array,
+    dest: &str,
+    expected: &str,
+) -> Result<String, Box<EvalAltResult>> {
+    verify::parse_hash(expected)?;
+    download_from(&mirror_urls(urls)?, dest, Some(expected))
+}
+
+fn mirror_urls(urls: rhai::Array) -> Result<Vec<String>, Box<EvalAltResult>> {
+    let urls: Vec<String> = urls
+        .into_iter()
+        .map(|url| url.to_string().trim().to_string())
+        .filter(|url| !url.is_empty())
+        .collect();
+    if urls.is_empty() {
+        return Err("download: no URLs given".into());
+    }
+    Ok(urls)
+}
+
+/// Try the cache, then every URL with its mirrors, until one download (that
+/// matches `expected`, if given) lands at `dest`. Cache entries are keyed by
+/// the URL as given, not the mirror it came from.
+fn download_from(
+    urls: &[String],
+    dest: &str,
+    expected: Option<&str>,
+) -> Result<String, Box<EvalAltResult>> {
     let dest_path = Path::new(dest);
     fs_utils::ensure_parent_dir(dest_path)?;
+    let expected = match expected {
+        Some(e) => {
+            let (algorithm, digest) = verify::parse_hash(e)?;
+            Some((algorithm, digest.to_ascii_lowercase()))
+        }
+        None => None,
+    };
+    for url in urls {
+        let cached = match &expected {
+            Some((algorithm, digest)) => {
+                let sha256 = matches!(algorithm, HashAlgorithm::Sha256).then_some(digest.as_str());
+                cache::fetch_checked(url, sha256, dest_path, |file| {
+                    hash::verify_file_hash(file, digest, *algorithm).is_ok()
+                })
+            }
+            None => cache::fetch(url, dest_path),
+        };
+        if cached {
+            return Ok(dest.to_string());
+        }
+    }
+    offline::check_uncached(&urls[0])?;
 
     let filename = dest_path
         .file_name()
         .map(|s| s.to_string_lossy().to_string())
         .unwrap_or_else(|| "download".to_string());
 
-    let total_bytes = download_with_progress(url, dest_path, &filename)?;
-    output::detail(&format!("downloaded {} ({} bytes)", filename, total_bytes));
+    let attempts: Vec<(&String, String)> = urls
+        .iter()
+        .flat_map(|url| {
+            mirrors::candidates(url)
+                .into_iter()
+                .map(move |candidate| (url, candidate))
+        })
+        .collect();
+    let recipe = offline::current_recipe();
+    let mut failures = Vec::new();
+    for (url, candidate) in &attempts {
+        let result = match &expected {
+            Some((algorithm, digest)) => {
+                download_checked(candidate, dest_path, &filename, *algorithm, digest)
+            }
+            None => download_with_progress(candidate, dest_path, &filename, None),
+        };
+        match result {
+            Ok(total_bytes) => {
+                output::detail(&format!("downloaded {} ({} bytes)", filename, total_bytes));
+                if attempts.len() > 1 {
+                    output::hook_event(
+                        &recipe,
+                        "download",
+                        "success",
+                        &format!("{filename} from {candidate}"),
+                    );
+                }
+                cache::store(url, dest_path);
+                if expected.is_some() {
+                    cache::verified(dest_path, true);
+                }
+                return Ok(dest.to_string());
+            }
+            Err(e) if attempts.len() == 1 => return Err(e),
+            Err(e) => {
+                if expected.is_none() {
+                    let _ = std::fs::remove_file(dest_path);
+                }
+                output::hook_event(
+                    &recipe,
+                    "download",
+                    "fallback",
+                    &format!("{candidate} failed: {e}"),
+                );
+                failures.push(format!("  {candidate}: {e}"));
+            }
+        }
+    }
 
-    Ok(dest.to_string())
+    Err(format!(
+        "download failed for {} from every mirror:\n{}",
+        filename,
+        failures.join("\n")
+    )
+    .into())
 }
 
 // ============================================================================
 // Internal helpers
 // ============================================================================
 
-/// Download a file with progress bar (shared implementation)
+/// Stream `url` into a temporary file beside `dest`, hashing it on the way,
+/// and rename it to `dest` only if the digest matches.
+fn download_checked(
+    url: &str,
+    dest: &Path,
+    filename: &str,
+    algorithm: HashAlgorithm,
+    digest: &str,
+) -> Result<u64, Box<EvalAltResult>> {
+    let parent = match dest.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let tmp = tempfile::NamedTempFile::new_in(parent)
+        .map_err(|e| format!("cannot create file: {}", e))?;
+    let mut hasher = StreamHasher::new(algorithm);
+    let total_bytes = download_with_progress(url, tmp.path(), filename, Some(&mut hasher))?;
+    let got = hasher.finalize();
+    if got != digest {
+        return Err(hash::mismatch(algorithm, url, digest, &got).into());
+    }
+    tmp.persist(dest)
+        .map_err(|e| format!("cannot write {}: {}", dest.display(), e.error))?;
+    Ok(total_bytes)
+}
+
+/// Download a file with progress bar (shared implementation), feeding
+/// `hasher` with the content if given
 fn download_with_progress(
     url: &str,
     dest: &Path,
     filename: &str,
+    mut hasher: Option<&mut StreamHasher>,
 ) -> Result<u64, Box<EvalAltResult>> {
     let pb = progress::create_spinner(&format!("downloading {}", filename));
 
@@ -92,6 +276,9 @@ fn download_with_progress(
 
         file.write_all(&buffer[..bytes_read])
             .map_err(|e| format!("write error: {}", e))?;
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&buffer[..bytes_read]);
+        }
 
         total_bytes += bytes_read as u64;
         pb.set_position(total_bytes);
@@ -100,3 +287,186 @@ fn download_with_progress(
     pb.finish_and_clear();
     Ok(total_bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MirrorTable, SourceCache};
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_download_falls_back_across_mirrors() {
+        let server = MockServer::start().await;
+        for (route, status, body) in [
+            ("/dead/foo.tar.gz", 404, ""),
+            ("/stale/foo.tar.gz", 200, "stale"),
+            ("/good/foo.tar.gz", 200, "hello world"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(status).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+        let uri = server.uri();
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("foo.tar.gz").to_string_lossy().to_string();
+        let sha256 = "sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        let urls: rhai::Array = vec![
+            format!("{uri}/dead/foo.tar.gz").into(),
+            format!("{uri}/stale/foo.tar.gz").into(),
+            format!("{uri}/good/foo.tar.gz").into(),
+        ];
+        download_mirrors_verified(urls.clone(), &dest, sha256).unwrap();
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "hello world");
+
+        let err = download_mirrors_verified(urls[..2].to_vec(), &dest, sha256)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("from every mirror"), "{err}");
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "hello world");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // The mirror table rewrites the single-URL form too.
+        let table = MirrorTable::new(BTreeMap::from([(
+            "https://ftp.example.invalid/".to_string(),
+            vec![format!("{uri}/dead/"), format!("{uri}/good/")],
+        )]));
+        mirrors::scoped(&table, || {
+            download("https://ftp.example.invalid/foo.tar.gz", &dest).unwrap();
+        });
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "hello world");
+    }
+
+    #[tokio::test]
+    async fn test_download_verified_renames_only_on_match() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/foo.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("hello world"))
+            .mount(&server)
+            .await;
+        let url = format!("{}/foo.tar.gz", server.uri());
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("foo.tar.gz").to_string_lossy().to_string();
+        std::fs::write(&dest, "previous").unwrap();
+
+        let err = download_verified(&url, &dest, "sha256:0000")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("SHA256 integrity check failed"), "{err}");
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "previous");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        for expected in [
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            "blake3:d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24",
+        ] {
+            std::fs::remove_file(&dest).unwrap();
+            download_verified(&url, &dest, expected).unwrap();
+            assert_eq!(std::fs::read_to_string(&dest).unwrap(), "hello world");
+        }
+
+        assert!(download_verified(&url, &dest, "md5:5eb63bbbe01eeed093cb22bb8f5acdc3").is_err());
+    }
+
+    const HELLO_SHA512: &str = "sha512:309ecc489c12d6eb4cc40f50c902f2b4d0ed77ee511a7c7a9bcd3ca86d4cd86f989dd35bc5ff499670da34255b45b0cfd830e81f605dcf7dc5542e93ae9cd76f";
+
+    #[tokio::test]
+    async fn test_download_verified_sha512() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/foo.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("hello world"))
+            .mount(&server)
+            .await;
+        let url = format!("{}/foo.tar.gz", server.uri());
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("foo.tar.gz").to_string_lossy().to_string();
+
+        download_verified(&url, &dest, HELLO_SHA512).unwrap();
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "hello world");
+
+        std::fs::remove_file(&dest).unwrap();
+        let err = download_verified(&url, &dest, &HELLO_SHA512.replace("309e", "0000"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("SHA512 integrity check failed"), "{err}");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_download_verified_rechecks_cache_hits() {
+        let server = MockServer::start().await;
+        // The first request gets stale content, later ones the real file.
+        Mock::given(method("GET"))
+            .and(path("/foo.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("stale"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/foo.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("hello world"))
+            .mount(&server)
+            .await;
+        let url = format!("{}/foo.tar.gz", server.uri());
+        let dir = TempDir::new().unwrap();
+        let source_cache = SourceCache::new(dir.path().join("cache"));
+        let dest = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+
+        cache::scoped(Some(&source_cache), || {
+            download(&url, &dest("a")).unwrap();
+            assert_eq!(std::fs::read_to_string(dest("a")).unwrap(), "stale");
+
+            // The cached copy fails the SHA-512 check and is fetched again.
+            download_verified(&url, &dest("b"), HELLO_SHA512).unwrap();
+            assert_eq!(std::fs::read_to_string(dest("b")).unwrap(), "hello world");
+        });
+        cache::scoped(Some(&source_cache), || {
+            // Now the cached copy matches and is used as is.
+            download_verified(&url, &dest("c"), HELLO_SHA512).unwrap();
+            assert_eq!(std::fs::read_to_string(dest("c")).unwrap(), "hello world");
+        });
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_download_mismatch_on_every_mirror_leaves_no_temp_files() {
+        let server = MockServer::start().await;
+        for (route, body) in [("/one/foo.tar.gz", "stale"), ("/two/foo.tar.gz", "other")] {
+            Mock::given(method("GET"))
+                .and(path(route))
+                .respond_with(ResponseTemplate::new(200).set_body_string(body))
+                .mount(&server)
+                .await;
+        }
+        let uri = server.uri();
+        let dir = TempDir::new().unwrap();
+        let dest = dir.path().join("foo.tar.gz").to_string_lossy().to_string();
+        let urls: rhai::Array = vec![
+            format!("{uri}/one/foo.tar.gz").into(),
+            format!("{uri}/two/foo.tar.gz").into(),
+        ];
+
+        let err = download_mirrors_verified(urls.clone(), &dest, HELLO_SHA512)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("from every mirror"), "{err}");
+        assert_eq!(
+            err.matches("SHA512 integrity check failed").count(),
+            2,
+            "{err}"
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        std::fs::write(&dest, "previous").unwrap();
+        assert!(download_mirrors_verified(urls, &dest, HELLO_SHA512).is_err());
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "previous");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -13,7 +13,7 @@
 //! }
 //! ```
 
-use crate::core::output;
+use crate::core::{offline, output};
 use indicatif::{ProgressBar, ProgressStyle};
 use rhai::EvalAltResult;
 use std::path::Path;
@@ -91,6 +91,7 @@ pub fn git_clone(url: &str, dest_dir: &str) -> Result<String, Box<EvalAltResult>
         let _ = std::fs::remove_dir_all(&dest);
     }
 
+    offline::check(url)?;
     output::detail(&format!("git clone {}", url));
 
     // Create progress spinner with RAII guard for cleanup
@@ -185,6 +186,7 @@ pub fn git_clone_depth(
         let _ = std::fs::remove_dir_all(&dest);
     }
 
+    offline::check(url)?;
     output::detail(&format!("git clone --depth {} {}", depth, url));
 
     // Create progress spinner with RAII guard for cleanup
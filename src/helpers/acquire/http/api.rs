@@ -9,7 +9,7 @@
 //! export GITHUB_TOKEN="ghp_xxxxxxxxxxxxxxxxxxxx"
 //! ```
 
-use crate::core::output;
+use crate::core::{cache, offline, output};
 use rhai::EvalAltResult;
 use std::path::Path;
 use std::sync::OnceLock;
@@ -57,6 +57,7 @@ fn github_request(url: &str) -> ureq::Request {
 
 /// Fetch content from a URL (GET request)
 pub fn http_get(url: &str) -> Result<String, Box<EvalAltResult>> {
+    offline::check(url)?;
     ureq::get(url)
         .timeout(get_http_timeout())
         .call()
@@ -82,6 +83,7 @@ pub(crate) fn github_latest_release_with_base(
     base_url: &str,
 ) -> Result<String, Box<EvalAltResult>> {
     let url = format!("{}/repos/{}/releases/latest", base_url, repo);
+    offline::check(&url)?;
 
     let response = github_request(&url).call().map_err(|e| {
         // Handle rate limiting specifically
@@ -121,6 +123,7 @@ pub(crate) fn github_latest_tag_with_base(
     base_url: &str,
 ) -> Result<String, Box<EvalAltResult>> {
     let url = format!("{}/repos/{}/tags", base_url, repo);
+    offline::check(&url)?;
 
     let response = github_request(&url).call().map_err(|e| {
         // Handle rate limiting specifically
@@ -164,7 +167,9 @@ pub fn parse_version(version_str: &str) -> String {
 /// * `dest_dir` - Directory to save the downloaded file
 ///
 /// # Returns
-/// The path to the downloaded file
+/// The path to the downloaded file. The asset goes through the download cache
+/// when it is enabled; offline, the most recently fetched cached asset of
+/// `repo` matching the pattern is used.
 ///
 /// # Example
 /// ```rhai
@@ -185,8 +190,17 @@ fn github_download_release_impl(
     dest_dir: &str,
     base_url: &str,
 ) -> Result<String, Box<EvalAltResult>> {
+    let pattern = glob::Pattern::new(asset_pattern)
+        .map_err(|e| format!("Invalid asset pattern '{}': {}", asset_pattern, e))?;
+
     // Get latest release info
     let url = format!("{}/repos/{}/releases/latest", base_url, repo);
+    if offline::is_offline()
+        && let Some(path) = cached_release_asset(repo, &pattern, dest_dir)
+    {
+        return Ok(path);
+    }
+    offline::check_uncached(&url)?;
     let response = github_request(&url).call().map_err(|e| {
         if let ureq::Error::Status(403, _) = e {
             return "GitHub API rate limit exceeded. Try again later or set GITHUB_TOKEN.".into();
@@ -207,9 +221,6 @@ fn github_download_release_impl(
         .and_then(|a| a.as_array())
         .ok_or("No assets found in release")?;
 
-    let pattern = glob::Pattern::new(asset_pattern)
-        .map_err(|e| format!("Invalid asset pattern '{}': {}", asset_pattern, e))?;
-
     let asset = assets
         .iter()
         .find(|a| {
@@ -237,6 +248,9 @@ fn github_download_release_impl(
 
     // Download the asset
     let dest_path = Path::new(dest_dir).join(asset_name);
+    if cache::fetch(download_url, &dest_path) {
+        return Ok(dest_path.to_string_lossy().to_string());
+    }
 
     let response = ureq::get(download_url)
         .timeout(Duration::from_secs(300)) // 5 minute timeout for downloads
@@ -247,17 +261,31 @@ fn github_download_release_impl(
     let mut file =
         std::fs::File::create(&dest_path).map_err(|e| format!("Failed to create file: {}", e))?;
     std::io::copy(&mut reader, &mut file).map_err(|e| format!("Failed to write file: {}", e))?;
+    drop(file);
 
     output::detail(&format!("downloaded {}", asset_name));
+    cache::store(download_url, &dest_path);
     Ok(dest_path.to_string_lossy().to_string())
 }
 
+/// Offline stand-in for the release lookup: the most recently fetched
+/// cached asset of `repo` whose name matches `pattern`.
+fn cached_release_asset(repo: &str, pattern: &glob::Pattern, dest_dir: &str) -> Option<String> {
+    let prefix = format!("https://github.com/{}/releases/download/", repo);
+    let entry = cache::newest(|entry| {
+        entry.url.starts_with(&prefix) && !entry.name.contains('/') && pattern.matches(&entry.name)
+    })?;
+    let dest_path = Path::new(dest_dir).join(&entry.name);
+    cache::fetch(&entry.url, &dest_path).then(|| dest_path.to_string_lossy().to_string())
+}
+
 /// Get release assets metadata for a GitHub repository
 ///
 /// Returns a list of asset names and download URLs for the latest release.
 /// Useful for scripting when you need to inspect available assets.
 pub fn github_release_assets(repo: &str) -> Result<Vec<(String, String)>, Box<EvalAltResult>> {
     let url = format!("{}/repos/{}/releases/latest", GITHUB_API_BASE, repo);
+    offline::check(&url)?;
     let response = github_request(&url).call().map_err(|e| {
         if let ureq::Error::Status(403, _) = e {
             return "GitHub API rate limit exceeded. Try again later or set GITHUB_TOKEN.".into();
@@ -5,8 +5,10 @@
 //!
 //! ## Functions
 //!
-//! - **download**: Download files from HTTP(S) URLs
+//! - **download**: Download files from HTTP(S) URLs, optionally from a list of mirrors
+//! - **download_verified**: Download and check a hash in one pass, keeping `dest` on mismatch
 //! - **verify_sha256/sha512/blake3**: Verify file integrity with cryptographic hashes
+//! - **verify_gpg/minisign/signify/ssh_sig**: Verify detached signatures against pinned keys
 //! - **http_get**: Fetch content from URLs
 //! - **github_latest_release/tag**: Query GitHub for latest versions
 //! - **git_clone**: Clone git repositories
@@ -19,7 +21,7 @@ pub mod torrent;
 pub mod verify;
 
 // Re-export commonly used items
-pub use download::download;
+pub use download::{download, download_mirrors, download_mirrors_verified, download_verified};
 pub use git::{git_clone, git_clone_depth};
 pub use http::{
     extract_from_tarball, github_download_release, github_latest_release, github_latest_tag,
@@ -27,5 +29,6 @@ pub use http::{
 };
 pub use torrent::{download_with_resume, torrent};
 pub use verify::{
-    FileHashes, compute_hashes, fetch_sha256, verify_blake3, verify_sha256, verify_sha512,
+    FileHashes, compute_hashes, fetch_sha256, verify_blake3, verify_gpg, verify_minisign,
+    verify_sha256, verify_sha512, verify_signify, verify_ssh_sig, verify_ssh_sig_namespace,
 };
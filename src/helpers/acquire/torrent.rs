@@ -13,7 +13,7 @@
 //! }
 //! ```
 
-use crate::core::output;
+use crate::core::{cache, offline, output};
 use crate::helpers::internal::progress;
 use indicatif::ProgressBar;
 use librqbit::{AddTorrent, AddTorrentOptions, AddTorrentResponse, Session, SessionOptions};
@@ -47,7 +47,8 @@ fn validate_safe_relative_path(p: &Path) -> Result<(), Box<EvalAltResult>> {
 /// Download a file via BitTorrent.
 ///
 /// Takes a .torrent file URL or magnet link. Downloads the content to dest_dir.
-/// Returns the path to the downloaded file.
+/// Returns the path to the downloaded file. Single-file torrents go through
+/// the download cache when it is enabled.
 ///
 /// # Example
 /// ```rhai
@@ -61,12 +62,21 @@ pub fn torrent(url: &str, dest_dir: &str) -> Result<String, Box<EvalAltResult>>
     std::fs::create_dir_all(dest_dir_path)
         .map_err(|e| format!("cannot create destination directory {}: {}", dest_dir, e))?;
 
+    if let Some(name) = cache::cached_name(url) {
+        let cached = dest_dir_path.join(&name);
+        if validate_safe_relative_path(Path::new(&name)).is_ok() && cache::fetch(url, &cached) {
+            return Ok(cached.to_string_lossy().to_string());
+        }
+    }
+    offline::check_uncached(url)?;
+
     output::detail(&format!("torrent download: {}", url));
 
     let pb = progress::create_download_progress("downloading via bittorrent...");
     let pb_for_async = pb.clone();
     let _guard = progress::ProgressGuard::new(&pb);
 
+    let url_for_cache = url.to_string();
     let url = url.to_string();
     let output_folder = dest_dir.to_string();
     let dest_dir_path = dest_dir_path.to_path_buf();
@@ -160,6 +170,9 @@ pub fn torrent(url: &str, dest_dir: &str) -> Result<String, Box<EvalAltResult>>
         .map_err(|e| format!("torrent download failed: {:#}", e))?;
 
     output::detail(&format!("downloaded {}", downloaded_path));
+    if Path::new(&downloaded_path).is_file() {
+        cache::store(&url_for_cache, Path::new(&downloaded_path));
+    }
     Ok(downloaded_path)
 }
 
@@ -169,6 +182,8 @@ pub fn torrent(url: &str, dest_dir: &str) -> Result<String, Box<EvalAltResult>>
 /// requests (HTTP 206) or report that the file is already complete (HTTP 416 with a
 /// valid `Content-Range`). It will not fall back to restarting the download.
 ///
+/// When the download cache has `url`, the cached file replaces `dest`.
+///
 /// # Example
 /// ```rhai
 /// let file = download_with_resume("https://example.com/large-file.iso", BUILD_DIR + "/file.iso");
@@ -186,6 +201,10 @@ pub fn download_with_resume(url: &str, dest: &str) -> Result<String, Box<EvalAlt
 
     std::fs::create_dir_all(dest_dir)
         .map_err(|e| format!("cannot create destination directory: {}", e))?;
+    if cache::fetch(url, dest_path) {
+        return Ok(dest.to_string());
+    }
+    offline::check_uncached(url)?;
 
     // Determine current size for resume.
     let existing_len = dest_path.metadata().map(|m| m.len()).unwrap_or(0);
@@ -228,6 +247,7 @@ pub fn download_with_resume(url: &str, dest: &str) -> Result<String, Box<EvalAlt
 
                 if existing_len == total {
                     output::detail(&format!("{} already fully downloaded", dest_path.display()));
+                    cache::store(url, dest_path);
                     return Ok(dest.to_string());
                 }
                 return Err(format!(
@@ -299,7 +319,9 @@ pub fn download_with_resume(url: &str, dest: &str) -> Result<String, Box<EvalAlt
         pb.set_position(written);
     }
 
+    drop(file);
     output::detail(&format!("downloaded {}", dest_path.display()));
+    cache::store(url, dest_path);
     Ok(dest.to_string())
 }
 
@@ -1,6 +1,7 @@
-//! Hash verification helpers
+//! Hash and signature verification helpers
 //!
-//! Pure functions for verifying file integrity using cryptographic hashes.
+//! Pure functions for verifying file integrity using cryptographic hashes
+//! and detached signatures.
 //!
 //! ## Supported Hash Algorithms
 //!
@@ -8,6 +9,16 @@
 //! - `verify_sha512(path, expected)` - SHA-512 (stronger)
 //! - `verify_blake3(path, expected)` - BLAKE3 (fastest)
 //!
+//! ## Detached Signatures
+//!
+//! - `verify_gpg(file, sig, keyring)` - OpenPGP, with `gpgv`
+//! - `verify_minisign(file, sig, pubkey)` - minisign
+//! - `verify_signify(file, sig, pubkey)` - signify
+//! - `verify_ssh_sig(file, sig, allowed_signers[, namespace])` - SSH, with `ssh-keygen -Y`
+//!
+//! Keys are pinned by the recipe, as files (relative paths are resolved
+//! against `RECIPE_DIR`) or inline; the host's own keyrings are never trusted.
+//!
 //! ## Example
 //!
 //! ```rhai
@@ -15,11 +26,13 @@
 //! verify_sha256(archive, "abc123...");
 //! ```
 
-use crate::core::output;
+use crate::core::{cache, offline, output};
 use rhai::EvalAltResult;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 use super::super::internal::hash::{self, HashAlgorithm};
+use super::super::util::process::{command_exists, require_command};
 use super::http;
 
 fn is_valid_sha256(s: &str) -> bool {
@@ -71,7 +84,7 @@ fn parse_sha256_from_checksum_file(content: &str, filename: &str) -> Option<Stri
 /// ```
 pub fn verify_sha256(path: &str, expected: &str) -> Result<(), Box<EvalAltResult>> {
     output::detail(&format!("verifying sha256 of {}", path));
-    hash::verify_file_hash(Path::new(path), expected, HashAlgorithm::Sha256)
+    verify_file(path, expected, HashAlgorithm::Sha256)
 }
 
 /// Verify the SHA512 hash of a file.
@@ -79,7 +92,7 @@ pub fn verify_sha256(path: &str, expected: &str) -> Result<(), Box<EvalAltResult
 /// Throws an error if the hash doesn't match.
 pub fn verify_sha512(path: &str, expected: &str) -> Result<(), Box<EvalAltResult>> {
     output::detail(&format!("verifying sha512 of {}", path));
-    hash::verify_file_hash(Path::new(path), expected, HashAlgorithm::Sha512)
+    verify_file(path, expected, HashAlgorithm::Sha512)
 }
 
 /// Verify the BLAKE3 hash of a file.
@@ -87,7 +100,38 @@ pub fn verify_sha512(path: &str, expected: &str) -> Result<(), Box<EvalAltResult
 /// Throws an error if the hash doesn't match.
 pub fn verify_blake3(path: &str, expected: &str) -> Result<(), Box<EvalAltResult>> {
     output::detail(&format!("verifying blake3 of {}", path));
-    hash::verify_file_hash(Path::new(path), expected, HashAlgorithm::Blake3)
+    verify_file(path, expected, HashAlgorithm::Blake3)
+}
+
+/// Check the hash and report the outcome to the download cache, which marks
+/// a cached file verified or drops it.
+fn verify_file(
+    path: &str,
+    expected: &str,
+    algorithm: HashAlgorithm,
+) -> Result<(), Box<EvalAltResult>> {
+    let result = hash::verify_file_hash(Path::new(path), expected, algorithm);
+    cache::verified(Path::new(path), result.is_ok());
+    result
+}
+
+/// Split an expected hash into its algorithm and digest: `sha512:<hex>`,
+/// `blake3:<hex>`, or `sha256:<hex>` / bare hex for SHA-256.
+pub(crate) fn parse_hash(expected: &str) -> Result<(HashAlgorithm, &str), Box<EvalAltResult>> {
+    let expected = expected.trim();
+    match expected.split_once(':') {
+        None => Ok((HashAlgorithm::Sha256, expected)),
+        Some((prefix, digest)) => match prefix.to_ascii_lowercase().as_str() {
+            "sha256" => Ok((HashAlgorithm::Sha256, digest)),
+            "sha512" => Ok((HashAlgorithm::Sha512, digest)),
+            "blake3" => Ok((HashAlgorithm::Blake3, digest)),
+            _ => Err(format!(
+                "unsupported hash algorithm '{}' (expected sha256, sha512 or blake3)",
+                prefix
+            )
+            .into()),
+        },
+    }
 }
 
 /// Fetch a SHA256 checksum from a remote checksum file.
@@ -125,10 +169,509 @@ pub fn compute_hashes(file: &Path) -> Result<hash::FileHashes, std::io::Error> {
 /// Re-export FileHashes for backwards compatibility
 pub use super::super::internal::hash::FileHashes;
 
+// ============================================================================
+// Detached signatures
+// ============================================================================
+
+/// Verify a detached OpenPGP signature (`.sig`/`.asc`) with `gpgv`.
+///
+/// `keyring` pins the signing keys for the recipe: a keyring file (binary or
+/// ASCII-armored, e.g. `join_path(RECIPE_DIR, "keys/foo.asc")`) or an
+/// armored key block. Only keys in it are trusted; the user's GnuPG keyrings
+/// are never consulted.
+///
+/// Throws if the signature is not a good signature by one of those keys.
+///
+/// # Example
+/// ```rhai
+/// verify_gpg(archive, archive + ".sig", join_path(RECIPE_DIR, "keys/gnu-keyring.gpg"));
+/// ```
+pub fn verify_gpg(file: &str, sig: &str, keyring: &str) -> Result<(), Box<EvalAltResult>> {
+    output::detail(&format!("verifying gpg signature of {}", file));
+    let result = check_gpg(file, sig, keyring);
+    cache::verified(Path::new(file), result.is_ok());
+    result
+}
+
+/// Verify a detached minisign signature (`.minisig`).
+///
+/// `pubkey` is a minisign public key file or the public key itself
+/// (`RWQ...`), pinned in the recipe.
+///
+/// # Example
+/// ```rhai
+/// verify_minisign(archive, archive + ".minisig", "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3");
+/// ```
+pub fn verify_minisign(file: &str, sig: &str, pubkey: &str) -> Result<(), Box<EvalAltResult>> {
+    output::detail(&format!("verifying minisign signature of {}", file));
+    let result = check_minisign(file, sig, pubkey);
+    cache::verified(Path::new(file), result.is_ok());
+    result
+}
+
+/// Verify a detached signify signature (`.sig`) with `signify` (or
+/// `signify-openbsd`).
+///
+/// `pubkey` is a signify public key file or the base64 key line itself,
+/// pinned in the recipe.
+pub fn verify_signify(file: &str, sig: &str, pubkey: &str) -> Result<(), Box<EvalAltResult>> {
+    output::detail(&format!("verifying signify signature of {}", file));
+    let result = check_signify(file, sig, pubkey);
+    cache::verified(Path::new(file), result.is_ok());
+    result
+}
+
+/// Verify an SSH signature (`ssh-keygen -Y sign`) in the `file` namespace.
+///
+/// `allowed_signers` is an OpenSSH allowed-signers file or its content
+/// (`principal key-type base64-key` lines), pinned in the recipe.
+///
+/// # Example
+/// ```rhai
+/// verify_ssh_sig(archive, archive + ".sig", "release@example.org ssh-ed25519 AAAA...");
+/// ```
+pub fn verify_ssh_sig(
+    file: &str,
+    sig: &str,
+    allowed_signers: &str,
+) -> Result<(), Box<EvalAltResult>> {
+    verify_ssh_sig_namespace(file, sig, allowed_signers, "file")
+}
+
+/// [`verify_ssh_sig`] for signatures made with `-n <namespace>`.
+pub fn verify_ssh_sig_namespace(
+    file: &str,
+    sig: &str,
+    allowed_signers: &str,
+    namespace: &str,
+) -> Result<(), Box<EvalAltResult>> {
+    output::detail(&format!("verifying ssh signature of {}", file));
+    let result = check_ssh_sig(file, sig, allowed_signers, namespace);
+    cache::verified(Path::new(file), result.is_ok());
+    result
+}
+
+/// A key argument: an existing file, with relative paths resolved against
+/// the running recipe's `RECIPE_DIR`, is used as is. Anything else that looks
+/// like a path is reported as missing; the rest is key material, written to
+/// `name` in `work`.
+struct PinnedKey {
+    path: PathBuf,
+    /// How failures refer to the key.
+    label: String,
+}
+
+fn pinned_key(
+    helper: &str,
+    key: &str,
+    work: &Path,
+    name: &str,
+    content: impl FnOnce(&str) -> String,
+) -> Result<PinnedKey, Box<EvalAltResult>> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("{helper}: no key given").into());
+    }
+    let path = match offline::current_recipe_dir() {
+        Some(dir) if Path::new(key).is_relative() => dir.join(key),
+        _ => PathBuf::from(key),
+    };
+    if path.is_file() {
+        return Ok(PinnedKey {
+            path,
+            label: key.to_string(),
+        });
+    }
+    if looks_like_path(key) {
+        return Err(format!("{helper}: key file '{key}' not found").into());
+    }
+    let path = work.join(name);
+    std::fs::write(&path, content(key)).map_err(|e| format!("cannot write key: {}", e))?;
+    Ok(PinnedKey {
+        path,
+        label: "the key pinned in the recipe".to_string(),
+    })
+}
+
+/// Whether a key argument names a file rather than holding key material. Key
+/// material is multi-line, has spaces, or is plain base64; paths start with
+/// `/`, `.` or `~`, or use characters base64 does not (such as `.` before an
+/// extension).
+fn looks_like_path(key: &str) -> bool {
+    if key.contains(char::is_whitespace) {
+        return false;
+    }
+    key.starts_with(['/', '.', '~'])
+        || key
+            .chars()
+            .any(|c| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=')))
+}
+
+fn work_dir() -> Result<tempfile::TempDir, Box<EvalAltResult>> {
+    tempfile::tempdir().map_err(|e| format!("cannot create temp dir: {}", e).into())
+}
+
+fn run(cmd: &mut Command, tool: &str) -> Result<std::process::Output, Box<EvalAltResult>> {
+    cmd.output()
+        .map_err(|e| format!("Failed to execute {}: {}", tool, e).into())
+}
+
+/// Error text for a failed signature check.
+fn signature_mismatch(kind: &str, file: &str, expected: &str, got: &str) -> String {
+    format!(
+        "{} signature check failed for '{}'\n  expected: {}\n  got:      {}",
+        kind, file, expected, got
+    )
+}
+
+/// Last non-empty line a tool printed, as the reason for a failure.
+fn tool_reason(output: &std::process::Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stderr
+        .lines()
+        .chain(stdout.lines())
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .unwrap_or("no valid signature")
+        .to_string()
+}
+
+fn check_gpg(file: &str, sig: &str, keyring: &str) -> Result<(), Box<EvalAltResult>> {
+    require_command(
+        "verify_gpg",
+        "gpgv",
+        "install GnuPG to check OpenPGP signatures",
+    )?;
+    let work = work_dir()?;
+    let home = work.path().join("gnupg");
+    std::fs::create_dir(&home).map_err(|e| format!("cannot create temp dir: {}", e))?;
+    let mut key = pinned_key("verify_gpg", keyring, work.path(), "keyring.asc", |k| {
+        format!("{k}\n")
+    })?;
+
+    let armored = std::fs::read(&key.path)
+        .map_err(|e| format!("cannot read keyring '{}': {}", key.label, e))?
+        .starts_with(b"-----BEGIN PGP");
+    let signers = if command_exists("gpg") {
+        gpg_signers(&home, &key.path)
+    } else {
+        Vec::new()
+    };
+    if armored {
+        require_command(
+            "verify_gpg",
+            "gpg",
+            "armored keyrings are converted with gpg --dearmor",
+        )?;
+        let dearmored = work.path().join("keyring.gpg");
+        let output = run(
+            Command::new("gpg")
+                .arg("--homedir")
+                .arg(&home)
+                .args(["--batch", "--yes", "--dearmor", "-o"])
+                .arg(&dearmored)
+                .arg(&key.path),
+            "gpg",
+        )?;
+        if !output.status.success() {
+            return Err(format!(
+                "cannot read keyring '{}': {}",
+                key.label,
+                tool_reason(&output)
+            )
+            .into());
+        }
+        key.path = dearmored;
+    }
+
+    let output = run(
+        Command::new("gpgv")
+            .env("GNUPGHOME", &home)
+            .args(["--status-fd", "1", "--keyring"])
+            .arg(&key.path)
+            .arg(sig)
+            .arg(file),
+        "gpgv",
+    )?;
+    let status = String::from_utf8_lossy(&output.stdout);
+    let field = |tag: &str| {
+        status.lines().find_map(|line| {
+            line.strip_prefix("[GNUPG:] ")?
+                .strip_prefix(tag)?
+                .strip_prefix(' ')
+                .map(str::to_string)
+        })
+    };
+    if output.status.success()
+        && let (Some(good), Some(valid)) = (field("GOODSIG"), field("VALIDSIG"))
+    {
+        let who = good.split_once(' ').map_or(good.as_str(), |(_, uid)| uid);
+        let fingerprint = valid.split_whitespace().next().unwrap_or_default();
+        output::detail(&format!("good signature from {} ({})", who, fingerprint));
+        return Ok(());
+    }
+
+    let got = if let Some(id) = field("NO_PUBKEY") {
+        format!("a signature by key {id}, which is not in the keyring")
+    } else if let Some(bad) = field("BADSIG") {
+        let who = bad.split_once(' ').map_or(bad.as_str(), |(_, uid)| uid);
+        format!("a bad signature from {who} (the file does not match)")
+    } else if let Some(expired) = field("EXPKEYSIG") {
+        format!("a signature by expired key {expired}")
+    } else if let Some(revoked) = field("REVKEYSIG") {
+        format!("a signature by revoked key {revoked}")
+    } else {
+        tool_reason(&output)
+    };
+    let expected = if signers.is_empty() {
+        format!("a good signature by a key in {}", key.label)
+    } else {
+        format!(
+            "a good signature by {} (from {})",
+            signers.join(" or "),
+            key.label
+        )
+    };
+    Err(signature_mismatch("OpenPGP", file, &expected, &got).into())
+}
+
+/// `uid (fingerprint)` for every key in `keyring`, to name them in errors.
+fn gpg_signers(home: &Path, keyring: &Path) -> Vec<String> {
+    let Ok(output) = Command::new("gpg")
+        .arg("--homedir")
+        .arg(home)
+        .args(["--batch", "--with-colons", "--show-keys"])
+        .arg(keyring)
+        .output()
+    else {
+        return Vec::new();
+    };
+    let mut signers = Vec::new();
+    let mut fingerprint: Option<String> = None;
+    let mut named = false;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        match fields.first().copied() {
+            Some("pub") => {
+                fingerprint = None;
+                named = false;
+            }
+            Some("fpr") if fingerprint.is_none() => {
+                fingerprint = fields.get(9).map(|f| f.to_string());
+            }
+            Some("uid") if !named => {
+                if let (Some(uid), Some(fpr)) = (fields.get(9), &fingerprint) {
+                    signers.push(format!("{uid} ({fpr})"));
+                    named = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    signers
+}
+
+fn check_minisign(file: &str, sig: &str, pubkey: &str) -> Result<(), Box<EvalAltResult>> {
+    require_command(
+        "verify_minisign",
+        "minisign",
+        "install minisign to check minisign signatures",
+    )?;
+    let work = work_dir()?;
+    let key = pinned_key(
+        "verify_minisign",
+        pubkey,
+        work.path(),
+        "minisign.pub",
+        |k| {
+            if k.starts_with("untrusted comment:") {
+                format!("{k}\n")
+            } else {
+                format!("untrusted comment: minisign public key pinned in recipe\n{k}\n")
+            }
+        },
+    )?;
+    let output = run(
+        Command::new("minisign")
+            .args(["-V", "-m"])
+            .arg(file)
+            .arg("-x")
+            .arg(sig)
+            .arg("-p")
+            .arg(&key.path),
+        "minisign",
+    )?;
+    if output.status.success() {
+        if let Some(comment) = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find_map(|line| line.strip_prefix("Trusted comment: "))
+        {
+            output::detail(&format!("good signature ({})", comment));
+        }
+        return Ok(());
+    }
+    let expected = format!(
+        "a signature by minisign key {}",
+        key_line(&key.path).unwrap_or(key.label)
+    );
+    Err(signature_mismatch("minisign", file, &expected, &tool_reason(&output)).into())
+}
+
+fn check_signify(file: &str, sig: &str, pubkey: &str) -> Result<(), Box<EvalAltResult>> {
+    let tool = ["signify", "signify-openbsd"]
+        .into_iter()
+        .find(|tool| command_exists(tool))
+        .unwrap_or("signify");
+    require_command(
+        "verify_signify",
+        tool,
+        "install signify to check signify signatures",
+    )?;
+    let work = work_dir()?;
+    let key = pinned_key("verify_signify", pubkey, work.path(), "signify.pub", |k| {
+        if k.starts_with("untrusted comment:") {
+            format!("{k}\n")
+        } else {
+            format!("untrusted comment: signify public key pinned in recipe\n{k}\n")
+        }
+    })?;
+    let output = run(
+        Command::new(tool)
+            .args(["-V", "-q", "-p"])
+            .arg(&key.path)
+            .arg("-x")
+            .arg(sig)
+            .arg("-m")
+            .arg(file),
+        tool,
+    )?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let expected = format!(
+        "a signature by signify key {}",
+        key_line(&key.path).unwrap_or(key.label)
+    );
+    Err(signature_mismatch("signify", file, &expected, &tool_reason(&output)).into())
+}
+
+/// The base64 key line of a minisign or signify public key file.
+fn key_line(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()?
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+        .map(str::to_string)
+}
+
+fn check_ssh_sig(
+    file: &str,
+    sig: &str,
+    allowed_signers: &str,
+    namespace: &str,
+) -> Result<(), Box<EvalAltResult>> {
+    require_command(
+        "verify_ssh_sig",
+        "ssh-keygen",
+        "install OpenSSH to check SSH signatures",
+    )?;
+    let work = work_dir()?;
+    let signers = pinned_key(
+        "verify_ssh_sig",
+        allowed_signers,
+        work.path(),
+        "allowed_signers",
+        |k| format!("{k}\n"),
+    )?;
+    let principals: Vec<String> = std::fs::read_to_string(&signers.path)
+        .map_err(|e| format!("cannot read allowed signers '{}': {}", signers.label, e))?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect();
+    let expected = format!(
+        "a \"{}\" signature by {} (from {})",
+        namespace,
+        if principals.is_empty() {
+            "an allowed signer".to_string()
+        } else {
+            principals.join(" or ")
+        },
+        signers.label
+    );
+
+    let found = run(
+        Command::new("ssh-keygen")
+            .args(["-Y", "find-principals", "-s"])
+            .arg(sig)
+            .arg("-f")
+            .arg(&signers.path),
+        "ssh-keygen",
+    )?;
+    let matched = String::from_utf8_lossy(&found.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string);
+    let Some(principal) = matched.filter(|_| found.status.success()) else {
+        let got = format!(
+            "a signature by a key not in the allowed signers ({})",
+            tool_reason(&found)
+        );
+        return Err(signature_mismatch("SSH", file, &expected, &got).into());
+    };
+
+    let input = std::fs::File::open(file).map_err(|e| format!("cannot open file: {}", e))?;
+    let output = run(
+        Command::new("ssh-keygen")
+            .args(["-Y", "verify", "-f"])
+            .arg(&signers.path)
+            .arg("-I")
+            .arg(&principal)
+            .arg("-n")
+            .arg(namespace)
+            .arg("-s")
+            .arg(sig)
+            .stdin(input),
+        "ssh-keygen",
+    )?;
+    if output.status.success() {
+        output::detail(&format!("good signature from {}", principal));
+        return Ok(());
+    }
+    let got = format!(
+        "a signature by {} that does not verify ({})",
+        principal,
+        tool_reason(&output)
+    );
+    Err(signature_mismatch("SSH", file, &expected, &got).into())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_hash_prefixes() {
+        assert!(matches!(
+            parse_hash("abc").unwrap(),
+            (HashAlgorithm::Sha256, "abc")
+        ));
+        assert!(matches!(
+            parse_hash("SHA512:def").unwrap(),
+            (HashAlgorithm::Sha512, "def")
+        ));
+        assert!(matches!(
+            parse_hash("blake3:0a").unwrap(),
+            (HashAlgorithm::Blake3, "0a")
+        ));
+        assert!(parse_hash("md5:0a").is_err());
+    }
+
     #[test]
     fn test_parse_sha256_rocky_style() {
         let content = r#"
@@ -173,4 +716,220 @@ SHA256 (Other.iso) = deadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdeadbeefdea
             "SHA256 (Other.iso) = 55f96d45a052c0ed4f06309480155cb66281a008691eb7f3f359957205b1849a";
         assert!(parse_sha256_from_checksum_file(content, "Nope.iso").is_none());
     }
+
+    fn sh(dir: &Path, script: &str) {
+        let status = Command::new("sh")
+            .args(["-c", script])
+            .current_dir(dir)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "{script}");
+    }
+
+    #[test]
+    fn test_verify_ssh_sig_names_allowed_signers() {
+        if !command_exists("ssh-keygen") {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        sh(
+            dir.path(),
+            "ssh-keygen -q -t ed25519 -N '' -C rel -f release && \
+             ssh-keygen -q -t ed25519 -N '' -C other -f other && \
+             echo hello > f && ssh-keygen -Y sign -f release -n file f",
+        );
+        let key = |name: &str| {
+            let public = std::fs::read_to_string(dir.path().join(format!("{name}.pub"))).unwrap();
+            let mut parts = public.split_whitespace();
+            format!("{} {}", parts.next().unwrap(), parts.next().unwrap())
+        };
+        let file = dir.path().join("f").to_string_lossy().to_string();
+        let sig = format!("{file}.sig");
+
+        verify_ssh_sig(
+            &file,
+            &sig,
+            &format!("release@example.org {}", key("release")),
+        )
+        .unwrap();
+
+        let err = verify_ssh_sig(&file, &sig, &format!("other@example.org {}", key("other")))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("SSH signature check failed"), "{err}");
+        assert!(
+            err.contains("expected: a \"file\" signature by other@example.org"),
+            "{err}"
+        );
+
+        let allowed = dir.path().join("allowed_signers");
+        std::fs::write(
+            &allowed,
+            format!("release@example.org {}\n", key("release")),
+        )
+        .unwrap();
+        std::fs::write(&file, "tampered\n").unwrap();
+        let err = verify_ssh_sig(&file, &sig, &allowed.to_string_lossy())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("got:      a signature by release@example.org that does not verify"),
+            "{err}"
+        );
+
+        let err = verify_ssh_sig(&file, &sig, "./missing_signers")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("key file './missing_signers' not found"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_pinned_key_resolves_relative_paths_against_recipe_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let work = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("keys")).unwrap();
+        std::fs::write(dir.path().join("keys/foo.asc"), "key").unwrap();
+        let pin = |key: &str| pinned_key("verify_gpg", key, work.path(), "k", str::to_string);
+
+        offline::running(&dir.path().join("foo.rhai"), || {
+            assert_eq!(
+                pin("keys/foo.asc").unwrap().path,
+                dir.path().join("keys/foo.asc")
+            );
+            let err = pin("keys/missing.asc").err().unwrap().to_string();
+            assert!(
+                err.contains("verify_gpg: key file 'keys/missing.asc' not found"),
+                "{err}"
+            );
+            let inline = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+            let key = pin(inline).unwrap();
+            assert_eq!(key.path, work.path().join("k"));
+            assert_eq!(std::fs::read_to_string(key.path).unwrap(), inline);
+        });
+
+        assert!(looks_like_path("~/keys/foo"));
+        assert!(looks_like_path("release-key"));
+        assert!(!looks_like_path("release@example.org ssh-ed25519 AAAA"));
+        assert!(!looks_like_path("RWQ+abc/def="));
+    }
+
+    #[test]
+    fn test_verify_minisign_good_and_bad_signatures() {
+        if !command_exists("minisign") {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        sh(
+            dir.path(),
+            "minisign -G -W -p release.pub -s release.key && \
+             minisign -G -W -p other.pub -s other.key && \
+             echo hello > f && minisign -S -s release.key -m f",
+        );
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        let file = path("f");
+        let sig = path("f.minisig");
+
+        verify_minisign(&file, &sig, &path("release.pub")).unwrap();
+        let inline = key_line(Path::new(&path("release.pub"))).unwrap();
+        verify_minisign(&file, &sig, &inline).unwrap();
+
+        let err = verify_minisign(&file, &sig, &path("other.pub"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("minisign signature check failed"), "{err}");
+
+        std::fs::write(&file, "tampered\n").unwrap();
+        let err = verify_minisign(&file, &sig, &inline)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("minisign signature check failed"), "{err}");
+    }
+
+    #[test]
+    fn test_verify_signify_good_and_bad_signatures() {
+        let Some(tool) = ["signify", "signify-openbsd"]
+            .into_iter()
+            .find(|tool| command_exists(tool))
+        else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        sh(
+            dir.path(),
+            &format!(
+                "{tool} -G -n -p release.pub -s release.sec && \
+                 {tool} -G -n -p other.pub -s other.sec && \
+                 echo hello > f && {tool} -S -s release.sec -m f -x f.sig"
+            ),
+        );
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        let file = path("f");
+        let sig = path("f.sig");
+
+        verify_signify(&file, &sig, &path("release.pub")).unwrap();
+        let inline = key_line(Path::new(&path("release.pub"))).unwrap();
+        verify_signify(&file, &sig, &inline).unwrap();
+
+        let err = verify_signify(&file, &sig, &path("other.pub"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("signify signature check failed"), "{err}");
+
+        std::fs::write(&file, "tampered\n").unwrap();
+        let err = verify_signify(&file, &sig, &inline)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("signify signature check failed"), "{err}");
+    }
+
+    #[test]
+    fn test_verify_gpg_trusts_only_the_pinned_keyring() {
+        if !command_exists("gpg") || !command_exists("gpgv") {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        std::fs::create_dir(&home).unwrap();
+        sh(
+            dir.path(),
+            "export GNUPGHOME=\"$PWD/home\" && \
+             gpg --batch --passphrase '' --quick-gen-key 'Release <rel@example.org>' ed25519 sign never && \
+             gpg --batch --passphrase '' --quick-gen-key 'Other <other@example.org>' ed25519 sign never && \
+             echo hello > f && gpg --batch --local-user rel@example.org --detach-sign f && \
+             gpg --armor --export rel@example.org > release.asc && \
+             gpg --armor --export other@example.org > other.asc; \
+             status=$?; gpgconf --kill gpg-agent; exit $status",
+        );
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        let file = path("f");
+        let sig = path("f.sig");
+
+        verify_gpg(&file, &sig, &path("release.asc")).unwrap();
+        let inline = std::fs::read_to_string(path("release.asc")).unwrap();
+        verify_gpg(&file, &sig, &inline).unwrap();
+
+        let err = verify_gpg(&file, &sig, &path("other.asc"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("OpenPGP signature check failed"), "{err}");
+        assert!(
+            err.contains("expected: a good signature by Other <other@example.org>"),
+            "{err}"
+        );
+        assert!(err.contains("which is not in the keyring"), "{err}");
+
+        std::fs::write(&file, "tampered\n").unwrap();
+        let err = verify_gpg(&file, &sig, &path("release.asc"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("got:      a bad signature from Release <rel@example.org>"),
+            "{err}"
+        );
+    }
 }
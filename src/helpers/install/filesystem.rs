@@ -1,6 +1,7 @@
 //! Filesystem operation helpers
 
 use crate::core::output;
+use crate::helpers::internal::{sysroot, tracking};
 use rhai::EvalAltResult;
 use std::path::{Path, PathBuf};
 
@@ -32,13 +33,17 @@ pub fn is_dir(path: &str) -> bool {
 
 /// Create a directory and all parent directories
 pub fn mkdir(path: &str) -> Result<(), Box<EvalAltResult>> {
-    output::detail(&format!("mkdir {}", path));
-    std::fs::create_dir_all(path).map_err(|e| format!("mkdir failed: {}", e).into())
+    let path = sysroot::resolve(path)?;
+    output::detail(&format!("mkdir {}", path.display()));
+    tracking::create_dir_all(&path).map_err(|e| format!("mkdir failed: {}", e).into())
 }
 
 /// Remove files matching a glob pattern
 pub fn rm_files(pattern: &str) -> Result<(), Box<EvalAltResult>> {
-    for path in glob::glob(pattern).map_err(|e| format!("invalid pattern: {}", e))? {
+    let pattern = sysroot::resolve(pattern)?;
+    for path in
+        glob::glob(&pattern.to_string_lossy()).map_err(|e| format!("invalid pattern: {}", e))?
+    {
         let path = path.map_err(|e| format!("glob error: {}", e))?;
         output::detail(&format!("rm {}", path.display()));
         if path.is_dir() {
@@ -64,7 +69,7 @@ fn ensure_parent_dir(path: &Path) -> Result<(), Box<EvalAltResult>> {
     if parent.as_os_str().is_empty() {
         return Ok(());
     }
-    std::fs::create_dir_all(parent).map_err(|e| {
+    tracking::create_dir_all(parent).map_err(|e| {
         format!(
             "failed to create parent directory for {}: {}",
             path.display(),
@@ -78,9 +83,10 @@ fn copy_file_impl(src: &Path, dest: &Path) -> Result<(), Box<EvalAltResult>> {
     if !src.is_file() {
         return Err(format!("copy_file source is not a file: {}", src.display()).into());
     }
+    tracking::check_claim(dest)?;
     ensure_parent_dir(dest)?;
     output::detail(&format!("cp {} {}", src.display(), dest.display()));
-    std::fs::copy(src, dest).map(|_| ()).map_err(|e| {
+    std::fs::copy(src, dest).map_err(|e| -> Box<EvalAltResult> {
         format!(
             "copy_file failed: {} -> {}: {}",
             src.display(),
@@ -88,7 +94,9 @@ fn copy_file_impl(src: &Path, dest: &Path) -> Result<(), Box<EvalAltResult>> {
             e
         )
         .into()
-    })
+    })?;
+    tracking::record_file(dest);
+    Ok(())
 }
 
 #[cfg(target_os = "linux")]
@@ -131,7 +139,7 @@ fn copy_path_to_dir(src: &Path, dest_dir: &Path) -> Result<(), Box<EvalAltResult
 
 /// Copy files matching a glob pattern into a directory
 pub fn copy_into_dir(pattern: &str, dest_dir: &str) -> Result<(), Box<EvalAltResult>> {
-    let dest_dir = Path::new(dest_dir);
+    let dest_dir = &sysroot::resolve(dest_dir)?;
     if !dest_dir.is_dir() {
         return Err(format!(
             "copy_into_dir destination is not a directory: {}",
@@ -159,16 +167,17 @@ pub fn copy_into_dir(pattern: &str, dest_dir: &str) -> Result<(), Box<EvalAltRes
 
 /// Copy a file to an exact destination path
 pub fn copy_file(src: &str, dest: &str) -> Result<(), Box<EvalAltResult>> {
-    copy_file_impl(Path::new(src), Path::new(dest))
+    copy_file_impl(Path::new(src), &sysroot::resolve(dest)?)
 }
 
 /// Copy a file using reflink semantics when available, falling back to a plain copy
 pub fn copy_file_reflink(src: &str, dest: &str) -> Result<(), Box<EvalAltResult>> {
     let src = Path::new(src);
-    let dest = Path::new(dest);
+    let dest = &sysroot::resolve(dest)?;
     if !src.is_file() {
         return Err(format!("copy_file_reflink source is not a file: {}", src.display()).into());
     }
+    tracking::check_claim(dest)?;
     ensure_parent_dir(dest)?;
     output::detail(&format!(
         "cp --reflink=auto {} {}",
@@ -176,13 +185,17 @@ pub fn copy_file_reflink(src: &str, dest: &str) -> Result<(), Box<EvalAltResult>
         dest.display()
     ));
     match clone_file_reflink(src, dest) {
-        Ok(()) => Ok(()),
+        Ok(()) => {
+            tracking::record_file(dest);
+            Ok(())
+        }
         Err(_) => copy_file_impl(src, dest),
     }
 }
 
 #[cfg(unix)]
 fn copy_symlink(link_path: &Path, dest_path: &Path) -> Result<(), Box<EvalAltResult>> {
+    tracking::check_claim(dest_path)?;
     let target = std::fs::read_link(link_path)
         .map_err(|e| format!("read_link failed for {}: {}", link_path.display(), e))?;
     output::detail(&format!(
@@ -202,7 +215,7 @@ fn copy_symlink(link_path: &Path, dest_path: &Path) -> Result<(), Box<EvalAltRes
             .into());
         }
     }
-    std::os::unix::fs::symlink(&target, dest_path).map_err(|e| {
+    std::os::unix::fs::symlink(&target, dest_path).map_err(|e| -> Box<EvalAltResult> {
         format!(
             "copy_tree_contents symlink failed: {} -> {}: {}",
             target.display(),
@@ -210,7 +223,9 @@ fn copy_symlink(link_path: &Path, dest_path: &Path) -> Result<(), Box<EvalAltRes
             e
         )
         .into()
-    })
+    })?;
+    tracking::record_file(dest_path);
+    Ok(())
 }
 
 #[cfg(not(unix))]
@@ -223,7 +238,7 @@ pub fn copy_tree_contents(src_dir: &str, dst_dir: &str) -> Result<(), Box<EvalAl
     use walkdir::WalkDir;
 
     let src_root = Path::new(src_dir);
-    let dst_root = Path::new(dst_dir);
+    let dst_root = &sysroot::resolve(dst_dir)?;
 
     if !src_root.is_dir() {
         return Err(format!(
@@ -261,7 +276,7 @@ pub fn copy_tree_contents(src_dir: &str, dst_dir: &str) -> Result<(), Box<EvalAl
 
         if metadata.is_dir() {
             output::detail(&format!("mkdir {}", dest_path.display()));
-            std::fs::create_dir_all(&dest_path).map_err(|e| -> Box<EvalAltResult> {
+            tracking::create_dir_all(&dest_path).map_err(|e| -> Box<EvalAltResult> {
                 format!(
                     "copy_tree_contents mkdir failed for {}: {}",
                     dest_path.display(),
@@ -296,7 +311,7 @@ pub fn copy_tree_contents(src_dir: &str, dst_dir: &str) -> Result<(), Box<EvalAl
 
 /// Copy the first existing source file to an exact destination path
 pub fn copy_first_existing(sources: rhai::Array, dest: &str) -> Result<String, Box<EvalAltResult>> {
-    let dest = Path::new(dest);
+    let dest = &sysroot::resolve(dest)?;
     let mut candidates = Vec::new();
 
     for item in sources {
@@ -323,21 +338,33 @@ pub fn copy_first_existing(sources: rhai::Array, dest: &str) -> Result<String, B
 
 /// Move/rename a file
 pub fn move_file(src: &str, dest: &str) -> Result<(), Box<EvalAltResult>> {
-    output::detail(&format!("mv {} -> {}", src, dest));
-    std::fs::rename(src, dest).map_err(|e| format!("mv failed: {}", e).into())
+    let dest = sysroot::resolve(dest)?;
+    tracking::check_claim(&dest)?;
+    output::detail(&format!("mv {} -> {}", src, dest.display()));
+    std::fs::rename(src, &dest)
+        .map_err(|e| -> Box<EvalAltResult> { format!("mv failed: {}", e).into() })?;
+    tracking::record_file(&dest);
+    Ok(())
 }
 
 /// Create a symbolic link
 #[cfg(unix)]
 pub fn symlink(src: &str, dest: &str) -> Result<(), Box<EvalAltResult>> {
-    output::detail(&format!("ln -s {} {}", src, dest));
-    std::os::unix::fs::symlink(src, dest).map_err(|e| format!("symlink failed: {}", e).into())
+    let dest = sysroot::resolve(dest)?;
+    tracking::check_claim(&dest)?;
+    output::detail(&format!("ln -s {} {}", src, dest.display()));
+    std::os::unix::fs::symlink(src, &dest)
+        .map_err(|e| -> Box<EvalAltResult> { format!("symlink failed: {}", e).into() })?;
+    tracking::record_file(&dest);
+    Ok(())
 }
 
 /// Create or replace a symbolic link
 #[cfg(unix)]
 pub fn symlink_force(src: &str, dest: &str) -> Result<(), Box<EvalAltResult>> {
-    let dest_path = Path::new(dest);
+    let dest_path = &sysroot::resolve(dest)?;
+    let dest = &dest_path.to_string_lossy();
+    tracking::check_claim(dest_path)?;
     output::detail(&format!("ln -sfn {} {}", src, dest));
 
     match std::fs::remove_file(dest_path) {
@@ -351,7 +378,10 @@ pub fn symlink_force(src: &str, dest: &str) -> Result<(), Box<EvalAltResult>> {
         }
     }
 
-    std::os::unix::fs::symlink(src, dest).map_err(|e| format!("symlink failed: {}", e).into())
+    std::os::unix::fs::symlink(src, dest_path)
+        .map_err(|e| -> Box<EvalAltResult> { format!("symlink failed: {}", e).into() })?;
+    tracking::record_file(dest_path);
+    Ok(())
 }
 
 #[cfg(not(unix))]
@@ -368,8 +398,9 @@ pub fn symlink_force(_src: &str, _dest: &str) -> Result<(), Box<EvalAltResult>>
 #[cfg(unix)]
 pub fn chmod_file(path: &str, mode: i64) -> Result<(), Box<EvalAltResult>> {
     use std::os::unix::fs::PermissionsExt;
-    output::detail(&format!("chmod {:o} {}", mode, path));
-    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode as u32))
+    let path = sysroot::resolve(path)?;
+    output::detail(&format!("chmod {:o} {}", mode, path.display()));
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode as u32))
         .map_err(|e| format!("chmod failed: {}", e).into())
 }
 
@@ -1,7 +1,7 @@
 //! I/O helpers for reading and writing files
 
+use crate::helpers::internal::{sysroot, tracking};
 use rhai::EvalAltResult;
-use std::path::Path;
 
 /// Read a file's contents as a string
 pub fn read_file(path: &str) -> Result<String, Box<EvalAltResult>> {
@@ -18,7 +18,12 @@ pub fn read_file_or_empty(path: &str) -> String {
 
 /// Write content to a file
 pub fn write_file(path: &str, content: &str) -> Result<(), Box<EvalAltResult>> {
-    std::fs::write(path, content).map_err(|e| format!("write failed: {}", e).into())
+    let path = sysroot::resolve(path)?;
+    tracking::check_claim(&path)?;
+    std::fs::write(&path, content)
+        .map_err(|e| -> Box<EvalAltResult> { format!("write failed: {}", e).into() })?;
+    tracking::record_file(&path);
+    Ok(())
 }
 
 /// Append content to a file
@@ -26,11 +31,17 @@ pub fn append_file(path: &str, content: &str) -> Result<(), Box<EvalAltResult>>
     use std::fs::OpenOptions;
     use std::io::Write;
 
+    let path = sysroot::resolve(path)?;
+    // Appending to an existing file (e.g. /etc/shells) must not claim ownership of it.
+    let created = path.symlink_metadata().is_err();
     let mut file = OpenOptions::new()
         .create(true)
         .append(true)
-        .open(path)
+        .open(&path)
         .map_err(|e| format!("append failed: {}", e))?;
+    if created {
+        tracking::record_file(&path);
+    }
 
     file.write_all(content.as_bytes())
         .map_err(|e| format!("write failed: {}", e).into())
@@ -42,7 +53,9 @@ pub fn append_line_if_missing(path: &str, line: &str) -> Result<bool, Box<EvalAl
         return Err("append_line_if_missing requires a single line without newline".into());
     }
 
-    let existing = read_file_or_empty(path);
+    let path = sysroot::resolve(path)?;
+    let path = path.to_string_lossy();
+    let existing = read_file_or_empty(&path);
     if existing.lines().any(|candidate| candidate == line) {
         return Ok(false);
     }
@@ -53,7 +66,7 @@ pub fn append_line_if_missing(path: &str, line: &str) -> Result<bool, Box<EvalAl
     }
     to_append.push_str(line);
     to_append.push('\n');
-    append_file(path, &to_append)?;
+    append_file(&path, &to_append)?;
     Ok(true)
 }
 
@@ -63,7 +76,7 @@ pub fn replace_in_file(path: &str, from: &str, to: &str) -> Result<(), Box<EvalA
         return Err("replace_in_file requires a non-empty search string".into());
     }
 
-    let file_path = Path::new(path);
+    let file_path = &sysroot::resolve(path)?;
     let content = std::fs::read_to_string(file_path).map_err(|e| {
         format!(
             "replace_in_file read failed for {}: {}",
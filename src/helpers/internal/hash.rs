@@ -59,19 +59,62 @@ pub fn verify_file_hash(
     };
 
     if hash != expected.to_lowercase() {
-        return Err(format!(
-            "{} integrity check failed for '{}'\n  expected: {}\n  got:      {}",
-            algorithm.name(),
-            file.display(),
-            expected.to_lowercase(),
-            hash
-        )
-        .into());
+        return Err(mismatch(algorithm, &file.display().to_string(), expected, &hash).into());
     }
 
     Ok(())
 }
 
+/// Error text for a hash mismatch of `subject` (a file or URL).
+pub fn mismatch(algorithm: HashAlgorithm, subject: &str, expected: &str, got: &str) -> String {
+    format!(
+        "{} integrity check failed for '{}'\n  expected: {}\n  got:      {}",
+        algorithm.name(),
+        subject,
+        expected.to_lowercase(),
+        got
+    )
+}
+
+/// Incremental hasher for data that is hashed while it streams.
+pub enum StreamHasher {
+    Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl StreamHasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        use sha2::Digest;
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+            HashAlgorithm::Sha512 => Self::Sha512(sha2::Sha512::new()),
+            HashAlgorithm::Blake3 => Self::Blake3(Box::default()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        use sha2::Digest;
+        match self {
+            Self::Sha256(h) => h.update(data),
+            Self::Sha512(h) => h.update(data),
+            Self::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    /// Lowercase hex digest.
+    pub fn finalize(self) -> String {
+        use sha2::Digest;
+        match self {
+            Self::Sha256(h) => hex::encode(h.finalize()),
+            Self::Sha512(h) => hex::encode(h.finalize()),
+            Self::Blake3(h) => h.finalize().to_hex().to_string(),
+        }
+    }
+}
+
 /// Compute hash using sha2 crate (SHA256/SHA512)
 fn hash_with_progress<D: sha2::Digest>(
     reader: &mut impl Read,
@@ -200,6 +243,25 @@ mod tests {
         verify_file_hash(&file_path, expected, HashAlgorithm::Sha256).unwrap();
     }
 
+    #[test]
+    fn test_stream_hasher_matches_file_hashes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        std::fs::write(&file_path, b"hello world").unwrap();
+        let hashes = compute_all_hashes(&file_path).unwrap();
+
+        for (algorithm, expected) in [
+            (HashAlgorithm::Sha256, &hashes.sha256),
+            (HashAlgorithm::Sha512, &hashes.sha512),
+            (HashAlgorithm::Blake3, &hashes.blake3),
+        ] {
+            let mut hasher = StreamHasher::new(algorithm);
+            hasher.update(b"hello ");
+            hasher.update(b"world");
+            assert_eq!(&hasher.finalize(), expected);
+        }
+    }
+
     #[test]
     fn test_verify_sha256_mismatch() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -7,4 +7,6 @@ pub mod cmd;
 pub mod fs_utils;
 pub mod hash;
 pub mod progress;
+pub mod sysroot;
+pub mod tracking;
 pub mod url_utils;
@@ -0,0 +1,183 @@
+//! Sysroot confinement for install helpers
+//!
+//! With `--sysroot` the executor runs `install(ctx)`, `remove(ctx)` and
+//! `cleanup(ctx, reason)` inside a confinement on the current thread. Filesystem helpers pass their destination paths
+//! through [`resolve`]:
+//! - paths under a scratch root (`BUILD_DIR`, and so `STAGE_DIR`) are used as-is
+//! - paths already under the sysroot are used as-is
+//! - other absolute paths are re-rooted: `/usr/bin/foo` becomes
+//!   `$SYSROOT/usr/bin/foo`
+//!
+//! `..` components are resolved lexically and existing parent directories are
+//! canonicalized, so neither can be used to write outside the sysroot.
+//! Outside a confinement paths are returned unchanged.
+
+use rhai::EvalAltResult;
+use std::cell::RefCell;
+use std::path::{Component, Path, PathBuf};
+
+struct Confinement {
+    root: PathBuf,
+    scratch: Vec<PathBuf>,
+    /// `root` and `scratch` with symlinks resolved, for the parent check.
+    real: Vec<PathBuf>,
+}
+
+thread_local! {
+    static CONFINEMENT: RefCell<Option<Confinement>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with helper destinations confined to `sysroot`. Paths under
+/// `scratch` stay writable as they are.
+pub fn confine<R>(sysroot: &Path, scratch: &[PathBuf], f: impl FnOnce() -> R) -> R {
+    let root = normalize(&absolute(sysroot));
+    let scratch: Vec<PathBuf> = scratch.iter().map(|p| normalize(&absolute(p))).collect();
+    let real = std::iter::once(&root)
+        .chain(&scratch)
+        .map(|p| real_path(p))
+        .collect();
+    let confinement = Confinement {
+        root,
+        scratch,
+        real,
+    };
+    let previous = CONFINEMENT.with(|c| c.borrow_mut().replace(confinement));
+    let result = f();
+    CONFINEMENT.with(|c| *c.borrow_mut() = previous);
+    result
+}
+
+/// Map a helper destination into the active sysroot.
+///
+/// Fails when the path would land outside both the sysroot and the scratch
+/// roots.
+pub fn resolve(path: &str) -> Result<PathBuf, Box<EvalAltResult>> {
+    CONFINEMENT.with(|c| match c.borrow().as_ref() {
+        Some(confinement) => confinement.resolve(Path::new(path)),
+        None => Ok(PathBuf::from(path)),
+    })
+}
+
+impl Confinement {
+    fn resolve(&self, path: &Path) -> Result<PathBuf, Box<EvalAltResult>> {
+        let in_scratch = |p: &Path| self.scratch.iter().any(|s| p.starts_with(s));
+
+        let candidate = if !path.is_absolute() || path.starts_with(&self.root) || in_scratch(path) {
+            absolute(path)
+        } else {
+            self.root.join(path.strip_prefix("/").unwrap_or(path))
+        };
+        let resolved = normalize(&candidate);
+
+        let lexical_ok = resolved.starts_with(&self.root) || in_scratch(&resolved);
+        // Only the parent is canonicalized: the destination itself may be a
+        // symlink the helper is about to replace.
+        let is_root = resolved == self.root || self.scratch.contains(&resolved);
+        let real_ok = is_root
+            || resolved.parent().is_some_and(|parent| {
+                let parent = real_path(parent);
+                self.real.iter().any(|r| parent.starts_with(r))
+            });
+        if !lexical_ok || !real_ok {
+            return Err(escape_message(path, &self.root).into());
+        }
+        Ok(resolved)
+    }
+}
+
+/// Error text for a write that would leave the sysroot.
+pub fn escape_message(path: &Path, sysroot: &Path) -> String {
+    format!(
+        "path escapes sysroot {}: {}",
+        sysroot.display(),
+        path.display()
+    )
+}
+
+/// `path` with symlinks resolved: its deepest existing ancestor is
+/// canonicalized and the missing tail appended.
+pub fn real_path(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut tail = Vec::new();
+    while existing.symlink_metadata().is_err() {
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(up)) => {
+                tail.push(name.to_os_string());
+                existing = up;
+            }
+            _ => break,
+        }
+    }
+    let mut real = existing
+        .canonicalize()
+        .unwrap_or_else(|_| existing.to_path_buf());
+    for name in tail.into_iter().rev() {
+        real.push(name);
+    }
+    real
+}
+
+/// Resolve `.` and `..` without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resolve_reroots_absolute_paths() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("slot");
+        let build = dir.path().join("build");
+        std::fs::create_dir_all(&root).unwrap();
+
+        assert_eq!(resolve("/usr/bin/foo").unwrap(), Path::new("/usr/bin/foo"));
+        confine(&root, std::slice::from_ref(&build), || {
+            let root = normalize(&absolute(&root));
+            assert_eq!(resolve("/usr/bin/foo").unwrap(), root.join("usr/bin/foo"));
+            let inside = root.join("etc/foo.conf");
+            assert_eq!(resolve(inside.to_str().unwrap()).unwrap(), inside);
+            let staged = build.join(".stage/usr/bin/foo");
+            assert_eq!(
+                resolve(staged.to_str().unwrap()).unwrap(),
+                normalize(&absolute(&staged))
+            );
+        });
+    }
+
+    #[test]
+    fn test_resolve_rejects_escapes() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("slot");
+        let outside = dir.path().join("host");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+
+        confine(&root, &[], || {
+            assert!(resolve("/usr/../../etc/passwd").is_err());
+            let dotdot = format!("{}/../host/x", root.display());
+            assert!(resolve(&dotdot).is_err());
+            #[cfg(unix)]
+            assert!(resolve("/link/x").is_err());
+        });
+    }
+}
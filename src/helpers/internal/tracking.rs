@@ -0,0 +1,257 @@
+//! Install tracking for the installed-file manifest
+//!
+//! While the executor runs `install(ctx)` it opens a tracking session on the
+//! current thread. Install helpers report every file they write and every
+//! directory they create; outside a session reporting is a no-op.
+//!
+//! Paths are made absolute (not canonicalized, so symlinks are tracked as
+//! themselves). Paths under an excluded root, such as `BUILD_DIR`, are scratch
+//! space and are never recorded.
+//!
+//! A session can also carry [`Claims`]: paths owned by other recipes. Helpers
+//! call [`check_claim`] before writing so an install cannot overwrite another
+//! recipe's files.
+
+use rhai::EvalAltResult;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Files and directories created during one install.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// Files and symlinks, in the order they were first written.
+    pub files: Vec<PathBuf>,
+    /// Directories that did not exist before the install, parents first.
+    pub dirs: Vec<PathBuf>,
+}
+
+impl Manifest {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.dirs.is_empty()
+    }
+
+    /// Append another manifest, skipping paths already present.
+    pub fn merge(&mut self, other: Manifest) {
+        for file in other.files {
+            push_unique(&mut self.files, file);
+        }
+        for dir in other.dirs {
+            push_unique(&mut self.dirs, dir);
+        }
+    }
+}
+
+/// Paths owned by other recipes during an install.
+#[derive(Debug, Clone, Default)]
+pub struct Claims {
+    /// Absolute path -> description of the owning recipe(s).
+    pub owners: HashMap<PathBuf, String>,
+    /// `(stage_dir, root)`: writes under the stage are checked against the
+    /// path they will be committed to.
+    pub stage: Option<(PathBuf, PathBuf)>,
+}
+
+impl Claims {
+    /// Owner of the path `path` will occupy once installed, if any.
+    pub fn owner_of(&self, path: &Path) -> Option<&str> {
+        let target = match &self.stage {
+            Some((stage_dir, root)) => match path.strip_prefix(stage_dir) {
+                Ok(rel) => root.join(rel),
+                Err(_) => path.to_path_buf(),
+            },
+            None => path.to_path_buf(),
+        };
+        self.owners.get(&target).map(String::as_str)
+    }
+}
+
+struct Session {
+    manifest: Manifest,
+    exclude: Vec<PathBuf>,
+    claims: Claims,
+}
+
+thread_local! {
+    static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with tracking enabled, returning its result and what it installed.
+///
+/// Entries that no longer exist when `f` returns (e.g. removed again by the
+/// recipe) are dropped from the manifest.
+pub fn record<R>(exclude: &[PathBuf], f: impl FnOnce() -> R) -> (R, Manifest) {
+    record_guarded(exclude, Claims::default(), f)
+}
+
+/// [`record`], refusing writes to paths in `claims`.
+pub fn record_guarded<R>(
+    exclude: &[PathBuf],
+    claims: Claims,
+    f: impl FnOnce() -> R,
+) -> (R, Manifest) {
+    let exclude = exclude.iter().map(|p| absolute(p)).collect();
+    let previous = SESSION.with(|s| {
+        s.borrow_mut().replace(Session {
+            manifest: Manifest::default(),
+            exclude,
+            claims,
+        })
+    });
+
+    let result = f();
+
+    let session = SESSION.with(|s| std::mem::replace(&mut *s.borrow_mut(), previous));
+    let mut manifest = session.map(|s| s.manifest).unwrap_or_default();
+    manifest.files.retain(|p| p.symlink_metadata().is_ok());
+    manifest.dirs.retain(|p| p.is_dir());
+    (result, manifest)
+}
+
+/// Fail if `path` is owned by another recipe in the current session.
+///
+/// Helpers call this before writing. Outside a session it always succeeds.
+pub fn check_claim(path: &Path) -> Result<(), Box<EvalAltResult>> {
+    SESSION.with(|s| match s.borrow().as_ref() {
+        Some(session) => match session.claims.owner_of(&absolute(path)) {
+            Some(owner) => Err(conflict_message(path, owner).into()),
+            None => Ok(()),
+        },
+        None => Ok(()),
+    })
+}
+
+/// Error text for a write that would overwrite another recipe's file.
+pub fn conflict_message(path: &Path, owner: &str) -> String {
+    format!(
+        "file conflict: {} is owned by {}; refusing to overwrite (use --allow-conflicts to override)",
+        path.display(),
+        owner
+    )
+}
+
+/// Record a file or symlink written by a helper.
+pub fn record_file(path: &Path) {
+    with_session(path, |manifest, path| {
+        push_unique(&mut manifest.files, path)
+    });
+}
+
+/// Record directories created by a helper.
+pub fn record_dirs(dirs: Vec<PathBuf>) {
+    for dir in dirs {
+        with_session(&dir, |manifest, path| push_unique(&mut manifest.dirs, path));
+    }
+}
+
+/// `create_dir_all` that records each directory it actually creates.
+pub fn create_dir_all(path: &Path) -> std::io::Result<()> {
+    let missing = if is_active() {
+        missing_dirs(path)
+    } else {
+        Vec::new()
+    };
+    std::fs::create_dir_all(path)?;
+    record_dirs(missing);
+    Ok(())
+}
+
+fn is_active() -> bool {
+    SESSION.with(|s| s.borrow().is_some())
+}
+
+/// Ancestors of `path` (inclusive) that do not exist yet, parents first.
+fn missing_dirs(path: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|p| !p.as_os_str().is_empty() && p.symlink_metadata().is_err())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+fn with_session(path: &Path, f: impl FnOnce(&mut Manifest, PathBuf)) {
+    SESSION.with(|s| {
+        if let Some(session) = s.borrow_mut().as_mut() {
+            let path = absolute(path);
+            if session.exclude.iter().any(|root| path.starts_with(root)) {
+                return;
+            }
+            f(&mut session.manifest, path);
+        }
+    });
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn push_unique(paths: &mut Vec<PathBuf>, path: PathBuf) {
+    if !paths.contains(&path) {
+        paths.push(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_record_tracks_files_and_new_dirs() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().join("root");
+        let scratch = dir.path().join("build");
+
+        let ((), manifest) = record(std::slice::from_ref(&scratch), || {
+            create_dir_all(&root.join("a/b")).unwrap();
+            std::fs::write(root.join("a/b/file"), "x").unwrap();
+            record_file(&root.join("a/b/file"));
+            create_dir_all(&scratch).unwrap();
+            record_file(&scratch.join("ignored"));
+        });
+
+        assert_eq!(manifest.files, vec![root.join("a/b/file")]);
+        assert_eq!(
+            manifest.dirs,
+            vec![root.clone(), root.join("a"), root.join("a/b")]
+        );
+    }
+
+    #[test]
+    fn test_record_outside_session_is_noop() {
+        let dir = TempDir::new().unwrap();
+        record_file(&dir.path().join("x"));
+        let ((), manifest) = record(&[], || {});
+        assert!(manifest.is_empty());
+    }
+
+    #[test]
+    fn test_check_claim_maps_stage_to_root() {
+        let stage = PathBuf::from("/build/.stage");
+        let claims = Claims {
+            owners: HashMap::from([(PathBuf::from("/usr/bin/foo"), "foo".to_string())]),
+            stage: Some((stage.clone(), PathBuf::from("/"))),
+        };
+
+        assert!(check_claim(Path::new("/usr/bin/foo")).is_ok());
+        let ((), _) = record_guarded(&[], claims, || {
+            assert!(check_claim(Path::new("/usr/bin/foo")).is_err());
+            assert!(check_claim(&stage.join("usr/bin/foo")).is_err());
+            assert!(check_claim(&stage.join("usr/bin/bar")).is_ok());
+        });
+    }
+
+    #[test]
+    fn test_record_drops_removed_entries() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("gone");
+        let ((), manifest) = record(&[], || {
+            std::fs::write(&file, "x").unwrap();
+            record_file(&file);
+            std::fs::remove_file(&file).unwrap();
+        });
+        assert!(manifest.files.is_empty());
+    }
+}
@@ -83,6 +83,7 @@ fn build_extract_prompt(content: &str, task: &str) -> String {
 }
 
 fn run_provider(prompt: String) -> Result<String, Box<EvalAltResult>> {
+    crate::core::offline::check("an LLM provider")?;
     let cfg = crate::llm::resolve_config_for_call().map_err(to_eval_err)?;
     let cwd = current_working_dir()?;
 
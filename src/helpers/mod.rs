@@ -262,6 +262,26 @@ pub fn register_all(engine: &mut Engine) {
         trace_helper("download");
         acquire::download(url, dest)
     });
+    // download(urls, dest[, expected]) -> path string (first mirror that works)
+    engine.register_fn("download", |urls: rhai::Array, dest: &str| {
+        trace_helper("download");
+        acquire::download_mirrors(urls, dest)
+    });
+    engine.register_fn(
+        "download",
+        |urls: rhai::Array, dest: &str, expected: &str| {
+            trace_helper("download");
+            acquire::download_mirrors_verified(urls, dest, expected)
+        },
+    );
+    // download_verified(url, dest, expected) -> path string (hashed while streaming)
+    engine.register_fn(
+        "download_verified",
+        |url: &str, dest: &str, expected: &str| {
+            trace_helper("download_verified");
+            acquire::download_verified(url, dest, expected)
+        },
+    );
     engine.register_fn("verify_sha256", |path: &str, expected: &str| {
         trace_helper("verify_sha256");
         acquire::verify_sha256(path, expected)
@@ -274,6 +294,34 @@ pub fn register_all(engine: &mut Engine) {
         trace_helper("verify_blake3");
         acquire::verify_blake3(path, expected)
     });
+    // verify_gpg / verify_minisign / verify_signify / verify_ssh_sig(file, sig, key) -> ()
+    // (throws unless signed by the pinned key)
+    engine.register_fn("verify_gpg", |file: &str, sig: &str, keyring: &str| {
+        trace_helper("verify_gpg");
+        acquire::verify_gpg(file, sig, keyring)
+    });
+    engine.register_fn("verify_minisign", |file: &str, sig: &str, pubkey: &str| {
+        trace_helper("verify_minisign");
+        acquire::verify_minisign(file, sig, pubkey)
+    });
+    engine.register_fn("verify_signify", |file: &str, sig: &str, pubkey: &str| {
+        trace_helper("verify_signify");
+        acquire::verify_signify(file, sig, pubkey)
+    });
+    engine.register_fn(
+        "verify_ssh_sig",
+        |file: &str, sig: &str, allowed_signers: &str| {
+            trace_helper("verify_ssh_sig");
+            acquire::verify_ssh_sig(file, sig, allowed_signers)
+        },
+    );
+    engine.register_fn(
+        "verify_ssh_sig",
+        |file: &str, sig: &str, allowed_signers: &str, namespace: &str| {
+            trace_helper("verify_ssh_sig");
+            acquire::verify_ssh_sig_namespace(file, sig, allowed_signers, namespace)
+        },
+    );
     engine.register_fn("fetch_sha256", |url: &str, filename: &str| {
         trace_helper("fetch_sha256");
         acquire::fetch_sha256(url, filename)
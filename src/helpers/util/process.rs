@@ -84,7 +84,11 @@ pub fn command_exists(name: &str) -> bool {
     command_path(name).is_some()
 }
 
-fn require_command(helper: &str, cmd: &str, note: &str) -> Result<(), Box<EvalAltResult>> {
+pub(crate) fn require_command(
+    helper: &str,
+    cmd: &str,
+    note: &str,
+) -> Result<(), Box<EvalAltResult>> {
     if command_exists(cmd) {
         return Ok(());
     }
@@ -39,7 +39,8 @@
 //! 2. `is_built()` - Check if build needed
 //! 3. `is_acquired()` - Check if acquire needed
 //! 4. Execute needed phases (acquire, build, install)
-//! 5. Persist ctx after each phase
+//! 5. Commit files staged under `STAGE_DIR` into the root
+//! 6. Persist ctx after each phase
 //!
 //! # Engine-Provided Functions
 //!
@@ -61,12 +62,17 @@
 //!
 //! ## Network
 //! - `download(url, dest)` - HTTP download
+//! - `download(urls, dest[, hash])` - Try mirror URLs in order, checking the hash on each
+//! - `download_verified(url, dest, hash)` - HTTP download hashed while streaming, kept only on match
 //! - `download_with_resume(url, dest)` - HTTP download with Range resume (throws if resume can't be honored)
 //! - `http_get(url)` - Fetch URL content as string
 //! - `torrent(url, dest_dir)` - BitTorrent download into `dest_dir`
 //!
 //! ## Verification
 //! - `verify_sha256(path, hash)` - Verify checksum
+//! - `verify_gpg(file, sig, keyring)` - Verify a detached OpenPGP signature against a pinned keyring
+//! - `verify_minisign(file, sig, pubkey)` / `verify_signify(file, sig, pubkey)` - Verify minisign/signify signatures
+//! - `verify_ssh_sig(file, sig, allowed_signers)` - Verify an `ssh-keygen -Y sign` signature
 //! - `fetch_sha256(url, filename)` - Fetch a SHA256 from a remote checksum file
 //! - `check_disk_space(path, bytes)` - Verify free space
 //!
@@ -87,6 +93,7 @@
 //!
 //! - `RECIPE_DIR` - Directory containing the recipe file
 //! - `BUILD_DIR` - Temporary build directory
+//! - `STAGE_DIR` - Staging root for `install()`, committed atomically on success
 //! - `ARCH` - Target architecture (x86_64, aarch64)
 //! - `NPROC` - Number of CPUs
 //! - `RPM_PATH` - Path to RPM repository (from environment)
@@ -96,16 +103,34 @@ pub mod helpers;
 mod llm;
 pub mod logging;
 
+pub use core::cache::{CacheEntry, GcReport, SourceCache};
+pub use core::catalog;
+pub use core::ctx_store::CtxStore;
+pub use core::deps::{DepGraph, DepNode};
+pub use core::format;
+pub use core::history::{self, CtxChange, HistoryEntry};
+pub use core::info::RecipeInfo;
+pub use core::lint::{LintIssue, LintReport, Severity};
+pub use core::mirrors::MirrorTable;
+pub use core::orphans::{InstallReason, Orphan};
 pub use core::output;
+pub use core::ownership::{FileOwner, OwnershipIndex};
+pub use core::plan::{InstallPlan, PlannedCheck, PlannedDependency};
+pub use core::repos::{Repo, RepoSet};
+pub use core::search::SearchHit;
+pub use core::update::UpdateInfo;
 pub use logging::{
     RECIPE_HOOK_EVENT, RecipeHookEvent, RecipeHookSink, emit_hook_event, emit_hook_event_struct,
     make_machine_hook_event, set_event_sink, set_event_sink_handler, set_machine_events,
 };
 
 use anyhow::Result;
-use rhai::{Engine, module_resolvers::FileModuleResolver};
+use rhai::Engine;
 use std::path::{Path, PathBuf};
 
+/// Default `PREFIX` exposed to recipes.
+pub const DEFAULT_PREFIX: &str = "/usr/local";
+
 /// Configure Recipe's automatic LLM-based repair loop for failed installs.
 ///
 /// When enabled, Recipe will (on selected failures) ask the configured LLM provider to return a
@@ -140,11 +165,22 @@ pub struct RecipeEngine {
     engine: Engine,
     build_dir: PathBuf,
     recipes_path: Option<PathBuf>,
+    /// Configured repositories searched after the recipes path.
+    repos: RepoSet,
     /// User-defined scope constants (injected via --define KEY=VALUE)
     defines: Vec<(String, String)>,
     persist_ctx: bool,
     llm_profile: Option<String>,
     autofix: Option<AutoFixConfig>,
+    allow_conflicts: bool,
+    /// Target root for installs (`--sysroot`); `None` installs into `/`.
+    sysroot: Option<PathBuf>,
+    prefix: String,
+    ctx_store: CtxStore,
+    history_dir: Option<PathBuf>,
+    source_cache: Option<SourceCache>,
+    mirrors: MirrorTable,
+    offline: bool,
 }
 
 impl RecipeEngine {
@@ -163,22 +199,46 @@ impl RecipeEngine {
             engine,
             build_dir,
             recipes_path: None,
+            repos: RepoSet::default(),
             defines: Vec::new(),
             persist_ctx: true,
             llm_profile: None,
             autofix: None,
+            allow_conflicts: false,
+            sysroot: None,
+            prefix: DEFAULT_PREFIX.to_string(),
+            ctx_store: CtxStore::Source,
+            history_dir: None,
+            source_cache: None,
+            mirrors: MirrorTable::default(),
+            offline: false,
         }
     }
 
     /// Set the recipes path for module resolution
     pub fn with_recipes_path(mut self, path: PathBuf) -> Self {
-        let mut resolver = FileModuleResolver::new();
-        resolver.set_base_path(&path);
-        self.engine.set_module_resolver(resolver);
         self.recipes_path = Some(path);
+        self.set_module_resolver();
+        self
+    }
+
+    /// Search these repositories after the recipes path.
+    ///
+    /// Recipe names, dependencies, `//! extends:` bases and `import` resolve
+    /// in the first repository that has them; see [`RepoSet`].
+    pub fn with_repos(mut self, repos: RepoSet) -> Self {
+        self.repos = repos;
+        self.set_module_resolver();
         self
     }
 
+    fn set_module_resolver(&mut self) {
+        if self.recipes_path.is_some() || !self.repos.is_empty() {
+            let resolver = self.repos.module_resolver(self.recipes_path.as_deref());
+            self.engine.set_module_resolver(resolver);
+        }
+    }
+
     /// Select an LLM profile (from XDG `recipe/llm.toml`) for this engine execution.
     pub fn with_llm_profile(mut self, profile: Option<String>) -> Self {
         self.llm_profile = profile;
@@ -193,17 +253,128 @@ impl RecipeEngine {
         self
     }
 
+    /// Choose where persisted ctx is kept.
+    ///
+    /// Default is [`CtxStore::Source`], which rewrites the ctx block in the
+    /// recipe file. [`CtxStore::StateDir`] leaves recipe sources untouched and
+    /// keeps per-recipe overlays in a state directory instead.
+    pub fn with_ctx_store(mut self, store: CtxStore) -> Self {
+        self.ctx_store = store;
+        self
+    }
+
+    /// Keep ctx history journals under `dir`.
+    ///
+    /// Default is `None`: a [`CtxStore::StateDir`] store journals under its
+    /// own `history/` directory and [`CtxStore::Source`] keeps no journal.
+    /// The CLI passes [`history::xdg_dir`]. See [`HistoryEntry`].
+    pub fn with_history_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.history_dir = dir;
+        self
+    }
+
+    /// Share downloads through `cache`.
+    ///
+    /// Default is `None`: every download fetches from the network. With a
+    /// cache, `download`, `download_with_resume`, single-file `torrent` and
+    /// `github_download_release` copy URLs fetched before from it; see
+    /// [`SourceCache`].
+    pub fn with_source_cache(mut self, cache: Option<SourceCache>) -> Self {
+        self.source_cache = cache;
+        self
+    }
+
+    /// Rewrite download URLs through `mirrors`.
+    ///
+    /// `download` tries the mirrors for a URL in order before the URL itself;
+    /// see [`MirrorTable`].
+    pub fn with_mirrors(mut self, mirrors: MirrorTable) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Forbid network access.
+    ///
+    /// Default is `false`. When offline, network helpers only serve URLs
+    /// found in the download cache (see [`Self::with_source_cache`]) and
+    /// otherwise fail at once with an `offline:` error naming the recipe and
+    /// URL. `git_clone` only succeeds for an existing clone.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
     /// Enable Recipe's automatic LLM-based repair loop for this engine execution.
     pub fn with_autofix(mut self, cfg: Option<AutoFixConfig>) -> Self {
         self.autofix = cfg;
         self
     }
 
+    /// Allow installs to overwrite files owned by other recipes.
+    ///
+    /// Default is `false`: an install that would write a file listed in another
+    /// recipe's `installed_files` fails before anything is committed.
+    pub fn with_allow_conflicts(mut self, allow: bool) -> Self {
+        self.allow_conflicts = allow;
+        self
+    }
+
+    /// Install into `sysroot` instead of `/`.
+    ///
+    /// Recipes see it as `SYSROOT`. During `install(ctx)` the filesystem
+    /// helpers re-root absolute destinations under it (`/usr/bin/foo` lands at
+    /// `$SYSROOT/usr/bin/foo`), staged files are committed into it, and writes
+    /// that would escape it fail. `BUILD_DIR` stays writable.
+    pub fn with_sysroot(mut self, sysroot: Option<PathBuf>) -> Self {
+        self.sysroot = sysroot;
+        self
+    }
+
+    /// Installation prefix exposed to recipes as `PREFIX` (default
+    /// `/usr/local`). It never includes the sysroot.
+    pub fn with_prefix(mut self, prefix: String) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
     /// Add a user-defined scope constant.
     pub fn add_define(&mut self, key: String, value: String) {
         self.defines.push((key, value));
     }
 
+    /// Run `f` with this engine's ctx store, history directory, repositories,
+    /// download cache, mirrors, offline mode and LLM profile active.
+    fn scoped<T>(&self, f: impl FnOnce() -> T) -> T {
+        core::ctx_store::scoped(&self.ctx_store, || {
+            core::history::scoped(self.history_dir.as_deref(), || {
+                core::repos::scoped(&self.repos, || {
+                    core::cache::scoped(self.source_cache.as_ref(), || {
+                        core::mirrors::scoped(&self.mirrors, || {
+                            core::offline::scoped(self.offline, || {
+                                llm::with_llm_profile(self.llm_profile.as_deref(), f)
+                            })
+                        })
+                    })
+                })
+            })
+        })
+    }
+
+    /// Scope constants for every recipe function: user defines, then
+    /// `SYSROOT` and `PREFIX` (which a define cannot override).
+    fn scope_defines(&self) -> Vec<(String, String)> {
+        let sysroot = self
+            .sysroot
+            .as_deref()
+            .unwrap_or(Path::new("/"))
+            .to_string_lossy()
+            .to_string();
+        let mut defines = self.defines.clone();
+        defines.push(("SYSROOT".to_string(), sysroot));
+        defines.push(("PREFIX".to_string(), self.prefix.clone()));
+        defines
+    }
+
     /// Execute a recipe script (install a package)
     ///
     /// Follows the package lifecycle:
@@ -215,27 +386,66 @@ impl RecipeEngine {
     ///
     /// Returns the final ctx map containing all recipe state.
     pub fn execute(&self, recipe_path: &Path) -> Result<rhai::Map> {
-        llm::with_llm_profile(self.llm_profile.as_deref(), || {
-            if let Some(autofix) = self.autofix.as_ref() {
-                core::executor::install_with_options(
-                    &self.engine,
-                    &self.build_dir,
-                    recipe_path,
-                    &self.defines,
-                    self.persist_ctx,
-                    self.recipes_path.as_deref(),
-                    Some(autofix),
-                )
-            } else {
-                core::executor::install(
-                    &self.engine,
-                    &self.build_dir,
-                    recipe_path,
-                    &self.defines,
-                    self.persist_ctx,
-                    self.recipes_path.as_deref(),
-                )
-            }
+        self.scoped(|| {
+            core::executor::install_with_options(
+                &self.engine,
+                &self.build_dir,
+                recipe_path,
+                &self.scope_defines(),
+                self.recipes_path.as_deref(),
+                &core::executor::InstallOptions {
+                    persist_ctx: self.persist_ctx,
+                    autofix: self.autofix.as_ref(),
+                    allow_conflicts: self.allow_conflicts,
+                    sysroot: self.sysroot.as_deref(),
+                    ..Default::default()
+                },
+            )
+        })
+    }
+
+    /// Upgrade a recipe to the newer version reported by its `check_update`
+    /// hook.
+    ///
+    /// The new version is written into ctx and acquire → build → install rerun
+    /// in a fresh build directory under the build dir. The old installation is
+    /// kept until the new install commits, then files only the old version
+    /// installed are removed. On failure the recipe is left at its old version.
+    ///
+    /// Returns `None` when the recipe has no `check_update` hook; otherwise
+    /// the update check, which was applied when [`UpdateInfo::is_newer`].
+    pub fn upgrade(&self, recipe_path: &Path) -> Result<Option<UpdateInfo>> {
+        self.scoped(|| {
+            core::executor::upgrade(
+                &self.engine,
+                &self.build_dir,
+                recipe_path,
+                &self.scope_defines(),
+                self.recipes_path.as_deref(),
+                &core::executor::InstallOptions {
+                    persist_ctx: self.persist_ctx,
+                    allow_conflicts: self.allow_conflicts,
+                    sysroot: self.sysroot.as_deref(),
+                    ..Default::default()
+                },
+            )
+        })
+    }
+
+    /// Plan a recipe install without running any phase
+    ///
+    /// Evaluates `is_installed()`, `is_built()`, and `is_acquired()` like
+    /// [`RecipeEngine::execute`], then stops. Dependencies are located and
+    /// checked but not installed, and ctx is never persisted.
+    pub fn plan(&self, recipe_path: &Path) -> Result<InstallPlan> {
+        self.scoped(|| {
+            core::executor::plan(
+                &self.engine,
+                &self.build_dir,
+                recipe_path,
+                &self.scope_defines(),
+                self.recipes_path.as_deref(),
+            )
         })
     }
 
@@ -243,13 +453,14 @@ impl RecipeEngine {
     ///
     /// Returns the final ctx map after removal.
     pub fn remove(&self, recipe_path: &Path) -> Result<rhai::Map> {
-        llm::with_llm_profile(self.llm_profile.as_deref(), || {
+        self.scoped(|| {
             core::executor::remove(
                 &self.engine,
                 recipe_path,
                 self.recipes_path.as_deref(),
-                &self.defines,
+                &self.scope_defines(),
                 self.persist_ctx,
+                self.sysroot.as_deref(),
             )
         })
     }
@@ -265,58 +476,230 @@ impl RecipeEngine {
     ///
     /// Returns the final ctx map after cleanup.
     pub fn cleanup_with_reason(&self, recipe_path: &Path, reason: &str) -> Result<rhai::Map> {
-        llm::with_llm_profile(self.llm_profile.as_deref(), || {
+        self.scoped(|| {
             core::executor::cleanup(
                 &self.engine,
                 &self.build_dir,
                 recipe_path,
                 self.recipes_path.as_deref(),
-                &self.defines,
+                &self.scope_defines(),
                 reason,
                 self.persist_ctx,
+                self.sysroot.as_deref(),
             )
         })
     }
 
     /// Execute `is_installed(ctx)` manually.
     pub fn is_installed(&self, recipe_path: &Path) -> Result<rhai::Map> {
-        llm::with_llm_profile(self.llm_profile.as_deref(), || {
+        self.scoped(|| {
             core::executor::is_installed(
                 &self.engine,
                 &self.build_dir,
                 recipe_path,
                 self.recipes_path.as_deref(),
-                &self.defines,
+                &self.scope_defines(),
             )
         })
     }
 
     /// Execute `is_built(ctx)` manually.
     pub fn is_built(&self, recipe_path: &Path) -> Result<rhai::Map> {
-        llm::with_llm_profile(self.llm_profile.as_deref(), || {
+        self.scoped(|| {
             core::executor::is_built(
                 &self.engine,
                 &self.build_dir,
                 recipe_path,
                 self.recipes_path.as_deref(),
-                &self.defines,
+                &self.scope_defines(),
             )
         })
     }
 
     /// Execute `is_acquired(ctx)` manually.
     pub fn is_acquired(&self, recipe_path: &Path) -> Result<rhai::Map> {
-        llm::with_llm_profile(self.llm_profile.as_deref(), || {
+        self.scoped(|| {
             core::executor::is_acquired(
                 &self.engine,
                 &self.build_dir,
                 recipe_path,
                 self.recipes_path.as_deref(),
-                &self.defines,
+                &self.scope_defines(),
+            )
+        })
+    }
+
+    /// Run the recipe's `check_update` hook and report the latest upstream
+    /// version.
+    ///
+    /// Returns `None` when the recipe defines no `check_update`. Neither the
+    /// recipe nor the target root is modified.
+    pub fn check_update(&self, recipe_path: &Path) -> Result<Option<UpdateInfo>> {
+        self.scoped(|| {
+            core::executor::check_update(
+                &self.engine,
+                &self.build_dir,
+                recipe_path,
+                self.recipes_path.as_deref(),
+                &self.scope_defines(),
             )
         })
     }
 
+    /// Check a recipe for common mistakes without running it.
+    ///
+    /// See [`LintReport`]; `//! extends:` bases are checked too.
+    pub fn lint(&self, recipe_path: &Path) -> LintReport {
+        self.scoped(|| core::lint::lint(&self.engine, recipe_path, self.recipes_path.as_deref()))
+    }
+
+    /// Name, version, dependencies and hooks of a recipe, read from its ctx
+    /// and `deps`/`build_deps` literals without running it.
+    ///
+    /// `//! extends:` bases are merged and the ctx store's overlay applies;
+    /// see [`RecipeInfo`].
+    pub fn info(&self, recipe_path: &Path) -> Result<RecipeInfo> {
+        self.scoped(|| core::info::load(&self.engine, recipe_path, self.recipes_path.as_deref()))
+    }
+
+    /// Recipes in the recipes path and configured repositories whose name,
+    /// description, homepage or (when installed) installed files match
+    /// `query`, a case-insensitive regular expression.
+    ///
+    /// See [`SearchHit`].
+    pub fn search(&self, query: &str) -> Result<Vec<SearchHit>> {
+        let pattern = core::search::pattern(query)?;
+        Ok(self
+            .scoped(|| core::search::search(&self.engine, self.recipes_path.as_deref(), &pattern)))
+    }
+
+    /// ctx history of a recipe, oldest first.
+    ///
+    /// Every ctx persist appends an entry with the phase, its outcome, the
+    /// changed keys and the full snapshot; see [`HistoryEntry`]. Empty when
+    /// nothing keeps a journal (see [`Self::with_history_dir`]).
+    pub fn history(&self, recipe_path: &Path) -> Result<Vec<HistoryEntry>> {
+        let recipe_path = recipe_path
+            .canonicalize()
+            .unwrap_or_else(|_| recipe_path.to_path_buf());
+        self.scoped(|| core::history::entries(&recipe_path))
+    }
+
+    /// Restore a recipe's persisted ctx to the snapshot of history entry
+    /// `seq`.
+    ///
+    /// Nothing is installed or removed; only ctx changes. The restore is
+    /// recorded as a new `rollback` entry. Returns the restored ctx.
+    pub fn rollback_ctx(&self, recipe_path: &Path, seq: u64) -> Result<rhai::Map> {
+        self.scoped(|| {
+            core::executor::rollback_ctx(
+                &self.engine,
+                recipe_path,
+                self.recipes_path.as_deref(),
+                seq,
+            )
+        })
+    }
+
+    /// Build the `deps`/`build_deps` graph of every recipe under the recipes
+    /// path.
+    ///
+    /// When `recipe_path` is given it is (re)read and added too, so recipes
+    /// outside the recipes path can be inspected; its node name is returned
+    /// alongside the graph.
+    pub fn dep_graph(&self, recipe_path: Option<&Path>) -> Result<(DepGraph, Option<String>)> {
+        self.scoped(|| self.dep_graph_inner(recipe_path))
+    }
+
+    fn dep_graph_inner(&self, recipe_path: Option<&Path>) -> Result<(DepGraph, Option<String>)> {
+        let recipes_path = self.recipes_path.as_deref();
+        let mut graph = match recipes_path {
+            Some(rp) => DepGraph::scan(&self.engine, rp, &self.build_dir, &self.scope_defines()),
+            None => DepGraph::default(),
+        };
+        let root = match recipe_path {
+            Some(path) => {
+                let search = recipes_path
+                    .or_else(|| path.parent())
+                    .unwrap_or(Path::new("."));
+                Some(graph.add_recipe(
+                    &self.engine,
+                    path,
+                    search,
+                    &self.build_dir,
+                    &self.scope_defines(),
+                )?)
+            }
+            None => None,
+        };
+        Ok((graph, root))
+    }
+
+    /// Dependency-only recipes that no explicitly installed recipe still
+    /// needs, in removal order.
+    ///
+    /// Install reasons are read from each recipe's persisted ctx
+    /// (`install_reason`); see [`InstallReason`].
+    pub fn orphans(&self) -> Result<Vec<Orphan>> {
+        let (graph, _) = self.dep_graph(None)?;
+        let reasons = self.scoped(|| core::orphans::install_reasons(&graph));
+        core::orphans::find_orphans(&graph, &reasons)
+    }
+
+    /// Remove every orphan (see [`RecipeEngine::orphans`]), dependents before
+    /// their dependencies. Stops at the first failed removal.
+    ///
+    /// Returns the recipes that were removed.
+    pub fn autoremove(&self) -> Result<Vec<Orphan>> {
+        let mut removed = Vec::new();
+        for orphan in self.orphans()? {
+            self.remove(&orphan.recipe_path)?;
+            removed.push(orphan);
+        }
+        Ok(removed)
+    }
+
+    /// Find the recipes under the recipes path whose installed-file manifest
+    /// lists `path`.
+    ///
+    /// `path` is matched as given (made absolute) and, failing that, by its
+    /// canonical form. Returns an empty list when no recipes path is set.
+    pub fn owners_of(&self, path: &Path) -> Vec<FileOwner> {
+        let Some(recipes_path) = self.recipes_path.as_deref() else {
+            return Vec::new();
+        };
+        let index = self.scoped(|| OwnershipIndex::build(recipes_path));
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let owners = index.owners(&absolute);
+        if !owners.is_empty() {
+            return owners.to_vec();
+        }
+        path.canonicalize()
+            .map(|canonical| index.owners(&canonical).to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Recipes under the recipes path whose persisted ctx records an install
+    /// reason, i.e. the currently installed ones.
+    pub fn installed_recipes(&self) -> Vec<PathBuf> {
+        let Some(recipes_path) = self.recipes_path.as_deref() else {
+            return Vec::new();
+        };
+        self.scoped(|| catalog::installed_recipes(recipes_path))
+    }
+
+    /// Recipe files of the recipes path and every configured repository, in
+    /// search order, leaving out recipes shadowed by an earlier repository.
+    pub fn recipe_files(&self) -> Vec<PathBuf> {
+        self.repos.recipe_files(self.recipes_path.as_deref())
+    }
+
+    /// Locate a recipe by `<name>` or `<repo>/<name>` in the recipes path and
+    /// configured repositories.
+    pub fn find_recipe(&self, name: &str) -> Option<PathBuf> {
+        self.repos.find(self.recipes_path.as_deref(), name)
+    }
+
     /// Get the recipes path
     pub fn recipes_path(&self) -> Option<&Path> {
         self.recipes_path.as_deref()
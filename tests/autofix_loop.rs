@@ -464,3 +464,81 @@ fn autofix_can_fix_build_deps() {
         "dep recipe not patched:\n{updated}"
     );
 }
+
+#[test]
+#[cfg(unix)]
+fn autofix_skips_ctx_schema_violations() {
+    let _guard = test_lock().lock().unwrap();
+
+    let tmp = tempfile::TempDir::new().unwrap();
+    let cfg_home = tmp.path().join("cfg");
+    let bin_dir = tmp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+
+    // Any LLM call leaves a marker behind.
+    let called = tmp.path().join("codex-called");
+    let stub_codex = bin_dir.join("codex");
+    fs::write(
+        &stub_codex,
+        format!("#!/bin/sh\ntouch '{}'\nexit 1\n", called.display()),
+    )
+    .unwrap();
+    chmod_x(&stub_codex);
+    let stub_claude = write_stub_claude(&bin_dir);
+    write_llm_toml(
+        &cfg_home,
+        &stub_codex.display().to_string(),
+        &stub_claude.display().to_string(),
+    );
+
+    unsafe {
+        std::env::set_var("XDG_CONFIG_HOME", &cfg_home);
+        std::env::set_var("XDG_CONFIG_DIRS", "");
+    }
+
+    let repo = tempfile::TempDir::new().unwrap();
+    init_git_repo(repo.path());
+
+    let recipe = r#"let ctx = #{
+    name: "autofix-test",
+    installed: false,
+};
+let ctx_schema = #{ name: "string", installed: "bool" };
+
+fn is_acquired(ctx) { ctx }
+fn is_built(ctx) { throw "not built"; }
+fn is_installed(ctx) { if !ctx.installed { throw "not installed"; } ctx }
+
+fn acquire(ctx) { ctx }
+fn build(ctx) {
+    ctx.verison = "1.0";
+    ctx
+}
+fn install(ctx) {
+    ctx.installed = true;
+    ctx
+}
+fn cleanup(ctx, reason) { ctx }
+"#;
+    let recipe_path = repo.path().join("autofix-test.rhai");
+    fs::write(&recipe_path, recipe).unwrap();
+
+    let build_dir = tempfile::TempDir::new().unwrap();
+    let engine = levitate_recipe::RecipeEngine::new(build_dir.path().to_path_buf())
+        .with_llm_profile(Some("codex".to_owned()))
+        .with_autofix(Some(levitate_recipe::AutoFixConfig {
+            attempts: 1,
+            cwd: Some(repo.path().to_path_buf()),
+            prompt_file: None,
+            allow_paths: Vec::new(),
+        }));
+
+    let err = engine.execute(&recipe_path).unwrap_err();
+    let msg = format!("{err:#}");
+    assert!(
+        msg.contains("ctx schema violation after build: `verison`"),
+        "unexpected error:\n{msg}"
+    );
+    assert!(!called.exists(), "autofix ran for a schema violation");
+    assert_eq!(fs::read_to_string(&recipe_path).unwrap(), recipe);
+}
@@ -16,6 +16,17 @@ fn recipe_bin() -> std::path::PathBuf {
     path
 }
 
+/// The recipe binary, with `XDG_STATE_HOME` kept inside the target dir so no
+/// run reaches the real `~/.local/state/recipe`.
+fn recipe_command() -> Command {
+    let mut cmd = Command::new(recipe_bin());
+    cmd.env(
+        "XDG_STATE_HOME",
+        Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"),
+    );
+    cmd
+}
+
 /// Create a test environment
 fn create_test_env() -> (TempDir, std::path::PathBuf) {
     let dir = TempDir::new().unwrap();
@@ -37,9 +48,10 @@ fn write_recipe(recipes_dir: &Path, name: &str, content: &str) {
 
 /// Run recipe CLI with arguments
 fn run_recipe(args: &[&str], recipes: &Path) -> std::process::Output {
-    Command::new(recipe_bin())
+    recipe_command()
         .args(args)
         .args(["--recipes-path", recipes.to_str().unwrap()])
+        .env("XDG_STATE_HOME", recipes.with_file_name("state"))
         .output()
         .expect("Failed to execute recipe command")
 }
@@ -6,7 +6,7 @@ use leviso_cheat_test::cheat_aware;
 
 #[test]
 fn test_cli_help() {
-    let output = Command::new(recipe_bin())
+    let output = recipe_command()
         .arg("--help")
         .output()
         .expect("Failed to run recipe --help");
@@ -20,7 +20,7 @@ fn test_cli_help() {
 
 #[test]
 fn test_cli_help_includes_machine_events_flag() {
-    let output = Command::new(recipe_bin())
+    let output = recipe_command()
         .arg("--help")
         .output()
         .expect("Failed to run recipe --help");
@@ -32,7 +32,7 @@ fn test_cli_help_includes_machine_events_flag() {
 
 #[test]
 fn test_cli_version() {
-    let output = Command::new(recipe_bin())
+    let output = recipe_command()
         .arg("--version")
         .output()
         .expect("Failed to run recipe --version");
@@ -143,6 +143,68 @@ fn install(ctx) {
     assert!(output.status.success());
 }
 
+#[test]
+fn test_cli_install_dry_run_runs_no_phases() {
+    let (_dir, recipes) = create_test_env();
+
+    write_recipe(
+        &recipes,
+        "tool",
+        r#"
+let ctx = #{ name: "tool", installed: true };
+fn is_installed(ctx) { ctx }
+"#,
+    );
+    write_recipe(
+        &recipes,
+        "planned",
+        r#"
+let deps = ["tool"];
+let ctx = #{
+    name: "planned",
+    installed: false,
+};
+
+fn is_installed(ctx) {
+    if !ctx.installed { throw "not installed"; }
+    ctx
+}
+fn is_built(ctx) { throw "not built"; }
+fn is_acquired(ctx) { ctx }
+
+fn acquire(ctx) { throw "acquire should not be called"; }
+fn build(ctx) { throw "build should not be called"; }
+fn install(ctx) { throw "install should not be called"; }
+"#,
+    );
+
+    for args in [
+        &["install", "planned", "--dry-run"][..],
+        &["plan", "planned"],
+    ] {
+        let output = run_recipe(args, &recipes);
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let plan: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(plan["name"], "planned");
+        assert_eq!(plan["needs_install"], true);
+        assert_eq!(plan["phases"], serde_json::json!(["build", "install"]));
+        assert_eq!(plan["deps"][0]["name"], "tool");
+        assert_eq!(plan["deps"][0]["needs_install"], false);
+        assert_eq!(plan["checks"][2]["check"], "is_acquired");
+        assert_eq!(plan["checks"][2]["needs_work"], false);
+    }
+
+    // ctx is untouched by planning.
+    let content = std::fs::read_to_string(recipes.join("planned.rhai")).unwrap();
+    assert!(content.contains("installed: false"));
+}
+
 #[test]
 fn test_cli_accepts_explicit_rhai_path() {
     let (_dir, recipes) = create_test_env();
@@ -168,7 +230,7 @@ fn cleanup(ctx, reason) { ctx }
     )
     .unwrap();
 
-    let output = Command::new(recipe_bin())
+    let output = recipe_command()
         .args(["install", recipe_path.to_str().unwrap()])
         .output()
         .expect("Failed to execute recipe command");
@@ -287,7 +349,7 @@ fn remove(ctx) {
 "#,
     );
 
-    let output = Command::new(recipe_bin())
+    let output = recipe_command()
         .args([
             "remove",
             "remove-define",
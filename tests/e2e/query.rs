@@ -56,7 +56,7 @@ fn cleanup(ctx, reason) {
 "#,
     );
 
-    let output = Command::new(recipe_bin())
+    let output = recipe_command()
         .args([
             "cleanup",
             "cleanup-define",
@@ -363,3 +363,714 @@ let ctx = #{
     assert!(stdout.contains("mypackage"));
     assert!(stdout.contains("1.2.3"));
 }
+
+#[test]
+fn test_cli_info_and_list_read_ctx_from_bases_and_nested_maps() {
+    let (dir, recipes) = create_test_env();
+
+    write_recipe(
+        &recipes,
+        "gnu",
+        r#"
+let ctx = #{
+    name: "hello",
+    version: "2.12",
+    license: "GPL-3.0-or-later",
+    mirrors: #{ name: "ftp", version: "0" },
+};
+let deps = ["glibc"];
+fn install(ctx) { ctx }
+"#,
+    );
+    write_recipe(
+        &recipes,
+        "hello",
+        r#"//! extends: gnu.rhai
+fn is_installed(ctx) { throw "not installed"; }
+"#,
+    );
+
+    let json = dir.path().join("info.json");
+    let output = run_recipe(
+        &["info", "hello", "--json-output", json.to_str().unwrap()],
+        &recipes,
+    );
+    assert!(
+        output.status.success(),
+        "Info failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let info: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+    assert_eq!(info["name"], "hello");
+    assert_eq!(info["version"], "2.12");
+    assert_eq!(info["license"], "GPL-3.0-or-later");
+    assert_eq!(info["deps"], serde_json::json!(["glibc"]));
+    assert_eq!(
+        info["hooks"],
+        serde_json::json!(["is_installed", "install", "cleanup"])
+    );
+
+    let output = run_recipe(&["list"], &recipes);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("2.12"), "{stdout}");
+    assert!(!stdout.contains("ftp"), "{stdout}");
+}
+
+#[test]
+fn test_cli_search_matches_names_descriptions_and_files() {
+    let (_dir, recipes) = create_test_env();
+
+    write_recipe(
+        &recipes,
+        "ripgrep",
+        r#"
+let ctx = #{
+    description: "Recursive line-oriented search tool",
+    install_reason: "explicit",
+    installed_files: ["/usr/bin/rg", "/usr/share/man/man1/rg.1"],
+    name: "ripgrep",
+    version: "14.1.0",
+};
+fn install(ctx) { ctx }
+"#,
+    );
+    write_recipe(
+        &recipes,
+        "zstd",
+        r#"
+let ctx = #{
+    description: "Fast real-time compression",
+    homepage: "https://facebook.github.io/zstd/",
+    name: "zstd",
+    version: "1.5.6",
+};
+fn install(ctx) { ctx }
+"#,
+    );
+
+    let output = run_recipe(&["search", "COMPRESS"], &recipes);
+    assert!(
+        output.status.success(),
+        "Search failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("zstd"), "{stdout}");
+    assert!(!stdout.contains("ripgrep"), "{stdout}");
+
+    let output = run_recipe(&["search", "bin/rg$", "--format", "json"], &recipes);
+    assert!(output.status.success());
+    let hits: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(hits.as_array().unwrap().len(), 1);
+    assert_eq!(hits[0]["name"], "ripgrep");
+    assert_eq!(hits[0]["installed"], true);
+    assert_eq!(hits[0]["matched"], serde_json::json!(["files"]));
+    assert_eq!(hits[0]["files"], serde_json::json!(["/usr/bin/rg"]));
+
+    let output = run_recipe(&["search", "nothing-matches-this"], &recipes);
+    assert!(output.status.success());
+
+    let output = run_recipe(&["search", "("], &recipes);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid search pattern"));
+}
+
+#[test]
+fn test_cli_owns_reports_owner() {
+    let (dir, recipes) = create_test_env();
+    let owned = dir.path().join("root/bin/tool");
+
+    write_recipe(
+        &recipes,
+        "tool",
+        &format!(
+            r#"
+let ctx = #{{
+    name: "tool",
+    installed_files: ["{}"],
+}};
+"#,
+            owned.display()
+        ),
+    );
+
+    let output = run_recipe(&["owns", owned.to_str().unwrap()], &recipes);
+    assert!(
+        output.status.success(),
+        "Owns failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("is owned by tool"));
+
+    let output = run_recipe(&["owns", "/nonexistent/unowned"], &recipes);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No recipe owns"));
+}
+
+#[test]
+fn test_cli_deps_rdeps_and_tree() {
+    let (_dir, recipes) = create_test_env();
+
+    write_recipe(&recipes, "core", "let ctx = #{ name: \"core\" };");
+    write_recipe(&recipes, "cc", "let ctx = #{ name: \"cc\" };");
+    write_recipe(
+        &recipes,
+        "web",
+        "let deps = [\"core\"];\nlet ctx = #{ name: \"web\" };",
+    );
+    write_recipe(
+        &recipes,
+        "app",
+        "let deps = [\"web\"];\nlet build_deps = [\"cc\"];\nlet ctx = #{ name: \"app\" };",
+    );
+
+    let stdout = |args: &[&str]| {
+        let output = run_recipe(args, &recipes);
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(stdout(&["deps", "app"]), "core\nweb\ncc\n");
+    assert_eq!(stdout(&["rdeps", "core"]), "app\nweb\n");
+    assert_eq!(
+        stdout(&["tree", "app"]),
+        "app\n├── web\n│   └── core\n└── cc [build]\n"
+    );
+
+    let dot = stdout(&["deps", "--format", "dot"]);
+    assert!(dot.starts_with("digraph deps {"));
+    assert!(dot.contains("\"app\" -> \"cc\" [style=dashed"));
+
+    let json: serde_json::Value =
+        serde_json::from_str(&stdout(&["deps", "web", "--format", "json"])).unwrap();
+    assert_eq!(json["web"]["deps"], serde_json::json!(["core"]));
+    assert!(json.get("app").is_none());
+}
+
+#[test]
+fn test_cli_resolves_recipes_across_configured_repos() {
+    let (dir, recipes) = create_test_env();
+    let system = dir.path().join("config/recipe/rocky10");
+    std::fs::create_dir_all(&system).unwrap();
+    std::fs::write(
+        dir.path().join("config/recipe/repos.toml"),
+        "[repos.rocky10]\npath = \"rocky10\"\npriority = 10\n",
+    )
+    .unwrap();
+
+    write_recipe(
+        &system,
+        "less",
+        "let ctx = #{ name: \"less\", version: \"661\" };",
+    );
+    write_recipe(
+        &system,
+        "zlib",
+        "let ctx = #{ name: \"zlib\", version: \"1.3\" };",
+    );
+    std::fs::write(system.join("common.rhai"), "fn greet() { \"hi\" }\n").unwrap();
+    write_recipe(
+        &recipes,
+        "less",
+        "let ctx = #{ name: \"less\", version: \"668\" };",
+    );
+    write_recipe(
+        &recipes,
+        "app",
+        "import \"common\" as common;\nlet deps = [\"zlib\", \"rocky10/less\"];\nlet ctx = #{ name: \"app\" };",
+    );
+
+    let stdout = |args: &[&str]| {
+        let output = recipe_command()
+            .args(args)
+            .args(["--recipes-path", recipes.to_str().unwrap()])
+            .env("XDG_CONFIG_HOME", dir.path().join("config"))
+            .env("XDG_CONFIG_DIRS", dir.path().join("none"))
+            .output()
+            .expect("Failed to execute recipe command");
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // The recipes path shadows the repository; `repo/name` reaches past it.
+    assert!(stdout(&["info", "less"]).contains("668"));
+    assert!(stdout(&["info", "rocky10/less"]).contains("661"));
+    assert!(stdout(&["info", "zlib"]).contains("1.3"));
+    assert_eq!(stdout(&["deps", "app"]), "zlib\nrocky10/less\n");
+
+    let list = stdout(&["list"]);
+    assert!(list.contains("zlib") && list.contains("668"), "{list}");
+    assert!(!list.contains("661"), "{list}");
+}
+
+#[test]
+fn test_cli_update_lists_newer_versions() {
+    let (dir, recipes) = create_test_env();
+
+    write_recipe(
+        &recipes,
+        "old",
+        r#"
+let ctx = #{ name: "old", version: "1.2.0" };
+fn check_update(ctx) { "1.10.0" }
+"#,
+    );
+    write_recipe(
+        &recipes,
+        "meta",
+        r#"
+let ctx = #{ name: "meta", version: "2.0" };
+fn check_update() { #{ version: "2.1", url: "https://example.com/meta-2.1.tar.gz" } }
+"#,
+    );
+    write_recipe(
+        &recipes,
+        "current",
+        r#"
+let ctx = #{ name: "current", version: "3.0" };
+fn check_update(ctx) { ctx.version }
+"#,
+    );
+    write_recipe(&recipes, "nohook", "let ctx = #{ name: \"nohook\" };");
+
+    let output = run_recipe(&["update"], &recipes);
+    assert!(
+        output.status.success(),
+        "Update failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1.10.0"), "{stdout}");
+    assert!(stdout.contains("meta"), "{stdout}");
+    assert!(!stdout.contains("current"), "{stdout}");
+
+    let json = dir.path().join("updates.json");
+    let output = run_recipe(
+        &[
+            "update",
+            "old",
+            "current",
+            "--json-output",
+            json.to_str().unwrap(),
+        ],
+        &recipes,
+    );
+    assert!(output.status.success());
+    let updates: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+    let updates = updates.as_array().unwrap();
+    assert_eq!(updates.len(), 1);
+    assert_eq!(updates[0]["name"], "old");
+    assert_eq!(updates[0]["current"], "1.2.0");
+    assert_eq!(updates[0]["latest"], "1.10.0");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("current is up to date"));
+
+    // Recipes are not modified.
+    let source = std::fs::read_to_string(recipes.join("old.rhai")).unwrap();
+    assert!(source.contains("version: \"1.2.0\""));
+}
+
+#[test]
+fn test_cli_history_and_rollback_ctx() {
+    let (_dir, recipes) = create_test_env();
+
+    write_recipe(
+        &recipes,
+        "counter",
+        r#"
+let ctx = #{ name: "counter", runs: 0 };
+fn is_installed(ctx) { throw "always reinstall"; }
+fn acquire(ctx) { ctx }
+fn install(ctx) {
+    ctx.runs += 1;
+    ctx
+}
+"#,
+    );
+    for _ in 0..2 {
+        let output = run_recipe(&["install", "counter"], &recipes);
+        assert!(
+            output.status.success(),
+            "Install failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    // The journal lives in the state directory, not next to the recipe.
+    assert!(!recipes.join("counter.rhai.history.jsonl").exists());
+    assert!(
+        recipes
+            .with_file_name("state/recipe/history")
+            .join(recipes.canonicalize().unwrap().strip_prefix("/").unwrap())
+            .join("counter.rhai.jsonl")
+            .exists()
+    );
+
+    let output = run_recipe(&["history", "counter"], &recipes);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("install"), "{stdout}");
+    assert!(stdout.contains("runs: "), "{stdout}");
+    assert!(stdout.contains(" -> 2"), "{stdout}");
+
+    let json = recipes.join("history.json");
+    let output = run_recipe(
+        &[
+            "history",
+            "counter",
+            "--json-output",
+            json.to_str().unwrap(),
+        ],
+        &recipes,
+    );
+    assert!(output.status.success());
+    let entries: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+    let first_install = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|e| e["phase"] == "install" && e["ctx"]["runs"] == 1)
+        .expect("entry for the first install");
+    let seq = first_install["seq"].to_string();
+
+    let output = run_recipe(&["rollback-ctx", "counter", "--to", &seq], &recipes);
+    assert!(
+        output.status.success(),
+        "Rollback failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let source = std::fs::read_to_string(recipes.join("counter.rhai")).unwrap();
+    assert!(source.contains("runs: 1,"), "{source}");
+
+    let output = run_recipe(&["history", "counter"], &recipes);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("rollback"), "{stdout}");
+
+    let output = run_recipe(&["rollback-ctx", "counter", "--to", "999"], &recipes);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cli_lint_reports_issues_as_text_and_json() {
+    let (_dir, recipes) = create_test_env();
+
+    write_recipe(
+        &recipes,
+        "clean",
+        r#"
+let ctx = #{ name: "clean", version: "1.0" };
+fn is_installed(ctx) { throw "not installed"; }
+fn install(ctx) { mkdir(PREFIX + "/bin"); ctx }
+"#,
+    );
+    write_recipe(
+        &recipes,
+        "broken",
+        r#"
+let ctx = #{ name: "broken", version: "1.0" };
+fn acquire(ctx) { dowload(ctx.url); ctx }
+fn install(ctx) { ctx.done = true; }
+"#,
+    );
+
+    let output = run_recipe(&["lint", "clean"], &recipes);
+    assert!(
+        output.status.success(),
+        "Lint failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = run_recipe(&["lint"], &recipes);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("broken.rhai:3: "), "{stdout}");
+    assert!(stdout.contains("unknown function `dowload`"), "{stdout}");
+    assert!(stdout.contains("phase-return"), "{stdout}");
+    assert!(!stdout.contains("clean.rhai"), "{stdout}");
+
+    let output = run_recipe(&["lint", "broken", "--format", "json"], &recipes);
+    assert!(!output.status.success());
+    let reports: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let codes: Vec<&str> = reports[0]["issues"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|issue| issue["code"].as_str().unwrap())
+        .collect();
+    assert_eq!(codes, ["unknown-function", "phase-return"]);
+    assert_eq!(reports[0]["issues"][0]["severity"], "error");
+    assert_eq!(reports[0]["issues"][0]["line"], 3);
+}
+
+#[test]
+fn test_cli_new_scaffolds_recipes_that_pass_lint() {
+    let (_dir, recipes) = create_test_env();
+
+    for (name, template) in [
+        ("tarball-tool", "tarball"),
+        ("git-tool", "git"),
+        ("release-tool", "github-release"),
+        ("rpm-tool", "rpm-shim"),
+    ] {
+        let output = run_recipe(&["new", name, "--template", template], &recipes);
+        assert!(
+            output.status.success(),
+            "new --template {template} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let source = std::fs::read_to_string(recipes.join(format!("{name}.rhai"))).unwrap();
+        assert!(source.contains(&format!("name: \"{name}\"")), "{source}");
+        assert!(source.contains("fn cleanup(ctx, reason)"), "{source}");
+
+        let output = run_recipe(&["lint", name, "--format", "json"], &recipes);
+        assert!(output.status.success(), "{template} template fails lint");
+        let reports: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(
+            reports[0]["issues"],
+            serde_json::json!([]),
+            "{template} template has lint issues"
+        );
+    }
+
+    let output = run_recipe(&["fmt", "--check"], &recipes);
+    assert!(
+        output.status.success(),
+        "templates are not formatted: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let output = run_recipe(
+        &["new", "git-tool", "--template", "git", "--version", "2.0.0"],
+        &recipes,
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+
+    let output = run_recipe(
+        &[
+            "new",
+            "git-tool",
+            "--template",
+            "git",
+            "--url",
+            "https://example.com/git-tool.git",
+            "--version",
+            "2.0.0",
+            "--force",
+        ],
+        &recipes,
+    );
+    assert!(output.status.success());
+    let source = std::fs::read_to_string(recipes.join("git-tool.rhai")).unwrap();
+    assert!(source.contains("version: \"2.0.0\""), "{source}");
+    assert!(
+        source.contains("repo_url: \"https://example.com/git-tool.git\""),
+        "{source}"
+    );
+}
+
+#[test]
+fn test_cli_fmt_rewrites_recipes_and_check_reports_drift() {
+    let (_dir, recipes) = create_test_env();
+    let messy = r#"//! extends:   base.rhai
+let ctx = #{ version: "1.0", name: "messy" };
+fn install(ctx) { ctx }
+
+
+fn is_installed(ctx) { throw "not installed"; }
+"#;
+    std::fs::write(recipes.join("base.rhai"), "fn helper() { 1 }\n").unwrap();
+    write_recipe(&recipes, "messy", messy);
+
+    let output = run_recipe(&["fmt", "--check"], &recipes);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("messy.rhai"), "{stdout}");
+    assert!(!stdout.contains("base.rhai"), "{stdout}");
+    let content = std::fs::read_to_string(recipes.join("messy.rhai")).unwrap();
+    assert!(
+        content.starts_with("//! extends:   "),
+        "--check must not write"
+    );
+
+    let output = run_recipe(&["fmt"], &recipes);
+    assert!(
+        output.status.success(),
+        "fmt failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let content = std::fs::read_to_string(recipes.join("messy.rhai")).unwrap();
+    assert!(
+        content.starts_with(
+            "//! extends: base.rhai\n\nlet ctx = #{\n    name: \"messy\",\n    version: \"1.0\",\n};\n\nfn is_installed(ctx)"
+        ),
+        "{content}"
+    );
+    assert!(
+        content.find("fn install(ctx)").unwrap() < content.find("fn cleanup(ctx").unwrap(),
+        "{content}"
+    );
+
+    let output = run_recipe(&["fmt", "--check", "messy"], &recipes);
+    assert!(output.status.success());
+    let output = run_recipe(&["lint", "messy"], &recipes);
+    assert!(output.status.success());
+}
+
+/// Put `content` into the download cache under `cache_home` as fetched from
+/// `url` at the epoch.
+fn seed_cache(cache_home: &Path, url: &str, content: &str) -> std::path::PathBuf {
+    use sha2::{Digest, Sha256};
+
+    let root = cache_home.join("recipe/sources");
+    let sha256 = hex::encode(Sha256::digest(content));
+    std::fs::create_dir_all(root.join("sha256")).unwrap();
+    std::fs::create_dir_all(root.join("urls")).unwrap();
+    let blob = root.join("sha256").join(&sha256);
+    std::fs::write(&blob, content).unwrap();
+    let entry = serde_json::json!({
+        "url": url,
+        "sha256": sha256,
+        "size": content.len(),
+        "name": url.rsplit('/').next().unwrap(),
+        "verified": true,
+        "fetched_at": 0,
+        "last_used": 0,
+    });
+    std::fs::write(
+        root.join("urls")
+            .join(format!("{}.json", hex::encode(Sha256::digest(url)))),
+        entry.to_string(),
+    )
+    .unwrap();
+    blob
+}
+
+#[test]
+fn test_cli_cache_ls_verify_and_gc() {
+    let (dir, recipes) = create_test_env();
+    let url = "https://example.com/foo-1.0.tar.gz";
+    let blob = seed_cache(&dir.path().join("cache"), url, "hello world");
+
+    let cache = |args: &[&str]| {
+        recipe_command()
+            .arg("cache")
+            .args(args)
+            .args(["--recipes-path", recipes.to_str().unwrap()])
+            .env("XDG_CACHE_HOME", dir.path().join("cache"))
+            .output()
+            .expect("Failed to execute recipe command")
+    };
+
+    let output = cache(&["ls"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(url) && stdout.contains("b94d27b9934d"),
+        "{stdout}"
+    );
+
+    let output = cache(&["verify"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Entries last used at the epoch are older than the default 30 days.
+    let output = cache(&["gc"]);
+    assert!(output.status.success());
+    assert!(!blob.exists());
+    assert!(!String::from_utf8_lossy(&cache(&["ls"]).stdout).contains(url));
+
+    seed_cache(&dir.path().join("cache"), url, "hello world");
+    std::fs::write(&blob, "tampered").unwrap();
+    let output = cache(&["verify"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("corrupt"));
+    assert!(!blob.exists());
+}
+
+#[test]
+fn test_cli_offline_serves_cache_and_fails_fast() {
+    let (dir, recipes) = create_test_env();
+    let cached = "https://example.com/src/cached-1.0.tar.gz";
+    seed_cache(&dir.path().join("cache"), cached, "cached source");
+
+    for (name, call) in [
+        (
+            "cached",
+            format!("download(\"{cached}\", BUILD_DIR + \"/src.tar.gz\")"),
+        ),
+        (
+            "uncached",
+            "download(\"https://example.invalid/missing.tar.gz\", BUILD_DIR + \"/x\")".into(),
+        ),
+        (
+            "scraper",
+            "http_get(\"https://example.invalid/releases\")".into(),
+        ),
+    ] {
+        write_recipe(
+            &recipes,
+            name,
+            &format!(
+                r#"let ctx = #{{ name: "{name}", fetched: "" }};
+fn is_installed(ctx) {{ throw "not installed"; }}
+fn acquire(ctx) {{ ctx.fetched = read_file({call}); ctx }}
+fn install(ctx) {{ ctx }}
+"#
+            ),
+        );
+    }
+
+    let install = |name: &str| {
+        recipe_command()
+            .args(["install", name, "--offline"])
+            .args(["--recipes-path", recipes.to_str().unwrap()])
+            .args(["--build-dir", dir.path().join("build").to_str().unwrap()])
+            .env("XDG_CACHE_HOME", dir.path().join("cache"))
+            .output()
+            .expect("Failed to execute recipe command")
+    };
+
+    let output = install("cached");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let ctx: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(ctx["fetched"], "cached source");
+
+    let output = install("uncached");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "offline: uncached needs https://example.invalid/missing.tar.gz (not in the download cache)"
+        ),
+        "{stderr}"
+    );
+
+    let output = install("scraper");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("offline: scraper needs https://example.invalid/releases"),
+        "{stderr}"
+    );
+}
@@ -2,12 +2,14 @@
 //!
 //! These tests verify the ctx-based recipe execution pattern.
 
-use levitate_recipe::RecipeEngine;
+use levitate_recipe::{CtxStore, RecipeEngine};
 use std::path::Path;
+use std::sync::Once;
 use tempfile::TempDir;
 
 /// Create a test environment with build_dir and recipes directories
 fn create_test_env() -> (TempDir, std::path::PathBuf, std::path::PathBuf) {
+    isolate_state_home();
     let dir = TempDir::new().unwrap();
     let build_dir = dir.path().join("build");
     let recipes_dir = dir.path().join("recipes");
@@ -16,6 +18,18 @@ fn create_test_env() -> (TempDir, std::path::PathBuf, std::path::PathBuf) {
     (dir, build_dir, recipes_dir)
 }
 
+/// Point `XDG_STATE_HOME` at a directory owned by this test run, so nothing
+/// reaches the real `~/.local/state/recipe`.
+fn isolate_state_home() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| unsafe {
+        std::env::set_var(
+            "XDG_STATE_HOME",
+            Path::new(env!("CARGO_TARGET_TMPDIR")).join("state"),
+        );
+    });
+}
+
 /// Write a recipe file and return its path
 fn write_recipe(recipes_dir: &Path, name: &str, content: &str) -> std::path::PathBuf {
     let path = recipes_dir.join(format!("{}.rhai", name));
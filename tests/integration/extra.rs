@@ -81,3 +81,531 @@ fn install(ctx) {
     assert!(content1.contains("installed-one"));
     assert!(content2.contains("installed-two"));
 }
+
+#[test]
+fn test_transitive_deps_install_in_topological_order() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let log = dir.path().join("order.log");
+
+    let dep = |name: &str, deps: &str| {
+        write_recipe(
+            &recipes_dir,
+            name,
+            &format!(
+                r#"
+let deps = [{deps}];
+let ctx = #{{ name: "{name}" }};
+fn is_installed(ctx) {{ throw "not installed"; }}
+fn is_built(ctx) {{ ctx }}
+fn install(ctx) {{
+    append_file(LOG, "{name}\n");
+    ctx
+}}
+"#
+            ),
+        )
+    };
+    dep("core", "");
+    dep("web", r#""core""#);
+    dep("db", r#""core""#);
+    let app = dep("app", r#""web", "db""#);
+
+    let mut engine = RecipeEngine::new(build_dir).with_recipes_path(recipes_dir.clone());
+    engine.add_define("LOG".to_string(), log.display().to_string());
+    engine.execute(&app).unwrap();
+
+    let order = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(order, "core\nweb\ndb\napp\n");
+
+    dep("core", r#""app""#);
+    let err = engine.execute(&app).unwrap_err();
+    assert!(
+        format!("{err:#}").contains("app -> web -> core -> app"),
+        "{err:#}"
+    );
+}
+
+#[test]
+fn test_autoremove_removes_orphaned_dependencies() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let log = dir.path().join("order.log");
+
+    let recipe = |name: &str, deps: &str| {
+        write_recipe(
+            &recipes_dir,
+            name,
+            &format!(
+                r#"
+let deps = [{deps}];
+let ctx = #{{ name: "{name}" }};
+fn is_installed(ctx) {{ throw "not installed"; }}
+fn is_built(ctx) {{ ctx }}
+fn install(ctx) {{
+    append_file(LOG, "install {name}\n");
+    ctx
+}}
+fn remove(ctx) {{
+    append_file(LOG, "remove {name}\n");
+    ctx
+}}
+"#
+            ),
+        )
+    };
+    recipe("core", "");
+    recipe("lib", r#""core""#);
+    let app = recipe("app", r#""lib""#);
+    // Pre-create the log so it is not recorded as a file `core` installed.
+    std::fs::write(&log, "").unwrap();
+
+    let mut engine = RecipeEngine::new(build_dir).with_recipes_path(recipes_dir.clone());
+    engine.add_define("LOG".to_string(), log.display().to_string());
+    engine.execute(&app).unwrap();
+
+    let reason = |name: &str| {
+        let source = std::fs::read_to_string(recipes_dir.join(format!("{name}.rhai"))).unwrap();
+        source
+            .lines()
+            .find(|l| l.contains("install_reason"))
+            .map(str::trim)
+            .map(String::from)
+    };
+    assert!(reason("app").unwrap().contains("\"explicit\""));
+    assert!(reason("lib").unwrap().contains("\"dependency\""));
+    assert!(engine.orphans().unwrap().is_empty());
+
+    engine.remove(&app).unwrap();
+    assert_eq!(reason("app"), None);
+    let names: Vec<String> = engine
+        .orphans()
+        .unwrap()
+        .into_iter()
+        .map(|o| o.name)
+        .collect();
+    assert_eq!(names, ["lib", "core"]);
+
+    std::fs::write(&log, "").unwrap();
+    engine.autoremove().unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&log).unwrap(),
+        "remove lib\nremove core\n"
+    );
+    assert!(engine.orphans().unwrap().is_empty());
+}
+
+#[test]
+fn test_upgrade_reinstalls_new_version_and_keeps_old_on_failure() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let root = dir.path().join("root");
+    std::fs::create_dir_all(&root).unwrap();
+
+    let recipe_path = write_recipe(
+        &recipes_dir,
+        "tool",
+        r#"
+let ctx = #{ name: "tool", version: "1.0" };
+fn is_installed(ctx) {
+    if !exists(ROOT + "/tool-" + ctx.version) { throw "not installed"; }
+    ctx
+}
+fn acquire(ctx) { ctx }
+fn install(ctx) {
+    if ctx.version == "3.0" { throw "broken release"; }
+    write_file(ROOT + "/tool-" + ctx.version, ctx.version);
+    ctx
+}
+fn check_update(ctx) { LATEST }
+"#,
+    );
+
+    let engine_with = |latest: &str| {
+        let mut engine =
+            RecipeEngine::new(build_dir.clone()).with_recipes_path(recipes_dir.clone());
+        engine.add_define("ROOT".to_string(), root.display().to_string());
+        engine.add_define("LATEST".to_string(), latest.to_string());
+        engine
+    };
+
+    engine_with("1.0").execute(&recipe_path).unwrap();
+    assert!(root.join("tool-1.0").exists());
+
+    // Up to date: nothing happens.
+    let info = engine_with("1.0").upgrade(&recipe_path).unwrap().unwrap();
+    assert!(!info.is_newer());
+
+    let info = engine_with("2.0").upgrade(&recipe_path).unwrap().unwrap();
+    assert!(info.is_newer());
+    assert!(root.join("tool-2.0").exists());
+    assert!(!root.join("tool-1.0").exists(), "stale file from 1.0 kept");
+    let source = std::fs::read_to_string(&recipe_path).unwrap();
+    assert!(source.contains("version: \"2.0\""), "{source}");
+    assert!(source.contains("install_reason: \"explicit\""), "{source}");
+
+    let err = engine_with("3.0").upgrade(&recipe_path).unwrap_err();
+    assert!(format!("{err:#}").contains("broken release"), "{err:#}");
+    assert!(root.join("tool-2.0").exists());
+    let source = std::fs::read_to_string(&recipe_path).unwrap();
+    assert!(source.contains("version: \"2.0\""), "{source}");
+}
+
+#[test]
+fn test_upgrade_without_persistence_writes_nothing() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let root = dir.path().join("root");
+    std::fs::create_dir_all(&root).unwrap();
+
+    let recipe_path = write_recipe(
+        &recipes_dir,
+        "tool",
+        r#"
+let ctx = #{ name: "tool", version: "1.0" };
+fn is_installed(ctx) {
+    if !exists(ROOT + "/tool-" + ctx.version) { throw "not installed"; }
+    ctx
+}
+fn acquire(ctx) { ctx }
+fn install(ctx) {
+    if ctx.version == "3.0" { throw "broken release"; }
+    write_file(ROOT + "/tool-" + ctx.version, ctx.version);
+    ctx
+}
+fn check_update(ctx) { LATEST }
+"#,
+    );
+    let before = std::fs::read_to_string(&recipe_path).unwrap();
+    let history_dir = dir.path().join("history");
+
+    let engine_with = |latest: &str| {
+        let mut engine = RecipeEngine::new(build_dir.clone())
+            .with_recipes_path(recipes_dir.clone())
+            .with_ctx_persistence(false)
+            .with_history_dir(Some(history_dir.clone()));
+        engine.add_define("ROOT".to_string(), root.display().to_string());
+        engine.add_define("LATEST".to_string(), latest.to_string());
+        engine
+    };
+
+    let info = engine_with("2.0").upgrade(&recipe_path).unwrap().unwrap();
+    assert!(info.is_newer());
+    assert!(root.join("tool-2.0").exists());
+    assert!(engine_with("3.0").upgrade(&recipe_path).is_err());
+
+    // Neither run wrote the recipe or its history journal.
+    assert_eq!(std::fs::read_to_string(&recipe_path).unwrap(), before);
+    assert!(engine_with("2.0").history(&recipe_path).unwrap().is_empty());
+    assert!(!history_dir.exists());
+}
+
+#[test]
+fn test_sysroot_confines_install_helpers() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let sysroot = dir.path().join("sysroot");
+    std::fs::create_dir_all(&sysroot).unwrap();
+
+    let tool = write_recipe(
+        &recipes_dir,
+        "tool",
+        r#"
+let ctx = #{ name: "tool" };
+fn is_installed(ctx) {
+    if !exists(SYSROOT + PREFIX + "/bin/tool") { throw "not installed"; }
+    ctx
+}
+fn acquire(ctx) { ctx }
+fn install(ctx) {
+    mkdir(PREFIX + "/bin");
+    write_file(PREFIX + "/bin/tool", "tool");
+    ln("tool", PREFIX + "/bin/t");
+    ctx
+}
+"#,
+    );
+    let escape = write_recipe(
+        &recipes_dir,
+        "escape",
+        r#"
+let ctx = #{ name: "escape" };
+fn is_installed(ctx) { throw "not installed"; }
+fn acquire(ctx) { ctx }
+fn install(ctx) {
+    write_file("/usr/../../../escaped", "x");
+    ctx
+}
+"#,
+    );
+
+    let engine = RecipeEngine::new(build_dir)
+        .with_recipes_path(recipes_dir)
+        .with_sysroot(Some(sysroot.clone()))
+        .with_prefix("/usr".to_string());
+
+    engine.execute(&tool).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(sysroot.join("usr/bin/tool")).unwrap(),
+        "tool"
+    );
+    assert!(sysroot.join("usr/bin/t").is_symlink());
+    // Already installed under the sysroot.
+    engine.execute(&tool).unwrap();
+
+    let err = engine.execute(&escape).unwrap_err();
+    assert!(format!("{err:#}").contains("escapes sysroot"), "{err:#}");
+    assert!(!dir.path().join("escaped").exists());
+}
+
+#[test]
+fn test_sysroot_confines_remove_and_cleanup() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let sysroot = dir.path().join("sysroot");
+    let host = dir.path().join("host");
+    let in_sysroot = sysroot.join(host.strip_prefix("/").unwrap());
+    for root in [&host, &in_sysroot] {
+        std::fs::create_dir_all(root).unwrap();
+        std::fs::write(root.join("tool"), "tool").unwrap();
+        std::fs::write(root.join("cache"), "cache").unwrap();
+    }
+
+    let recipe = write_recipe(
+        &recipes_dir,
+        "tool",
+        r#"
+let ctx = #{ name: "tool" };
+fn remove(ctx) {
+    rm(HOST + "/tool");
+    ctx
+}
+fn cleanup(ctx, reason) {
+    rm(HOST + "/cache");
+    ctx
+}
+"#,
+    );
+
+    let mut engine = RecipeEngine::new(build_dir)
+        .with_recipes_path(recipes_dir)
+        .with_sysroot(Some(sysroot.clone()));
+    engine.add_define("HOST".to_string(), host.display().to_string());
+    engine.remove(&recipe).unwrap();
+    engine.cleanup(&recipe).unwrap();
+
+    // Absolute paths were re-rooted: only the sysroot copies are gone.
+    assert!(!in_sysroot.join("tool").exists());
+    assert!(!in_sysroot.join("cache").exists());
+    assert!(host.join("tool").exists());
+    assert!(host.join("cache").exists());
+}
+
+#[test]
+fn test_sysroot_confines_dependency_install() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let sysroot = dir.path().join("sysroot");
+    std::fs::create_dir_all(&sysroot).unwrap();
+
+    write_recipe(
+        &recipes_dir,
+        "sysroot-dep",
+        r#"
+let ctx = #{ name: "sysroot-dep" };
+fn is_installed(ctx) { throw "not installed"; }
+fn install(ctx) {
+    mkdir(PREFIX + "/lib/sysroot-dep");
+    write_file(PREFIX + "/lib/sysroot-dep/lib.so", "lib");
+    ctx
+}
+"#,
+    );
+    let app = write_recipe(
+        &recipes_dir,
+        "app",
+        r#"
+let deps = ["sysroot-dep"];
+let ctx = #{ name: "app" };
+fn is_installed(ctx) { throw "not installed"; }
+fn acquire(ctx) { ctx }
+fn install(ctx) { ctx }
+"#,
+    );
+
+    let engine = RecipeEngine::new(build_dir)
+        .with_recipes_path(recipes_dir)
+        .with_sysroot(Some(sysroot.clone()))
+        .with_prefix("/usr".to_string());
+    engine.execute(&app).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(sysroot.join("usr/lib/sysroot-dep/lib.so")).unwrap(),
+        "lib"
+    );
+    assert!(!std::path::Path::new("/usr/lib/sysroot-dep").exists());
+}
+
+#[test]
+fn test_state_store_keeps_recipe_source_untouched() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let state_dir = dir.path().join("state");
+    let root = dir.path().join("root");
+    std::fs::create_dir_all(&root).unwrap();
+
+    let recipe_path = write_recipe(
+        &recipes_dir,
+        "tool",
+        r#"
+let ctx = #{ name: "tool", version: "1.0", runs: 0 };
+fn is_installed(ctx) {
+    if !exists(ROOT + "/tool") { throw "not installed"; }
+    ctx
+}
+fn acquire(ctx) { ctx }
+fn install(ctx) {
+    write_file(ROOT + "/tool", ctx.version);
+    ctx.runs += 1;
+    ctx
+}
+"#,
+    );
+    let original = std::fs::read_to_string(&recipe_path).unwrap();
+
+    let mut engine = RecipeEngine::new(build_dir)
+        .with_recipes_path(recipes_dir)
+        .with_ctx_store(CtxStore::StateDir(state_dir.clone()));
+    engine.add_define("ROOT".to_string(), root.display().to_string());
+
+    let ctx = engine.execute(&recipe_path).unwrap();
+    assert_eq!(ctx["runs"].as_int().unwrap(), 1);
+    assert_eq!(std::fs::read_to_string(&recipe_path).unwrap(), original);
+
+    let state_file = CtxStore::StateDir(state_dir)
+        .state_file(&recipe_path)
+        .unwrap();
+    let state: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&state_file).unwrap()).unwrap();
+    assert_eq!(state["runs"], 1);
+    assert_eq!(state["install_reason"], "explicit");
+    assert!(state.get("version").is_none(), "{state}");
+
+    // The overlay is loaded back: the recipe counts as installed.
+    assert_eq!(
+        engine.installed_recipes(),
+        [recipe_path.canonicalize().unwrap()]
+    );
+    std::fs::remove_file(root.join("tool")).unwrap();
+    let ctx = engine.execute(&recipe_path).unwrap();
+    assert_eq!(ctx["runs"].as_int().unwrap(), 2);
+    assert_eq!(std::fs::read_to_string(&recipe_path).unwrap(), original);
+}
+
+#[test]
+fn test_ctx_schema_rejects_violations_after_phases() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let recipe = |name: &str, acquire: &str| {
+        write_recipe(
+            &recipes_dir,
+            name,
+            &format!(
+                r#"
+let ctx = #{{ name: "{name}", version: "1.0", channel: "stable" }};
+let ctx_schema = #{{
+    name: "string",
+    version: "string",
+    channel: #{{ type: "string", values: ["stable", "beta"] }},
+    jobs: #{{ type: "int", required: false }},
+}};
+fn is_installed(ctx) {{ throw "not installed"; }}
+fn acquire(ctx) {{ {acquire} ctx }}
+fn install(ctx) {{
+    write_file(BUILD_DIR + "/../{name}-installed", "x");
+    ctx
+}}
+"#
+            ),
+        )
+    };
+    let good = recipe("good", "ctx.jobs = 4;");
+    let typo = recipe("typo", "ctx.verison = \"2.0\";");
+    let bad_value = recipe("bad-value", "ctx.channel = \"nightly\";");
+
+    let engine = RecipeEngine::new(build_dir).with_recipes_path(recipes_dir);
+    engine.execute(&good).unwrap();
+
+    let err = format!("{:#}", engine.execute(&typo).unwrap_err());
+    assert!(
+        err.contains("typo: ctx schema violation after acquire: `verison` is not declared"),
+        "{err}"
+    );
+    let err = format!("{:#}", engine.execute(&bad_value).unwrap_err());
+    assert!(
+        err.contains("bad-value: ctx schema violation after acquire: `channel` must be one of"),
+        "{err}"
+    );
+    assert!(!dir.path().join("typo-installed").exists());
+}
+
+#[test]
+fn test_dependency_install_uses_install_scope_and_stage() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+
+    write_recipe(
+        &recipes_dir,
+        "staged",
+        r#"
+let ctx = #{ name: "staged", stage: STAGE_DIR, rpm: RPM_PATH };
+fn is_installed(ctx) { throw "not installed"; }
+fn install(ctx) {
+    write_file(join_path(STAGE_DIR, "partial"), "x");
+    throw "install broke";
+}
+"#,
+    );
+    let app = write_recipe(
+        &recipes_dir,
+        "app",
+        r#"
+let deps = ["staged"];
+let ctx = #{ name: "app" };
+fn is_installed(ctx) { throw "not installed"; }
+fn install(ctx) { ctx }
+"#,
+    );
+
+    let engine = RecipeEngine::new(build_dir.clone()).with_recipes_path(recipes_dir);
+    let err = engine.execute(&app).unwrap_err();
+    assert!(format!("{err:#}").contains("install broke"), "{err:#}");
+    // The failed dependency's staged files were discarded, not left behind.
+    assert!(!build_dir.join(".deps/staged/.stage").exists());
+    assert!(!dir.path().join("partial").exists());
+}
+
+#[test]
+fn test_tool_dependency_in_build_dir_is_not_recorded() {
+    let (_dir, build_dir, recipes_dir) = create_test_env();
+
+    let tool_src = r#"
+let ctx = #{ name: "tool", installed_files: [] };
+fn is_installed(ctx) { throw "not installed"; }
+fn install(ctx) {
+    mkdir(TOOLS_PREFIX);
+    write_file(join_path(TOOLS_PREFIX, "tool"), "tool");
+    ctx
+}
+"#;
+    let tool = write_recipe(&recipes_dir, "tool", tool_src);
+    let before = std::fs::read_to_string(&tool).unwrap();
+    let app = write_recipe(
+        &recipes_dir,
+        "app",
+        r#"
+let deps = ["tool"];
+let ctx = #{ name: "app" };
+fn is_installed(ctx) { throw "not installed"; }
+fn acquire(ctx) { ctx }
+fn install(ctx) { ctx }
+"#,
+    );
+
+    let engine = RecipeEngine::new(build_dir.clone()).with_recipes_path(recipes_dir);
+    engine.execute(&app).unwrap();
+
+    // The tool lived only under TOOLS_PREFIX, so it is neither tracked nor
+    // persisted as an installed dependency.
+    assert_eq!(std::fs::read_to_string(&tool).unwrap(), before);
+}
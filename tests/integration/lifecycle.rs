@@ -414,6 +414,256 @@ let ctx = #{
     );
 }
 
+#[test]
+fn test_remove_uses_installed_file_manifest() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let target = dir.path().join("root/opt/tracked");
+
+    let recipe_path = write_recipe(
+        &recipes_dir,
+        "tracked",
+        r#"
+let ctx = #{
+    name: "tracked",
+    installed: false,
+};
+
+fn is_installed(ctx) {
+    if !ctx.installed { throw "not installed"; }
+    ctx
+}
+fn is_built(ctx) { ctx }
+
+fn install(ctx) {
+    // Written directly into the target...
+    mkdir(`${TARGET}/bin`);
+    write_file(`${TARGET}/bin/tool`, "tool");
+    ln("tool", `${TARGET}/bin/alias`);
+    // ...and through the stage.
+    mkdir(`${STAGE_DIR}${TARGET}/share`);
+    write_file(`${STAGE_DIR}${TARGET}/share/data`, "data");
+    // Scratch files under BUILD_DIR are never tracked.
+    write_file(`${BUILD_DIR}/scratch`, "scratch");
+    ctx.installed = true;
+    ctx
+}
+"#,
+    );
+
+    let mut engine = RecipeEngine::new(build_dir);
+    engine.add_define("TARGET".to_string(), target.display().to_string());
+
+    let ctx = engine.execute(&recipe_path).unwrap();
+    let files: Vec<String> = ctx["installed_files"]
+        .clone()
+        .into_array()
+        .unwrap()
+        .into_iter()
+        .map(|v| v.into_string().unwrap())
+        .collect();
+    let expected =
+        ["bin/tool", "bin/alias", "share/data"].map(|p| target.join(p).display().to_string());
+    assert_eq!(files, expected);
+
+    let content = std::fs::read_to_string(&recipe_path).unwrap();
+    assert!(content.contains("installed_files: ["));
+
+    engine.remove(&recipe_path).unwrap();
+    assert!(!dir.path().join("root").exists());
+    let content = std::fs::read_to_string(&recipe_path).unwrap();
+    assert!(content.contains("installed: false"));
+    assert!(content.contains("installed_files: []"));
+}
+
+#[test]
+fn test_custom_remove_keeps_files_it_leaves() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let target = dir.path().join("root");
+
+    let recipe_path = write_recipe(
+        &recipes_dir,
+        "keeper",
+        r#"
+let ctx = #{
+    name: "keeper",
+    installed: false,
+};
+
+fn is_installed(ctx) {
+    if !ctx.installed { throw "not installed"; }
+    ctx
+}
+fn is_built(ctx) { ctx }
+
+fn install(ctx) {
+    mkdir(`${TARGET}/etc`);
+    write_file(`${TARGET}/etc/keeper.conf`, "config");
+    write_file(`${TARGET}/keeper`, "binary");
+    ctx.installed = true;
+    ctx
+}
+
+fn remove(ctx) {
+    // User config stays behind on purpose.
+    rm(`${TARGET}/keeper`);
+    ctx.installed = false;
+    ctx
+}
+"#,
+    );
+
+    let mut engine = RecipeEngine::new(build_dir);
+    engine.add_define("TARGET".to_string(), target.display().to_string());
+
+    engine.execute(&recipe_path).unwrap();
+    engine.remove(&recipe_path).unwrap();
+
+    assert!(!target.join("keeper").exists());
+    assert_eq!(
+        std::fs::read_to_string(target.join("etc/keeper.conf")).unwrap(),
+        "config"
+    );
+}
+
+#[test]
+fn test_install_refuses_files_owned_by_other_recipes() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let target = dir.path().join("root");
+
+    let recipe = |name: &str, via: &str| {
+        write_recipe(
+            &recipes_dir,
+            name,
+            &format!(
+                r#"
+let ctx = #{{
+    name: "{name}",
+    installed: false,
+}};
+
+fn is_installed(ctx) {{
+    if !ctx.installed {{ throw "not installed"; }}
+    ctx
+}}
+fn is_built(ctx) {{ ctx }}
+
+fn install(ctx) {{
+    mkdir(`{via}${{TARGET}}/bin`);
+    write_file(`{via}${{TARGET}}/bin/tool`, "{name}");
+    ctx.installed = true;
+    ctx
+}}
+"#
+            ),
+        )
+    };
+    let first = recipe("first", "");
+    let second = recipe("second", "${STAGE_DIR}");
+
+    let mut engine = RecipeEngine::new(build_dir).with_recipes_path(recipes_dir.clone());
+    engine.add_define("TARGET".to_string(), target.display().to_string());
+
+    engine.execute(&first).unwrap();
+    // Reinstalling never conflicts with the recipe's own files.
+    std::fs::write(
+        &first,
+        std::fs::read_to_string(&first)
+            .unwrap()
+            .replace("installed: true", "installed: false"),
+    )
+    .unwrap();
+    engine.execute(&first).unwrap();
+
+    let err = engine.execute(&second).unwrap_err();
+    assert!(format!("{err:#}").contains("owned by first"), "{err:#}");
+    assert_eq!(
+        std::fs::read_to_string(target.join("bin/tool")).unwrap(),
+        "first"
+    );
+
+    let engine = engine.with_allow_conflicts(true);
+    engine.execute(&second).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(target.join("bin/tool")).unwrap(),
+        "second"
+    );
+    let owners = engine.owners_of(&target.join("bin/tool"));
+    let names: Vec<_> = owners.iter().map(|o| o.name.as_str()).collect();
+    assert_eq!(names, ["first", "second"]);
+}
+
+#[test]
+fn test_dependency_install_refuses_files_owned_by_other_recipes() {
+    let (dir, build_dir, recipes_dir) = create_test_env();
+    let target = dir.path().join("root");
+
+    let owner = write_recipe(
+        &recipes_dir,
+        "owner",
+        r#"
+let ctx = #{
+    name: "owner",
+    installed: false,
+};
+
+fn is_installed(ctx) {
+    if !ctx.installed { throw "not installed"; }
+    ctx
+}
+fn is_built(ctx) { ctx }
+
+fn install(ctx) {
+    mkdir(`${TARGET}/bin`);
+    write_file(`${TARGET}/bin/tool`, "owner");
+    ctx.installed = true;
+    ctx
+}
+"#,
+    );
+    write_recipe(
+        &recipes_dir,
+        "clobber",
+        r#"
+let ctx = #{ name: "clobber" };
+fn is_installed(ctx) { throw "not installed"; }
+fn install(ctx) {
+    mkdir(`${STAGE_DIR}${TARGET}/bin`);
+    write_file(`${STAGE_DIR}${TARGET}/bin/tool`, "clobber");
+    ctx
+}
+"#,
+    );
+    let app = write_recipe(
+        &recipes_dir,
+        "app",
+        r#"
+let deps = ["clobber"];
+let ctx = #{ name: "app" };
+fn is_installed(ctx) { throw "not installed"; }
+fn acquire(ctx) { ctx }
+fn install(ctx) { ctx }
+"#,
+    );
+
+    let mut engine = RecipeEngine::new(build_dir).with_recipes_path(recipes_dir.clone());
+    engine.add_define("TARGET".to_string(), target.display().to_string());
+
+    engine.execute(&owner).unwrap();
+    let err = engine.execute(&app).unwrap_err();
+    assert!(format!("{err:#}").contains("owned by owner"), "{err:#}");
+    assert_eq!(
+        std::fs::read_to_string(target.join("bin/tool")).unwrap(),
+        "owner"
+    );
+
+    let engine = engine.with_allow_conflicts(true);
+    engine.execute(&app).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(target.join("bin/tool")).unwrap(),
+        "clobber"
+    );
+}
+
 // =============================================================================
 // Cleanup Tests
 // =============================================================================